@@ -0,0 +1,556 @@
+//! Code for altering the structure of existing tables.
+//!
+//! PostgreSQL supports all of the operations defined here directly, via `ALTER TABLE`. SQLite
+//! supports adding and renaming columns and renaming tables directly, but in order to drop a
+//! column or change its type or nullability the table must be rebuilt, i.e., a new table with the
+//! desired structure must be created, the data copied to it from the old table, the old table
+//! dropped, and the new table renamed to the old table's name (see
+//! <https://www.sqlite.org/lang_altertable.html#otheralter>). The indexes and triggers defined on
+//! the old table are then recreated on the new table.
+
+use crate::{
+    cache::clear_cache_for_dropped_tables,
+    core::{DbError, DbQuery},
+    db_value::DbRow,
+    params,
+    parse::validate_table_name,
+};
+use std::collections::BTreeMap;
+
+/// The prefix used for the name of the temporary table that is created when rebuilding a table.
+pub static REBUILD_TABLE_PREFIX: &str = "rltbl_db_rebuild_";
+
+/// Add a column with the given name and SQL type to the given table.
+pub async fn add_column(
+    pool: &impl DbQuery,
+    table: &str,
+    column: &str,
+    sql_type: &str,
+) -> Result<(), DbError> {
+    let table = validate_table_name(table)?;
+    // Make sure that the type is one that is supported:
+    pool.kind().db_type(sql_type)?;
+    let sql = pool.kind().add_column_sql(&table, column, sql_type);
+    pool.execute_no_cache_clean(&sql, ()).await?;
+    clear_cache_for_dropped_tables(pool, &[&table]).await
+}
+
+/// Drop the given column from the given table.
+pub async fn drop_column(pool: &impl DbQuery, table: &str, column: &str) -> Result<(), DbError> {
+    let table = validate_table_name(table)?;
+    let sql = pool.kind().drop_column_sql(&table, column);
+    match sql {
+        Some(sql) => pool.execute_no_cache_clean(&sql, ()).await?,
+        None => rebuild_table(pool, &table, &TableChange::DropColumn(column)).await?,
+    };
+    clear_cache_for_dropped_tables(pool, &[&table]).await
+}
+
+/// Rename the given column of the given table.
+pub async fn rename_column(
+    pool: &impl DbQuery,
+    table: &str,
+    column: &str,
+    new_name: &str,
+) -> Result<(), DbError> {
+    let table = validate_table_name(table)?;
+    let sql = pool.kind().rename_column_sql(&table, column, new_name);
+    pool.execute_no_cache_clean(&sql, ()).await?;
+    clear_cache_for_dropped_tables(pool, &[&table]).await
+}
+
+/// Change the SQL type of the given column of the given table.
+pub async fn alter_column_type(
+    pool: &impl DbQuery,
+    table: &str,
+    column: &str,
+    sql_type: &str,
+) -> Result<(), DbError> {
+    let table = validate_table_name(table)?;
+    // Make sure that the type is one that is supported:
+    pool.kind().db_type(sql_type)?;
+    let sql = pool.kind().alter_column_type_sql(&table, column, sql_type);
+    match sql {
+        Some(sql) => pool.execute_no_cache_clean(&sql, ()).await?,
+        None => {
+            rebuild_table(
+                pool,
+                &table,
+                &TableChange::AlterColumnType(column, sql_type),
+            )
+            .await?
+        }
+    };
+    clear_cache_for_dropped_tables(pool, &[&table]).await
+}
+
+/// Add (if `not_null` is true) or remove (otherwise) a NOT NULL constraint on the given column of
+/// the given table.
+pub async fn alter_column_not_null(
+    pool: &impl DbQuery,
+    table: &str,
+    column: &str,
+    not_null: bool,
+) -> Result<(), DbError> {
+    let table = validate_table_name(table)?;
+    let sql = pool
+        .kind()
+        .alter_column_not_null_sql(&table, column, not_null);
+    match sql {
+        Some(sql) => pool.execute_no_cache_clean(&sql, ()).await?,
+        None => {
+            rebuild_table(
+                pool,
+                &table,
+                &TableChange::AlterColumnNotNull(column, not_null),
+            )
+            .await?
+        }
+    };
+    clear_cache_for_dropped_tables(pool, &[&table]).await
+}
+
+/// Rename the given table.
+pub async fn rename_table(pool: &impl DbQuery, table: &str, new_name: &str) -> Result<(), DbError> {
+    let table = validate_table_name(table)?;
+    let new_name = validate_table_name(new_name)?;
+    let sql = pool.kind().rename_table_sql(&table, &new_name);
+    pool.execute_no_cache_clean(&sql, ()).await?;
+    clear_cache_for_dropped_tables(pool, &[&table, &new_name]).await
+}
+
+/// The kinds of change that may require a table to be rebuilt.
+enum TableChange<'a> {
+    DropColumn(&'a str),
+    AlterColumnType(&'a str, &'a str),
+    AlterColumnNotNull(&'a str, bool),
+}
+
+/// A foreign key constraint on a SQLite table, as reported by `pragma_foreign_key_list`.
+#[derive(Default)]
+struct ForeignKey {
+    table: String,
+    from: Vec<String>,
+    to: Vec<String>,
+    on_update: String,
+    on_delete: String,
+}
+
+// Get the value of the given column of the given row as a string.
+fn get_string(row: &DbRow, column: &str) -> Result<String, DbError> {
+    row.get(column)
+        .map(|value| value.to_string())
+        .ok_or(DbError::DataError(format!("No column '{column}' in row")))
+}
+
+// Get the value of the given column of the given row as a string, or None if it is NULL.
+fn get_optional_string(row: &DbRow, column: &str) -> Result<Option<String>, DbError> {
+    match row.get(column) {
+        Some(value) if value.is_null() => Ok(None),
+        Some(value) => Ok(Some(value.to_string())),
+        None => Err(DbError::DataError(format!("No column '{column}' in row"))),
+    }
+}
+
+// Get the value of the given column of the given row as an integer.
+fn get_integer(row: &DbRow, column: &str) -> Result<i64, DbError> {
+    row.get(column)
+        .ok_or(DbError::DataError(format!("No column '{column}' in row")))?
+        .try_into()
+}
+
+// Get the names of the columns indexed by the given SQLite index.
+async fn sqlite_index_columns(pool: &impl DbQuery, index: &str) -> Result<Vec<String>, DbError> {
+    let rows = pool
+        .query_no_cache_clean(
+            r#"SELECT "name" FROM pragma_index_info(?1) ORDER BY "seqno""#,
+            params![index],
+        )
+        .await?;
+    // Expressions in an index have a NULL name:
+    rows.iter()
+        .filter_map(|row| get_optional_string(row, "name").transpose())
+        .collect()
+}
+
+// Rebuild the given SQLite table with the given change applied, preserving its data, its
+// primary key, unique, not null, default, and foreign key constraints, and its indexes and
+// triggers. Note that CHECK constraints are not preserved. Indexes, unique constraints, and
+// foreign keys that refer to a dropped column are themselves dropped.
+async fn rebuild_table(
+    pool: &impl DbQuery,
+    table: &str,
+    change: &TableChange<'_>,
+) -> Result<(), DbError> {
+    let changed_column = match change {
+        TableChange::DropColumn(column) => column,
+        TableChange::AlterColumnType(column, _) => column,
+        TableChange::AlterColumnNotNull(column, _) => column,
+    };
+    let dropped_column = match change {
+        TableChange::DropColumn(column) => Some(*column),
+        _ => None,
+    };
+    let is_kept = |columns: &[String]| match dropped_column {
+        Some(dropped) => !columns.iter().any(|column| column == dropped),
+        None => true,
+    };
+    let quote = |columns: &[String]| {
+        columns
+            .iter()
+            .map(|column| format!(r#""{column}""#))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    // Collect the column definitions, primary key, and list of columns to copy:
+    let rows = pool
+        .query_no_cache_clean(
+            r#"SELECT "name", "type", "notnull", "dflt_value", "pk"
+               FROM pragma_table_info(?1)
+               ORDER BY "cid""#,
+            params![table],
+        )
+        .await?;
+    if rows.is_empty() {
+        return Err(DbError::InputError(format!("No such table '{table}'")));
+    }
+    if !rows
+        .iter()
+        .any(|row| get_string(row, "name").ok().as_deref() == Some(changed_column))
+    {
+        return Err(DbError::InputError(format!(
+            "No column '{changed_column}' in table '{table}'"
+        )));
+    }
+    let mut definitions = vec![];
+    let mut copied_columns = vec![];
+    let mut primary_key = BTreeMap::new();
+    for row in rows.iter() {
+        let name = get_string(row, "name")?;
+        let pk = get_integer(row, "pk")?;
+        if pk > 0 {
+            primary_key.insert(pk, name.to_string());
+        }
+        if Some(name.as_str()) == dropped_column {
+            continue;
+        }
+        let sql_type = match change {
+            TableChange::AlterColumnType(column, sql_type) if *column == name => {
+                sql_type.to_string()
+            }
+            _ => get_string(row, "type")?,
+        };
+        let mut definition = format!(r#""{name}" {sql_type}"#);
+        let not_null = match change {
+            TableChange::AlterColumnNotNull(column, not_null) if *column == name => *not_null,
+            _ => get_integer(row, "notnull")? != 0,
+        };
+        if not_null {
+            definition.push_str(" NOT NULL");
+        }
+        if let Some(default) = get_optional_string(row, "dflt_value")? {
+            definition.push_str(&format!(" DEFAULT {default}"));
+        }
+        definitions.push(definition);
+        copied_columns.push(format!(r#""{name}""#));
+    }
+    if copied_columns.is_empty() {
+        return Err(DbError::InputError(format!(
+            "Cannot drop the only column of table '{table}'"
+        )));
+    }
+    let primary_key = primary_key.into_values().collect::<Vec<_>>();
+    if !primary_key.is_empty() {
+        if !is_kept(&primary_key) {
+            return Err(DbError::InputError(format!(
+                "Cannot drop column '{changed_column}' of table '{table}', which is part of \
+                 its primary key"
+            )));
+        }
+        definitions.push(format!("PRIMARY KEY ({})", quote(&primary_key)));
+    }
+
+    // Collect the unique constraints, which are implemented using automatic indexes:
+    let rows = pool
+        .query_no_cache_clean(
+            r#"SELECT "name" FROM pragma_index_list(?1) WHERE "origin" = 'u' ORDER BY "seq""#,
+            params![table],
+        )
+        .await?;
+    for row in rows.iter() {
+        let columns = sqlite_index_columns(pool, &get_string(row, "name")?).await?;
+        if is_kept(&columns) {
+            definitions.push(format!("UNIQUE ({})", quote(&columns)));
+        }
+    }
+
+    // Collect the foreign key constraints:
+    let rows = pool
+        .query_no_cache_clean(
+            r#"SELECT "id", "table", "from", "to", "on_update", "on_delete"
+               FROM pragma_foreign_key_list(?1)
+               ORDER BY "id", "seq""#,
+            params![table],
+        )
+        .await?;
+    let mut foreign_keys: BTreeMap<i64, ForeignKey> = BTreeMap::new();
+    for row in rows.iter() {
+        let foreign_key = foreign_keys.entry(get_integer(row, "id")?).or_default();
+        foreign_key.table = get_string(row, "table")?;
+        foreign_key.from.push(get_string(row, "from")?);
+        if let Some(to) = get_optional_string(row, "to")? {
+            foreign_key.to.push(to);
+        }
+        foreign_key.on_update = get_string(row, "on_update")?;
+        foreign_key.on_delete = get_string(row, "on_delete")?;
+    }
+    for foreign_key in foreign_keys.into_values() {
+        if !is_kept(&foreign_key.from) {
+            continue;
+        }
+        let ForeignKey {
+            table: foreign_table,
+            from,
+            to,
+            on_update,
+            on_delete,
+        } = foreign_key;
+        let to = match to.is_empty() {
+            true => String::new(),
+            false => format!(" ({})", quote(&to)),
+        };
+        definitions.push(format!(
+            r#"FOREIGN KEY ({}) REFERENCES "{foreign_table}"{to}
+               ON UPDATE {on_update} ON DELETE {on_delete}"#,
+            quote(&from),
+        ));
+    }
+
+    // Collect the SQL for the explicitly created indexes and the triggers on the table, which
+    // are dropped along with it:
+    let rows = pool
+        .query_no_cache_clean(
+            r#"SELECT "type", "name", "sql" FROM "sqlite_master"
+               WHERE "tbl_name" = ?1 AND "type" IN ('index', 'trigger') AND "sql" IS NOT NULL
+               ORDER BY "type", "name""#,
+            params![table],
+        )
+        .await?;
+    let mut recreate_sql = vec![];
+    for row in rows.iter() {
+        if get_string(row, "type")? == "index" {
+            let columns = sqlite_index_columns(pool, &get_string(row, "name")?).await?;
+            if !is_kept(&columns) {
+                continue;
+            }
+        }
+        recreate_sql.push(get_string(row, "sql")?);
+    }
+
+    // Rebuild the table within a single transaction. Note that legacy_alter_table must be
+    // turned on during the rebuild, since otherwise SQLite will refuse to rename the new table
+    // if there are any views that refer to the old one (which no longer exists at that point):
+    let temp_table = format!("{REBUILD_TABLE_PREFIX}{table}");
+    let copied_columns = copied_columns.join(", ");
+    let mut statements = vec![
+        "PRAGMA legacy_alter_table = ON".to_string(),
+        "BEGIN".to_string(),
+        format!(r#"DROP TABLE IF EXISTS "{temp_table}""#),
+        format!(
+            r#"CREATE TABLE "{temp_table}" (
+                 {}
+               )"#,
+            definitions.join(",\n")
+        ),
+        format!(
+            r#"INSERT INTO "{temp_table}" ({copied_columns})
+               SELECT {copied_columns} FROM "{table}""#
+        ),
+        format!(r#"DROP TABLE "{table}""#),
+        format!(r#"ALTER TABLE "{temp_table}" RENAME TO "{table}""#),
+    ];
+    statements.append(&mut recreate_sql);
+    statements.push("COMMIT".to_string());
+    statements.push("PRAGMA legacy_alter_table = OFF".to_string());
+    // If the rebuild fails, make sure that the transaction does not remain open on the
+    // connection. If there is no transaction (for instance because the BEGIN statement itself
+    // failed), the ROLLBACK will fail harmlessly:
+    pool.execute_batch_with_recovery(
+        &statements.join(";\n"),
+        &["ROLLBACK", "PRAGMA legacy_alter_table = OFF"],
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        any::AnyPool,
+        cache::{CachingStrategy, MemoryCacheConfig},
+        db_row,
+        db_value::DbValue,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_alter_table() {
+        #[cfg(feature = "rusqlite")]
+        alter_table(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        alter_table("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        alter_table(":memory:").await;
+    }
+
+    async fn alter_table(url: &str) {
+        let mut pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.set_caching_strategy(&CachingStrategy::Memory(MemoryCacheConfig::default()));
+        pool.set_cache_aware_query(true);
+        let kind = pool.kind();
+        let cascade = match kind.to_string().as_str() {
+            "postgresql" => " CASCADE",
+            "sqlite" => "",
+            _ => panic!("Invalid kind '{kind}'"),
+        };
+        pool.execute_batch(&format!(
+            "DROP VIEW IF EXISTS test_alter_view;\
+             DROP TABLE IF EXISTS test_alter1{cascade};\
+             DROP TABLE IF EXISTS test_alter2{cascade};\
+             CREATE TABLE test_alter1 (\
+                 id BIGINT PRIMARY KEY,\
+                 code TEXT UNIQUE,\
+                 name TEXT NOT NULL DEFAULT 'unknown',\
+                 age TEXT\
+             );\
+             CREATE INDEX test_alter1_name_idx ON test_alter1 (name);\
+             CREATE UNIQUE INDEX test_alter1_age_idx ON test_alter1 (age);\
+             CREATE VIEW test_alter_view AS SELECT id, name FROM test_alter1;\
+             INSERT INTO test_alter1 VALUES (1, 'a', 'alice', '30'), (2, 'b', 'bob', '40')",
+        ))
+        .await
+        .unwrap();
+        let select = "SELECT * FROM test_alter1 ORDER BY id";
+        let rows = pool.cache(select, ()).await.unwrap();
+        assert_eq!(rows.len(), 2);
+
+        // Add a column:
+        pool.add_column("test_alter1", "height", "BIGINT")
+            .await
+            .unwrap();
+        assert!(
+            pool.add_column("test_alter1", "x", "NO_SUCH_TYPE")
+                .await
+                .is_err()
+        );
+        let rows = pool.cache(select, ()).await.unwrap();
+        assert_eq!(rows[0].get("height"), Some(DbValue::Null));
+
+        // Rename a column:
+        pool.rename_column("test_alter1", "height", "stature")
+            .await
+            .unwrap();
+        assert_eq!(
+            pool.columns("test_alter1")
+                .await
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>()
+                .contains(&&"stature".to_string()),
+            true
+        );
+
+        // Change the type of a column. The data, constraints, and indexes should be preserved:
+        pool.alter_column_type("test_alter1", "age", "BIGINT")
+            .await
+            .unwrap();
+        let age_type = pool.columns("test_alter1").await.unwrap()["age"].clone();
+        assert_eq!(
+            kind.db_type(&age_type).unwrap(),
+            kind.db_type("bigint").unwrap()
+        );
+        let rows = pool.cache(select, ()).await.unwrap();
+        assert_eq!(rows[1].get("age"), Some(DbValue::BigInteger(40)));
+        assert_eq!(pool.primary_keys("test_alter1").await.unwrap(), ["id"]);
+        assert!(
+            pool.execute(
+                "INSERT INTO test_alter1 (id, code, age) VALUES (3, 'c', 40)",
+                ()
+            )
+            .await
+            .is_err()
+        );
+        assert!(
+            pool.execute("INSERT INTO test_alter1 (id, code) VALUES (3, 'a')", ())
+                .await
+                .is_err()
+        );
+        pool.execute("INSERT INTO test_alter1 (id, code) VALUES (3, 'c')", ())
+            .await
+            .unwrap();
+        let rows = pool.cache(select, ()).await.unwrap();
+        assert_eq!(rows[2].get("name"), Some(DbValue::from("unknown")));
+
+        // Change the nullability of a column. A NOT NULL constraint cannot be added to a column
+        // that contains NULLs, in which case the table should be left as it was:
+        assert!(
+            pool.alter_column_not_null("test_alter1", "stature", true)
+                .await
+                .is_err()
+        );
+        assert_eq!(pool.cache(select, ()).await.unwrap().len(), 3);
+        pool.alter_column_not_null("test_alter1", "name", false)
+            .await
+            .unwrap();
+        pool.execute(
+            "INSERT INTO test_alter1 (id, code, name) VALUES (4, 'd', NULL)",
+            (),
+        )
+        .await
+        .unwrap();
+        pool.execute("DELETE FROM test_alter1 WHERE id = 4", ())
+            .await
+            .unwrap();
+        assert_eq!(pool.primary_keys("test_alter1").await.unwrap(), ["id"]);
+
+        // Drop an indexed column:
+        pool.drop_column("test_alter1", "age").await.unwrap();
+        let rows = pool.cache(select, ()).await.unwrap();
+        assert_eq!(
+            rows[0],
+            db_row! {
+                "id" => DbValue::BigInteger(1),
+                "code" => DbValue::from("a"),
+                "name" => DbValue::from("alice"),
+                "stature" => DbValue::Null,
+            }
+        );
+        assert!(
+            pool.drop_column("test_alter1", "no_such_column")
+                .await
+                .is_err()
+        );
+
+        // Rename the table:
+        pool.rename_table("test_alter1", "test_alter2")
+            .await
+            .unwrap();
+        assert!(!pool.table_exists("test_alter1").await.unwrap());
+        assert!(pool.table_exists("test_alter2").await.unwrap());
+        let rows = pool.cache("SELECT * FROM test_alter2", ()).await.unwrap();
+        assert_eq!(rows.len(), 3);
+
+        // Views on the table should survive all of the above:
+        let rows = pool
+            .query("SELECT name FROM test_alter_view ORDER BY id", ())
+            .await
+            .unwrap();
+        assert_eq!(rows.to_strings().unwrap(), ["alice", "bob", "unknown"]);
+
+        // Clean up:
+        pool.drop_view("test_alter_view").await.unwrap();
+        pool.drop_table("test_alter2").await.unwrap();
+    }
+}
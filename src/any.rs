@@ -20,11 +20,13 @@
 /// }
 /// ```
 use crate::{
-    cache::CachingStrategy,
+    cache::{CacheCounters, CacheEncoding, CachingStrategy, MemoryCaches},
     core::{DbError, DbQuery},
     db_kind::DbKind,
-    db_value::{DbRows, IntoDbParams, IntoDbRows},
+    db_value::{DbRow, DbRows, IntoDbParams, IntoDbRows},
+    shared::UpsertOptions,
 };
+use std::{sync::Arc, time::Duration};
 
 #[cfg(feature = "rusqlite")]
 use crate::rusqlite::RusqlitePool;
@@ -112,6 +114,21 @@ impl AnyPool {
             }
         }
     }
+
+    /// Listen for edits made to the database by other processes, and clear the memory caches
+    /// accordingly. This is only supported for PostgreSQL (see
+    /// `TokioPostgresPool::listen_for_cache_invalidations()`).
+    pub async fn listen_for_cache_invalidations(&self) -> Result<(), DbError> {
+        match self {
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.listen_for_cache_invalidations().await,
+            #[allow(unreachable_patterns)]
+            _ => Err(DbError::InputError(format!(
+                "Listening for cache invalidations is not supported for {}",
+                self.kind()
+            ))),
+        }
+    }
 }
 
 impl DbQuery for AnyPool {
@@ -139,6 +156,30 @@ impl DbQuery for AnyPool {
         }
     }
 
+    /// Implements [DbQuery::memory_caches()]
+    fn memory_caches(&self) -> Arc<MemoryCaches> {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.memory_caches(),
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.memory_caches(),
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.memory_caches(),
+        }
+    }
+
+    /// Implements [DbQuery::cache_counters()]
+    fn cache_counters(&self) -> Arc<CacheCounters> {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.cache_counters(),
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.cache_counters(),
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.cache_counters(),
+        }
+    }
+
     /// Implements [DbQuery::execute_batch()]
     async fn execute_batch(&self, sql: &str) -> Result<(), DbError> {
         match self {
@@ -151,6 +192,22 @@ impl DbQuery for AnyPool {
         }
     }
 
+    /// Implements [DbQuery::execute_batch_with_recovery()]
+    async fn execute_batch_with_recovery(
+        &self,
+        sql: &str,
+        recovery: &[&str],
+    ) -> Result<(), DbError> {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.execute_batch_with_recovery(sql, recovery).await,
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.execute_batch_with_recovery(sql, recovery).await,
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.execute_batch_with_recovery(sql, recovery).await,
+        }
+    }
+
     /// Implements [DbQuery::query_no_cache_clean()]
     async fn query_no_cache_clean(
         &self,
@@ -278,6 +335,99 @@ impl DbQuery for AnyPool {
         }
     }
 
+    /// Implements [DbQuery::bulk_insert()]
+    async fn bulk_insert(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl Iterator<Item = DbRow> + Send,
+        progress: impl FnMut(u64) + Send,
+    ) -> Result<u64, DbError> {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.bulk_insert(table, columns, rows, progress).await,
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.bulk_insert(table, columns, rows, progress).await,
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.bulk_insert(table, columns, rows, progress).await,
+        }
+    }
+
+    /// Implements [DbQuery::upsert_with()]
+    async fn upsert_with(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl IntoDbRows,
+        options: &UpsertOptions,
+    ) -> Result<(), DbError> {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.upsert_with(table, columns, rows, options).await,
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.upsert_with(table, columns, rows, options).await,
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.upsert_with(table, columns, rows, options).await,
+        }
+    }
+
+    /// Implements [DbQuery::upsert_with_returning()]
+    async fn upsert_with_returning(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl IntoDbRows,
+        options: &UpsertOptions,
+        returning: &[&str],
+    ) -> Result<DbRows, DbError> {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => {
+                pool.upsert_with_returning(table, columns, rows, options, returning)
+                    .await
+            }
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => {
+                pool.upsert_with_returning(table, columns, rows, options, returning)
+                    .await
+            }
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => {
+                pool.upsert_with_returning(table, columns, rows, options, returning)
+                    .await
+            }
+        }
+    }
+
+    /// Implements [DbQuery::delete()]
+    async fn delete(&self, table: &str, rows: impl IntoDbRows) -> Result<(), DbError> {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.delete(table, rows).await,
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.delete(table, rows).await,
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.delete(table, rows).await,
+        }
+    }
+
+    /// Implements [DbQuery::delete_returning()]
+    async fn delete_returning(
+        &self,
+        table: &str,
+        rows: impl IntoDbRows,
+        returning: &[&str],
+    ) -> Result<DbRows, DbError> {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.delete_returning(table, rows, returning).await,
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.delete_returning(table, rows, returning).await,
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.delete_returning(table, rows, returning).await,
+        }
+    }
+
     /// Implements [DbQuery::drop_table()]
     async fn drop_table(&self, table: &str) -> Result<(), DbError> {
         match self {
@@ -349,20 +499,67 @@ impl DbQuery for AnyPool {
             AnyPool::LibSQL(pool) => pool.get_cache_aware_query(),
         }
     }
+
+    /// Implements [DbQuery::set_cache_ttl()]
+    fn set_cache_ttl(&mut self, ttl: Option<Duration>) {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.set_cache_ttl(ttl),
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.set_cache_ttl(ttl),
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.set_cache_ttl(ttl),
+        }
+    }
+
+    /// Implements [DbQuery::get_cache_ttl()]
+    fn get_cache_ttl(&self) -> Option<Duration> {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.get_cache_ttl(),
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.get_cache_ttl(),
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.get_cache_ttl(),
+        }
+    }
+
+    /// Implements [DbQuery::set_cache_encoding()]
+    fn set_cache_encoding(&mut self, encoding: CacheEncoding) {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.set_cache_encoding(encoding),
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.set_cache_encoding(encoding),
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.set_cache_encoding(encoding),
+        }
+    }
+
+    /// Implements [DbQuery::get_cache_encoding()]
+    fn get_cache_encoding(&self) -> CacheEncoding {
+        match self {
+            #[cfg(feature = "rusqlite")]
+            AnyPool::Rusqlite(pool) => pool.get_cache_encoding(),
+            #[cfg(feature = "tokio-postgres")]
+            AnyPool::TokioPostgres(pool) => pool.get_cache_encoding(),
+            #[cfg(feature = "libsql")]
+            AnyPool::LibSQL(pool) => pool.get_cache_encoding(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
-        cache::{CachingStrategy, QUERY_CACHE_TABLE, TABLE_CACHE_TABLE},
         cache::{
-            clear_memory_query_cache, clear_memory_table_cache, clear_meta_cache,
-            get_memory_query_cache_contents, get_memory_table_cache_contents,
+            CacheEncoding, CacheStats, CachingStrategy, MemoryCacheConfig, QUERY_CACHE_TABLE,
+            TABLE_CACHE_TABLE,
         },
         db_kind::DbType,
         db_row,
-        db_value::{ColumnMap, DbRow, DbValue, JsonValue, StringRow},
+        db_value::{ColumnMap, DbParams, DbRow, DbValue, JsonValue, StringRow},
         params,
     };
     use rand::{
@@ -373,7 +570,7 @@ mod tests {
     use rust_decimal::dec;
     use serde_json::json;
     use std::{
-        collections::BTreeMap,
+        collections::{BTreeMap, BTreeSet, HashSet},
         ops::Deref,
         str::FromStr,
         thread,
@@ -391,8 +588,8 @@ mod tests {
     }
 
     async fn text_column_query(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
 
         let p = kind.param_prefix().to_string();
@@ -480,8 +677,8 @@ mod tests {
     }
 
     async fn integer_column_query(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let p = kind.param_prefix().to_string();
         pool.execute_batch(&format!(
@@ -557,8 +754,8 @@ mod tests {
     }
 
     async fn float_column_query(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let p = kind.param_prefix().to_string();
 
@@ -653,8 +850,8 @@ mod tests {
     }
 
     async fn mixed_column_query(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let p = kind.param_prefix().to_string();
         pool.execute_batch(&format!(
@@ -791,8 +988,8 @@ mod tests {
     }
 
     async fn input_params(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let p = kind.param_prefix().to_string();
         let cascade = match kind.to_string().as_str() {
@@ -918,8 +1115,8 @@ mod tests {
     }
 
     async fn insert(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let cascade = match kind.to_string().as_str() {
             "postgresql" => " CASCADE",
@@ -1002,8 +1199,8 @@ mod tests {
     }
 
     async fn insert_returning(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let cascade = match kind.to_string().as_str() {
             "postgresql" => " CASCADE",
@@ -1110,8 +1307,8 @@ mod tests {
     }
 
     async fn drop_table(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let cascade = match kind.to_string().as_str() {
             "postgresql" => " CASCADE",
@@ -1160,8 +1357,8 @@ mod tests {
     }
 
     async fn primary_keys(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let cascade = match kind.to_string().as_str() {
             "postgresql" => " CASCADE",
@@ -1209,8 +1406,8 @@ mod tests {
     }
 
     async fn update(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let cascade = match kind.to_string().as_str() {
             "postgresql" => " CASCADE",
@@ -1315,8 +1512,8 @@ mod tests {
     }
 
     async fn update_returning(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let cascade = match kind.to_string().as_str() {
             "postgresql" => " CASCADE",
@@ -1523,8 +1720,8 @@ mod tests {
     }
 
     async fn upsert(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let cascade = match kind.to_string().as_str() {
             "postgresql" => " CASCADE",
@@ -1635,8 +1832,8 @@ mod tests {
     }
 
     async fn upsert_returning(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         let cascade = match kind.to_string().as_str() {
             "postgresql" => " CASCADE",
@@ -1734,143 +1931,1087 @@ mod tests {
         pool.drop_table("test_upsert_returning").await.unwrap();
     }
 
-    async fn count_query_cache_rows(pool: &mut AnyPool) -> u64 {
-        let rows = pool
-            .query(&format!("SELECT COUNT(1) from {QUERY_CACHE_TABLE}"), ())
-            .await
-            .unwrap();
-        let value: u64 = rows.value().unwrap().try_into().unwrap();
-        value
+    #[tokio::test]
+    async fn test_bulk_insert() {
+        #[cfg(feature = "rusqlite")]
+        bulk_insert(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        bulk_insert("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        bulk_insert(":memory:").await;
     }
 
-    async fn count_table_cache_rows(pool: &mut AnyPool) -> u64 {
+    async fn bulk_insert(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        let kind = pool.kind();
+        let cascade = match kind.to_string().as_str() {
+            "postgresql" => " CASCADE",
+            "sqlite" => "",
+            _ => panic!("Invalid kind '{kind}'"),
+        };
+        pool.execute_batch(&format!(
+            "DROP TABLE IF EXISTS test_bulk_insert{cascade};\
+             CREATE TABLE test_bulk_insert (\
+               id BIGINT PRIMARY KEY,\
+               name TEXT,\
+               score INTEGER\
+             )",
+        ))
+        .await
+        .unwrap();
+        let count_rows = async || -> u64 {
+            pool.query("SELECT COUNT(1) FROM test_bulk_insert", ())
+                .await
+                .unwrap()
+                .value()
+                .unwrap()
+                .try_into()
+                .unwrap()
+        };
+
+        // Values are converted to the types of the columns, and missing values are NULL:
+        let rows = (1..=25_000_i64).map(|i| match i % 2 {
+            0 => db_row! {"id" => i, "name" => format!("n{i}"), "score" => i.to_string()},
+            _ => db_row! {"id" => i.to_string()},
+        });
+        let mut progress = vec![];
+        let count = pool
+            .bulk_insert(
+                "test_bulk_insert",
+                &["id", "name", "score"],
+                rows,
+                |count| progress.push(count),
+            )
+            .await
+            .unwrap();
+        assert_eq!(count, 25_000);
+        assert_eq!(progress, [10_000, 20_000, 25_000]);
+        assert_eq!(count_rows().await, 25_000);
         let rows = pool
-            .query(&format!("SELECT COUNT(1) from {TABLE_CACHE_TABLE}"), ())
+            .query(
+                "SELECT id, name, score FROM test_bulk_insert WHERE id IN (1, 2) ORDER BY id",
+                (),
+            )
             .await
             .unwrap();
-        let value: u64 = rows.value().unwrap().try_into().unwrap();
-        value
-    }
+        assert_eq!(
+            rows.iter()
+                .map(|row| {
+                    row.values()
+                        .map(|value| match value {
+                            DbValue::Null => "NULL".to_string(),
+                            value => value.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>(),
+            ["1 NULL NULL", "2 n2 2"]
+        );
 
-    fn count_memory_query_cache_rows() -> u64 {
-        let cache = get_memory_query_cache_contents().unwrap();
-        cache.keys().len().try_into().unwrap()
-    }
+        // If any row cannot be inserted then none of them are:
+        let rows = (25_000..25_010_i64).map(|i| db_row! {"id" => i});
+        assert!(
+            pool.bulk_insert("test_bulk_insert", &["id"], rows, |_| ())
+                .await
+                .is_err()
+        );
+        assert_eq!(count_rows().await, 25_000);
+        let rows = ["1", "x"].into_iter().map(|id| db_row! {"id" => id});
+        assert!(
+            pool.bulk_insert("test_bulk_insert", &["id", "name"], rows, |_| ())
+                .await
+                .is_err()
+        );
+        assert!(
+            pool.bulk_insert(
+                "test_bulk_insert",
+                &["no_such_column"],
+                [].into_iter(),
+                |_| ()
+            )
+            .await
+            .is_err()
+        );
 
-    fn count_memory_table_cache_rows() -> u64 {
-        let cache = get_memory_table_cache_contents().unwrap();
-        cache.keys().len().try_into().unwrap()
+        let mut progress = vec![];
+        let count = pool
+            .bulk_insert("test_bulk_insert", &["id"], [].into_iter(), |count| {
+                progress.push(count)
+            })
+            .await
+            .unwrap();
+        assert_eq!((count, progress), (0, vec![0]));
+
+        // Clean up:
+        pool.drop_table("test_bulk_insert").await.unwrap();
     }
 
     #[tokio::test]
-    async fn test_caching() {
-        let all_strategies = ["truncate_all", "truncate", "trigger", "memory:5"]
-            .iter()
-            .map(|strategy| CachingStrategy::from_str(strategy).unwrap())
-            .collect::<Vec<_>>();
+    async fn test_upsert_with() {
         #[cfg(feature = "rusqlite")]
-        {
-            let mut pool = AnyPool::connect(":memory:").await.unwrap();
-            for caching_strategy in &all_strategies {
-                table_caching(&mut pool, &caching_strategy).await;
-            }
-            for strategy in &all_strategies {
-                view_caching(&mut pool, strategy).await;
-            }
-        }
+        upsert_with(":memory:").await;
         #[cfg(feature = "tokio-postgres")]
-        {
-            let mut pool = AnyPool::connect("postgresql:///rltbl_db").await.unwrap();
-            for caching_strategy in &all_strategies {
-                table_caching(&mut pool, &caching_strategy).await;
-            }
-            for strategy in &all_strategies {
-                view_caching(&mut pool, strategy).await;
-            }
-        }
+        upsert_with("postgresql:///rltbl_db").await;
         #[cfg(feature = "libsql")]
-        {
-            let mut pool = AnyPool::connect(":memory:").await.unwrap();
-            for caching_strategy in &all_strategies {
-                table_caching(&mut pool, &caching_strategy).await;
-            }
-            for strategy in &all_strategies {
-                view_caching(&mut pool, strategy).await;
-            }
-        }
+        upsert_with(":memory:").await;
     }
 
-    async fn table_caching(pool: &mut AnyPool, strategy: &CachingStrategy) {
-        clear_meta_cache().unwrap();
-        clear_memory_table_cache(&[]).unwrap();
-        clear_memory_query_cache(&[]).unwrap();
-        pool.drop_table(&format!("{QUERY_CACHE_TABLE}"))
-            .await
-            .unwrap();
-        pool.drop_table(&format!("{TABLE_CACHE_TABLE}"))
-            .await
-            .unwrap();
-        pool.drop_table("test_table_caching_1").await.unwrap();
-        pool.drop_table("test_table_caching_2").await.unwrap();
-        pool.execute_batch(
-            "CREATE TABLE test_table_caching_1 (\
-               value TEXT \
-             );\
-             CREATE TABLE test_table_caching_2 (\
-               value TEXT \
+    async fn upsert_with(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        let kind = pool.kind();
+        let cascade = match kind.to_string().as_str() {
+            "postgresql" => " CASCADE",
+            "sqlite" => "",
+            _ => panic!("Invalid kind '{kind}'"),
+        };
+        pool.execute_batch(&format!(
+            "DROP TABLE IF EXISTS test_upsert_with{cascade};\
+             CREATE TABLE test_upsert_with (\
+               id BIGINT PRIMARY KEY,\
+               email TEXT UNIQUE,\
+               name TEXT,\
+               score BIGINT\
              )",
-        )
+        ))
         .await
         .unwrap();
 
-        pool.set_caching_strategy(strategy);
-        pool.set_cache_aware_query(true);
-
+        let columns = ["id", "email", "name", "score"];
         pool.insert(
-            "test_table_caching_1",
-            &["value"],
+            "test_upsert_with",
+            &columns,
             &[
-                &db_row! {
-                    "value" => "alpha",
-                },
-                &db_row! {
-                    "value" => "beta",
-                },
+                &db_row! {"id" => 1_i64, "email" => "a@x", "name" => "Alice", "score" => 1_i64},
+                &db_row! {"id" => 2_i64, "email" => "b@x", "name" => "Bob", "score" => 2_i64},
             ],
         )
         .await
         .unwrap();
+        let select = "SELECT id, email, name, score FROM test_upsert_with ORDER BY id";
 
+        // Insert if absent:
         let rows = pool
-            .cache("SELECT * from test_table_caching_1", ())
+            .upsert_with_returning(
+                "test_upsert_with",
+                &columns,
+                &[
+                    &db_row! {"id" => 1_i64, "email" => "a@x", "name" => "Al", "score" => 9_i64},
+                    &db_row! {"id" => 3_i64, "email" => "c@x", "name" => "Cat", "score" => 3_i64},
+                ],
+                &UpsertOptions::default().do_nothing(),
+                &["id"],
+            )
             .await
             .unwrap();
-
-        match strategy {
-            CachingStrategy::None => (),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 1),
-            _ => assert_eq!(count_query_cache_rows(pool).await, 1),
-        };
+        assert_eq!(rows.to_strings().unwrap(), ["3"]);
+        let rows = pool.query(select, ()).await.unwrap();
         assert_eq!(
-            *rows.deref(),
-            vec![
-                db_row! {
-                    "value" => "alpha",
-                },
-                db_row! {
-                    "value" => "beta",
-                },
-            ]
+            rows.iter()
+                .map(|row| row.get("name").unwrap().to_string())
+                .collect::<Vec<_>>(),
+            ["Alice", "Bob", "Cat"]
         );
 
-        let rows = pool
-            .cache("SELECT * from test_table_caching_1", ())
+        // Target a unique constraint other than the primary key, and only update some columns:
+        pool.upsert_with(
+            "test_upsert_with",
+            &columns,
+            &[&db_row! {"id" => 4_i64, "email" => "b@x", "name" => "Robert", "score" => 20_i64}],
+            &UpsertOptions::default()
+                .on_conflict(&["email"])
+                .update_columns(&["score"]),
+        )
+        .await
+        .unwrap();
+
+        // Only overwrite with non-null values:
+        pool.upsert_with(
+            "test_upsert_with",
+            &columns,
+            &[
+                &db_row! {"id" => 1_i64, "email" => "a@x", "name" => DbValue::Null, "score" => 10_i64},
+                &db_row! {"id" => 3_i64, "email" => "c@x", "name" => "Cathy", "score" => 30_i64},
+            ],
+            &UpsertOptions::default().update_where(r#""excluded"."name" IS NOT NULL"#),
+        )
+        .await
+        .unwrap();
+
+        let rows = pool.query(select, ()).await.unwrap();
+        assert_eq!(
+            *rows.deref(),
+            [
+                db_row! {"id" => 1_i64, "email" => "a@x", "name" => "Alice", "score" => 1_i64},
+                db_row! {"id" => 2_i64, "email" => "b@x", "name" => "Bob", "score" => 20_i64},
+                db_row! {"id" => 3_i64, "email" => "c@x", "name" => "Cathy", "score" => 30_i64},
+            ]
+        );
+
+        // The conflict columns and the columns to update must be among the given columns:
+        assert!(
+            pool.upsert_with(
+                "test_upsert_with",
+                &["id", "name"],
+                &[&db_row! {"id" => 1_i64, "name" => "Al"}],
+                &UpsertOptions::default().on_conflict(&["email"]),
+            )
+            .await
+            .is_err()
+        );
+        assert!(
+            pool.upsert_with(
+                "test_upsert_with",
+                &["id", "name"],
+                &[&db_row! {"id" => 1_i64, "name" => "Al"}],
+                &UpsertOptions::default().update_columns(&["score"]),
+            )
+            .await
+            .is_err()
+        );
+
+        // Clean up:
+        pool.drop_table("test_upsert_with").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete() {
+        #[cfg(feature = "rusqlite")]
+        delete(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        delete("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        delete(":memory:").await;
+    }
+
+    async fn delete(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        let kind = pool.kind();
+        let cascade = match kind.to_string().as_str() {
+            "postgresql" => " CASCADE",
+            "sqlite" => "",
+            _ => panic!("Invalid kind '{kind}'"),
+        };
+        pool.execute_batch(&format!(
+            "DROP TABLE IF EXISTS test_delete{cascade};\
+             CREATE TABLE test_delete (\
+               foo BIGINT,\
+               bar BIGINT,\
+               car BIGINT,\
+               PRIMARY KEY (foo, bar)\
+             )",
+        ))
+        .await
+        .unwrap();
+
+        // Enough rows that deleting all of them requires more than one statement:
+        let rows = (0..20_000_i64)
+            .map(|i| db_row! {"foo" => i, "bar" => i % 7, "car" => i * 2})
+            .collect::<Vec<_>>();
+        pool.insert("test_delete", &["foo", "bar", "car"], rows.clone())
+            .await
+            .unwrap();
+
+        // Only the primary key columns are used to identify the rows to delete. Rows that do not
+        // exist are ignored:
+        pool.delete(
+            "test_delete",
+            &[
+                &db_row! {"foo" => 1_i64, "bar" => 1_i64, "car" => 999_i64},
+                &db_row! {"foo" => 2_i64, "bar" => 2_i64},
+                &db_row! {"foo" => 3_i64, "bar" => 4_i64},
+            ],
+        )
+        .await
+        .unwrap();
+        let count: u64 = pool
+            .query("SELECT COUNT(1) FROM test_delete", ())
+            .await
+            .unwrap()
+            .value()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(count, 19_998);
+
+        pool.delete("test_delete", rows).await.unwrap();
+        let count: u64 = pool
+            .query("SELECT COUNT(1) FROM test_delete", ())
+            .await
+            .unwrap()
+            .value()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(count, 0);
+
+        // Clean up:
+        pool.drop_table("test_delete").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete_returning() {
+        #[cfg(feature = "rusqlite")]
+        delete_returning(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        delete_returning("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        delete_returning(":memory:").await;
+    }
+
+    async fn delete_returning(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        let kind = pool.kind();
+        let cascade = match kind.to_string().as_str() {
+            "postgresql" => " CASCADE",
+            "sqlite" => "",
+            _ => panic!("Invalid kind '{kind}'"),
+        };
+        pool.execute_batch(&format!(
+            "DROP TABLE IF EXISTS test_delete_returning{cascade};\
+             CREATE TABLE test_delete_returning (\
+               foo BIGINT PRIMARY KEY,\
+               bar BIGINT\
+             )",
+        ))
+        .await
+        .unwrap();
+
+        pool.insert(
+            "test_delete_returning",
+            &["foo", "bar"],
+            &[
+                &db_row! {"foo" => 1_i64, "bar" => 10_i64},
+                &db_row! {"foo" => 2_i64, "bar" => 20_i64},
+                &db_row! {"foo" => 3_i64, "bar" => 30_i64},
+            ],
+        )
+        .await
+        .unwrap();
+
+        let rows = pool
+            .delete_returning(
+                "test_delete_returning",
+                &[&db_row! {"foo" => 1_i64}, &db_row! {"foo" => 3_i64}],
+                &["bar"],
+            )
+            .await
+            .unwrap();
+        let mut values = rows.to_strings().unwrap();
+        values.sort();
+        assert_eq!(values, ["10", "30"]);
+
+        let rows = pool
+            .delete_returning("test_delete_returning", &[&db_row! {"foo" => 2_i64}], &[])
+            .await
+            .unwrap();
+        assert_eq!(*rows.deref(), [db_row! {"foo" => 2_i64, "bar" => 20_i64}]);
+
+        // A table without a primary key cannot be deleted from in this way:
+        pool.execute_batch(&format!(
+            "DROP TABLE IF EXISTS test_delete_returning{cascade};\
+             CREATE TABLE test_delete_returning (foo BIGINT)",
+        ))
+        .await
+        .unwrap();
+        assert!(
+            pool.delete("test_delete_returning", &[&db_row! {"foo" => 1_i64}])
+                .await
+                .is_err()
+        );
+
+        // Clean up:
+        pool.drop_table("test_delete_returning").await.unwrap();
+    }
+
+    async fn count_query_cache_rows(pool: &mut AnyPool) -> u64 {
+        let rows = pool
+            .query(&format!("SELECT COUNT(1) from {QUERY_CACHE_TABLE}"), ())
+            .await
+            .unwrap();
+        let value: u64 = rows.value().unwrap().try_into().unwrap();
+        value
+    }
+
+    async fn count_table_cache_rows(pool: &mut AnyPool) -> u64 {
+        let rows = pool
+            .query(&format!("SELECT COUNT(1) from {TABLE_CACHE_TABLE}"), ())
+            .await
+            .unwrap();
+        let value: u64 = rows.value().unwrap().try_into().unwrap();
+        value
+    }
+
+    fn count_memory_query_cache_rows(pool: &AnyPool) -> u64 {
+        let cache = pool.memory_caches().query_cache_contents();
+        cache.keys().len().try_into().unwrap()
+    }
+
+    fn count_memory_table_cache_rows(pool: &AnyPool) -> u64 {
+        let cache = pool.memory_caches().table_cache_contents();
+        cache.keys().len().try_into().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_caching() {
+        let all_strategies = [
+            "truncate_all",
+            "truncate",
+            "trigger",
+            "row_trigger",
+            "memory:lru:1mb",
+        ]
+        .iter()
+        .map(|strategy| CachingStrategy::from_str(strategy).unwrap())
+        .collect::<Vec<_>>();
+        #[cfg(feature = "rusqlite")]
+        {
+            let mut pool = AnyPool::connect(":memory:").await.unwrap();
+            for caching_strategy in &all_strategies {
+                table_caching(&mut pool, &caching_strategy).await;
+            }
+            for strategy in &all_strategies {
+                view_caching(&mut pool, strategy).await;
+            }
+        }
+        #[cfg(feature = "tokio-postgres")]
+        {
+            let mut pool = AnyPool::connect("postgresql:///rltbl_db").await.unwrap();
+            for caching_strategy in &all_strategies {
+                table_caching(&mut pool, &caching_strategy).await;
+            }
+            for strategy in &all_strategies {
+                view_caching(&mut pool, strategy).await;
+            }
+        }
+        #[cfg(feature = "libsql")]
+        {
+            let mut pool = AnyPool::connect(":memory:").await.unwrap();
+            for caching_strategy in &all_strategies {
+                table_caching(&mut pool, &caching_strategy).await;
+            }
+            for strategy in &all_strategies {
+                view_caching(&mut pool, strategy).await;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_caches_per_pool() {
+        #[cfg(feature = "rusqlite")]
+        memory_caches_per_pool(":memory:", Some("test_memory_caches.db")).await;
+        #[cfg(feature = "tokio-postgres")]
+        memory_caches_per_pool(":memory:", Some("postgresql:///rltbl_db")).await;
+        #[cfg(feature = "libsql")]
+        memory_caches_per_pool(":memory:", None).await;
+        std::fs::remove_file("test_memory_caches.db").ok();
+    }
+
+    async fn memory_caches_per_pool(private_url: &str, shared_url: Option<&str>) {
+        // Pools connected to distinct in-memory databases have distinct caches:
+        let pool_1 = AnyPool::connect(private_url).await.unwrap();
+        let pool_2 = AnyPool::connect(private_url).await.unwrap();
+        pool_1.drop_table("test_memory_caches").await.unwrap();
+        pool_1
+            .execute_batch("CREATE TABLE test_memory_caches (value TEXT)")
+            .await
+            .unwrap();
+        assert!(pool_1.table_exists("test_memory_caches").await.unwrap());
+        assert!(!pool_2.table_exists("test_memory_caches").await.unwrap());
+        assert!(
+            pool_1
+                .memory_caches()
+                .exists_in_meta_cache("test_memory_caches_TABLE")
+        );
+        assert!(
+            !pool_2
+                .memory_caches()
+                .exists_in_meta_cache("test_memory_caches_TABLE")
+        );
+
+        // Clones of a pool share its caches:
+        let clone = pool_1.clone();
+        assert!(Arc::ptr_eq(&clone.memory_caches(), &pool_1.memory_caches()));
+        pool_1.memory_caches().clear_meta_cache();
+        assert!(clone.memory_caches().meta_cache_contents().is_empty());
+
+        // Pools connected to the same database share their caches:
+        if let Some(url) = shared_url {
+            let pool_1 = AnyPool::connect(url).await.unwrap();
+            let pool_2 = AnyPool::connect(url).await.unwrap();
+            assert!(Arc::ptr_eq(
+                &pool_1.memory_caches(),
+                &pool_2.memory_caches()
+            ));
+            assert!(!Arc::ptr_eq(
+                &pool_1.memory_caches(),
+                &clone.memory_caches()
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_encoding() {
+        #[cfg(feature = "rusqlite")]
+        cache_encoding(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        cache_encoding("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        cache_encoding(":memory:").await;
+    }
+
+    async fn cache_encoding(url: &str) {
+        let mut pool = AnyPool::connect(url).await.unwrap();
+        pool.set_caching_strategy(&CachingStrategy::Trigger);
+        let sql = "SELECT * FROM test_cache_encoding ORDER BY id";
+        for encoding in ["json", "cbor", "compressed_cbor"] {
+            let encoding = CacheEncoding::from_str(encoding).unwrap();
+            assert_eq!(CacheEncoding::from_str(&encoding.to_string()), Ok(encoding));
+            pool.set_cache_encoding(encoding);
+            pool.drop_table("test_cache_encoding").await.unwrap();
+            pool.execute_batch(
+                "CREATE TABLE test_cache_encoding (
+                   id BIGINT,
+                   real_value REAL,
+                   numeric_value NUMERIC,
+                   text_value TEXT
+                 );
+                 INSERT INTO test_cache_encoding VALUES
+                   (1, 1.1, 2.25, 'a'),
+                   (2, NULL, NULL, NULL)",
+            )
+            .await
+            .unwrap();
+
+            // The cached rows are identical to those retrieved from the table:
+            let rows = pool.query(sql, ()).await.unwrap();
+            assert_eq!(*pool.cache(sql, ()).await.unwrap(), *rows);
+            pool.reset_cache_stats();
+            assert_eq!(*pool.cache(sql, ()).await.unwrap(), *rows);
+            assert_eq!(pool.cache_stats().await.unwrap().hits, 1);
+            assert!(pool.cache_entries().await.unwrap()[0].size > 0);
+
+            // Rows cached in one encoding can be read using another:
+            pool.set_cache_encoding(CacheEncoding::default());
+            assert_eq!(*pool.cache(sql, ()).await.unwrap(), *rows);
+            assert_eq!(pool.cache_stats().await.unwrap().hits, 2);
+        }
+        pool.drop_table("test_cache_encoding").await.unwrap();
+        pool.reset_cache_stats();
+    }
+
+    #[tokio::test]
+    async fn test_cache_encoding_migration() {
+        // Since the query cache table is replaced by this test, it is only run for private,
+        // in-memory databases:
+        #[cfg(feature = "rusqlite")]
+        cache_encoding_migration(":memory:").await;
+        #[cfg(feature = "libsql")]
+        cache_encoding_migration(":memory:").await;
+    }
+
+    async fn cache_encoding_migration(url: &str) {
+        let mut pool = AnyPool::connect(url).await.unwrap();
+        pool.set_caching_strategy(&CachingStrategy::Trigger);
+        pool.execute_batch(
+            "CREATE TABLE test_cache_migration (value TEXT);
+             INSERT INTO test_cache_migration VALUES ('a')",
+        )
+        .await
+        .unwrap();
+        let sql = "SELECT value FROM test_cache_migration";
+        let rows = pool.cache(sql, ()).await.unwrap();
+
+        // Drop the columns for binary values and for the times at which entries were cached from
+        // the query cache table, as in the tables created by earlier versions, keeping the entry
+        // that was cached:
+        pool.execute_batch(&format!(
+            r#"ALTER TABLE "{QUERY_CACHE_TABLE}" DROP COLUMN "binary_value";
+               ALTER TABLE "{QUERY_CACHE_TABLE}" DROP COLUMN "cached_at""#
+        ))
+        .await
+        .unwrap();
+        pool.memory_caches().clear_meta_cache();
+        let columns = pool.columns(QUERY_CACHE_TABLE).await.unwrap();
+        assert!(!columns.contains_key("binary_value"));
+        assert!(!columns.contains_key("cached_at"));
+
+        // The columns are added when the cache is next used, and the entry remains readable:
+        pool.set_cache_encoding(CacheEncoding::Cbor);
+        pool.reset_cache_stats();
+        let ttl = Duration::from_secs(3600);
+        assert_eq!(*pool.cache_with_ttl(sql, (), ttl).await.unwrap(), *rows);
+        assert_eq!(pool.cache_stats().await.unwrap().hits, 1);
+        let columns = pool.columns(QUERY_CACHE_TABLE).await.unwrap();
+        assert!(columns.contains_key("binary_value"));
+        assert!(columns.contains_key("cached_at"));
+
+        // New entries are stored in the binary encoding:
+        pool.execute("INSERT INTO test_cache_migration VALUES ('b')", ())
+            .await
+            .unwrap();
+        pool.cache(sql, ()).await.unwrap();
+        let binary_values = pool
+            .query(
+                &format!(
+                    r#"SELECT COUNT(1) AS "count" FROM "{QUERY_CACHE_TABLE}"
+                       WHERE "binary_value" IS NOT NULL"#
+                ),
+                (),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            binary_values.content[0]
+                .get("count")
+                .map(|count| count.to_string()),
+            Some("1".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_ttl() {
+        #[cfg(feature = "rusqlite")]
+        cache_ttl(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        cache_ttl("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        cache_ttl(":memory:").await;
+    }
+
+    async fn cache_ttl(url: &str) {
+        let mut pool = AnyPool::connect(url).await.unwrap();
+        let sql = "SELECT COUNT(*) AS count FROM test_cache_ttl";
+        for strategy in ["truncate_all", "truncate", "memory"] {
+            pool.set_caching_strategy(&CachingStrategy::from_str(strategy).unwrap());
+            pool.set_cache_ttl(None);
+            pool.drop_table("test_cache_ttl").await.unwrap();
+            pool.execute_batch("CREATE TABLE test_cache_ttl (value TEXT)")
+                .await
+                .unwrap();
+            let count = async |rows: Result<DbRows, DbError>| -> u64 {
+                rows.unwrap().value().unwrap().try_into().unwrap()
+            };
+            assert_eq!(count(pool.cache(sql, ()).await).await, 0);
+
+            // Edit the table without maintaining the cache, as another process might:
+            pool.execute_no_cache_clean("INSERT INTO test_cache_ttl VALUES ('a')", ())
+                .await
+                .unwrap();
+            assert_eq!(count(pool.cache(sql, ()).await).await, 0);
+            let ttl = Duration::from_secs(3600);
+            assert_eq!(count(pool.cache_with_ttl(sql, (), ttl).await).await, 0);
+            let ttl = Duration::ZERO;
+            assert_eq!(count(pool.cache_with_ttl(sql, (), ttl).await).await, 1);
+
+            // The time-to-live can also be set for the pool:
+            pool.execute_no_cache_clean("INSERT INTO test_cache_ttl VALUES ('b')", ())
+                .await
+                .unwrap();
+            assert_eq!(count(pool.cache(sql, ()).await).await, 1);
+            pool.set_cache_ttl(Some(Duration::ZERO));
+            assert_eq!(pool.get_cache_ttl(), Some(Duration::ZERO));
+            assert_eq!(count(pool.cache(sql, ()).await).await, 2);
+
+            // Using the cached results of a query on a view does not postpone their expiry:
+            pool.set_cache_ttl(None);
+            pool.execute_batch(
+                "CREATE VIEW test_cache_ttl_view AS SELECT value FROM test_cache_ttl",
+            )
+            .await
+            .unwrap();
+            let view_sql = "SELECT COUNT(*) AS count FROM test_cache_ttl_view";
+            assert_eq!(count(pool.cache(view_sql, ()).await).await, 2);
+            pool.execute_no_cache_clean("INSERT INTO test_cache_ttl VALUES ('c')", ())
+                .await
+                .unwrap();
+            let ttl = Duration::from_millis(900);
+            tokio::time::sleep(Duration::from_millis(700)).await;
+            pool.cache_with_ttl(view_sql, (), ttl).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(700)).await;
+            assert_eq!(count(pool.cache_with_ttl(view_sql, (), ttl).await).await, 3);
+            pool.drop_view("test_cache_ttl_view").await.unwrap();
+        }
+        pool.drop_table("test_cache_ttl").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cache_stats() {
+        #[cfg(feature = "rusqlite")]
+        cache_stats(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        cache_stats("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        cache_stats(":memory:").await;
+    }
+
+    async fn cache_stats(url: &str) {
+        let mut pool = AnyPool::connect(url).await.unwrap();
+        pool.set_cache_aware_query(true);
+        let sql = "SELECT value FROM test_cache_stats";
+        for strategy in [
+            "none",
+            "truncate_all",
+            "truncate",
+            "trigger",
+            "row_trigger",
+            "memory",
+        ] {
+            let strategy = CachingStrategy::from_str(strategy).unwrap();
+            pool.set_caching_strategy(&strategy);
+            pool.drop_table("test_cache_stats").await.unwrap();
+            pool.execute_batch(
+                "CREATE TABLE test_cache_stats (value TEXT);
+                 INSERT INTO test_cache_stats VALUES ('a'), ('b')",
+            )
+            .await
+            .unwrap();
+            pool.reset_cache_stats();
+            assert_eq!(pool.cache_stats().await.unwrap(), CacheStats::default());
+
+            pool.cache(sql, ()).await.unwrap();
+            pool.cache(sql, ()).await.unwrap();
+            pool.execute("INSERT INTO test_cache_stats VALUES ('c')", ())
+                .await
+                .unwrap();
+            pool.cache(sql, ()).await.unwrap();
+            let stats = pool.cache_stats().await.unwrap();
+            let entries = pool.cache_entries().await.unwrap();
+            match strategy {
+                CachingStrategy::None => {
+                    assert_eq!((stats.hits, stats.misses), (0, 3));
+                    assert!(stats.invalidations.is_empty());
+                    assert_eq!(stats.bytes_stored, 0);
+                    assert!(entries.is_empty());
+                }
+                _ => {
+                    assert_eq!((stats.hits, stats.misses), (1, 2));
+                    assert_eq!(
+                        stats.invalidations,
+                        BTreeMap::from([("test_cache_stats".to_string(), 1)])
+                    );
+                    assert_eq!(entries.len(), 1);
+                    assert_eq!(entries[0].statement, sql);
+                    assert_eq!(
+                        entries[0].tables,
+                        BTreeSet::from(["test_cache_stats".to_string()])
+                    );
+                    assert!(entries[0].size > 0);
+                    assert_eq!(stats.bytes_stored, entries[0].size);
+                }
+            };
+            assert_eq!(stats.evictions, 0);
+        }
+        pool.drop_table("test_cache_stats").await.unwrap();
+        pool.reset_cache_stats();
+    }
+
+    #[tokio::test]
+    async fn test_warm_cache() {
+        // Each connection to an in-memory SQLite database has a database of its own, so that
+        // the tables created by one are not seen by the others that run the queries concurrently.
+        // We therefore use database files for SQLite:
+        #[cfg(feature = "rusqlite")]
+        warm_cache("test_warm_cache.db").await;
+        #[cfg(feature = "tokio-postgres")]
+        warm_cache("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        warm_cache("test_warm_cache_libsql.db").await;
+        std::fs::remove_file("test_warm_cache.db").ok();
+        std::fs::remove_file("test_warm_cache_libsql.db").ok();
+    }
+
+    async fn warm_cache(url: &str) {
+        let mut pool = AnyPool::connect(url).await.unwrap();
+        let by_value = format!(
+            "SELECT id FROM test_warm_cache_1 WHERE value = {}1",
+            pool.kind().param_prefix()
+        );
+        let queries = [
+            ("SELECT * FROM test_warm_cache_1", DbParams::None),
+            (by_value.as_str(), params!["a"].into_db_params()),
+            (by_value.as_str(), params!["b"].into_db_params()),
+            (by_value.as_str(), params!["a"].into_db_params()),
+            ("SELECT * FROM test_warm_cache_2", DbParams::None),
+            (
+                "SELECT * FROM test_warm_cache_1 JOIN test_warm_cache_2 USING (id)",
+                DbParams::None,
+            ),
+        ];
+        for strategy in ["truncate", "trigger", "memory"] {
+            let strategy = CachingStrategy::from_str(strategy).unwrap();
+            pool.set_caching_strategy(&strategy);
+            pool.drop_table("test_warm_cache_1").await.unwrap();
+            pool.drop_table("test_warm_cache_2").await.unwrap();
+            pool.execute_batch(
+                "CREATE TABLE test_warm_cache_1 (id BIGINT, value TEXT);
+                 CREATE TABLE test_warm_cache_2 (id BIGINT, other TEXT);
+                 INSERT INTO test_warm_cache_1 VALUES (1, 'a'), (2, 'b');
+                 INSERT INTO test_warm_cache_2 VALUES (1, 'c')",
+            )
+            .await
+            .unwrap();
+
+            // Duplicate queries are only cached once:
+            pool.warm_cache(&queries).await.unwrap();
+            let entries = pool.cache_entries().await.unwrap();
+            assert_eq!(
+                entries
+                    .iter()
+                    .filter(|entry| entry.statement.contains("test_warm_cache"))
+                    .count(),
+                5
+            );
+            pool.reset_cache_stats();
+            for (sql, params) in &queries {
+                pool.cache(sql, params).await.unwrap();
+            }
+            let stats = pool.cache_stats().await.unwrap();
+            assert_eq!((stats.hits, stats.misses), (6, 0));
+        }
+
+        // Save the keys of the memory cache, clear it, and then warm it with them again:
+        let mut keys = vec![];
+        pool.save_memory_cache_keys(&mut keys).await.unwrap();
+        let entries = pool.cache_entries().await.unwrap();
+        pool.memory_caches().clear_query_cache(&[]);
+        assert!(pool.cache_entries().await.unwrap().is_empty());
+        pool.load_memory_cache_keys(keys.as_slice()).await.unwrap();
+        assert_eq!(
+            pool.cache_entries()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|entry| (entry.tables, entry.statement, entry.parameters))
+                .collect::<BTreeSet<_>>(),
+            entries
+                .into_iter()
+                .map(|entry| (entry.tables, entry.statement, entry.parameters))
+                .collect::<BTreeSet<_>>()
+        );
+
+        pool.drop_table("test_warm_cache_1").await.unwrap();
+        pool.drop_table("test_warm_cache_2").await.unwrap();
+        pool.reset_cache_stats();
+    }
+
+    #[tokio::test]
+    async fn test_row_level_invalidation() {
+        #[cfg(feature = "rusqlite")]
+        row_level_invalidation(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        row_level_invalidation("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        row_level_invalidation(":memory:").await;
+    }
+
+    async fn row_level_invalidation(url: &str) {
+        let mut pool = AnyPool::connect(url).await.unwrap();
+        pool.set_caching_strategy(&CachingStrategy::RowTrigger);
+        pool.drop_table("test_row_invalidation").await.unwrap();
+        pool.execute_batch(
+            "CREATE TABLE test_row_invalidation (id BIGINT PRIMARY KEY, value TEXT);
+             INSERT INTO test_row_invalidation VALUES (1, 'a'), (2, 'b'), (3, 'c')",
+        )
+        .await
+        .unwrap();
+
+        let by_id = "SELECT value FROM test_row_invalidation WHERE id = 1";
+        let by_param = format!(
+            "SELECT value FROM test_row_invalidation WHERE id = {}1",
+            pool.kind().param_prefix()
+        );
+        let by_ids = "SELECT value FROM test_row_invalidation WHERE id IN (1, 3) ORDER BY id";
+        let all = "SELECT value FROM test_row_invalidation ORDER BY id";
+        let cache_all = async |pool: &AnyPool| {
+            for sql in [by_id, by_ids, all] {
+                pool.cache(sql, ()).await.unwrap();
+            }
+            pool.cache(&by_param, &params![2_i64]).await.unwrap()
+        };
+        let cached_statements = async |pool: &AnyPool| {
+            let entries = pool.cache_entries().await.unwrap();
+            entries
+                .into_iter()
+                .filter(|entry| entry.tables.contains("test_row_invalidation"))
+                .map(|entry| entry.statement)
+                .collect::<HashSet<_>>()
+        };
+        cache_all(&pool).await;
+        assert_eq!(
+            cached_statements(&pool).await,
+            HashSet::from([by_id, by_ids, all, &by_param].map(String::from))
+        );
+
+        // Only the entries for queries that may read the edited row are invalidated:
+        pool.execute(
+            "UPDATE test_row_invalidation SET value = 'B' WHERE id = 2",
+            (),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            cached_statements(&pool).await,
+            HashSet::from([by_id, by_ids].map(String::from))
+        );
+        let rows = cache_all(&pool).await;
+        assert_eq!(*rows.value().unwrap(), DbValue::Text("B".to_string()));
+
+        pool.execute("INSERT INTO test_row_invalidation VALUES (4, 'd')", ())
+            .await
+            .unwrap();
+        assert_eq!(
+            cached_statements(&pool).await,
+            HashSet::from([by_id, by_ids, &by_param].map(String::from))
+        );
+
+        pool.execute("DELETE FROM test_row_invalidation WHERE id = 3", ())
+            .await
+            .unwrap();
+        assert_eq!(
+            cached_statements(&pool).await,
+            HashSet::from([by_id, &by_param].map(String::from))
+        );
+        let rows = pool.cache(by_ids, ()).await.unwrap();
+        assert_eq!(rows.len(), 1);
+
+        pool.drop_table("test_row_invalidation").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_exact_invalidation() {
+        #[cfg(feature = "rusqlite")]
+        exact_invalidation(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        exact_invalidation("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        exact_invalidation(":memory:").await;
+    }
+
+    async fn exact_invalidation(url: &str) {
+        let mut pool = AnyPool::connect(url).await.unwrap();
+        pool.set_cache_aware_query(true);
+        // Each table's name is a prefix of, or matches the LIKE pattern for, the next one's:
+        let tables = [
+            "test_exact_user",
+            "test_exact_user_roles",
+            "test_exact_userxroles",
+        ];
+        for strategy in ["truncate", "trigger", "row_trigger", "memory"] {
+            pool.set_caching_strategy(&CachingStrategy::from_str(strategy).unwrap());
+            for table in tables {
+                pool.drop_table(table).await.unwrap();
+                pool.execute_batch(&format!(
+                    "CREATE TABLE {table} (value TEXT);
+                     INSERT INTO {table} VALUES ('a')"
+                ))
+                .await
+                .unwrap();
+                pool.cache(&format!("SELECT value FROM {table}"), ())
+                    .await
+                    .unwrap();
+            }
+            let cached_tables = async |pool: &AnyPool| {
+                let entries = pool.cache_entries().await.unwrap();
+                entries
+                    .into_iter()
+                    .flat_map(|entry| entry.tables)
+                    .filter(|table| table.starts_with("test_exact_"))
+                    .collect::<BTreeSet<_>>()
+            };
+            let expected = |tables: &[&str]| tables.iter().map(|t| t.to_string()).collect();
+            assert_eq!(cached_tables(&pool).await, expected(&tables));
+
+            pool.execute("INSERT INTO test_exact_user VALUES ('b')", ())
+                .await
+                .unwrap();
+            assert_eq!(cached_tables(&pool).await, expected(&tables[1..]));
+            pool.execute("INSERT INTO test_exact_user_roles VALUES ('b')", ())
+                .await
+                .unwrap();
+            assert_eq!(cached_tables(&pool).await, expected(&tables[2..]));
+        }
+        for table in tables {
+            pool.drop_table(table).await.unwrap();
+        }
+    }
+
+    async fn table_caching(pool: &mut AnyPool, strategy: &CachingStrategy) {
+        pool.memory_caches().clear_meta_cache();
+        let caches = pool.memory_caches();
+        caches.clear_table_cache(&[]);
+        caches.clear_query_cache(&[]);
+        pool.drop_table(&format!("{QUERY_CACHE_TABLE}"))
+            .await
+            .unwrap();
+        pool.drop_table(&format!("{TABLE_CACHE_TABLE}"))
+            .await
+            .unwrap();
+        pool.drop_table("test_table_caching_1").await.unwrap();
+        pool.drop_table("test_table_caching_2").await.unwrap();
+        pool.execute_batch(
+            "CREATE TABLE test_table_caching_1 (\
+               value TEXT \
+             );\
+             CREATE TABLE test_table_caching_2 (\
+               value TEXT \
+             )",
+        )
+        .await
+        .unwrap();
+
+        pool.set_caching_strategy(strategy);
+        pool.set_cache_aware_query(true);
+
+        pool.insert(
+            "test_table_caching_1",
+            &["value"],
+            &[
+                &db_row! {
+                    "value" => "alpha",
+                },
+                &db_row! {
+                    "value" => "beta",
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        let rows = pool
+            .cache("SELECT * from test_table_caching_1", ())
+            .await
+            .unwrap();
+
+        match strategy {
+            CachingStrategy::None => (),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 1),
+            _ => assert_eq!(count_query_cache_rows(pool).await, 1),
+        };
+        assert_eq!(
+            *rows.deref(),
+            vec![
+                db_row! {
+                    "value" => "alpha",
+                },
+                db_row! {
+                    "value" => "beta",
+                },
+            ]
+        );
+
+        let rows = pool
+            .cache("SELECT * from test_table_caching_1", ())
             .await
             .unwrap();
 
         match strategy {
             CachingStrategy::None => (),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 1),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 1),
             _ => assert_eq!(count_query_cache_rows(pool).await, 1),
         };
         assert_eq!(
@@ -1902,7 +3043,7 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => (),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 0),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 0),
             _ => assert_eq!(count_query_cache_rows(pool).await, 0),
         };
 
@@ -1913,7 +3054,7 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => (),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 1),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 1),
             _ => assert_eq!(count_query_cache_rows(pool).await, 1),
         };
         assert_eq!(
@@ -1967,7 +3108,7 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => (),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 3),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 3),
             _ => assert_eq!(count_query_cache_rows(pool).await, 3),
         };
 
@@ -1980,8 +3121,8 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => (),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 1),
-            CachingStrategy::Truncate | CachingStrategy::Trigger => {
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 1),
+            CachingStrategy::Truncate | CachingStrategy::Trigger | CachingStrategy::RowTrigger => {
                 assert_eq!(count_query_cache_rows(pool).await, 1)
             }
             CachingStrategy::TruncateAll => assert_eq!(count_query_cache_rows(pool).await, 0),
@@ -1994,8 +3135,8 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => (),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 2),
-            CachingStrategy::Truncate | CachingStrategy::Trigger => {
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 2),
+            CachingStrategy::Truncate | CachingStrategy::Trigger | CachingStrategy::RowTrigger => {
                 assert_eq!(count_query_cache_rows(pool).await, 2)
             }
             CachingStrategy::TruncateAll => assert_eq!(count_query_cache_rows(pool).await, 1),
@@ -2034,8 +3175,8 @@ mod tests {
             .unwrap();
         match strategy {
             CachingStrategy::None => (),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 3),
-            CachingStrategy::Truncate | CachingStrategy::Trigger => {
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 3),
+            CachingStrategy::Truncate | CachingStrategy::Trigger | CachingStrategy::RowTrigger => {
                 assert_eq!(count_query_cache_rows(pool).await, 3)
             }
             CachingStrategy::TruncateAll => assert_eq!(count_query_cache_rows(pool).await, 2),
@@ -2052,8 +3193,8 @@ mod tests {
             .unwrap();
         match strategy {
             CachingStrategy::None => (),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 3),
-            CachingStrategy::Truncate | CachingStrategy::Trigger => {
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 3),
+            CachingStrategy::Truncate | CachingStrategy::Trigger | CachingStrategy::RowTrigger => {
                 assert_eq!(count_query_cache_rows(pool).await, 3)
             }
             CachingStrategy::TruncateAll => assert_eq!(count_query_cache_rows(pool).await, 2),
@@ -2066,9 +3207,10 @@ mod tests {
     }
 
     async fn view_caching(pool: &mut AnyPool, strategy: &CachingStrategy) {
-        clear_meta_cache().unwrap();
-        clear_memory_table_cache(&[]).unwrap();
-        clear_memory_query_cache(&[]).unwrap();
+        pool.memory_caches().clear_meta_cache();
+        let caches = pool.memory_caches();
+        caches.clear_table_cache(&[]);
+        caches.clear_query_cache(&[]);
         pool.drop_table(&format!("{QUERY_CACHE_TABLE}"))
             .await
             .unwrap();
@@ -2125,12 +3267,12 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => unimplemented!(),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 1),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 1),
             _ => assert_eq!(count_query_cache_rows(pool).await, 1),
         };
         match strategy {
             CachingStrategy::None => unimplemented!(),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_table_cache_rows(), 0),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_table_cache_rows(pool), 0),
             _ => assert_eq!(count_table_cache_rows(pool).await, 0),
         };
 
@@ -2147,7 +3289,7 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => unimplemented!(),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 1),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 1),
             // Truncate and trigger give different answers here because the query cache is cleaned
             // for views at different times according to each strategy. The query cache is cleaned
             // immediately after an edit in the case of the trigger option, while when using the
@@ -2158,7 +3300,7 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => unimplemented!(),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_table_cache_rows(), 1),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_table_cache_rows(pool), 1),
             _ => assert_eq!(count_table_cache_rows(pool).await, 1),
         };
 
@@ -2168,7 +3310,7 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => unimplemented!(),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 1),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 1),
             _ => assert_eq!(count_query_cache_rows(pool).await, 1),
         };
 
@@ -2178,7 +3320,7 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => unimplemented!(),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 2),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 2),
             _ => assert_eq!(count_query_cache_rows(pool).await, 2),
         };
 
@@ -2188,7 +3330,7 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => unimplemented!(),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 3),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 3),
             _ => assert_eq!(count_query_cache_rows(pool).await, 3),
         };
 
@@ -2205,7 +3347,7 @@ mod tests {
 
         match strategy {
             CachingStrategy::None => unimplemented!(),
-            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(), 2),
+            CachingStrategy::Memory(_) => assert_eq!(count_memory_query_cache_rows(pool), 2),
             // Truncate and trigger give different answers here because the query cache is cleaned
             // for views at different times according to each strategy. The query cache is cleaned
             // immediately after an edit in the case of the trigger option, while when using the
@@ -2253,12 +3395,19 @@ mod tests {
     // for the given number of runs for each of the supported caching strategies. The running
     // time for each strategy is then summarized and reported via STDOUT.
     async fn perform_caching(url: &str, runs: usize, edit_rate: usize, fail_after: usize) {
-        clear_meta_cache().unwrap();
         let mut pool = AnyPool::connect(url).await.unwrap();
-        let all_strategies = ["none", "truncate_all", "truncate", "trigger", "memory:1000"]
-            .iter()
-            .map(|strategy| CachingStrategy::from_str(strategy).unwrap())
-            .collect::<Vec<_>>();
+        pool.memory_caches().clear_meta_cache();
+        let all_strategies = [
+            "none",
+            "truncate_all",
+            "truncate",
+            "trigger",
+            "row_trigger",
+            "memory",
+        ]
+        .iter()
+        .map(|strategy| CachingStrategy::from_str(strategy).unwrap())
+        .collect::<Vec<_>>();
 
         pool.set_cache_aware_query(true);
         let this_test = "Caching Performance Test -";
@@ -2414,6 +3563,96 @@ mod tests {
         (elapsed, actual_edits)
     }
 
+    // Unlike [test_caching_performance()], this test queries the same pool from many tasks at
+    // once, on several threads, to check that the in-memory caches never fail under contention.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    #[cfg(any(feature = "rusqlite", feature = "tokio-postgres"))]
+    async fn test_memory_caching_under_load() {
+        let tasks = 16;
+        let runs = 50;
+        let edit_rate = 25;
+        #[cfg(feature = "rusqlite")]
+        perform_concurrent_caching("test_caching_load.db", tasks, runs, edit_rate).await;
+        #[cfg(feature = "tokio-postgres")]
+        perform_concurrent_caching("postgresql:///rltbl_db", tasks, runs, edit_rate).await;
+        // Note that this test is not run for libsql, which, unlike rusqlite, does not wait for
+        // other connections to release their locks on the database, so that concurrent edits
+        // fail regardless of caching.
+        std::fs::remove_file("test_caching_load.db").ok();
+    }
+
+    // Query the views of a few tables, using the memory caching strategy, from the given number
+    // of concurrent tasks, each of which performs the given number of runs, editing one of the
+    // tables in one out of every `edit_rate` runs on average. Every cached result must match the
+    // current contents of the table.
+    #[cfg(any(feature = "rusqlite", feature = "tokio-postgres"))]
+    async fn perform_concurrent_caching(url: &str, tasks: usize, runs: usize, edit_rate: usize) {
+        let mut pool = AnyPool::connect(url).await.unwrap();
+        pool.set_caching_strategy(&CachingStrategy::Memory(MemoryCacheConfig::default()));
+        pool.set_cache_aware_query(true);
+        let tables = ["test_load_alpha", "test_load_beta", "test_load_gamma"];
+        for table in tables {
+            pool.drop_view(&format!("{table}_view")).await.unwrap();
+            pool.drop_table(table).await.unwrap();
+            pool.execute_batch(&format!(
+                "CREATE TABLE {table} ( foo INT, bar INT );
+                 CREATE VIEW {table}_view AS SELECT * FROM {table};
+                 INSERT INTO {table} VALUES (1, 1), (2, 2), (3, 3)"
+            ))
+            .await
+            .unwrap();
+        }
+
+        let mut handles = vec![];
+        for _ in 0..tasks {
+            let pool = pool.clone();
+            handles.push(tokio::spawn(async move {
+                let mut rng = StdRng::from_rng(&mut rand::rng());
+                let choose = Uniform::try_from(0..tables.len()).unwrap();
+                let edit = Uniform::try_from(0..edit_rate).unwrap();
+                for _ in 0..runs {
+                    let table = tables[choose.sample(&mut rng)];
+                    let sql = format!("SELECT COUNT(*) AS count FROM {table}_view");
+                    pool.cache(&sql, ()).await.unwrap();
+                    if edit.sample(&mut rng) == 0 {
+                        pool.execute(&format!("INSERT INTO {table} VALUES (4, 4)"), ())
+                            .await
+                            .unwrap();
+                    }
+                }
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        // Once all of the edits are done, the cache must be up to date:
+        for table in tables {
+            let sql = format!("SELECT COUNT(*) AS count FROM {table}_view");
+            let cached: u64 = pool
+                .cache(&sql, ())
+                .await
+                .unwrap()
+                .value()
+                .unwrap()
+                .try_into()
+                .unwrap();
+            let actual: u64 = pool
+                .query(&sql, ())
+                .await
+                .unwrap()
+                .value()
+                .unwrap()
+                .try_into()
+                .unwrap();
+            assert_eq!(cached, actual);
+        }
+        for table in tables {
+            pool.drop_view(&format!("{table}_view")).await.unwrap();
+            pool.drop_table(table).await.unwrap();
+        }
+    }
+
     #[tokio::test]
     async fn test_json_values() {
         #[cfg(feature = "rusqlite")]
@@ -2425,8 +3664,8 @@ mod tests {
     }
 
     async fn json_values(url: &str) {
-        clear_meta_cache().unwrap();
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
 
         pool.drop_table("test_json_values").await.unwrap();
         // We only test the JSON type here and not JSONB since the latter is used only in
@@ -2495,9 +3734,8 @@ mod tests {
     }
 
     async fn db_type(url: &str) {
-        clear_meta_cache().unwrap();
-
         let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
         let kind = pool.kind();
         pool.drop_table("test_db_type").await.unwrap();
         pool.execute(
@@ -0,0 +1,466 @@
+//! Code for converting query results to Apache Arrow record batches and writing them to Parquet
+//! files. Requires the `arrow` feature.
+//!
+//! The Arrow type of each column is determined by the SQL type of the corresponding database
+//! column (see [DbKind::db_type()]): booleans map to `Boolean`, integers to `Int16`, `Int32` or
+//! `Int64`, floating point numbers to `Float32` or `Float64`, and numerics to `Decimal128`, using
+//! the precision and scale given in the column's declared SQL type if there are any, and
+//! otherwise [DEFAULT_DECIMAL_PRECISION] and [DEFAULT_DECIMAL_SCALE].
+//! Text, JSON, and values of any other type map to `Utf8`. Note that SQLite does not distinguish
+//! between different sizes of integers and floating point numbers, so all integer columns
+//! (including booleans) map to `Int64` and all floating point columns to `Float64` for SQLite.
+
+use crate::{
+    core::{DbError, DbQuery},
+    db_kind::{DbKind, DbType},
+    db_value::{ColumnMap, DbRows, DbValue},
+    paginate::for_each_page,
+    select::{Aggregate, Select, SelectColumn},
+};
+use ::arrow::{
+    array::{
+        ArrayRef, BooleanArray, Decimal128Array, Float32Array, Float64Array, Int16Array,
+        Int32Array, Int64Array, NullArray, StringArray,
+    },
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    record_batch::RecordBatch,
+};
+use parquet::arrow::ArrowWriter;
+use std::{io::Write, sync::Arc};
+
+/// The precision of the `Decimal128` columns corresponding to numeric columns whose SQL type
+/// does not specify one.
+pub static DEFAULT_DECIMAL_PRECISION: u8 = 38;
+
+/// The scale of the `Decimal128` columns corresponding to numeric columns whose SQL type does not
+/// specify one. Values with more decimal places are rounded.
+pub static DEFAULT_DECIMAL_SCALE: i8 = 10;
+
+/// Get the Arrow data type corresponding to the given SQL type for the given kind of database.
+pub fn arrow_data_type(kind: &dyn DbKind, sql_type: &str) -> DataType {
+    let base_type = sql_type.split('(').next().unwrap_or(sql_type).trim();
+    match kind.db_type(base_type) {
+        Ok(DbType::Null(_)) => DataType::Null,
+        Ok(DbType::Boolean(_)) => DataType::Boolean,
+        Ok(DbType::I16(_) | DbType::SmallInteger(_)) => DataType::Int16,
+        Ok(DbType::Integer(_)) => DataType::Int32,
+        Ok(DbType::BigInteger(_)) => DataType::Int64,
+        Ok(DbType::Real(_)) => DataType::Float32,
+        Ok(DbType::BigReal(_)) => DataType::Float64,
+        Ok(DbType::Numeric(_)) => {
+            let (precision, scale) = decimal_precision_and_scale(sql_type);
+            DataType::Decimal128(precision, scale)
+        }
+        Ok(DbType::Text(_)) | Err(_) => DataType::Utf8,
+    }
+}
+
+// Get the precision and scale given in the given numeric SQL type, e.g., `numeric(10, 2)`, or
+// the defaults if it does not give them.
+fn decimal_precision_and_scale(sql_type: &str) -> (u8, i8) {
+    let arguments = sql_type
+        .split_once('(')
+        .and_then(|(_, arguments)| arguments.strip_suffix(')'))
+        .map(|arguments| {
+            arguments
+                .split(',')
+                .map(|argument| argument.trim().parse::<u8>().ok())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    match arguments[..] {
+        [Some(precision)] if (1..=38).contains(&precision) => (precision, 0),
+        [Some(precision), Some(scale)] if (1..=38).contains(&precision) && scale <= precision => {
+            (precision, scale as i8)
+        }
+        _ => (DEFAULT_DECIMAL_PRECISION, DEFAULT_DECIMAL_SCALE),
+    }
+}
+
+/// Get the Arrow schema of the rows selected by the given [Select], based on the SQL types of
+/// the columns that it selects. All fields are nullable.
+pub async fn arrow_schema(pool: &impl DbQuery, select: &Select) -> Result<SchemaRef, DbError> {
+    // Columns may be qualified or not, as in [Select::render()]. We use the columns' declared
+    // types, rather than those given by [DbQuery::columns()], so as to preserve the precision
+    // and scale of numeric columns where possible:
+    let mut column_map = ColumnMap::new();
+    let mut all_columns = vec![];
+    for table in select.tables() {
+        let (sql, params) = pool.kind().table_columns_sql(table);
+        for row in pool.query_no_cache_clean(&sql, params).await?.iter() {
+            let get = |column: &str| {
+                row.get(column)
+                    .map(|value| value.to_string())
+                    .ok_or(DbError::DataError(format!(
+                        "No column '{column}' in row {row:?}"
+                    )))
+            };
+            let (column, sql_type) = (get("column_name")?, get("data_type")?);
+            column_map.insert(format!("{table}.{column}"), sql_type.clone());
+            all_columns.push((column.clone(), sql_type.clone()));
+            column_map.entry(column).or_insert(sql_type);
+        }
+    }
+
+    let kind = pool.kind();
+    let get_data_type = |column: &str| match column_map.get(column) {
+        Some(sql_type) => Ok(arrow_data_type(&*kind, sql_type)),
+        None => Err(DbError::InputError(format!("Unknown column: {column}"))),
+    };
+    let mut fields = vec![];
+    if select.columns.is_empty() {
+        for (column, sql_type) in &all_columns {
+            fields.push(Field::new(column, arrow_data_type(&*kind, sql_type), true));
+        }
+    }
+    for column in &select.columns {
+        let (name, data_type) = match column {
+            SelectColumn::Column(column) => (
+                column.rsplit('.').next().unwrap_or(column),
+                get_data_type(column)?,
+            ),
+            SelectColumn::Aggregate {
+                function,
+                column,
+                alias,
+            } => {
+                let data_type = match function {
+                    Aggregate::Count => DataType::Int64,
+                    Aggregate::Avg => DataType::Float64,
+                    Aggregate::Min | Aggregate::Max => get_data_type(column)?,
+                    // The sum of integers is a numeric in PostgreSQL:
+                    Aggregate::Sum => match get_data_type(column)? {
+                        DataType::Int16 | DataType::Int32 | DataType::Int64 => {
+                            DataType::Decimal128(DEFAULT_DECIMAL_PRECISION, 0)
+                        }
+                        other => other,
+                    },
+                };
+                (alias.as_str(), data_type)
+            }
+        };
+        fields.push(Field::new(name, data_type, true));
+    }
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// Convert the given rows to an Arrow [RecordBatch] with the given schema. The value of each
+/// field is taken from the column of the same name, and is NULL if there is no such column.
+pub fn to_record_batch(rows: &DbRows, schema: SchemaRef) -> Result<RecordBatch, DbError> {
+    let mut arrays = vec![];
+    for field in schema.fields() {
+        let values = rows
+            .iter()
+            .map(|row| row.get(field.name()).unwrap_or_default())
+            .collect::<Vec<_>>();
+        arrays.push(to_array(&values, field.data_type())?);
+    }
+    RecordBatch::try_new(schema, arrays).map_err(arrow_error)
+}
+
+// Convert the given values to an Arrow array of the given type.
+fn to_array(values: &[DbValue], data_type: &DataType) -> Result<ArrayRef, DbError> {
+    // Convert each non-NULL value using the given function:
+    fn convert<T>(
+        values: &[DbValue],
+        data_type: &DataType,
+        convert_value: impl Fn(&DbValue) -> Option<T>,
+    ) -> Result<Vec<Option<T>>, DbError> {
+        values
+            .iter()
+            .map(|value| match value {
+                DbValue::Null => Ok(None),
+                value => match convert_value(value) {
+                    Some(converted) => Ok(Some(converted)),
+                    None => Err(DbError::DataError(format!(
+                        "Can't convert {value:?} to {data_type}"
+                    ))),
+                },
+            })
+            .collect()
+    }
+
+    let array: ArrayRef = match data_type {
+        DataType::Null => Arc::new(NullArray::new(values.len())),
+        DataType::Boolean => Arc::new(BooleanArray::from(convert(values, data_type, |value| {
+            value.as_bool()
+        })?)),
+        DataType::Int16 => Arc::new(Int16Array::from(convert(values, data_type, |value| {
+            value.as_i16()
+        })?)),
+        DataType::Int32 => Arc::new(Int32Array::from(convert(values, data_type, |value| {
+            value.as_i32()
+        })?)),
+        DataType::Int64 => Arc::new(Int64Array::from(convert(values, data_type, |value| {
+            value.as_i64()
+        })?)),
+        DataType::Float32 => Arc::new(Float32Array::from(convert(values, data_type, |value| {
+            value
+                .as_f32()
+                .or_else(|| value.as_i64().map(|value| value as f32))
+        })?)),
+        DataType::Float64 => Arc::new(Float64Array::from(convert(values, data_type, |value| {
+            value
+                .as_f64()
+                .or_else(|| value.as_i64().map(|value| value as f64))
+        })?)),
+        DataType::Decimal128(precision, scale) => {
+            let values = convert(values, data_type, |value| {
+                let scale = u32::try_from(*scale).ok()?;
+                let mut decimal = value.as_decimal()?;
+                decimal.rescale(scale);
+                // The scale is silently lowered if the value is too large to be represented with
+                // the requested one, in which case the mantissa would be wrong:
+                match decimal.scale() == scale {
+                    true => Some(decimal.mantissa()),
+                    false => None,
+                }
+            })?;
+            Arc::new(
+                Decimal128Array::from(values)
+                    .with_precision_and_scale(*precision, *scale)
+                    .map_err(arrow_error)?,
+            )
+        }
+        DataType::Utf8 => Arc::new(StringArray::from(convert(values, data_type, |value| {
+            Some(value.to_string())
+        })?)),
+        other => {
+            return Err(DbError::InputError(format!(
+                "Unsupported Arrow data type: {other}"
+            )));
+        }
+    };
+    Ok(array)
+}
+
+/// Retrieve the rows selected by the given [Select] as Arrow record batches with the schema
+/// given by [arrow_schema()], one batch per page of [crate::paginate::EXPORT_PAGE_SIZE] rows.
+pub async fn record_batches(
+    pool: &impl DbQuery,
+    select: &Select,
+) -> Result<Vec<RecordBatch>, DbError> {
+    let schema = arrow_schema(pool, select).await?;
+    let mut batches = vec![];
+    for_each_page(pool, select, |rows| {
+        if !rows.is_empty() {
+            batches.push(to_record_batch(&rows, schema.clone())?);
+        }
+        Ok(())
+    })
+    .await?;
+    Ok(batches)
+}
+
+/// Write the rows selected by the given [Select] to the given writer in Parquet format, with the
+/// schema given by [arrow_schema()], retrieving and writing them one page at a time.
+pub async fn export_parquet(
+    pool: &impl DbQuery,
+    select: &Select,
+    writer: impl Write + Send,
+) -> Result<(), DbError> {
+    let schema = arrow_schema(pool, select).await?;
+    let mut parquet_writer =
+        ArrowWriter::try_new(writer, schema.clone(), None).map_err(parquet_error)?;
+    for_each_page(pool, select, |rows| {
+        if !rows.is_empty() {
+            let batch = to_record_batch(&rows, schema.clone())?;
+            parquet_writer.write(&batch).map_err(parquet_error)?;
+        }
+        Ok(())
+    })
+    .await?;
+    parquet_writer.close().map_err(parquet_error)?;
+    Ok(())
+}
+
+// Convert the given Arrow error to a [DbError].
+fn arrow_error(err: ::arrow::error::ArrowError) -> DbError {
+    DbError::DataError(format!("Arrow error: {err}"))
+}
+
+// Convert the given Parquet error to a [DbError].
+fn parquet_error(err: parquet::errors::ParquetError) -> DbError {
+    DbError::DataError(format!("Parquet error: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{any::AnyPool, db_kind::PostgreSQLKind, db_value::DbRow};
+    use ::arrow::util::display::array_value_to_string;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use pretty_assertions::assert_eq;
+    use rust_decimal::dec;
+    use serde_json::json;
+
+    // Render the values of the given column of the given batch as strings.
+    fn column_strings(batch: &RecordBatch, column: &str) -> Vec<String> {
+        let array = batch.column_by_name(column).unwrap();
+        (0..array.len())
+            .map(|i| array_value_to_string(array, i).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_to_record_batch() {
+        let kind = PostgreSQLKind {};
+        assert_eq!(arrow_data_type(&kind, "int4"), DataType::Int32);
+        assert_eq!(
+            arrow_data_type(&kind, "numeric(10, 2)"),
+            DataType::Decimal128(10, 2)
+        );
+        assert_eq!(
+            arrow_data_type(&kind, "numeric"),
+            DataType::Decimal128(DEFAULT_DECIMAL_PRECISION, DEFAULT_DECIMAL_SCALE)
+        );
+        assert_eq!(arrow_data_type(&kind, "jsonb"), DataType::Utf8);
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("amount", DataType::Decimal128(10, 2), true),
+            Field::new("data", DataType::Utf8, true),
+            Field::new("flag", DataType::Boolean, true),
+        ]));
+        let rows = DbRows {
+            content: vec![
+                DbRow::from_iter([
+                    ("amount".to_string(), DbValue::Numeric(dec!(1.5))),
+                    ("data".to_string(), DbValue::Json(json!({"a": [1]}))),
+                    ("flag".to_string(), DbValue::Boolean(true)),
+                ]),
+                DbRow::from_iter([("amount".to_string(), DbValue::Null)]),
+            ],
+        };
+        let batch = to_record_batch(&rows, schema.clone()).unwrap();
+        assert_eq!(column_strings(&batch, "amount"), ["1.50", ""]);
+        assert_eq!(column_strings(&batch, "data"), [r#"{"a":[1]}"#, ""]);
+        assert_eq!(column_strings(&batch, "flag"), ["true", ""]);
+
+        let rows = DbRows {
+            content: vec![DbRow::from_iter([(
+                "flag".to_string(),
+                DbValue::Text("yes".to_string()),
+            )])],
+        };
+        assert!(to_record_batch(&rows, schema).is_err());
+
+        // Large values can be converted as long as they can be represented with the given scale:
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "amount",
+            DataType::Decimal128(38, 10),
+            true,
+        )]));
+        let rows = DbRows {
+            content: vec![DbRow::from_iter([(
+                "amount".to_string(),
+                DbValue::Numeric(dec!(123456789012345678.5)),
+            )])],
+        };
+        let batch = to_record_batch(&rows, schema.clone()).unwrap();
+        assert_eq!(
+            column_strings(&batch, "amount"),
+            ["123456789012345678.5000000000"]
+        );
+        let rows = DbRows {
+            content: vec![DbRow::from_iter([(
+                "amount".to_string(),
+                DbValue::Numeric(dec!(12345678901234567890123456)),
+            )])],
+        };
+        assert!(matches!(
+            to_record_batch(&rows, schema),
+            Err(DbError::DataError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_export_arrow() {
+        #[cfg(feature = "rusqlite")]
+        export_arrow(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        export_arrow("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        export_arrow(":memory:").await;
+    }
+
+    async fn export_arrow(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.drop_table("test_arrow").await.unwrap();
+        pool.execute_batch(
+            r#"CREATE TABLE test_arrow (
+                 id BIGINT PRIMARY KEY,
+                 name TEXT,
+                 score DOUBLE PRECISION,
+                 amount NUMERIC,
+                 price NUMERIC(8, 2)
+               );
+               INSERT INTO test_arrow VALUES
+                 (1, 'a', 1.5, 10.25, 1.5), (2, 'b', NULL, 3, 2), (3, NULL, 2.5, NULL, NULL)"#,
+        )
+        .await
+        .unwrap();
+
+        let select = Select::new("test_arrow");
+        let schema = arrow_schema(&pool, &select).await.unwrap();
+        assert_eq!(
+            schema
+                .fields()
+                .iter()
+                .map(|field| field.data_type().clone())
+                .collect::<Vec<_>>(),
+            [
+                DataType::Int64,
+                DataType::Utf8,
+                DataType::Float64,
+                DataType::Decimal128(DEFAULT_DECIMAL_PRECISION, DEFAULT_DECIMAL_SCALE),
+                DataType::Decimal128(8, 2)
+            ]
+        );
+        let batches = record_batches(&pool, &select).await.unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(column_strings(&batches[0], "id"), ["1", "2", "3"]);
+        assert_eq!(column_strings(&batches[0], "name"), ["a", "b", ""]);
+        assert_eq!(column_strings(&batches[0], "score"), ["1.5", "", "2.5"]);
+        assert_eq!(
+            column_strings(&batches[0], "amount"),
+            ["10.2500000000", "3.0000000000", ""]
+        );
+        assert_eq!(column_strings(&batches[0], "price"), ["1.50", "2.00", ""]);
+
+        let select = Select::new("test_arrow")
+            .aggregate(Aggregate::Count, "*", "count")
+            .aggregate(Aggregate::Sum, "id", "total")
+            .aggregate(Aggregate::Max, "name", "last");
+        let batches = record_batches(&pool, &select).await.unwrap();
+        assert_eq!(column_strings(&batches[0], "count"), ["3"]);
+        assert_eq!(column_strings(&batches[0], "total"), ["6"]);
+        assert_eq!(column_strings(&batches[0], "last"), ["b"]);
+
+        // Write the table to Parquet and read it back:
+        let mut output = vec![];
+        export_parquet(&pool, &Select::new("test_arrow"), &mut output)
+            .await
+            .unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(output))
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(
+            batches[0].schema().fields(),
+            arrow_schema(&pool, &Select::new("test_arrow"))
+                .await
+                .unwrap()
+                .fields()
+        );
+        assert_eq!(
+            column_strings(&batches[0], "amount"),
+            ["10.2500000000", "3.0000000000", ""]
+        );
+
+        // Clean up:
+        pool.drop_table("test_arrow").await.unwrap();
+    }
+}
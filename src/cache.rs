@@ -1,47 +1,59 @@
 //! Code for query caching.
 
 use crate::{
+    any::AnyPool,
     core::{DbError, DbQuery, get_view_sql, which_are_views},
+    db_kind::DbType,
     db_value::{DbParams, DbRow, DbValue},
     params,
-    parse::{get_affected_tables, get_view_tables},
+    parse::{KeyValue, get_affected_tables, get_key_filter, get_view_tables, validate_table_name},
 };
 
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
 use indexmap::IndexMap;
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::Display,
+    hash::{Hash, Hasher},
+    ops::Deref,
     str::FromStr,
-    sync::{Mutex, MutexGuard},
-    thread,
+    sync::{
+        Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use tokio::task::JoinSet;
 
 /// The name of the database's query cache table.
 pub static QUERY_CACHE_TABLE: &str = "rltbl_db_query_cache";
 
+/// The name of the table that maps the entries of the query cache table to the tables (and
+/// views) that they depend on.
+pub static QUERY_CACHE_TABLES_TABLE: &str = "rltbl_db_query_cache_tables";
+
 /// The name of the database's table cache table.
 pub static TABLE_CACHE_TABLE: &str = "rltbl_db_table_cache";
 
-/// Default size for the in-memory query cache
-pub static DEFAULT_MEMORY_QUERY_CACHE_SIZE: usize = 1000;
-
-// Maximum number of times to try to retrieve an in-memory cache (retrieval will fail when the
-// cache is locked by another thread).
-static MAX_RETRIEVAL_ATTEMPTS: usize = 20;
+/// The name of the table used to keep track of the primary key values to which query cache
+/// entries are restricted (see [CachingStrategy::RowTrigger]).
+pub static QUERY_CACHE_KEYS_TABLE: &str = "rltbl_db_query_cache_keys";
 
-lazy_static! {
-    /// The in-memory query cache, used by [CachingStrategy::Memory].
-    pub static ref MEMORY_QUERY_CACHE: Mutex<IndexMap<MemoryQueryCacheKey, MemoryQueryCacheValue>>
-        = Mutex::new(IndexMap::new());
+/// The channel on which PostgreSQL databases notify listeners of edits to tables, so that the
+/// memory caches of other processes can be kept up to date (see [MemoryCaches::is_listening()]).
+pub static CACHE_NOTIFY_CHANNEL: &str = "rltbl_db_cache";
 
-    /// The in-memory table cache, used by [CachingStrategy::Memory].
-    pub static ref MEMORY_TABLE_CACHE: Mutex<HashMap<String, u128>> = Mutex::new(HashMap::new());
+/// Default size, in bytes, of the in-memory query cache
+pub static DEFAULT_MEMORY_QUERY_CACHE_BYTES: usize = 64 * 1024 * 1024;
 
-    /// The in-memory meta cache. It holds a set of things known to exist.
-    pub static ref MEMORY_META_CACHE: Mutex<HashSet<String>>
-        = Mutex::new(HashSet::new());
+lazy_static! {
+    // The in-memory caches of the pools connected to each database, by database identity (see
+    // [MemoryCaches::for_database()]). Only weak references are held here, so that the caches for
+    // a database are dropped once no pool that is connected to it remains.
+    static ref MEMORY_CACHE_REGISTRY: Mutex<HashMap<String, Weak<MemoryCaches>>>
+        = Mutex::new(HashMap::new());
 }
 
 /// Strategy to use when caching query results
@@ -55,38 +67,160 @@ pub enum CachingStrategy {
     Truncate,
     /// Truncate cache entries, for edited tables only, automatically whenever tables are edited.
     Trigger,
-    /// Similar to Truncate, but use an in-memory cache.
-    Memory(usize),
+    /// Like Trigger, but cache entries for queries that select rows from a single table by
+    /// primary key are only truncated when one of those rows is edited.
+    RowTrigger,
+    /// Similar to Truncate, but use an in-memory cache. Its size is given in bytes, and when
+    /// parsed from a string must have a unit, e.g., "memory:64mb" (see
+    /// [CachingStrategy::from_str()]); a bare number, which used to give the maximum number of
+    /// entries, is rejected.
+    Memory(MemoryCacheConfig),
+}
+
+/// The policy used to choose which entries to evict from the in-memory query cache when it is
+/// full.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used entries first.
+    #[default]
+    Lru,
+    /// Evict the least frequently used entries first.
+    Lfu,
+    /// Evict the entries that are cheapest to replace first, i.e., those whose queries took the
+    /// least time to run, relative to their size and to how often they have been used.
+    Cost,
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = DbError;
+
+    fn from_str(policy: &str) -> Result<Self, DbError> {
+        match policy.to_lowercase().as_str() {
+            "lru" => Ok(EvictionPolicy::Lru),
+            "lfu" => Ok(EvictionPolicy::Lfu),
+            "cost" => Ok(EvictionPolicy::Cost),
+            _ => Err(DbError::InputError(format!(
+                "Unrecognized eviction policy: {policy}"
+            ))),
+        }
+    }
+}
+
+impl Display for EvictionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvictionPolicy::Lru => write!(f, "lru"),
+            EvictionPolicy::Lfu => write!(f, "lfu"),
+            EvictionPolicy::Cost => write!(f, "cost"),
+        }
+    }
+}
+
+/// The configuration of the in-memory query cache used by [CachingStrategy::Memory].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MemoryCacheConfig {
+    pub eviction_policy: EvictionPolicy,
+    /// The maximum (approximate) number of bytes of query results to hold in the cache.
+    pub max_bytes: usize,
+}
+
+impl Default for MemoryCacheConfig {
+    fn default() -> Self {
+        Self {
+            eviction_policy: EvictionPolicy::default(),
+            max_bytes: DEFAULT_MEMORY_QUERY_CACHE_BYTES,
+        }
+    }
+}
+
+impl MemoryCacheConfig {
+    /// Use the given [EvictionPolicy].
+    pub fn eviction_policy(mut self, eviction_policy: EvictionPolicy) -> Self {
+        self.eviction_policy = eviction_policy;
+        self
+    }
+
+    /// Hold at most the given number of bytes of query results in the cache.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+}
+
+// The units that may be used to specify the size of the in-memory query cache, largest first.
+static BYTE_UNITS: [(&str, usize); 4] = [
+    ("gb", 1024 * 1024 * 1024),
+    ("mb", 1024 * 1024),
+    ("kb", 1024),
+    ("b", 1),
+];
+
+// Parse the given memory cache size, e.g., "4096b", "512kb", or "64mb". A unit is required, since
+// a bare number used to mean a number of entries, with the exception of zero, which means the
+// default size either way.
+fn parse_byte_size(size: &str) -> Result<usize, DbError> {
+    let Some((number, multiplier)) = BYTE_UNITS.iter().find_map(|(unit, multiplier)| {
+        size.strip_suffix(unit)
+            .map(|number| (number.trim(), *multiplier))
+    }) else {
+        return match size {
+            "0" => Ok(0),
+            _ => Err(DbError::InputError(format!(
+                "Memory cache size '{size}' has no unit. Sizes are given in bytes, with a unit \
+                 of b, kb, mb, or gb, e.g., '64mb'. (A number without a unit used to give the \
+                 maximum number of entries in the cache.)"
+            ))),
+        };
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or(DbError::InputError(format!(
+            "Error parsing memory cache size specification: '{size}'"
+        )))
 }
 
 impl FromStr for CachingStrategy {
     type Err = DbError;
 
+    /// Parse the given caching strategy. The memory strategy may be followed by an eviction
+    /// policy and/or a size in bytes, e.g., "memory", "memory:lfu", "memory:64mb", or
+    /// "memory:cost:512kb". The size must have a unit (b, kb, mb, or gb), and a size of zero is
+    /// taken to mean the default size. Note that a size without a unit, e.g., "memory:1000", used
+    /// to give the maximum number of entries in the cache, and is now rejected. Such strategies
+    /// should be rewritten with a size in bytes, or as plain "memory" to use the default size.
     fn from_str(strategy: &str) -> Result<Self, DbError> {
         match strategy.to_lowercase().as_str() {
             "none" => Ok(CachingStrategy::None),
             "truncate_all" => Ok(CachingStrategy::TruncateAll),
             "truncate" => Ok(CachingStrategy::Truncate),
             "trigger" => Ok(CachingStrategy::Trigger),
-            strategy if strategy.starts_with("memory") => {
-                let elems = strategy.split(":").collect::<Vec<_>>();
-                let cache_size = {
-                    if elems.len() < 2 {
-                        DEFAULT_MEMORY_QUERY_CACHE_SIZE
-                    } else {
-                        let cache_size = elems[1];
-                        match cache_size.parse::<usize>() {
-                            Ok(0) => DEFAULT_MEMORY_QUERY_CACHE_SIZE,
-                            Ok(size) => size,
-                            Err(err) => return Err(DbError::InputError(format!(
-                                "Error parsing memory cache size specification: '{cache_size}': \
-                                 {err}"
-                            ))
-                            .into()),
-                        }
+            "row_trigger" => Ok(CachingStrategy::RowTrigger),
+            strategy if strategy.split(":").next() == Some("memory") => {
+                let mut config = MemoryCacheConfig::default();
+                let elems = strategy.split(":").skip(1).collect::<Vec<_>>();
+                let (policy, size) = match elems[..] {
+                    [] => (None, None),
+                    [elem] => match elem.parse::<EvictionPolicy>() {
+                        Ok(policy) => (Some(policy), None),
+                        Err(_) => (None, Some(elem)),
+                    },
+                    [policy, size] => (Some(policy.parse::<EvictionPolicy>()?), Some(size)),
+                    _ => {
+                        return Err(DbError::InputError(format!(
+                            "Unrecognized memory strategy: {strategy}"
+                        )));
                     }
                 };
-                Ok(CachingStrategy::Memory(cache_size))
+                if let Some(policy) = policy {
+                    config.eviction_policy = policy;
+                }
+                match size.map(parse_byte_size).transpose()? {
+                    None | Some(0) => (),
+                    Some(size) => config.max_bytes = size,
+                };
+                Ok(CachingStrategy::Memory(config))
             }
             _ => {
                 return Err(
@@ -104,8 +238,111 @@ impl Display for CachingStrategy {
             CachingStrategy::TruncateAll => write!(f, "truncate_all"),
             CachingStrategy::Truncate => write!(f, "truncate"),
             CachingStrategy::Trigger => write!(f, "trigger"),
-            CachingStrategy::Memory(size) => write!(f, "memory:{size}"),
+            CachingStrategy::RowTrigger => write!(f, "row_trigger"),
+            CachingStrategy::Memory(config) => {
+                // Use the largest unit in which the size is a whole number:
+                let (unit, multiplier) = BYTE_UNITS
+                    .iter()
+                    .find(|(_, multiplier)| config.max_bytes % multiplier == 0)
+                    .unwrap_or(&("b", 1));
+                write!(
+                    f,
+                    "memory:{}:{}{unit}",
+                    config.eviction_policy,
+                    config.max_bytes / multiplier
+                )
+            }
+        }
+    }
+}
+
+/// The encoding in which the query results cached by the non-memory [CachingStrategy] variants
+/// are stored in the query cache table. Entries stored in any encoding can be read regardless of
+/// the encoding currently in use.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CacheEncoding {
+    /// A JSON array of rows, stored in the "value" column.
+    #[default]
+    Json,
+    /// A CBOR array of rows, stored in the "binary_value" column. This is faster to decode than
+    /// JSON, and preserves the type of every value exactly.
+    Cbor,
+    /// Like Cbor, but compressed using DEFLATE.
+    CompressedCbor,
+}
+
+impl FromStr for CacheEncoding {
+    type Err = DbError;
+
+    fn from_str(encoding: &str) -> Result<Self, DbError> {
+        match encoding.to_lowercase().as_str() {
+            "json" => Ok(CacheEncoding::Json),
+            "cbor" => Ok(CacheEncoding::Cbor),
+            "compressed_cbor" => Ok(CacheEncoding::CompressedCbor),
+            _ => Err(DbError::InputError(format!(
+                "Unrecognized cache encoding: {encoding}"
+            ))),
+        }
+    }
+}
+
+impl Display for CacheEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheEncoding::Json => write!(f, "json"),
+            CacheEncoding::Cbor => write!(f, "cbor"),
+            CacheEncoding::CompressedCbor => write!(f, "compressed_cbor"),
+        }
+    }
+}
+
+// The first byte of a value in the "binary_value" column of the query cache table, which
+// indicates how the rest of the value is encoded.
+const CBOR_TAG: u8 = 0;
+const COMPRESSED_CBOR_TAG: u8 = 1;
+
+// Encode the given rows as CBOR, compressing the result if so instructed, and return it as
+// hexadecimal text (see [DbKind::hex_to_binary_sql()]).
+pub(crate) fn encode_binary_value(rows: &[DbRow], compress: bool) -> Result<String, DbError> {
+    let encode_err = |err: String| DbError::SerdeError(format!("Error encoding rows: {err}"));
+    let bytes = match compress {
+        false => {
+            let mut bytes = vec![CBOR_TAG];
+            ciborium::into_writer(rows, &mut bytes).map_err(|err| encode_err(err.to_string()))?;
+            bytes
+        }
+        true => {
+            let mut encoder =
+                DeflateEncoder::new(vec![COMPRESSED_CBOR_TAG], Compression::default());
+            ciborium::into_writer(rows, &mut encoder).map_err(|err| encode_err(err.to_string()))?;
+            encoder
+                .finish()
+                .map_err(|err| encode_err(err.to_string()))?
+        }
+    };
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+// Decode the rows encoded by [encode_binary_value()], given as hexadecimal text.
+pub(crate) fn decode_binary_value(hex: &str) -> Result<Vec<DbRow>, DbError> {
+    let decode_err = |err: String| DbError::SerdeError(format!("Error decoding rows: {err}"));
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(decode_err(format!("Invalid hexadecimal text: '{hex}'")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match bytes.split_first() {
+        Some((&CBOR_TAG, cbor)) => {
+            ciborium::from_reader(cbor).map_err(|err| decode_err(err.to_string()))
+        }
+        Some((&COMPRESSED_CBOR_TAG, compressed)) => {
+            ciborium::from_reader(DeflateDecoder::new(compressed))
+                .map_err(|err| decode_err(err.to_string()))
         }
+        _ => Err(decode_err(format!("Unrecognized binary value: '{hex}'"))),
     }
 }
 
@@ -113,194 +350,826 @@ impl Display for CachingStrategy {
 // Memory cache code
 ////////////////////////
 
-/// The structure used to look up query results in the in-memory query cache.
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+/// The structure used to look up query results in the in-memory query cache. Keys are compared
+/// by their tables, statement, and the representation of their parameters.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MemoryQueryCacheKey {
-    pub tables: String,
+    pub tables: BTreeSet<String>,
     pub statement: String,
     pub parameters: String,
+    /// The parameters themselves, so that the query can be run again (see
+    /// [DbQuery::load_memory_cache_keys()]).
+    pub params: DbParams,
+}
+
+impl PartialEq for MemoryQueryCacheKey {
+    fn eq(&self, other: &Self) -> bool {
+        (&self.tables, &self.statement, &self.parameters)
+            == (&other.tables, &other.statement, &other.parameters)
+    }
+}
+
+impl Eq for MemoryQueryCacheKey {}
+
+impl Hash for MemoryQueryCacheKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (&self.tables, &self.statement, &self.parameters).hash(state);
+    }
+}
+
+impl MemoryQueryCacheKey {
+    /// Create the key for the given statement and parameters, whose result depends on the
+    /// given tables.
+    pub fn new(tables: &[&str], statement: &str, params: &DbParams) -> Self {
+        Self {
+            tables: tables.iter().map(|table| table.to_string()).collect(),
+            statement: statement.to_string(),
+            parameters: format!("{params:?}"),
+            params: params.clone(),
+        }
+    }
 }
 
 /// Represents the value of an entry in the in-memory query cache.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct MemoryQueryCacheValue {
     pub content: Vec<DbRow>,
     pub last_verified: u128,
+    /// The time at which the content was retrieved from the database, in milliseconds since the
+    /// epoch. Unlike the last verified time, this is not updated when the entry is used.
+    pub cached_at: u128,
+    /// The approximate number of bytes used by the content.
+    pub size: usize,
+    /// The time taken to run the query, in microseconds.
+    pub cost: u128,
+    // The number of times the entry has been retrieved from the cache.
+    hits: AtomicU64,
+    // The value of the cache's clock (see [MemoryCaches::tick()]) when the entry was last
+    // inserted or retrieved.
+    last_used: AtomicU64,
 }
 
-/// Returns true if the given object exists in the meta-cache.
-pub fn exists_in_meta_cache(object: &str) -> Result<bool, DbError> {
-    match get_meta_cache()?.get(object) {
-        Some(_) => Ok(true),
-        None => Ok(false),
+impl Clone for MemoryQueryCacheValue {
+    fn clone(&self) -> Self {
+        Self {
+            content: self.content.clone(),
+            last_verified: self.last_verified,
+            cached_at: self.cached_at,
+            size: self.size,
+            cost: self.cost,
+            hits: AtomicU64::new(self.hits()),
+            last_used: AtomicU64::new(self.last_used.load(Ordering::Relaxed)),
+        }
     }
 }
 
-/// Retrieve the in-memory meta-cache [MEMORY_META_CACHE].
-pub fn get_meta_cache<'a>() -> Result<MutexGuard<'a, HashSet<String>>, DbError> {
-    let mut remaining_attempts = MAX_RETRIEVAL_ATTEMPTS;
-    let mut meta_cache = MEMORY_META_CACHE.try_lock();
-    while let Err(err) = meta_cache {
-        meta_cache = MEMORY_META_CACHE.try_lock();
-        if let Ok(_) = meta_cache {
-            break;
-        }
-        remaining_attempts -= 1;
-        if remaining_attempts == 0 {
-            return Err(DbError::ConnectError(format!(
-                "Error locking cache: {err} (retried {MAX_RETRIEVAL_ATTEMPTS} times)"
-            )));
-        } else {
-            thread::sleep(Duration::from_millis(5));
+impl MemoryQueryCacheValue {
+    /// The number of times the entry has been retrieved from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    // Returns a key by which to sort entries so that those that should be evicted first, according
+    // to the given policy, come first.
+    fn eviction_key(&self, policy: &EvictionPolicy) -> (u128, u64) {
+        let last_used = self.last_used.load(Ordering::Relaxed);
+        match policy {
+            EvictionPolicy::Lru => (0, last_used),
+            EvictionPolicy::Lfu => (self.hits().into(), last_used),
+            EvictionPolicy::Cost => {
+                // The time saved by the entry so far, and expected to be saved in future, per
+                // kilobyte of memory used:
+                let saved = self.cost * (u128::from(self.hits()) + 1);
+                (saved * 1024 / self.size.max(1) as u128, last_used)
+            }
         }
     }
-    let meta_cache = meta_cache.unwrap();
-    Ok(meta_cache)
 }
 
-/// Retrieve the in-memory query cache (see [MEMORY_QUERY_CACHE]).
-pub fn get_memory_query_cache<'a>()
--> Result<MutexGuard<'a, IndexMap<MemoryQueryCacheKey, MemoryQueryCacheValue>>, DbError> {
-    let mut remaining_attempts = MAX_RETRIEVAL_ATTEMPTS;
-    let mut memory_cache = MEMORY_QUERY_CACHE.try_lock();
-    while let Err(err) = memory_cache {
-        memory_cache = MEMORY_QUERY_CACHE.try_lock();
-        if let Ok(_) = memory_cache {
-            break;
+/// The entries in the in-memory query cache, together with their total size. The entries can be
+/// read directly (via [Deref]), but may only be modified through the methods defined here, which
+/// keep the total size up to date. Note that the order of the entries is not significant.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryQueryCache {
+    entries: IndexMap<MemoryQueryCacheKey, MemoryQueryCacheValue>,
+    total_size: usize,
+    // The number of times the entries for each table have been invalidated.
+    generations: HashMap<String, u64>,
+    // The number of times all of the entries have been cleared.
+    clears: u64,
+}
+
+/// The number of times the entries for each of a set of tables had been invalidated in the memory
+/// query cache at some point in time (see [MemoryCaches::get_generations()]).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CacheGenerations {
+    tables: Vec<String>,
+    generations: Vec<u64>,
+}
+
+impl Deref for MemoryQueryCache {
+    type Target = IndexMap<MemoryQueryCacheKey, MemoryQueryCacheValue>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+impl MemoryQueryCache {
+    /// The approximate number of bytes used by the content of all of the entries.
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+
+    /// Add the given entry, replacing and returning any existing entry with the same key.
+    pub fn insert(
+        &mut self,
+        key: MemoryQueryCacheKey,
+        value: MemoryQueryCacheValue,
+    ) -> Option<MemoryQueryCacheValue> {
+        self.total_size += value.size;
+        let replaced = self.entries.insert(key, value);
+        if let Some(replaced) = &replaced {
+            self.total_size -= replaced.size;
         }
-        remaining_attempts -= 1;
-        if remaining_attempts == 0 {
-            return Err(DbError::ConnectError(format!(
-                "Error locking cache: {err} (retried {MAX_RETRIEVAL_ATTEMPTS} times)"
-            )));
-        } else {
-            thread::sleep(Duration::from_millis(5));
+        replaced
+    }
+
+    /// Remove and return the entry with the given key, if there is one.
+    pub fn remove(&mut self, key: &MemoryQueryCacheKey) -> Option<MemoryQueryCacheValue> {
+        let removed = self.entries.swap_remove(key);
+        if let Some(removed) = &removed {
+            self.total_size -= removed.size;
         }
+        removed
     }
-    let memory_cache = memory_cache.unwrap();
-    Ok(memory_cache)
-}
 
-/// Retrieve the in-memory table cache (see [MEMORY_TABLE_CACHE]).
-pub fn get_memory_table_cache<'a>() -> Result<MutexGuard<'a, HashMap<String, u128>>, DbError> {
-    let mut remaining_attempts = MAX_RETRIEVAL_ATTEMPTS;
-    let mut memory_cache = MEMORY_TABLE_CACHE.try_lock();
-    while let Err(err) = memory_cache {
-        memory_cache = MEMORY_TABLE_CACHE.try_lock();
-        if let Ok(_) = memory_cache {
-            break;
+    /// Set the last verified time of the entry with the given key, if there is one.
+    pub fn set_last_verified(&mut self, key: &MemoryQueryCacheKey, last_verified: u128) {
+        if let Some(value) = self.entries.get_mut(key) {
+            value.last_verified = last_verified;
         }
-        remaining_attempts -= 1;
-        if remaining_attempts == 0 {
-            return Err(DbError::ConnectError(format!(
-                "Error locking cache: {err} (retried {MAX_RETRIEVAL_ATTEMPTS} times)"
-            )));
-        } else {
-            thread::sleep(Duration::from_millis(5));
+    }
+
+    /// Retain only the entries that satisfy the given predicate.
+    pub fn retain(
+        &mut self,
+        mut keep: impl FnMut(&MemoryQueryCacheKey, &MemoryQueryCacheValue) -> bool,
+    ) {
+        let mut total_size = self.total_size;
+        self.entries.retain(|key, value| {
+            let kept = keep(key, value);
+            if !kept {
+                total_size -= value.size;
+            }
+            kept
+        });
+        self.total_size = total_size;
+    }
+
+    /// Remove all of the entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.total_size = 0;
+        self.clears += 1;
+    }
+
+    /// Remove the entries for the given tables, or all of the entries if no tables are given.
+    pub fn invalidate(&mut self, tables: &[&str]) {
+        if tables.is_empty() {
+            self.clear();
+            return;
+        }
+        for table in tables {
+            *self.generations.entry(table.to_string()).or_default() += 1;
+        }
+        self.retain(|key, _| !tables.iter().any(|table| key.tables.contains(*table)));
+    }
+
+    // Get the number of times the entries for the given tables have been invalidated.
+    fn generations(&self, tables: &[String]) -> CacheGenerations {
+        CacheGenerations {
+            tables: tables.to_vec(),
+            generations: std::iter::once(self.clears)
+                .chain(
+                    tables
+                        .iter()
+                        .map(|table| self.generations.get(table).copied().unwrap_or_default()),
+                )
+                .collect(),
         }
     }
-    let memory_cache = memory_cache.unwrap();
-    Ok(memory_cache)
 }
 
-/// Retrieve a copy of the contents of the meta cache.
-pub fn get_meta_cache_contents() -> Result<HashSet<String>, DbError> {
-    let cache = get_meta_cache()?;
-    Ok(cache.clone())
+// Estimate the number of bytes used by the given rows.
+fn estimate_size(rows: &[DbRow]) -> usize {
+    rows.iter()
+        .map(|row| {
+            row.map
+                .iter()
+                .map(|(column, value)| {
+                    let heap_size = match value {
+                        DbValue::Text(text) => text.len(),
+                        DbValue::Json(json) => json.to_string().len(),
+                        DbValue::Other(sql_type, raw, text) => {
+                            sql_type.len() + raw.len() + text.as_ref().map_or(0, |t| t.len())
+                        }
+                        _ => 0,
+                    };
+                    column.len() + size_of::<String>() + size_of::<DbValue>() + heap_size
+                })
+                .sum::<usize>()
+                + size_of::<DbRow>()
+        })
+        .sum()
 }
 
-/// Retrieve a copy of the contents of the memory query cache.
-pub fn get_memory_query_cache_contents()
--> Result<IndexMap<MemoryQueryCacheKey, MemoryQueryCacheValue>, DbError> {
-    let cache = get_memory_query_cache()?;
-    Ok(cache.clone())
+/// Statistics describing the use of the query cache, as returned by [DbQuery::cache_stats()].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// The number of query results that were retrieved from the cache.
+    pub hits: u64,
+    /// The number of query results that were not found in the cache, and so were retrieved from
+    /// the database. When the [CachingStrategy] is [None](CachingStrategy::None), every query
+    /// is a miss.
+    pub misses: u64,
+    /// The number of entries evicted from the memory query cache to make room for others.
+    pub evictions: u64,
+    /// The number of times that the cached results for each table were invalidated because the
+    /// table was edited or dropped.
+    pub invalidations: BTreeMap<String, u64>,
+    /// The approximate number of bytes of query results currently held in the cache.
+    pub bytes_stored: u64,
+    /// The average time saved by each hit, i.e., the difference between the average times taken
+    /// by misses and by hits, or zero if there have not been both hits and misses.
+    pub average_saved_time: Duration,
 }
 
-/// Retrieve a copy of the contents of the memory table cache.
-pub fn get_memory_table_cache_contents() -> Result<HashMap<String, u128>, DbError> {
-    let cache = get_memory_table_cache()?;
-    Ok(cache.clone())
+/// An entry in the query cache, as returned by [DbQuery::cache_entries()].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CacheEntry {
+    pub tables: BTreeSet<String>,
+    pub statement: String,
+    pub parameters: String,
+    /// The approximate number of bytes used by the cached query result.
+    pub size: u64,
 }
 
-/// Clear the meta cache.
-pub fn clear_meta_cache() -> Result<(), DbError> {
-    let mut cache = get_meta_cache()?;
-    cache.clear();
-    Ok(())
+/// The counters, kept by a pool and shared with its clones, from which [CacheStats] are
+/// generated.
+#[derive(Debug, Default)]
+pub struct CacheCounters {
+    counts: RwLock<Counts>,
+}
+
+// The values of a set of [CacheCounters].
+#[derive(Debug, Default)]
+struct Counts {
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    invalidations: BTreeMap<String, u64>,
+    hit_time: Duration,
+    miss_time: Duration,
 }
 
-/// Clear the memory query cache.
-pub fn clear_memory_query_cache(tables: &[&str]) -> Result<(), DbError> {
-    let mut cache = get_memory_query_cache()?;
-    if tables.is_empty() {
-        cache.clear();
+impl CacheCounters {
+    /// Get the statistics described by the counters. Note that the number of bytes stored is
+    /// not counted, and is always zero (see [DbQuery::cache_stats()]).
+    pub fn stats(&self) -> CacheStats {
+        let counts = read(&self.counts);
+        let average = |time: Duration, count: u64| match count {
+            0 => Duration::ZERO,
+            count => time / count.try_into().unwrap_or(u32::MAX),
+        };
+        CacheStats {
+            hits: counts.hits,
+            misses: counts.misses,
+            evictions: counts.evictions,
+            invalidations: counts.invalidations.clone(),
+            bytes_stored: 0,
+            average_saved_time: match counts.hits > 0 && counts.misses > 0 {
+                true => average(counts.miss_time, counts.misses)
+                    .saturating_sub(average(counts.hit_time, counts.hits)),
+                false => Duration::ZERO,
+            },
+        }
+    }
+
+    /// Reset the counters.
+    pub fn reset(&self) {
+        *write(&self.counts) = Counts::default();
     }
-    let keys = cache
-        .keys()
-        .map(|k| k)
-        .cloned()
-        .collect::<HashSet<_>>()
-        .into_iter()
-        .collect::<Vec<_>>();
-    for table in tables {
-        for key in keys.iter() {
-            if key.tables.contains(table) {
-                cache.shift_remove(key);
-            }
+
+    // Record a query result that was retrieved from the cache in the given time.
+    pub(crate) fn record_hit(&self, elapsed: Duration) {
+        let mut counts = write(&self.counts);
+        counts.hits += 1;
+        counts.hit_time += elapsed;
+    }
+
+    // Record a query result that was retrieved from the database in the given time.
+    pub(crate) fn record_miss(&self, elapsed: Duration) {
+        let mut counts = write(&self.counts);
+        counts.misses += 1;
+        counts.miss_time += elapsed;
+    }
+
+    // Record the eviction of the given number of entries from the memory query cache.
+    pub(crate) fn record_evictions(&self, evictions: u64) {
+        write(&self.counts).evictions += evictions;
+    }
+
+    // Record the invalidation of the cached results for the given tables.
+    pub(crate) fn record_invalidations(&self, tables: &[&str]) {
+        let mut counts = write(&self.counts);
+        for table in tables {
+            *counts.invalidations.entry(table.to_string()).or_default() += 1;
         }
     }
-    Ok(())
 }
 
-/// Clear the memory table cache.
-pub fn clear_memory_table_cache(tables: &[&str]) -> Result<(), DbError> {
-    let mut cache = get_memory_table_cache()?;
-    if tables.is_empty() {
-        cache.clear();
+/// The in-memory caches used by a pool: the query and table caches used by
+/// [CachingStrategy::Memory], and the meta cache, which holds a set of things (tables, views,
+/// and caching triggers) known to exist in the database. The caches are shared by every pool
+/// that is connected to the same database (see [MemoryCaches::for_database()]), including
+/// clones of the same pool.
+///
+/// Each cache is guarded by a [RwLock], so that any number of readers may access it at once.
+/// Locks are only ever held while the cache is being read or updated, and never across an
+/// `.await`, so that accessing a cache never blocks for long and never fails. If a thread
+/// panics while holding a lock, the cache is used as it was left.
+#[derive(Debug, Default)]
+pub struct MemoryCaches {
+    query_cache: RwLock<MemoryQueryCache>,
+    table_cache: RwLock<HashMap<String, u128>>,
+    meta_cache: RwLock<HashSet<String>>,
+    // A counter that is incremented whenever an entry in the query cache is used.
+    clock: AtomicU64,
+    // Whether a listener is clearing the caches in response to edits made by other processes.
+    listening: AtomicBool,
+}
+
+impl MemoryCaches {
+    /// Get the caches for the database with the given identity (e.g., its URL), creating them if
+    /// no existing pool is connected to that database. If no identity is given (e.g., for an
+    /// in-memory SQLite database, which is private to the pool), new caches are always created.
+    pub fn for_database(identity: Option<&str>) -> Arc<Self> {
+        let identity = match identity {
+            None => return Arc::new(Self::default()),
+            Some(identity) => identity,
+        };
+        let mut registry = MEMORY_CACHE_REGISTRY
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        registry.retain(|_, caches| caches.strong_count() > 0);
+        match registry.get(identity).and_then(|caches| caches.upgrade()) {
+            Some(caches) => caches,
+            None => {
+                let caches = Arc::new(Self::default());
+                registry.insert(identity.to_string(), Arc::downgrade(&caches));
+                caches
+            }
+        }
     }
-    for table in tables {
-        cache.remove(&table.to_string());
+
+    /// Retrieve the rows cached for the given key in the memory query cache, if any, recording
+    /// their use. If a time-to-live is given, rows that were retrieved from the database longer ago
+    /// than that are not returned.
+    pub fn get_query_result(
+        &self,
+        key: &MemoryQueryCacheKey,
+        ttl: Option<Duration>,
+    ) -> Result<Option<Vec<DbRow>>, DbError> {
+        let epoch_now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| DbError::DataError(format!("Error getting epoch time: {err}")))?;
+        let cache = self.query_cache();
+        match cache.get(key) {
+            Some(value)
+                if ttl.is_none_or(|ttl| {
+                    epoch_now.as_millis().saturating_sub(value.cached_at) < ttl.as_millis()
+                }) =>
+            {
+                value.hits.fetch_add(1, Ordering::Relaxed);
+                value.last_used.store(self.tick(), Ordering::Relaxed);
+                Ok(Some(value.content.to_vec()))
+            }
+            _ => Ok(None),
+        }
     }
-    Ok(())
+
+    /// Add the given rows, which took the given time to retrieve from the database, to the memory
+    /// query cache under the given key. If necessary, first evict entries in accordance with the
+    /// given configuration to make room for them, and return the number of entries evicted. Rows
+    /// that would not fit in the cache even if it were empty are not cached. Neither are rows
+    /// that may be stale because the entries for any of the tables in the given generations
+    /// (obtained using [MemoryCaches::get_generations()] before the rows were retrieved) have
+    /// since been invalidated.
+    pub fn insert_query_result(
+        &self,
+        key: MemoryQueryCacheKey,
+        content: Vec<DbRow>,
+        cost: Duration,
+        config: &MemoryCacheConfig,
+        generations: Option<&CacheGenerations>,
+    ) -> Result<u64, DbError> {
+        let size = estimate_size(&content);
+        if size > config.max_bytes {
+            return Ok(0);
+        }
+        let epoch_now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| DbError::DataError(format!("Error getting epoch time: {err}")))?
+            .as_millis();
+        let value = MemoryQueryCacheValue {
+            content,
+            last_verified: epoch_now,
+            cached_at: epoch_now,
+            size,
+            cost: cost.as_micros(),
+            hits: AtomicU64::new(0),
+            last_used: AtomicU64::new(self.tick()),
+        };
+        let mut cache = self.query_cache_mut();
+        if generations
+            .is_some_and(|generations| cache.generations(&generations.tables) != *generations)
+        {
+            return Ok(0);
+        }
+        cache.remove(&key);
+        let mut evictions = 0;
+        if cache.total_size() + size > config.max_bytes {
+            // Sort the entries once, so that those that should be evicted first come first, and
+            // evict them in that order until there is enough room:
+            let mut victims = cache
+                .iter()
+                .map(|(key, value)| (value.eviction_key(&config.eviction_policy), key.clone()))
+                .collect::<Vec<_>>();
+            victims.sort_unstable_by_key(|(eviction_key, _)| *eviction_key);
+            for (_, victim) in victims {
+                if cache.total_size() + size <= config.max_bytes {
+                    break;
+                }
+                cache.remove(&victim);
+                evictions += 1;
+            }
+        }
+        cache.insert(key, value);
+        Ok(evictions)
+    }
+
+    /// Returns true if a listener is clearing these caches whenever a table is edited by another
+    /// process, in which case [CachingStrategy::Memory] creates triggers in the database to
+    /// notify it of such edits (see [ensure_notify_triggers_exist()]).
+    pub fn is_listening(&self) -> bool {
+        self.listening.load(Ordering::Relaxed)
+    }
+
+    // Record whether a listener is clearing these caches, returning the previous value.
+    #[cfg(feature = "tokio-postgres")]
+    pub(crate) fn set_listening(&self, listening: bool) -> bool {
+        self.listening.swap(listening, Ordering::Relaxed)
+    }
+
+    /// Clear the entries for the given tables, which have been edited by another process, from
+    /// the memory query cache, and record them as having been modified now so that the entries
+    /// for any views that depend on them are also refreshed.
+    pub fn invalidate_tables(&self, tables: &[&str]) -> Result<(), DbError> {
+        let epoch_now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| DbError::DataError(format!("Error getting epoch time: {err}")))?;
+        self.clear_query_cache(tables);
+        let mut cache = self.table_cache_mut();
+        for table in tables {
+            cache.insert(table.to_string(), epoch_now.as_millis());
+        }
+        Ok(())
+    }
+
+    // Advance the clock used to determine which entries in the query cache were used most
+    // recently, and return its new value.
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Returns true if the given object exists in the meta cache.
+    pub fn exists_in_meta_cache(&self, object: &str) -> bool {
+        self.meta_cache().contains(object)
+    }
+
+    /// Retrieve the meta cache for reading.
+    pub fn meta_cache(&self) -> RwLockReadGuard<'_, HashSet<String>> {
+        read(&self.meta_cache)
+    }
+
+    /// Retrieve the meta cache for writing.
+    pub fn meta_cache_mut(&self) -> RwLockWriteGuard<'_, HashSet<String>> {
+        write(&self.meta_cache)
+    }
+
+    /// Retrieve the memory query cache for reading.
+    pub fn query_cache(&self) -> RwLockReadGuard<'_, MemoryQueryCache> {
+        read(&self.query_cache)
+    }
+
+    /// Retrieve the memory query cache for writing.
+    pub fn query_cache_mut(&self) -> RwLockWriteGuard<'_, MemoryQueryCache> {
+        write(&self.query_cache)
+    }
+
+    /// Retrieve the memory table cache for reading.
+    pub fn table_cache(&self) -> RwLockReadGuard<'_, HashMap<String, u128>> {
+        read(&self.table_cache)
+    }
+
+    /// Retrieve the memory table cache for writing.
+    pub fn table_cache_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, u128>> {
+        write(&self.table_cache)
+    }
+
+    /// Retrieve a copy of the contents of the meta cache.
+    pub fn meta_cache_contents(&self) -> HashSet<String> {
+        self.meta_cache().clone()
+    }
+
+    /// Retrieve a copy of the contents of the memory query cache.
+    pub fn query_cache_contents(&self) -> IndexMap<MemoryQueryCacheKey, MemoryQueryCacheValue> {
+        self.query_cache().entries.clone()
+    }
+
+    /// Retrieve a copy of the contents of the memory table cache.
+    pub fn table_cache_contents(&self) -> HashMap<String, u128> {
+        self.table_cache().clone()
+    }
+
+    /// Clear the meta cache.
+    pub fn clear_meta_cache(&self) {
+        self.meta_cache_mut().clear();
+    }
+
+    /// Clear the entries for the given tables from the memory query cache, or all of its
+    /// entries if no tables are given.
+    pub fn clear_query_cache(&self, tables: &[&str]) {
+        self.query_cache_mut().invalidate(tables);
+    }
+
+    /// Get the number of times the entries for the given tables have so far been invalidated
+    /// in the memory query cache, for use with [MemoryCaches::insert_query_result()].
+    pub fn get_generations(&self, tables: &[String]) -> CacheGenerations {
+        self.query_cache().generations(tables)
+    }
+
+    /// Clear the entries for the given tables from the memory table cache, or all of its
+    /// entries if no tables are given.
+    pub fn clear_table_cache(&self, tables: &[&str]) {
+        let mut cache = self.table_cache_mut();
+        if tables.is_empty() {
+            cache.clear();
+        }
+        for table in tables {
+            cache.remove(&table.to_string());
+        }
+    }
+}
+
+/// Get the identity of the SQLite database with the given URL, for use with
+/// [MemoryCaches::for_database()], or None if it is an in-memory database.
+pub fn sqlite_database_identity(url: &str) -> Option<String> {
+    match url.is_empty() || url == ":memory:" || url.contains("mode=memory") {
+        true => None,
+        false => {
+            let path = std::path::absolute(url).unwrap_or(url.into());
+            Some(format!("sqlite:{}", path.display()))
+        }
+    }
+}
+
+// Lock the given in-memory cache for reading. A lock that has been poisoned by a panic in
+// another thread is recovered, since the caches are left consistent after every update.
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Lock the given in-memory cache for writing (see [read()]).
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
 }
 
 ////////////////////////
 // Database cache code
 ////////////////////////
 
+// The representation of the given parameters that is stored in the "parameters" column of the
+// query cache table.
+pub(crate) fn query_cache_parameters(params: &DbParams) -> String {
+    match params {
+        DbParams::None => "[]".to_string(),
+        DbParams::Positional(params) => {
+            let params = params.iter().map(|p| p.into()).collect::<Vec<String>>();
+            format!("[{}]", params.join(", "))
+        }
+    }
+}
+
 /// Ensure that caching triggers exist for the given table. Note that this function calls
 /// [ensure_cache_tables_exist()] implicitly.
 pub async fn ensure_caching_triggers_exist_for_table(
     pool: &impl DbQuery,
     table: &str,
 ) -> Result<(), DbError> {
-    let table_triggers_name = format!("{table}_triggers");
-    if !exists_in_meta_cache(&table_triggers_name)? {
+    let table_triggers_name = caching_triggers_name(pool, table);
+    if !pool
+        .memory_caches()
+        .exists_in_meta_cache(&table_triggers_name)
+    {
         ensure_cache_tables_exist(pool).await?;
-        let sql = pool
-            .kind()
-            .create_table_caching_triggers_for_table_sql(&table)?
-            .join(";\n");
+        let sql = caching_triggers_sql(pool, table).await?.join(";\n");
         pool.execute_batch(&sql).await?;
 
         // Indicate that triggers exist for `table` in the meta-cache:
-        let mut cache = get_meta_cache()?;
-        cache.insert(table_triggers_name);
+        pool.memory_caches()
+            .meta_cache_mut()
+            .insert(table_triggers_name);
+    }
+    Ok(())
+}
+
+// The name under which the existence of caching triggers for the given table is recorded in the
+// meta-cache. Row-level triggers are recorded under a different name than table-level triggers
+// so that switching between the two strategies causes the triggers to be recreated.
+fn caching_triggers_name(pool: &impl DbQuery, table: &str) -> String {
+    match pool.get_caching_strategy() {
+        CachingStrategy::RowTrigger => format!("{table}_row_triggers"),
+        _ => format!("{table}_triggers"),
+    }
+}
+
+// Generate the SQL statements needed to create caching triggers for the given table. These are
+// row-level triggers if the current caching strategy is [CachingStrategy::RowTrigger] and the
+// table has a suitable primary key (see [get_key_column()]), and table-level triggers otherwise.
+async fn caching_triggers_sql(pool: &impl DbQuery, table: &str) -> Result<Vec<String>, DbError> {
+    if pool.get_caching_strategy() == CachingStrategy::RowTrigger
+        && let Some((key_column, _)) = get_key_column(pool, table).await?
+    {
+        ensure_query_cache_keys_table_exists(pool).await?;
+        return pool
+            .kind()
+            .create_row_caching_triggers_for_table_sql(table, &key_column);
+    }
+    pool.kind()
+        .create_table_caching_triggers_for_table_sql(table)
+}
+
+// Ensure that the query cache keys table (see [QUERY_CACHE_KEYS_TABLE]) exists.
+async fn ensure_query_cache_keys_table_exists(pool: &impl DbQuery) -> Result<(), DbError> {
+    if !pool
+        .memory_caches()
+        .exists_in_meta_cache(QUERY_CACHE_KEYS_TABLE)
+    {
+        let sql = pool.kind().create_query_cache_keys_table_sql();
+        pool.execute_no_cache_clean(&sql, ()).await?;
+        pool.memory_caches()
+            .meta_cache_mut()
+            .insert(QUERY_CACHE_KEYS_TABLE.to_string());
+    }
+    Ok(())
+}
+
+// Get the name and type of the primary key column of the given table, if it has a primary key
+// consisting of a single column of an integer or text type. Only the rows of such tables can be
+// tracked by [CachingStrategy::RowTrigger].
+async fn get_key_column(
+    pool: &impl DbQuery,
+    table: &str,
+) -> Result<Option<(String, DbType)>, DbError> {
+    let primary_keys = pool.primary_keys(table).await?;
+    let [key_column] = &primary_keys[..] else {
+        return Ok(None);
+    };
+    if validate_table_name(key_column).is_err() {
+        return Ok(None);
+    }
+    let columns = pool.columns(table).await?;
+    let db_type = match columns.get(key_column) {
+        Some(sql_type) => pool.kind().db_type(sql_type),
+        None => return Ok(None),
+    };
+    match db_type {
+        Ok(
+            db_type @ (DbType::I16(_)
+            | DbType::SmallInteger(_)
+            | DbType::Integer(_)
+            | DbType::BigInteger(_)
+            | DbType::Text(_)),
+        ) => Ok(Some((key_column.to_string(), db_type))),
+        _ => Ok(None),
+    }
+}
+
+/// Record the primary key values of the rows to which the query cache entry for the given
+/// statement and parameters is restricted, if the statement selects rows by primary key from a
+/// single table (see [get_key_filter()]). The triggers created for the
+/// [CachingStrategy::RowTrigger] strategy only delete such an entry when one of those rows is
+/// edited. Here `parameters` is the representation of `params` used in the query cache table.
+pub async fn record_query_cache_keys(
+    pool: &impl DbQuery,
+    tables: &[&str],
+    statement: &str,
+    params: &DbParams,
+    parameters: &str,
+) -> Result<(), DbError> {
+    ensure_query_cache_keys_table_exists(pool).await?;
+    pool.execute_no_cache_clean(
+        &format!(
+            r#"DELETE FROM "{QUERY_CACHE_KEYS_TABLE}"
+               WHERE "statement" = {p}1 AND "parameters" = {p}2"#,
+            p = pool.kind().param_prefix(),
+        ),
+        [statement, parameters],
+    )
+    .await?;
+
+    let [table] = tables else {
+        return Ok(());
+    };
+    let Some((key_column, db_type)) = get_key_column(pool, table).await? else {
+        return Ok(());
+    };
+    let Some(key_values) = get_key_filter(statement, &key_column)? else {
+        return Ok(());
+    };
+
+    // Convert the key values to the type of the primary key column, so that they are
+    // represented in the same way as the keys of the edited rows will be by the triggers:
+    let mut keys: BTreeSet<String> = BTreeSet::new();
+    for key_value in key_values {
+        let key = match (key_value, params) {
+            (KeyValue::Literal(key), _) => DbValue::Text(key),
+            (KeyValue::Parameter(index), DbParams::Positional(params)) => match params.get(index) {
+                Some(key) => key.clone(),
+                None => return Ok(()),
+            },
+            (KeyValue::Parameter(_), DbParams::None) => return Ok(()),
+        };
+        let key: String = match key {
+            DbValue::Null => return Ok(()),
+            key => key.into(),
+        };
+        match db_type.parse_str(&key) {
+            Ok(key) => keys.insert(key.into()),
+            Err(_) => return Ok(()),
+        };
+    }
+
+    let sql = format!(
+        r#"INSERT INTO "{QUERY_CACHE_KEYS_TABLE}" ("statement", "parameters", "key")
+           VALUES ({p}1, {p}2, {p}3)
+           ON CONFLICT DO NOTHING"#,
+        p = pool.kind().param_prefix(),
+    );
+    for key in keys {
+        pool.execute_no_cache_clean(&sql, [statement, parameters, &key])
+            .await?;
     }
     Ok(())
 }
 
-/// Ensure that the query cache table and the table cache table exist (see
-/// [QUERY_CACHE_TABLE] and [TABLE_CACHE_TABLE]).
+/// Ensure that the query cache table, the query cache tables table, and the table cache table
+/// exist (see [QUERY_CACHE_TABLE], [QUERY_CACHE_TABLES_TABLE], and [TABLE_CACHE_TABLE]).
 pub async fn ensure_cache_tables_exist(pool: &impl DbQuery) -> Result<(), DbError> {
-    if !exists_in_meta_cache(QUERY_CACHE_TABLE)? || !exists_in_meta_cache(TABLE_CACHE_TABLE)? {
-        for special_table in [QUERY_CACHE_TABLE, TABLE_CACHE_TABLE] {
+    let caches = pool.memory_caches();
+    let special_tables = [
+        QUERY_CACHE_TABLE,
+        QUERY_CACHE_TABLES_TABLE,
+        TABLE_CACHE_TABLE,
+    ];
+    if !special_tables
+        .iter()
+        .all(|table| caches.exists_in_meta_cache(table))
+    {
+        // Entries of a query cache table created before the query cache tables table was
+        // introduced cannot be invalidated, so they are deleted when the latter is created:
+        let legacy_entries = !pool.table_exists(QUERY_CACHE_TABLES_TABLE).await?
+            && pool.table_exists(QUERY_CACHE_TABLE).await?;
+        // A query cache table created before binary values (see [CacheEncoding]) or the times at
+        // which entries were cached (see [DbQuery::set_cache_ttl()]) were introduced lacks
+        // columns for them, which are added to it. Its entries are kept, since they remain
+        // readable:
+        let legacy_columns = match pool.table_exists(QUERY_CACHE_TABLE).await? {
+            true => missing_query_cache_columns(pool).await?,
+            false => vec![],
+        };
+        for special_table in special_tables {
             let sql = match special_table {
                 table if table == QUERY_CACHE_TABLE => pool.kind().create_query_cache_table_sql(),
+                table if table == QUERY_CACHE_TABLES_TABLE => {
+                    pool.kind().create_query_cache_tables_table_sql()
+                }
                 table if table == TABLE_CACHE_TABLE => pool.kind().create_table_cache_table_sql(),
                 _ => unreachable!(),
             };
-            match pool.execute_no_cache_clean(&sql, ()).await {
+            match pool.execute_batch(&sql).await {
                 Ok(_) => (),
                 Err(_) => {
                     // Since we are not using transactions, a race condition could occur in
@@ -318,13 +1187,132 @@ pub async fn ensure_cache_tables_exist(pool: &impl DbQuery) -> Result<(), DbErro
                     }
                 }
             };
-            let mut cache = get_meta_cache()?;
-            cache.insert(special_table.to_string());
+            caches.meta_cache_mut().insert(special_table.to_string());
+        }
+        for (column, sql_type) in legacy_columns {
+            let sql = pool
+                .kind()
+                .add_column_sql(QUERY_CACHE_TABLE, column, &sql_type);
+            // As above, another thread may have added the column in the meantime:
+            if let Err(err) = pool.execute_no_cache_clean(&sql, ()).await
+                && missing_query_cache_columns(pool)
+                    .await?
+                    .iter()
+                    .any(|(missing, _)| *missing == column)
+            {
+                return Err(err);
+            }
+        }
+        if legacy_entries {
+            delete_query_cache_entries(pool, &[]).await?;
         }
     }
     Ok(())
 }
 
+// Get the names and SQL types of the columns that a query cache table created by an earlier
+// version of this crate may lack, and that are missing from the existing one.
+async fn missing_query_cache_columns(
+    pool: &impl DbQuery,
+) -> Result<Vec<(&'static str, String)>, DbError> {
+    let columns = pool.columns(QUERY_CACHE_TABLE).await?;
+    Ok([
+        ("binary_value", pool.kind().binary_type().to_string()),
+        ("cached_at", "BIGINT".to_string()),
+    ]
+    .into_iter()
+    .filter(|(column, _)| !columns.contains_key(*column))
+    .collect())
+}
+
+/// Ensure that triggers exist to notify the listeners on the [CACHE_NOTIFY_CHANNEL] channel of
+/// edits to the given tables, or to the source tables of those that are views.
+pub async fn ensure_notify_triggers_exist(
+    pool: &impl DbQuery,
+    tables: &[&str],
+) -> Result<(), DbError> {
+    for table in &get_source_tables(pool, tables).await? {
+        let notify_triggers_name = format!("{table}_notify_triggers");
+        if !pool
+            .memory_caches()
+            .exists_in_meta_cache(&notify_triggers_name)
+        {
+            let sql = pool.kind().create_notify_triggers_for_table_sql(table)?;
+            if !sql.is_empty() {
+                pool.execute_batch(&sql.join(";\n")).await?;
+            }
+            pool.memory_caches()
+                .meta_cache_mut()
+                .insert(notify_triggers_name);
+        }
+    }
+    Ok(())
+}
+
+// Get those of the given tables that are not views, together with the source tables of those
+// that are.
+pub(crate) async fn get_source_tables(
+    pool: &impl DbQuery,
+    tables: &[&str],
+) -> Result<Vec<String>, DbError> {
+    let views = which_are_views(pool, tables).await?;
+    let mut source_tables = vec![];
+    for table in tables {
+        match views.iter().any(|view| view == table) {
+            true => {
+                let view_sql = get_view_sql(pool, table).await?;
+                source_tables.append(&mut get_view_tables(&view_sql)?);
+            }
+            false => source_tables.push(table.to_string()),
+        }
+    }
+    Ok(source_tables)
+}
+
+/// Populate the cache used by the given pool's [CachingStrategy] with the results of the given
+/// queries, each of which is given along with the tables it depends on and its parameters (see
+/// [DbQuery::warm_cache()]). Duplicate queries are only run once.
+pub(crate) async fn warm_query_cache(
+    pool: &AnyPool,
+    queries: Vec<(Vec<String>, String, DbParams)>,
+) -> Result<(), DbError> {
+    let mut seen_queries = HashSet::new();
+    let mut seen_tables = HashSet::new();
+    let mut tasks = JoinSet::new();
+    for (tables, statement, params) in queries {
+        if !seen_queries.insert((statement.clone(), query_cache_parameters(&params))) {
+            continue;
+        }
+        // The first query to read from a table is run on its own, so that the cache tables and
+        // triggers needed to cache it are not created concurrently by other queries:
+        let first = tables
+            .iter()
+            .filter(|table| seen_tables.insert(table.to_string()))
+            .count()
+            > 0;
+        let pool = pool.clone();
+        let cache = async move {
+            let tables = tables
+                .iter()
+                .map(|table| table.as_str())
+                .collect::<Vec<_>>();
+            pool.cache_tables(&tables, &statement, &params).await
+        };
+        match first {
+            true => {
+                cache.await?;
+            }
+            false => {
+                tasks.spawn(cache);
+            }
+        };
+    }
+    while let Some(result) = tasks.join_next().await {
+        result.map_err(|err| DbError::DatabaseError(format!("Error warming cache: {err}")))??;
+    }
+    Ok(())
+}
+
 /// Ensure that caching triggers exist for the source tables of the given view. Note that
 /// this function calls [ensure_cache_tables_exist()] implicitly.
 pub async fn ensure_caching_triggers_exist_for_view(
@@ -332,16 +1320,16 @@ pub async fn ensure_caching_triggers_exist_for_view(
     view: &str,
 ) -> Result<(), DbError> {
     let view_triggers_name = format!("{view}_triggers");
-    if !exists_in_meta_cache(&view_triggers_name)? {
+    if !pool
+        .memory_caches()
+        .exists_in_meta_cache(&view_triggers_name)
+    {
         ensure_cache_tables_exist(pool).await?;
         let view_sql = get_view_sql(pool, &view).await?;
         let source_tables = get_view_tables(&view_sql)?;
         for source_table in source_tables.iter() {
             // Add a trigger to clean entries from the cache for the source table itself:
-            let sql = pool
-                .kind()
-                .create_table_caching_triggers_for_table_sql(&source_table)?
-                .join(";\n");
+            let sql = caching_triggers_sql(pool, source_table).await?.join(";\n");
             pool.execute_batch(&sql).await?;
             // Add a trigger to clean entries from the cache for the view:
             let sql = pool
@@ -352,12 +1340,14 @@ pub async fn ensure_caching_triggers_exist_for_view(
             // Add an entry for the source table triggers to the metacache. If there is another
             // entry for this source table it will be overwritten, which is desirable in
             // case it was not previously known if the table was the source table for a view.
-            let mut cache = get_meta_cache()?;
-            let source_triggers_name = format!("{source_table}_triggers");
-            cache.insert(source_triggers_name);
+            let source_triggers_name = caching_triggers_name(pool, source_table);
+            pool.memory_caches()
+                .meta_cache_mut()
+                .insert(source_triggers_name);
         }
-        let mut cache = get_meta_cache()?;
-        cache.insert(view_triggers_name);
+        pool.memory_caches()
+            .meta_cache_mut()
+            .insert(view_triggers_name);
     }
     Ok(())
 }
@@ -411,8 +1401,11 @@ pub async fn clear_cache_for_edited_tables(
     pool: &impl DbQuery,
     tables: &[&str],
 ) -> Result<(), DbError> {
+    if pool.get_caching_strategy() != CachingStrategy::None {
+        pool.cache_counters().record_invalidations(tables);
+    }
     match pool.get_caching_strategy() {
-        CachingStrategy::None | CachingStrategy::Trigger => (),
+        CachingStrategy::None | CachingStrategy::Trigger | CachingStrategy::RowTrigger => (),
         CachingStrategy::TruncateAll => {
             update_last_modified_times(pool, tables).await?;
             delete_query_cache_entries(pool, &[]).await?
@@ -423,7 +1416,7 @@ pub async fn clear_cache_for_edited_tables(
         }
         CachingStrategy::Memory(_) => {
             update_last_modified_times(pool, tables).await?;
-            clear_memory_query_cache(tables)?;
+            pool.memory_caches().clear_query_cache(tables);
         }
     };
     Ok(())
@@ -436,14 +1429,22 @@ pub async fn clear_cache_for_dropped_tables(
     tables: &[&str],
 ) -> Result<(), DbError> {
     if let CachingStrategy::Memory(_) = pool.get_caching_strategy() {
+        pool.cache_counters().record_invalidations(tables);
         update_last_modified_times(pool, tables).await?;
-        clear_memory_query_cache(&tables)?;
+        pool.memory_caches().clear_query_cache(tables);
     } else {
         // Do not clear the cache if the dropped tables include the cache tables themselves:
-        if !tables
-            .iter()
-            .any(|table| [QUERY_CACHE_TABLE, TABLE_CACHE_TABLE].contains(table))
-        {
+        if !tables.iter().any(|table| {
+            [
+                QUERY_CACHE_TABLE,
+                QUERY_CACHE_TABLES_TABLE,
+                TABLE_CACHE_TABLE,
+            ]
+            .contains(table)
+        }) {
+            if pool.get_caching_strategy() != CachingStrategy::None {
+                pool.cache_counters().record_invalidations(tables);
+            }
             match pool.get_caching_strategy() {
                 CachingStrategy::Memory(_) => unreachable!(),
                 CachingStrategy::None => (),
@@ -451,7 +1452,9 @@ pub async fn clear_cache_for_dropped_tables(
                     update_last_modified_times(pool, tables).await?;
                     delete_query_cache_entries(pool, &[]).await?;
                 }
-                CachingStrategy::Trigger | CachingStrategy::Truncate => {
+                CachingStrategy::Trigger
+                | CachingStrategy::RowTrigger
+                | CachingStrategy::Truncate => {
                     update_last_modified_times(pool, tables).await?;
                     delete_query_cache_entries(pool, tables).await?;
                 }
@@ -459,14 +1462,21 @@ pub async fn clear_cache_for_dropped_tables(
         }
     }
     // Update the meta-cache to remove any entries associated with tables that no longer exist:
-    let mut meta_cache = get_meta_cache()?;
+    let caches = pool.memory_caches();
+    let mut meta_cache = caches.meta_cache_mut();
     for table in tables {
         if *table == QUERY_CACHE_TABLE {
             meta_cache.remove(QUERY_CACHE_TABLE);
+        } else if *table == QUERY_CACHE_TABLES_TABLE {
+            meta_cache.remove(QUERY_CACHE_TABLES_TABLE);
+        } else if *table == QUERY_CACHE_KEYS_TABLE {
+            meta_cache.remove(QUERY_CACHE_KEYS_TABLE);
         } else if *table == TABLE_CACHE_TABLE {
             meta_cache.remove(TABLE_CACHE_TABLE);
         } else {
             meta_cache.remove(&format!("{table}_triggers"));
+            meta_cache.remove(&format!("{table}_row_triggers"));
+            meta_cache.remove(&format!("{table}_notify_triggers"));
             meta_cache.remove(&format!("{table}_VIEW"));
             meta_cache.remove(&format!("{table}_TABLE"));
         }
@@ -484,52 +1494,26 @@ pub async fn update_last_verified(
 ) -> Result<(), DbError> {
     match pool.get_caching_strategy() {
         CachingStrategy::Memory(_) => {
-            let mut cache = get_memory_query_cache()?;
+            let caches = pool.memory_caches();
+            let mut cache = caches.query_cache_mut();
             let epoch_now = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .map_err(|err| DbError::DataError(format!("Error getting epoch time: {err}")))?;
-            let mem_key = MemoryQueryCacheKey {
-                tables: format!(
-                    "[{}]",
-                    tables
-                        .iter()
-                        .map(|table| format!("\"{table}\""))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                ),
-                statement: statement.to_string(),
-                parameters: format!("{params:?}"),
-            };
-            match cache.get_mut(&mem_key) {
-                Some(value) => value.last_verified = epoch_now.as_millis(),
-                None => (),
-            };
+            let mem_key = MemoryQueryCacheKey::new(tables, statement, params);
+            cache.set_last_verified(&mem_key, epoch_now.as_millis());
         }
         _ => match pool.table_exists(QUERY_CACHE_TABLE).await? {
             true => {
-                let tables_param = format!(
-                    "[{}]",
-                    tables
-                        .iter()
-                        .map(|table| format!("\"{table}\""))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                );
                 pool.execute(
                     &format!(
                         r#"UPDATE "{QUERY_CACHE_TABLE}"
                                SET "last_verified" = {ts}
-                               WHERE "tables" = {p}1
-                               AND "statement" = {p}2
-                               AND "parameters" = {p}3"#,
+                               WHERE "statement" = {p}1
+                               AND "parameters" = {p}2"#,
                         p = pool.kind().param_prefix(),
                         ts = pool.kind().get_epoch_time_sql(),
                     ),
-                    &[
-                        &format!("[{tables_param}]"),
-                        statement,
-                        &format!("{params:?}"),
-                    ],
+                    [statement, &query_cache_parameters(params)],
                 )
                 .await?;
             }
@@ -546,7 +1530,8 @@ pub async fn update_last_modified_times(
 ) -> Result<(), DbError> {
     match pool.get_caching_strategy() {
         CachingStrategy::Memory(_) => {
-            let mut cache = get_memory_table_cache()?;
+            let caches = pool.memory_caches();
+            let mut cache = caches.table_cache_mut();
             let epoch_now = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .map_err(|err| DbError::DataError(format!("Error getting epoch time: {err}")))?;
@@ -581,19 +1566,19 @@ pub async fn delete_query_cache_entries(
 ) -> Result<(), DbError> {
     if pool.table_exists(QUERY_CACHE_TABLE).await? {
         if tables.is_empty() {
-            pool.execute_no_cache_clean(&format!(r#"DELETE FROM "{QUERY_CACHE_TABLE}""#), ())
-                .await?;
+            for cache_table in [QUERY_CACHE_TABLE, QUERY_CACHE_TABLES_TABLE] {
+                pool.execute_no_cache_clean(&format!(r#"DELETE FROM "{cache_table}""#), ())
+                    .await?;
+            }
         } else {
+            let table_param = format!("{}1", pool.kind().param_prefix());
+            let sqls = pool
+                .kind()
+                .delete_query_cache_entries_sql(&table_param, None);
             for table in tables {
-                let table_param = format!(r#"%"{table}"%"#);
-                pool.execute_no_cache_clean(
-                    &format!(
-                        r#"DELETE FROM "{QUERY_CACHE_TABLE}" WHERE "tables" LIKE {}1"#,
-                        pool.kind().param_prefix()
-                    ),
-                    &[table_param],
-                )
-                .await?;
+                for sql in &sqls {
+                    pool.execute_no_cache_clean(sql, params![table]).await?;
+                }
             }
         }
     }
@@ -610,7 +1595,7 @@ pub async fn update_cached_views(pool: &impl DbQuery, tables: &[&str]) -> Result
             for view in &views {
                 let last_verified = {
                     let mut last_verified = 0;
-                    for (key, value) in get_memory_query_cache()?.iter() {
+                    for (key, value) in pool.memory_caches().query_cache().iter() {
                         if key.tables.contains(view) && value.last_verified > last_verified {
                             last_verified = value.last_verified;
                         }
@@ -622,7 +1607,7 @@ pub async fn update_cached_views(pool: &impl DbQuery, tables: &[&str]) -> Result
                 let last_modified = {
                     let mut latest_last_modified = 0;
                     for view_table in &view_tables {
-                        match get_memory_table_cache()?.get(view_table) {
+                        match pool.memory_caches().table_cache().get(view_table) {
                             Some(lm) if *lm > latest_last_modified => {
                                 latest_last_modified = *lm;
                             }
@@ -632,7 +1617,8 @@ pub async fn update_cached_views(pool: &impl DbQuery, tables: &[&str]) -> Result
                     latest_last_modified
                 };
                 if last_modified >= last_verified {
-                    clear_memory_query_cache(&[view])?;
+                    pool.cache_counters().record_invalidations(&[view]);
+                    pool.memory_caches().clear_query_cache(&[view]);
                 }
             }
         }
@@ -647,6 +1633,7 @@ pub async fn update_cached_views(pool: &impl DbQuery, tables: &[&str]) -> Result
                 )
                 .await?;
                 if last_modified >= last_verified {
+                    pool.cache_counters().record_invalidations(&[view]);
                     delete_query_cache_entries(&pool.pool(), &[&view]).await?;
                 }
             }
@@ -707,6 +1694,87 @@ pub async fn last_modified(pool: &impl DbQuery, table: &str) -> Result<u64, DbEr
     get_latest_last_modified(pool, &[table]).await
 }
 
+/// Get the statistics describing the use of the given pool's query cache, including the number
+/// of bytes of query results currently held in the cache used by its [CachingStrategy].
+pub async fn get_cache_stats(pool: &impl DbQuery) -> Result<CacheStats, DbError> {
+    let stats = pool.cache_counters().stats();
+    let bytes_stored = match pool.get_caching_strategy() {
+        CachingStrategy::None => 0,
+        CachingStrategy::Memory(_) => pool.memory_caches().query_cache().total_size() as u64,
+        _ => get_cache_entries(pool)
+            .await?
+            .iter()
+            .map(|entry| entry.size)
+            .sum(),
+    };
+    Ok(CacheStats {
+        bytes_stored,
+        ..stats
+    })
+}
+
+/// Get the entries in the query cache used by the given pool's [CachingStrategy], sorted by
+/// tables, statement, and parameters.
+pub async fn get_cache_entries(pool: &impl DbQuery) -> Result<Vec<CacheEntry>, DbError> {
+    let mut entries = match pool.get_caching_strategy() {
+        CachingStrategy::None => vec![],
+        CachingStrategy::Memory(_) => pool
+            .memory_caches()
+            .query_cache()
+            .iter()
+            .map(|(key, value)| CacheEntry {
+                tables: key.tables.clone(),
+                statement: key.statement.clone(),
+                parameters: key.parameters.clone(),
+                size: value.size as u64,
+            })
+            .collect(),
+        _ => match pool.table_exists(QUERY_CACHE_TABLE).await? {
+            false => vec![],
+            true => {
+                let sql = format!(
+                    r#"SELECT q."statement", q."parameters", t."table",
+                         CAST(COALESCE(LENGTH(q."value"), LENGTH(q."binary_value"), 0) AS BIGINT)
+                           AS "size"
+                       FROM "{QUERY_CACHE_TABLE}" q
+                       LEFT JOIN "{QUERY_CACHE_TABLES_TABLE}" t
+                         ON t."statement" = q."statement"
+                        AND t."parameters" = q."parameters""#
+                );
+                let mut entries = BTreeMap::new();
+                for row in pool.query_no_cache_clean(&sql, ()).await?.iter() {
+                    let get = |column: &str| {
+                        row.get(column).ok_or(DbError::DataError(format!(
+                            "No '{column}' found in row: {row:?}"
+                        )))
+                    };
+                    let statement = get("statement")?.to_string();
+                    let parameters = get("parameters")?.to_string();
+                    let entry = entries
+                        .entry((statement.clone(), parameters.clone()))
+                        .or_insert(CacheEntry {
+                            tables: BTreeSet::new(),
+                            statement,
+                            parameters,
+                            size: get("size")?.try_into()?,
+                        });
+                    match get("table")? {
+                        DbValue::Null => (),
+                        table => {
+                            entry.tables.insert(table.to_string());
+                        }
+                    };
+                }
+                entries.into_values().collect()
+            }
+        },
+    };
+    entries.sort_by(|a, b| {
+        (&a.tables, &a.statement, &a.parameters).cmp(&(&b.tables, &b.statement, &b.parameters))
+    });
+    Ok(entries)
+}
+
 /// Gets the last time that the given table was verified, as read from the query cache table.
 /// If there is no entry involving the given table in the query cache, or if the query cache
 /// table doesn't exist, returns 0.
@@ -716,11 +1784,14 @@ pub async fn last_verified(pool: &impl DbQuery, table: &str) -> Result<u64, DbEr
             let sql = format!(
                 r#"SELECT MAX("last_verified") AS "last_verified"
                        FROM "{QUERY_CACHE_TABLE}"
-                       WHERE "tables" LIKE {p}1"#,
+                       WHERE ("statement", "parameters") IN (
+                         SELECT "statement", "parameters"
+                         FROM "{QUERY_CACHE_TABLES_TABLE}"
+                         WHERE "table" = {p}1
+                       )"#,
                 p = pool.kind().param_prefix(),
             );
-            let table_param = format!(r#"%"{table}"%"#);
-            let rows = pool.query_no_cache_clean(&sql, &[&table_param]).await?;
+            let rows = pool.query_no_cache_clean(&sql, params![table]).await?;
             match rows.first() {
                 Some(row) => match row.get("last_verified") {
                     Some(value) if value == DbValue::Null => Ok(0),
@@ -735,3 +1806,186 @@ pub async fn last_verified(pool: &impl DbQuery, table: &str) -> Result<u64, DbEr
         false => Ok(0),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_row;
+    use pretty_assertions::assert_eq;
+    use rust_decimal::dec;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_caching_strategy() {
+        let parse = |strategy: &str| CachingStrategy::from_str(strategy).unwrap();
+        let memory = |policy: EvictionPolicy, max_bytes: usize| {
+            CachingStrategy::Memory(
+                MemoryCacheConfig::default()
+                    .eviction_policy(policy)
+                    .max_bytes(max_bytes),
+            )
+        };
+        let default_size = DEFAULT_MEMORY_QUERY_CACHE_BYTES;
+        assert_eq!(parse("memory"), memory(EvictionPolicy::Lru, default_size));
+        assert_eq!(
+            parse("memory:lfu"),
+            memory(EvictionPolicy::Lfu, default_size)
+        );
+        assert_eq!(parse("memory:0"), memory(EvictionPolicy::Lru, default_size));
+        assert_eq!(parse("memory:5000b"), memory(EvictionPolicy::Lru, 5000));
+        assert_eq!(
+            parse("memory:512KB"),
+            memory(EvictionPolicy::Lru, 512 * 1024)
+        );
+        assert_eq!(
+            parse("memory:cost:64mb"),
+            memory(EvictionPolicy::Cost, 64 * 1024 * 1024)
+        );
+        for strategy in [
+            "memory:lfu:64mb",
+            "memory:cost:1gb",
+            "memory:lru:1000b",
+            "trigger",
+            "row_trigger",
+        ] {
+            assert_eq!(parse(strategy).to_string(), strategy);
+        }
+        for strategy in [
+            "memory:mru",
+            "memory:lru:64tb",
+            "memory:64mb:lru",
+            "memory:a:b:c",
+            "memory:1000",
+            "memory:lfu:5",
+        ] {
+            assert!(CachingStrategy::from_str(strategy).is_err());
+        }
+    }
+
+    #[test]
+    fn test_binary_value() {
+        let rows = vec![
+            db_row! {
+                "real" => DbValue::Real(1.1),
+                "big_real" => DbValue::BigReal(1.1),
+                "numeric" => DbValue::Numeric(dec!(2.25)),
+                "json" => DbValue::Json(json!({"b": [1, 2.5, null], "a": "x"})),
+                "other" => DbValue::Other("bytea".to_string(), vec![222, 173], None),
+            },
+            db_row! {"real" => DbValue::Null},
+        ];
+        for compress in [false, true] {
+            let hex = encode_binary_value(&rows, compress).unwrap();
+            assert_eq!(decode_binary_value(&hex).unwrap(), rows);
+            // Hexadecimal text returned by the database may be in upper case:
+            assert_eq!(decode_binary_value(&hex.to_uppercase()).unwrap(), rows);
+        }
+        assert!(decode_binary_value("").is_err());
+        assert!(decode_binary_value("0").is_err());
+        assert!(decode_binary_value("ff00").is_err());
+    }
+
+    #[test]
+    fn test_eviction() {
+        let key = |statement: &str| MemoryQueryCacheKey::new(&["test"], statement, &DbParams::None);
+        let rows = || vec![db_row! {"value" => "a value of moderate length"}];
+        let size = estimate_size(&rows());
+        let keys = |caches: &MemoryCaches| {
+            caches
+                .query_cache()
+                .keys()
+                .map(|key| key.statement.clone())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+
+        // Fill a cache with room for three entries, use some of them, and then add a fourth:
+        let fill = |policy: EvictionPolicy, uses: &[&str]| {
+            let config = MemoryCacheConfig::default()
+                .eviction_policy(policy)
+                .max_bytes(size * 3);
+            let caches = MemoryCaches::default();
+            for (statement, cost) in [("a", 10), ("b", 20), ("c", 30)] {
+                caches
+                    .insert_query_result(
+                        key(statement),
+                        rows(),
+                        Duration::from_millis(cost),
+                        &config,
+                        None,
+                    )
+                    .unwrap();
+            }
+            for statement in uses {
+                assert!(
+                    caches
+                        .get_query_result(&key(statement), None)
+                        .unwrap()
+                        .is_some()
+                );
+            }
+            let evictions = caches
+                .insert_query_result(key("d"), rows(), Duration::from_millis(5), &config, None)
+                .unwrap();
+            assert_eq!(evictions, 1);
+            assert_eq!(caches.query_cache().total_size(), size * 3);
+            keys(&caches)
+        };
+        assert_eq!(fill(EvictionPolicy::Lru, &["a"]), ["a", "c", "d"]);
+        assert_eq!(
+            fill(EvictionPolicy::Lfu, &["b", "b", "a", "c"]),
+            ["b", "c", "d"]
+        );
+        assert_eq!(fill(EvictionPolicy::Lfu, &["b", "c"]), ["b", "c", "d"]);
+        assert_eq!(fill(EvictionPolicy::Cost, &[]), ["b", "c", "d"]);
+        assert_eq!(
+            fill(EvictionPolicy::Cost, &["a", "a", "a"]),
+            ["a", "c", "d"]
+        );
+
+        // Results that are larger than the cache are not cached:
+        let caches = MemoryCaches::default();
+        let config = MemoryCacheConfig::default().max_bytes(size - 1);
+        caches
+            .insert_query_result(key("a"), rows(), Duration::ZERO, &config, None)
+            .unwrap();
+        assert!(caches.query_cache().is_empty());
+        assert_eq!(caches.query_cache().total_size(), 0);
+    }
+
+    #[test]
+    fn test_generations() {
+        let key = MemoryQueryCacheKey::new(&["test_view"], "SELECT 1", &DbParams::None);
+        let rows = || vec![db_row! {"value" => "a"}];
+        let config = MemoryCacheConfig::default();
+        let caches = MemoryCaches::default();
+        let tables = ["test_view".to_string(), "test_table".to_string()];
+        let insert = |generations: &CacheGenerations| {
+            caches
+                .insert_query_result(
+                    key.clone(),
+                    rows(),
+                    Duration::ZERO,
+                    &config,
+                    Some(generations),
+                )
+                .unwrap();
+            let inserted = caches.query_cache().contains_key(&key);
+            caches.clear_query_cache(&["test_view"]);
+            inserted
+        };
+
+        // Results are cached unless one of the given tables is invalidated in the meantime:
+        let generations = caches.get_generations(&tables);
+        caches.clear_query_cache(&["test_other_table"]);
+        assert!(insert(&generations));
+        let generations = caches.get_generations(&tables);
+        caches.clear_query_cache(&["test_table"]);
+        assert!(!insert(&generations));
+        let generations = caches.get_generations(&tables);
+        caches.clear_query_cache(&[]);
+        assert!(!insert(&generations));
+        assert!(insert(&caches.get_generations(&tables)));
+    }
+}
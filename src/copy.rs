@@ -0,0 +1,403 @@
+//! Code for copying tables from one database to another, possibly of a different kind, e.g., to
+//! promote a SQLite database to PostgreSQL or to snapshot PostgreSQL tables into SQLite.
+//!
+//! The structure of the source table (its columns, primary key, unique constraints, and indexes)
+//! is read using [TableSchema::from_pool()], and the table is created in the destination database
+//! using type names that are understood by the destination's [DbKind]. Foreign key constraints
+//! are not copied, since the tables they refer to may not exist in the destination database. Rows
+//! are then read from the source one page at a time (see [crate::paginate]), sorted by primary key
+//! (or all at once if the table has no primary key), and inserted into the destination using
+//! [DbQuery::bulk_insert()]. Note that partial index conditions are copied as they are, and so
+//! must be valid SQL for both kinds of database, and that the copy is not atomic (see
+//! [copy_table()]).
+
+use crate::{
+    core::{DbError, DbQuery},
+    db_kind::{DbKind, DbType},
+    db_value::{DbRow, DbRows, DbValue},
+    paginate::{Cursor, EXPORT_PAGE_SIZE, fetch_page},
+    schema::{SchemaChange, TableSchema, apply_schema_changes},
+    select::Select,
+};
+
+/// What to do when copying a table to a database in which the destination table already exists.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IfExists {
+    /// Fail without copying anything.
+    #[default]
+    Fail,
+    /// Drop the existing table and recreate it.
+    Replace,
+    /// Insert the copied rows into the existing table.
+    Append,
+}
+
+/// Options controlling how a table is copied.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CopyOptions {
+    /// The name of the table in the destination database, if different from the source table.
+    pub destination: Option<String>,
+    pub if_exists: IfExists,
+    /// The number of rows read from the source and inserted into the destination at a time.
+    pub batch_size: u64,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self {
+            destination: None,
+            if_exists: IfExists::default(),
+            batch_size: EXPORT_PAGE_SIZE,
+        }
+    }
+}
+
+impl CopyOptions {
+    /// Copy the table to a destination table with the given name.
+    pub fn destination(mut self, table: &str) -> Self {
+        self.destination = Some(table.to_string());
+        self
+    }
+
+    /// Use the given [IfExists] behaviour when the destination table already exists.
+    pub fn if_exists(mut self, if_exists: IfExists) -> Self {
+        self.if_exists = if_exists;
+        self
+    }
+
+    /// Copy the given number of rows at a time. Note that the rows of a table without a primary
+    /// key are all copied at once.
+    pub fn batch_size(mut self, batch_size: u64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+}
+
+/// Copy the given table from the source database to the destination database, and return the
+/// number of rows copied. The rows are inserted in a separate transaction for each batch, so
+/// that the copy is not atomic: if it fails, a table created by this function is dropped again,
+/// but note that a table that was replaced (see [IfExists::Replace]) is not restored, and that
+/// the rows already appended to an existing table (see [IfExists::Append]) are not removed.
+pub async fn copy_table(
+    src: &impl DbQuery,
+    dst: &impl DbQuery,
+    table: &str,
+    options: &CopyOptions,
+) -> Result<u64, DbError> {
+    if options.batch_size == 0 {
+        return Err(DbError::InputError(
+            "Batch size must be positive".to_string(),
+        ));
+    }
+    let schema = TableSchema::from_pool(src, table)
+        .await?
+        .ok_or(DbError::InputError(format!("No such table: {table}")))?;
+    let destination = options.destination.as_deref().unwrap_or(table);
+
+    let exists = dst.table_exists(destination).await?;
+    match (exists, options.if_exists) {
+        (true, IfExists::Fail) => {
+            return Err(DbError::InputError(format!(
+                "Table '{destination}' already exists in the destination database"
+            )));
+        }
+        (true, IfExists::Replace) => dst.drop_table(destination).await?,
+        _ => (),
+    };
+    let created = !exists || options.if_exists == IfExists::Replace;
+    if created {
+        let schema = {
+            let (src_kind, dst_kind) = (src.kind(), dst.kind());
+            destination_schema(&schema, destination, &*src_kind, &*dst_kind)?
+        };
+        apply_schema_changes(dst, &[SchemaChange::AddTable(schema)]).await?;
+    }
+
+    let result = copy_rows(src, dst, &schema, destination, options).await;
+    if result.is_err() && created {
+        // Report the error that caused the copy to fail rather than any error from dropping the
+        // partially filled table:
+        let _ = dst.drop_table(destination).await;
+    }
+    result
+}
+
+// Copy the rows of the table with the given schema from the source database to the given
+// destination table, which must already exist, and return the number of rows copied.
+async fn copy_rows(
+    src: &impl DbQuery,
+    dst: &impl DbQuery,
+    schema: &TableSchema,
+    destination: &str,
+    options: &CopyOptions,
+) -> Result<u64, DbError> {
+    let dst_columns = dst.columns(destination).await?;
+    let dst_types = {
+        let dst_kind = dst.kind();
+        let mut dst_types = vec![];
+        for column in &schema.columns {
+            let sql_type = dst_columns
+                .get(&column.name)
+                .ok_or(DbError::InputError(format!(
+                    "No column '{}' in table '{destination}' in the destination database",
+                    column.name
+                )))?;
+            dst_types.push(dst_kind.db_type(sql_type)?);
+        }
+        dst_types
+    };
+    let columns = schema
+        .columns
+        .iter()
+        .map(|column| column.name.as_str())
+        .collect::<Vec<_>>();
+
+    let adapt_rows = |rows: DbRows| {
+        rows.content.into_iter().map(|row| {
+            row.into_iter()
+                .zip(&dst_types)
+                .map(|((column, value), db_type)| (column, adapt_value(value, db_type)))
+                .collect::<DbRow>()
+        })
+    };
+    let mut select = Select::new(&schema.name).columns(&columns);
+    if schema.primary_key.is_empty() {
+        // Without a primary key there is no consistent order in which to retrieve the rows one
+        // batch at a time, so we retrieve all of them at once:
+        let rows = src.select(&select).await?;
+        return dst
+            .bulk_insert(destination, &columns, adapt_rows(rows), |_| ())
+            .await;
+    }
+    for pkey in &schema.primary_key {
+        select = select.order_by(pkey);
+    }
+    let mut count = 0;
+    let mut cursor: Option<Cursor> = None;
+    loop {
+        let (rows, next) =
+            fetch_page(src, &select, options.batch_size, cursor.as_ref(), false).await?;
+        count += dst
+            .bulk_insert(destination, &columns, adapt_rows(rows), |_| ())
+            .await?;
+        match next {
+            Some(next) => cursor = Some(next),
+            None => return Ok(count),
+        }
+    }
+}
+
+// Generate the schema of the table, with the given name, to which a table with the given schema
+// is to be copied, using column types that are understood by the destination kind of database,
+// and without foreign key constraints.
+fn destination_schema(
+    schema: &TableSchema,
+    destination: &str,
+    src_kind: &dyn DbKind,
+    dst_kind: &dyn DbKind,
+) -> Result<TableSchema, DbError> {
+    let mut dst_schema = TableSchema {
+        name: destination.to_string(),
+        foreign_keys: vec![],
+        ..schema.clone()
+    };
+    for column in dst_schema.columns.iter_mut() {
+        if dst_kind.db_type(&column.sql_type).is_ok() {
+            continue;
+        }
+        // Fall back to the generic name for the type of the column in the source database:
+        let sql_type = match src_kind.db_type(&column.sql_type) {
+            Ok(DbType::Null(_)) | Ok(DbType::Text(_)) => "text",
+            Ok(DbType::Boolean(_)) => "boolean",
+            Ok(DbType::I16(_)) | Ok(DbType::SmallInteger(_)) => "smallint",
+            Ok(DbType::Integer(_)) => "integer",
+            Ok(DbType::BigInteger(_)) => "bigint",
+            Ok(DbType::Real(_)) => "real",
+            Ok(DbType::BigReal(_)) => "double precision",
+            Ok(DbType::Numeric(_)) => "numeric",
+            Err(_) => {
+                return Err(DbError::InputError(format!(
+                    "Column '{}' has type '{}', which is not supported by {dst_kind}",
+                    column.name, column.sql_type
+                )));
+            }
+        };
+        column.sql_type = sql_type.to_string();
+    }
+    if destination != schema.name {
+        for index in dst_schema.indexes.iter_mut() {
+            index.table = destination.to_string();
+            index.name = match index.name.contains(&schema.name) {
+                true => index.name.replacen(&schema.name, destination, 1),
+                false => format!("{destination}_{}", index.name),
+            };
+        }
+    }
+    Ok(dst_schema)
+}
+
+// Adapt the given value, read from the source database, to the given type of the destination
+// column. Booleans are stored as integers in SQLite, so they are converted back and forth.
+fn adapt_value(value: DbValue, db_type: &DbType) -> DbValue {
+    match (db_type, &value) {
+        (DbType::Boolean(_), DbValue::SmallInteger(_))
+        | (DbType::Boolean(_), DbValue::Integer(_))
+        | (DbType::Boolean(_), DbValue::BigInteger(_)) => {
+            DbValue::Boolean(value.as_i64().unwrap_or_default() != 0)
+        }
+        (DbType::Boolean(_), _) => value,
+        (_, DbValue::Boolean(flag)) => DbValue::BigInteger(*flag as i64),
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{any::AnyPool, filter::Filter, index::Index};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_copy_table() {
+        #[cfg(feature = "rusqlite")]
+        copy_table_between(":memory:", ":memory:").await;
+        #[cfg(all(feature = "rusqlite", feature = "tokio-postgres"))]
+        {
+            copy_table_between(":memory:", "postgresql:///rltbl_db").await;
+            copy_table_between("postgresql:///rltbl_db", ":memory:").await;
+        }
+        #[cfg(feature = "libsql")]
+        copy_table_between(":memory:", ":memory:").await;
+    }
+
+    // Get the rows of the given table as strings, with booleans rendered as integers.
+    async fn get_rows(pool: &AnyPool, table: &str) -> Vec<Vec<String>> {
+        let rows = pool
+            .select(&Select::new(table).order_by("id"))
+            .await
+            .unwrap();
+        rows.iter()
+            .map(|row| {
+                row.values()
+                    .map(|value| adapt_value(value.clone(), &DbType::Text("".into())).to_string())
+                    .collect()
+            })
+            .collect()
+    }
+
+    async fn copy_table_between(src_url: &str, dst_url: &str) {
+        let src = AnyPool::connect(src_url).await.unwrap();
+        src.memory_caches().clear_meta_cache();
+        let dst = AnyPool::connect(dst_url).await.unwrap();
+        src.drop_table("test_copy_src").await.unwrap();
+        dst.drop_table("test_copy_dst").await.unwrap();
+        src.execute_batch(
+            r#"CREATE TABLE test_copy_src (
+                 id BIGINT PRIMARY KEY,
+                 name VARCHAR(20) NOT NULL,
+                 active BOOLEAN,
+                 score DOUBLE PRECISION
+               );
+               INSERT INTO test_copy_src VALUES
+                 (1, 'a', TRUE, 1.5), (2, 'b', FALSE, NULL), (3, 'c', NULL, 2.5)"#,
+        )
+        .await
+        .unwrap();
+        src.create_index(&Index::new(
+            "test_copy_src_name_idx",
+            "test_copy_src",
+            &["name"],
+        ))
+        .await
+        .unwrap();
+
+        // Copy the table, two rows at a time:
+        let options = CopyOptions::default()
+            .destination("test_copy_dst")
+            .batch_size(2);
+        assert_eq!(
+            copy_table(&src, &dst, "test_copy_src", &options)
+                .await
+                .unwrap(),
+            3
+        );
+        let src_schema = TableSchema::from_pool(&src, "test_copy_src")
+            .await
+            .unwrap()
+            .unwrap();
+        let dst_schema = TableSchema::from_pool(&dst, "test_copy_dst")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(dst_schema.columns, src_schema.columns);
+        assert_eq!(dst_schema.primary_key, ["id"]);
+        assert_eq!(
+            dst_schema.indexes,
+            [Index::new(
+                "test_copy_dst_name_idx",
+                "test_copy_dst",
+                &["name"]
+            )]
+        );
+        assert_eq!(
+            get_rows(&dst, "test_copy_dst").await,
+            get_rows(&src, "test_copy_src").await
+        );
+
+        // The destination table already exists:
+        assert!(
+            copy_table(&src, &dst, "test_copy_src", &options)
+                .await
+                .is_err()
+        );
+        dst.delete_where("test_copy_dst", &Filter::gt("id", 1_i64))
+            .await
+            .unwrap();
+        let options = options.if_exists(IfExists::Replace);
+        assert_eq!(
+            copy_table(&src, &dst, "test_copy_src", &options)
+                .await
+                .unwrap(),
+            3
+        );
+        assert_eq!(get_rows(&dst, "test_copy_dst").await.len(), 3);
+        dst.delete_where("test_copy_dst", &Filter::gt("id", 1_i64))
+            .await
+            .unwrap();
+        src.delete_where("test_copy_src", &Filter::eq("id", 1_i64))
+            .await
+            .unwrap();
+        let options = options.if_exists(IfExists::Append);
+        assert_eq!(
+            copy_table(&src, &dst, "test_copy_src", &options)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(get_rows(&dst, "test_copy_dst").await.len(), 3);
+
+        assert!(
+            copy_table(&src, &dst, "test_copy_missing", &options)
+                .await
+                .is_err()
+        );
+
+        // A table created by a copy that fails is dropped again:
+        if !src_url.starts_with("postgresql://") {
+            src.execute_batch("INSERT INTO test_copy_src VALUES (4, 'd', NULL, 'not a number')")
+                .await
+                .unwrap();
+            let options = options.if_exists(IfExists::Replace).batch_size(1);
+            assert!(
+                copy_table(&src, &dst, "test_copy_src", &options)
+                    .await
+                    .is_err()
+            );
+            assert!(!dst.table_exists("test_copy_dst").await.unwrap());
+        }
+
+        // Clean up:
+        src.drop_table("test_copy_src").await.unwrap();
+        dst.drop_table("test_copy_dst").await.unwrap();
+    }
+}
@@ -1,17 +1,25 @@
 //! # rltbl/rltbl_db
 
 use crate::{
+    alter,
     any::AnyPool,
     cache::{
-        CachingStrategy, MemoryQueryCacheKey, MemoryQueryCacheValue, QUERY_CACHE_TABLE,
-        clear_cache_for_affected_tables, ensure_cache_tables_exist,
-        ensure_caching_triggers_exist_for_table, ensure_caching_triggers_exist_for_view,
-        exists_in_meta_cache, get_memory_query_cache, get_meta_cache, update_cached_views,
-        update_last_verified,
+        CacheCounters, CacheEncoding, CacheEntry, CacheStats, CachingStrategy, MemoryCacheConfig,
+        MemoryCaches, MemoryQueryCacheKey, QUERY_CACHE_TABLE, QUERY_CACHE_TABLES_TABLE,
+        clear_cache_for_affected_tables, decode_binary_value, encode_binary_value,
+        ensure_cache_tables_exist, ensure_caching_triggers_exist_for_table,
+        ensure_caching_triggers_exist_for_view, ensure_notify_triggers_exist, get_cache_entries,
+        get_cache_stats, get_source_tables, query_cache_parameters, record_query_cache_keys,
+        update_cached_views, update_last_verified, warm_query_cache,
     },
     db_kind::DbKind,
-    db_value::{ColumnMap, DbParams, DbRow, DbRows, IntoDbParams, IntoDbRows},
+    db_value::{ColumnMap, DbParams, DbRow, DbRows, DbValue, IntoDbParams, IntoDbRows},
+    filter::{self, Filter},
+    index::{self, Index},
+    paginate::{self, Cursor, Page},
     parse::get_accessed_tables,
+    select::Select,
+    shared::UpsertOptions,
 };
 
 use async_trait::async_trait;
@@ -20,8 +28,10 @@ use std::{
     collections::HashSet,
     fmt::Display,
     future::Future,
+    io::{Read, Write},
     marker::Sync,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 /// Defines the various error types that can be generated by rltbl_db.
@@ -86,6 +96,14 @@ pub trait DbQuery: Sync {
     /// Get the underlying database pool
     fn pool(&self) -> AnyPool;
 
+    /// Get the in-memory caches used by this pool, which are shared with every other pool that
+    /// is connected to the same database (see [MemoryCaches]).
+    fn memory_caches(&self) -> Arc<MemoryCaches>;
+
+    /// Get the counters describing the use of the query cache by this pool (see
+    /// [DbQuery::cache_stats()]). The counters are shared by all clones of the pool.
+    fn cache_counters(&self) -> Arc<CacheCounters>;
+
     /// Set the caching strategy.
     fn set_caching_strategy(&mut self, strategy: &CachingStrategy);
 
@@ -109,6 +127,90 @@ pub trait DbQuery: Sync {
     /// Returns true if the cache-aware-query option is currently on.
     fn get_cache_aware_query(&self) -> bool;
 
+    /// Set the time-to-live of the results cached by [DbQuery::cache()] and
+    /// [DbQuery::cache_tables()]. Cached results that were retrieved from the database longer ago
+    /// than this are refreshed, which is useful when tables may be edited by other processes that
+    /// bypass the cache maintenance done by this crate. Note that the cache tables used by the
+    /// non-memory [CachingStrategy] variants record times to the second, so for those the
+    /// time-to-live is rounded up to the second. If no time-to-live is set (the default), cached
+    /// results are kept until an edit invalidates them.
+    fn set_cache_ttl(&mut self, ttl: Option<Duration>);
+
+    /// Get the current time-to-live of cached results, if any (see [DbQuery::set_cache_ttl()]).
+    fn get_cache_ttl(&self) -> Option<Duration>;
+
+    /// Set the encoding in which the results cached by the non-memory [CachingStrategy]
+    /// variants are stored in the query cache table. The default is [CacheEncoding::Json].
+    fn set_cache_encoding(&mut self, encoding: CacheEncoding);
+
+    /// Get the current encoding of cached results (see [DbQuery::set_cache_encoding()]).
+    fn get_cache_encoding(&self) -> CacheEncoding;
+
+    /// Get the statistics describing the use of the query cache by this pool and its clones since
+    /// they were last reset.
+    async fn cache_stats(&self) -> Result<CacheStats, DbError> {
+        get_cache_stats(&self.pool()).await
+    }
+
+    /// Reset the statistics describing the use of the query cache (see [DbQuery::cache_stats()]).
+    fn reset_cache_stats(&self) {
+        self.cache_counters().reset();
+    }
+
+    /// List the entries currently in the query cache used by the current [CachingStrategy],
+    /// along with their sizes.
+    async fn cache_entries(&self) -> Result<Vec<CacheEntry>, DbError> {
+        get_cache_entries(&self.pool()).await
+    }
+
+    /// Populate the query cache used by the current [CachingStrategy] with the results of the
+    /// given SQL commands and their parameters, e.g., so that the first users of a newly started
+    /// application do not find the cache empty. The commands are run concurrently, except that
+    /// the first command to read from a given table is run on its own. Since they are run on
+    /// different connections from the pool, all of its connections must share the same database:
+    /// in particular, this is not the case for an in-memory SQLite database (`:memory:`), each of
+    /// whose connections has a database of its own.
+    async fn warm_cache(&self, queries: &[(&str, DbParams)]) -> Result<(), DbError> {
+        let strategy = self.get_caching_strategy();
+        let mut tables_queries = vec![];
+        for (sql, params) in queries {
+            let tables = get_tables_to_cache(&strategy, sql)?;
+            tables_queries.push((tables, sql.to_string(), params.clone()));
+        }
+        warm_query_cache(&self.pool(), tables_queries).await
+    }
+
+    /// Write the keys of the entries in the memory query cache (see [CachingStrategy::Memory])
+    /// to the given writer as JSON, e.g., on shutdown, so that the cache can later be warmed with
+    /// them using [DbQuery::load_memory_cache_keys()]. The entries cached by the other strategies
+    /// are kept in the database, and so do not need to be saved.
+    async fn save_memory_cache_keys(
+        &self,
+        writer: &mut (impl Write + Send),
+    ) -> Result<(), DbError> {
+        let keys = self
+            .memory_caches()
+            .query_cache()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        serde_json::to_writer(writer, &keys)
+            .map_err(|err| DbError::SerdeError(format!("Error writing cache keys: {err}")))
+    }
+
+    /// Read the keys written by [DbQuery::save_memory_cache_keys()] from the given reader, and
+    /// warm the query cache used by the current [CachingStrategy] with the queries that they
+    /// identify (see [DbQuery::warm_cache()]).
+    async fn load_memory_cache_keys(&self, reader: impl Read + Send) -> Result<(), DbError> {
+        let keys: Vec<MemoryQueryCacheKey> = serde_json::from_reader(reader)
+            .map_err(|err| DbError::SerdeError(format!("Error reading cache keys: {err}")))?;
+        let queries = keys
+            .into_iter()
+            .map(|key| (key.tables.into_iter().collect(), key.statement, key.params))
+            .collect();
+        warm_query_cache(&self.pool(), queries).await
+    }
+
     /// Execute the given SQL command with the given parameters, returning a vector of rows.
     /// If the result of the command exists in the query cache for the given tables, get the
     /// value from there instead of from the tables themselves, in accordance with the given
@@ -118,19 +220,23 @@ pub trait DbQuery: Sync {
         sql: &str,
         params: impl IntoDbParams + Send + Copy + Sync,
     ) -> Result<DbRows, DbError> {
-        match self.get_caching_strategy() {
-            CachingStrategy::None => self.cache_tables(&[], sql, params).await,
-            _ => {
-                let tables_read = get_accessed_tables(sql)?;
-                let tables_read: Vec<_> = tables_read.iter().map(|s| s.as_str()).collect();
-                match tables_read.is_empty() {
-                    false => self.cache_tables(&tables_read, sql, params).await,
-                    true => Err(DbError::InputError(format!(
-                        "No tables are read from in SQL: {sql}"
-                    ))),
-                }
-            }
-        }
+        let tables = get_tables_to_cache(&self.get_caching_strategy(), sql)?;
+        let tables = tables.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        self.cache_tables(&tables, sql, params).await
+    }
+
+    /// Similar to [DbQuery::cache()], but refresh the cached result if it was retrieved from the
+    /// database longer ago than the given time-to-live, overriding [DbQuery::get_cache_ttl()].
+    async fn cache_with_ttl(
+        &self,
+        sql: &str,
+        params: impl IntoDbParams + Send + Copy + Sync,
+        ttl: Duration,
+    ) -> Result<DbRows, DbError> {
+        let tables = get_tables_to_cache(&self.get_caching_strategy(), sql)?;
+        let tables = tables.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        self.cache_tables_with_ttl(&tables, sql, params, Some(ttl))
+            .await
     }
 
     /// Similar to [DbQuery::cache()]. This version accepts an explicit list of tables, which
@@ -141,6 +247,21 @@ pub trait DbQuery: Sync {
         sql: &str,
         params: impl IntoDbParams + Send + Copy + Sync,
     ) -> Result<DbRows, DbError> {
+        self.cache_tables_with_ttl(tables, sql, params, self.get_cache_ttl())
+            .await
+    }
+
+    /// Similar to [DbQuery::cache_tables()], but refresh the cached result if it was retrieved
+    /// from the database longer ago than the given time-to-live, if any, overriding
+    /// [DbQuery::get_cache_ttl()].
+    async fn cache_tables_with_ttl(
+        &self,
+        tables: &[&str],
+        sql: &str,
+        params: impl IntoDbParams + Send + Copy + Sync,
+        ttl: Option<Duration>,
+    ) -> Result<DbRows, DbError> {
+        let start = Instant::now();
         let db_cache = async |tables: &[&str],
                               sql: &str,
                               params: &DbParams|
@@ -151,75 +272,122 @@ pub trait DbQuery: Sync {
             // tables.
             let prefix = self.kind().param_prefix().to_string();
             let cache_sql = format!(
-                r#"SELECT {prefix}1||rtrim(ltrim("value", '['), ']')||{prefix}2 AS "value"
+                r#"SELECT {prefix}1||rtrim(ltrim("value", '['), ']')||{prefix}2 AS "value",
+                         {binary_value} AS "binary_value"
                        FROM "{QUERY_CACHE_TABLE}"
-                       WHERE "tables" = {prefix}3
-                       AND "statement" = {prefix}4
-                       AND "parameters" = {prefix}5
+                       WHERE "statement" = {prefix}3
+                       AND "parameters" = {prefix}4
                        LIMIT 1"#,
+                binary_value = self.kind().binary_to_hex_sql(r#""binary_value""#),
             );
-            let tables_param = format!(
-                "[{}]",
-                tables
-                    .iter()
-                    .map(|table| format!("\"{table}\""))
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            );
-            let params_param = match params {
-                DbParams::None => "[]".to_string(),
-                DbParams::Positional(params) => {
-                    let params = params.iter().map(|p| p.into()).collect::<Vec<String>>();
-                    format!("[{}]", params.join(", "))
-                }
-            };
-            let cache_params = &["[", "]", &tables_param, sql, &params_param];
-
-            let strings = {
-                let rows = self.query_no_cache_clean(&cache_sql, cache_params).await?;
-                let strings = rows
-                    .iter()
-                    .map(|row| match row.values().nth(0) {
-                        Some(value) => Ok(value.into()),
-                        None => Err(DbError::DataError("Empty row".to_owned())),
-                    })
-                    .collect::<Vec<_>>();
-                let strings: Result<Vec<String>, DbError> = strings.into_iter().collect();
-                strings?
+            let params_param = query_cache_parameters(params);
+            let cache_params = &["[", "]", sql, &params_param];
+
+            // Remove the cached entry, if any, if it has expired. Since the times in the cache
+            // table are recorded to the second, the time-to-live is rounded up to the second. An
+            // entry with no time of caching was created by an earlier version of this crate:
+            if let Some(ttl) = ttl {
+                let expire_sql = format!(
+                    r#"DELETE FROM "{QUERY_CACHE_TABLE}"
+                       WHERE "statement" = {prefix}1
+                       AND "parameters" = {prefix}2
+                       AND COALESCE("cached_at", "last_verified")
+                           <= CAST({epoch_now} AS BIGINT) - {ttl}"#,
+                    epoch_now = self.kind().get_epoch_time_sql(),
+                    ttl = ttl.as_millis().div_ceil(1000),
+                );
+                self.execute_no_cache_clean(&expire_sql, [sql, &params_param])
+                    .await?;
+            }
+
+            // The cached rows are stored either as JSON in the "value" column or in a binary
+            // encoding in the "binary_value" column, depending on the encoding in use when they
+            // were cached (see [CacheEncoding]):
+            let rows = self.query_no_cache_clean(&cache_sql, cache_params).await?;
+            let cached_rows = match rows.first() {
+                None => None,
+                Some(row) => match (row.get("value"), row.get("binary_value")) {
+                    (Some(DbValue::Null) | None, Some(DbValue::Null) | None) => {
+                        return Err(DbError::DataError(format!(
+                            "No cached value found in row: {row:?}"
+                        )));
+                    }
+                    (Some(DbValue::Null) | None, Some(binary_value)) => {
+                        Some(decode_binary_value(&binary_value.to_string())?)
+                    }
+                    (Some(values), _) => {
+                        let values: String = values.into();
+                        let db_rows: Vec<DbRow> = serde_json::from_str(&values).map_err(|err| {
+                            DbError::DataError(format!(
+                                "Error serializing values '{values}': {err}"
+                            ))
+                        })?;
+                        Some(db_rows)
+                    }
+                },
             };
-            match strings.first() {
-                Some(values) => {
-                    let db_rows: Vec<DbRow> = serde_json::from_str(values).map_err(|err| {
-                        DbError::DataError(format!("Error serializing values '{values}': {err}"))
-                    })?;
+            match cached_rows {
+                Some(db_rows) => {
                     // Only views need to be verified every time they are accessed. Tables
                     // do not because they do not have any dependencies.
                     if which_are_views(&self.pool(), tables).await?.len() > 0 {
                         update_last_verified(&self.pool(), tables, sql, &params).await?;
                     }
+                    self.cache_counters().record_hit(start.elapsed());
                     Ok(DbRows { content: db_rows })
                 }
                 None => {
                     let db_rows = self.query_no_cache_clean(sql, params).await?;
-                    let rows_as_string = {
-                        let mut rows_as_string = vec![];
-                        for db_row in db_rows.iter() {
-                            let db_row = serde_json::to_string(db_row).map_err(|err| {
-                                DbError::DataError(format!("Invalid data ({err}): {db_row:?}"))
-                            })?;
-                            rows_as_string.push(db_row);
+                    let (value_column, value_sql, value) = match self.get_cache_encoding() {
+                        CacheEncoding::Json => {
+                            let mut rows_as_string = vec![];
+                            for db_row in db_rows.iter() {
+                                let db_row = serde_json::to_string(db_row).map_err(|err| {
+                                    DbError::DataError(format!("Invalid data ({err}): {db_row:?}"))
+                                })?;
+                                rows_as_string.push(db_row);
+                            }
+                            (
+                                "value",
+                                format!("{prefix}3"),
+                                format!("[{}]", rows_as_string.join(", ")),
+                            )
                         }
-                        format!("[{}]", rows_as_string.join(", "))
+                        encoding => (
+                            "binary_value",
+                            self.kind().hex_to_binary_sql(&format!("{prefix}3")),
+                            encode_binary_value(
+                                &db_rows,
+                                encoding == CacheEncoding::CompressedCbor,
+                            )?,
+                        ),
                     };
                     let insert_sql = format!(
                         r#"INSERT INTO "{QUERY_CACHE_TABLE}"
-                           ("tables", "statement", "parameters", "value")
-                           VALUES ({prefix}1, {prefix}2, {prefix}3, {prefix}4)"#,
-                        prefix = self.kind().param_prefix(),
+                           ("statement", "parameters", "{value_column}", "cached_at")
+                           VALUES ({prefix}1, {prefix}2, {value_sql}, {epoch_now})"#,
+                        epoch_now = self.kind().get_epoch_time_sql(),
                     );
-                    let insert_params = [&tables_param, sql, &params_param, &rows_as_string];
+                    let insert_params = [sql, &params_param, &value];
                     self.execute_no_cache_clean(&insert_sql, &insert_params)
                         .await?;
+
+                    // Record the tables that the entry depends on:
+                    let insert_sql = format!(
+                        r#"INSERT INTO "{QUERY_CACHE_TABLES_TABLE}"
+                           ("statement", "parameters", "table")
+                           VALUES ({prefix}1, {prefix}2, {prefix}3)
+                           ON CONFLICT DO NOTHING"#,
+                    );
+                    for table in tables {
+                        self.execute_no_cache_clean(&insert_sql, [sql, &params_param, table])
+                            .await?;
+                    }
+                    if self.get_caching_strategy() == CachingStrategy::RowTrigger {
+                        record_query_cache_keys(&self.pool(), tables, sql, params, &params_param)
+                            .await?;
+                    }
+                    self.cache_counters().record_miss(start.elapsed());
                     Ok(db_rows)
                 }
             }
@@ -228,28 +396,11 @@ pub trait DbQuery: Sync {
         let mem_cache = async |tables: &[&str],
                                sql: &str,
                                params: &DbParams,
-                               cache_size: usize|
+                               config: &MemoryCacheConfig|
                -> Result<DbRows, DbError> {
             let params = &params.into_db_params();
-            let mem_key = MemoryQueryCacheKey {
-                tables: format!(
-                    "[{}]",
-                    tables
-                        .iter()
-                        .map(|table| format!("\"{table}\""))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                ),
-                statement: sql.to_string(),
-                parameters: format!("{params:?}"),
-            };
-            let cached_rows = {
-                let cache = get_memory_query_cache()?;
-                match cache.get(&mem_key) {
-                    Some(mem_value) => Some(mem_value.content.to_vec()),
-                    None => None,
-                }
-            };
+            let mem_key = MemoryQueryCacheKey::new(tables, sql, params);
+            let cached_rows = self.memory_caches().get_query_result(&mem_key, ttl)?;
             match cached_rows {
                 Some(db_rows) => {
                     // Only views need to be verified every time they are accessed. Tables
@@ -257,32 +408,29 @@ pub trait DbQuery: Sync {
                     if which_are_views(&self.pool(), tables).await?.len() > 0 {
                         update_last_verified(&self.pool(), tables, sql, &params).await?;
                     }
+                    self.cache_counters().record_hit(start.elapsed());
                     Ok(DbRows { content: db_rows })
                 }
                 None => {
+                    // If the entries for any of the tables that the result depends on (including
+                    // the source tables of views) are invalidated while the query is running,
+                    // the result may be stale, and is not cached:
+                    let mut dependencies = get_source_tables(&self.pool(), tables).await?;
+                    dependencies.extend(tables.iter().map(|table| table.to_string()));
+                    let generations = self.memory_caches().get_generations(&dependencies);
+                    // The cost of the entry, used to decide which entries to evict, is the time
+                    // taken by the query itself:
+                    let query_start = Instant::now();
                     let db_rows = self.query_no_cache_clean(sql, params).await?;
-                    let mut cache = get_memory_query_cache()?;
-                    // If the number of entries exceeds the allowed cache size, remove the oldest
-                    // keys first.
-                    // TODO: We may want to do something smarter here. E.g., we could record the
-                    // length of time a query takes and/or the number of times it was requested
-                    // in order to determine which entries to delete (and/or the size of the
-                    // result).
-                    while cache.len() > cache_size {
-                        cache.shift_remove_index(0);
-                    }
-                    cache.insert(
+                    let evictions = self.memory_caches().insert_query_result(
                         mem_key,
-                        MemoryQueryCacheValue {
-                            content: db_rows.to_vec(),
-                            last_verified: SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .map_err(|err| {
-                                    DbError::DataError(format!("Error getting epoch time: {err}"))
-                                })?
-                                .as_millis(),
-                        },
-                    );
+                        db_rows.to_vec(),
+                        query_start.elapsed(),
+                        config,
+                        Some(&generations),
+                    )?;
+                    self.cache_counters().record_evictions(evictions);
+                    self.cache_counters().record_miss(start.elapsed());
                     Ok(db_rows)
                 }
             }
@@ -291,6 +439,7 @@ pub trait DbQuery: Sync {
         match self.get_caching_strategy() {
             CachingStrategy::None => {
                 let rows = self.query_no_cache_clean(sql, params).await?;
+                self.cache_counters().record_miss(start.elapsed());
                 Ok(rows)
             }
             CachingStrategy::TruncateAll | CachingStrategy::Truncate => {
@@ -299,7 +448,7 @@ pub trait DbQuery: Sync {
                 let rows = db_cache(tables, sql, &params.into_db_params()).await?;
                 Ok(rows)
             }
-            CachingStrategy::Trigger => {
+            CachingStrategy::Trigger | CachingStrategy::RowTrigger => {
                 let views = which_are_views(&self.pool(), tables)
                     .await?
                     .into_iter()
@@ -319,9 +468,12 @@ pub trait DbQuery: Sync {
                 let rows = db_cache(&tables, sql, &params.into_db_params()).await?;
                 Ok(rows)
             }
-            CachingStrategy::Memory(cache_size) => {
+            CachingStrategy::Memory(config) => {
+                if self.memory_caches().is_listening() {
+                    ensure_notify_triggers_exist(&self.pool(), tables).await?;
+                }
                 update_cached_views(&self.pool(), tables).await?;
-                let rows = mem_cache(tables, sql, &params.into_db_params(), cache_size).await?;
+                let rows = mem_cache(tables, sql, &params.into_db_params(), &config).await?;
                 Ok(rows)
             }
         }
@@ -404,6 +556,15 @@ pub trait DbQuery: Sync {
     /// Sequentially execute a semicolon-delimited list of statements, without parameters.
     fn execute_batch(&self, sql: &str) -> impl Future<Output = Result<(), DbError>> + Send;
 
+    /// Like [DbQuery::execute_batch()], but if one of the statements fails, execute each of the
+    /// given recovery statements (e.g., ROLLBACK), ignoring any errors they cause, before
+    /// returning the error. All of the statements are executed using the same connection.
+    fn execute_batch_with_recovery(
+        &self,
+        sql: &str,
+        recovery: &[&str],
+    ) -> impl Future<Output = Result<(), DbError>> + Send;
+
     /// Execute the given SQL command, with the given parameters, returning a vector of rows.
     /// Implements [DbQuery::query()] for SQLite.
     async fn query(&self, sql: &str, params: impl IntoDbParams + Send) -> Result<DbRows, DbError> {
@@ -486,6 +647,66 @@ pub trait DbQuery: Sync {
         returning: &[&str],
     ) -> impl Future<Output = Result<DbRows, DbError>>;
 
+    /// Insert the rows produced by the given iterator into the given columns of the given table,
+    /// returning the number of rows inserted. Unlike [DbQuery::insert()], which generates
+    /// multi-row INSERT statements, this uses the fastest loading mechanism available for the
+    /// database, and is intended for loading large amounts of data. Values are converted to the
+    /// SQL types of the columns, and missing values are treated as NULL. The given `progress`
+    /// function is called with the number of rows inserted so far every
+    /// [BULK_INSERT_BATCH_SIZE](crate::shared::BULK_INSERT_BATCH_SIZE) rows, as well as once all
+    /// of the rows have been inserted. Either all of the rows are inserted or, in case of an
+    /// error, none of them are.
+    fn bulk_insert(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl Iterator<Item = DbRow> + Send,
+        progress: impl FnMut(u64) + Send,
+    ) -> impl Future<Output = Result<u64, DbError>>;
+
+    /// Like [DbQuery::upsert()], but the conflict target and the action taken in case of a
+    /// conflict are determined by the given [UpsertOptions].
+    fn upsert_with(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl IntoDbRows,
+        options: &UpsertOptions,
+    ) -> impl Future<Output = Result<(), DbError>>;
+
+    /// Like [DbQuery::upsert_with()], but in addition this function also returns the data that
+    /// was upserted for the columns included in `returning`, or all of the upserted data if
+    /// `returning` is an empty list. Note that rows that were left unchanged due to a conflict
+    /// are not returned.
+    fn upsert_with_returning(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl IntoDbRows,
+        options: &UpsertOptions,
+        returning: &[&str],
+    ) -> impl Future<Output = Result<DbRows, DbError>>;
+
+    /// Delete the given rows from the given table. The table should have a primary key and the
+    /// columns that are part of the primary key should be present within each input row. The
+    /// primary key column values will be used to identify the rows to delete, and any other
+    /// values in the input rows will be ignored.
+    fn delete(
+        &self,
+        table: &str,
+        rows: impl IntoDbRows,
+    ) -> impl Future<Output = Result<(), DbError>>;
+
+    /// Like [DbQuery::delete()], but in addition this function also returns the data that was
+    /// deleted for the columns included in `returning`, or all of the deleted data if
+    /// `returning` is an empty list.
+    fn delete_returning(
+        &self,
+        table: &str,
+        rows: impl IntoDbRows,
+        returning: &[&str],
+    ) -> impl Future<Output = Result<DbRows, DbError>>;
+
     /// Check whether the given table exists in the database.
     async fn table_exists(&self, table: &str) -> Result<bool, DbError> {
         Ok(which_are_tables(&self.pool(), &[table]).await?.len() == 1)
@@ -504,6 +725,114 @@ pub trait DbQuery: Sync {
 
     /// Drop the given view from the database.
     fn drop_view(&self, view: &str) -> impl Future<Output = Result<(), DbError>> + Send;
+
+    /// Execute the given [Select] statement, returning a vector of rows.
+    async fn select(&self, select: &Select) -> Result<DbRows, DbError> {
+        let (sql, params) = select.render(&self.pool()).await?;
+        self.query(&sql, params).await
+    }
+
+    /// Like [DbQuery::select()], but using the query cache in the same way as
+    /// [DbQuery::cache()]. The tables read by the statement are known, so the SQL does not need
+    /// to be parsed in order to determine them.
+    async fn cache_select(&self, select: &Select) -> Result<DbRows, DbError> {
+        let (sql, params) = select.render(&self.pool()).await?;
+        self.cache_tables(&select.tables(), &sql, &params).await
+    }
+
+    /// Retrieve the page of at most `page_size` rows selected by the given [Select] that begins
+    /// at the given [Cursor], or the first page if no cursor is given, along with the total
+    /// number of rows selected and the cursor for the next page (see [paginate]).
+    async fn paginate(
+        &self,
+        select: &Select,
+        page_size: u64,
+        cursor: Option<&Cursor>,
+    ) -> Result<Page, DbError> {
+        paginate::paginate(&self.pool(), select, page_size, cursor).await
+    }
+
+    /// Set the columns of the given table to the given values in every row that satisfies the
+    /// given [Filter]. Unlike [DbQuery::update()], this does not require the primary keys of the
+    /// rows to be updated to be known beforehand.
+    async fn update_where(
+        &self,
+        table: &str,
+        values: &DbRow,
+        filter: &Filter,
+    ) -> Result<(), DbError> {
+        filter::update_where(&self.pool(), table, values, filter).await
+    }
+
+    /// Delete every row of the given table that satisfies the given [Filter].
+    async fn delete_where(&self, table: &str, filter: &Filter) -> Result<(), DbError> {
+        filter::delete_where(&self.pool(), table, filter).await
+    }
+
+    /// Add a column with the given name and SQL type to the given table.
+    async fn add_column(&self, table: &str, column: &str, sql_type: &str) -> Result<(), DbError> {
+        alter::add_column(&self.pool(), table, column, sql_type).await
+    }
+
+    /// Drop the given column from the given table, preserving the table's data and any indexes
+    /// that do not refer to the dropped column. For SQLite, this requires the table to be rebuilt
+    /// (see [alter]).
+    async fn drop_column(&self, table: &str, column: &str) -> Result<(), DbError> {
+        alter::drop_column(&self.pool(), table, column).await
+    }
+
+    /// Rename the given column of the given table.
+    async fn rename_column(
+        &self,
+        table: &str,
+        column: &str,
+        new_name: &str,
+    ) -> Result<(), DbError> {
+        alter::rename_column(&self.pool(), table, column, new_name).await
+    }
+
+    /// Change the SQL type of the given column of the given table, preserving the table's data
+    /// and indexes. For SQLite, this requires the table to be rebuilt (see [alter]).
+    async fn alter_column_type(
+        &self,
+        table: &str,
+        column: &str,
+        sql_type: &str,
+    ) -> Result<(), DbError> {
+        alter::alter_column_type(&self.pool(), table, column, sql_type).await
+    }
+
+    /// Add (if `not_null` is true) or remove (otherwise) a NOT NULL constraint on the given column
+    /// of the given table. For SQLite, this requires the table to be rebuilt (see [alter]).
+    async fn alter_column_not_null(
+        &self,
+        table: &str,
+        column: &str,
+        not_null: bool,
+    ) -> Result<(), DbError> {
+        alter::alter_column_not_null(&self.pool(), table, column, not_null).await
+    }
+
+    /// Rename the given table.
+    async fn rename_table(&self, table: &str, new_name: &str) -> Result<(), DbError> {
+        alter::rename_table(&self.pool(), table, new_name).await
+    }
+
+    /// Create the given [Index], if an index with the same name does not already exist.
+    async fn create_index(&self, index: &Index) -> Result<(), DbError> {
+        index::create_index(&self.pool(), index).await
+    }
+
+    /// Drop the index with the given name, if it exists.
+    async fn drop_index(&self, index: &str) -> Result<(), DbError> {
+        index::drop_index(&self.pool(), index).await
+    }
+
+    /// List the indexes that have been explicitly created on the given table (see
+    /// [index::list_indexes()]).
+    async fn list_indexes(&self, table: &str) -> Result<Vec<Index>, DbError> {
+        index::list_indexes(&self.pool(), table).await
+    }
 }
 
 /// Get the SQL code that is used to define the given view.
@@ -528,6 +857,23 @@ pub async fn get_view_sql(pool: &impl DbQuery, view: &str) -> Result<String, DbE
     Ok(view_sql)
 }
 
+// Get the tables read by the given SQL, under which its results are to be cached using the given
+// [CachingStrategy].
+fn get_tables_to_cache(strategy: &CachingStrategy, sql: &str) -> Result<Vec<String>, DbError> {
+    match strategy {
+        CachingStrategy::None => Ok(vec![]),
+        _ => {
+            let tables_read = get_accessed_tables(sql)?;
+            match tables_read.is_empty() {
+                false => Ok(tables_read.into_iter().collect()),
+                true => Err(DbError::InputError(format!(
+                    "No tables are read from in SQL: {sql}"
+                ))),
+            }
+        }
+    }
+}
+
 /// Return a list of the objects from the given list that are tables.
 pub async fn which_are_tables(
     pool: &impl DbQuery,
@@ -536,10 +882,11 @@ pub async fn which_are_tables(
     let mut tables = vec![];
     let mut unknowns = vec![];
     // Start by looking for the given objects in the meta cache:
+    let caches = pool.memory_caches();
     for object in objects {
-        if exists_in_meta_cache(&format!("{object}_TABLE"))? {
+        if caches.exists_in_meta_cache(&format!("{object}_TABLE")) {
             tables.push(object.to_string());
-        } else if !exists_in_meta_cache(&format!("{object}_VIEW"))? {
+        } else if !caches.exists_in_meta_cache(&format!("{object}_VIEW")) {
             unknowns.push(object.to_string());
         }
     }
@@ -555,8 +902,7 @@ pub async fn which_are_tables(
                 .get("table_name")
                 .ok_or(DbError::DataError("No table_name found in row".to_string()))?
                 .to_string();
-            let mut cache = get_meta_cache()?;
-            cache.insert(format!("{table}_TABLE"));
+            caches.meta_cache_mut().insert(format!("{table}_TABLE"));
             tables.push(table);
         }
 
@@ -569,8 +915,7 @@ pub async fn which_are_tables(
                 .get("view_name")
                 .ok_or(DbError::DataError("No view_name found in row".to_string()))?
                 .to_string();
-            let mut cache = get_meta_cache()?;
-            cache.insert(format!("{view}_VIEW"));
+            caches.meta_cache_mut().insert(format!("{view}_VIEW"));
         }
     }
     Ok(tables)
@@ -584,10 +929,11 @@ pub async fn which_are_views(
     let mut views = vec![];
     let mut unknowns = vec![];
     // Start by looking for the given objects in the meta cache:
+    let caches = pool.memory_caches();
     for object in objects {
-        if exists_in_meta_cache(&format!("{object}_VIEW"))? {
+        if caches.exists_in_meta_cache(&format!("{object}_VIEW")) {
             views.push(object.to_string());
-        } else if !exists_in_meta_cache(&format!("{object}_TABLE"))? {
+        } else if !caches.exists_in_meta_cache(&format!("{object}_TABLE")) {
             unknowns.push(object.to_string());
         }
     }
@@ -603,8 +949,7 @@ pub async fn which_are_views(
                 .get("view_name")
                 .ok_or(DbError::DataError("No view_name found in row".to_string()))?
                 .to_string();
-            let mut cache = get_meta_cache()?;
-            cache.insert(format!("{view}_VIEW"));
+            caches.meta_cache_mut().insert(format!("{view}_VIEW"));
             views.push(view);
         }
 
@@ -617,8 +962,7 @@ pub async fn which_are_views(
                 .get("table_name")
                 .ok_or(DbError::DataError("No table_name found in row".to_string()))?
                 .to_string();
-            let mut cache = get_meta_cache()?;
-            cache.insert(format!("{table}_TABLE"));
+            caches.meta_cache_mut().insert(format!("{table}_TABLE"));
         }
     }
     Ok(views)
@@ -1,11 +1,17 @@
 //! Code specific to supported database kinds.
 
 use crate::{
-    cache::{QUERY_CACHE_TABLE, TABLE_CACHE_TABLE},
+    cache::{
+        CACHE_NOTIFY_CHANNEL, QUERY_CACHE_KEYS_TABLE, QUERY_CACHE_TABLE, QUERY_CACHE_TABLES_TABLE,
+        TABLE_CACHE_TABLE,
+    },
     core::DbError,
     db_value::{DbValue, IntoDbValue, JsonValue},
+    index::Index,
+    migrate::{MIGRATIONS_LOCK_TABLE, MIGRATIONS_TABLE},
     params,
     parse::validate_table_name,
+    schema::{ForeignKey, TableSchema},
 };
 use rust_decimal::Decimal;
 use std::fmt::Display;
@@ -32,9 +38,23 @@ pub trait DbKind: Display {
     /// Get the prefix to use for parameters to queries that need to be bound.
     fn param_prefix(&self) -> &str;
 
+    /// Get the maximum number of parameters that can be bound to a single query.
+    fn max_params(&self) -> usize;
+
     /// Get the code needed to retrieve the current epoch time from the database.
     fn get_epoch_time_sql(&self) -> &str;
 
+    /// Get the SQL type used to store binary data.
+    fn binary_type(&self) -> &str;
+
+    /// Get the code needed to convert the given expression, which must evaluate to hexadecimal
+    /// text, to binary data.
+    fn hex_to_binary_sql(&self, expr: &str) -> String;
+
+    /// Get the code needed to convert the given expression, which must evaluate to binary data,
+    /// to hexadecimal text, or to NULL if it evaluates to NULL.
+    fn binary_to_hex_sql(&self, expr: &str) -> String;
+
     /// Generate the SQL and parameters needed to query the database's metadata for the names and
     /// types of the columns of the given table.
     fn columns_sql(&self, table: &str) -> (String, [DbValue; 1]);
@@ -43,12 +63,162 @@ pub trait DbKind: Display {
     /// key columns of the given table.
     fn primary_keys_sql(&self, table: &str) -> (String, [DbValue; 1]);
 
+    /// Generate the SQL and parameters needed to query the database's metadata for the names,
+    /// types, and nullability of the columns of the given table, in the order in which they
+    /// were defined. The query returns the columns "column_name", "data_type" (including the
+    /// precision and scale of numeric columns, if declared), and "not_null" (1 or 0).
+    fn table_columns_sql(&self, table: &str) -> (String, [DbValue; 1]);
+
+    /// Generate the SQL needed to query the database's metadata for the names of all of its
+    /// tables, in the column "table_name".
+    fn list_tables_sql(&self) -> String;
+
+    /// Generate the SQL needed to query the database's metadata for the names of all of its
+    /// views, in the column "view_name".
+    fn list_views_sql(&self) -> String;
+
+    /// Generate the SQL needed to create a table with the given schema (not including indexes).
+    fn create_table_sql(&self, schema: &TableSchema) -> String {
+        let quote = |columns: &[String]| {
+            columns
+                .iter()
+                .map(|column| format!(r#""{column}""#))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut definitions = schema
+            .columns
+            .iter()
+            .map(|column| {
+                let not_null = match column.not_null {
+                    true => " NOT NULL",
+                    false => "",
+                };
+                format!(r#""{}" {}{not_null}"#, column.name, column.sql_type)
+            })
+            .collect::<Vec<_>>();
+        if !schema.primary_key.is_empty() {
+            definitions.push(format!("PRIMARY KEY ({})", quote(&schema.primary_key)));
+        }
+        for columns in schema.unique_constraints.iter() {
+            definitions.push(format!("UNIQUE ({})", quote(columns)));
+        }
+        for foreign_key in schema.foreign_keys.iter() {
+            definitions.push(format!(
+                r#"FOREIGN KEY ({}) REFERENCES "{}" ({})"#,
+                quote(&foreign_key.columns),
+                foreign_key.foreign_table,
+                quote(&foreign_key.foreign_columns)
+            ));
+        }
+        format!(
+            r#"CREATE TABLE "{}" (
+                 {}
+               )"#,
+            schema.name,
+            definitions.join(",\n")
+        )
+    }
+
     /// Generate the SQL and parameters needed to drop the given table.
     fn drop_table_sql(&self, table: &str) -> String;
 
     /// Generate the SQL and parameters needed to drop the given view.
     fn drop_view_sql(&self, table: &str) -> String;
 
+    /// Generate the SQL needed to add a column with the given name and SQL type to the given
+    /// table.
+    fn add_column_sql(&self, table: &str, column: &str, sql_type: &str) -> String {
+        format!(r#"ALTER TABLE "{table}" ADD COLUMN "{column}" {sql_type}"#)
+    }
+
+    /// Generate the SQL needed to rename the given column of the given table.
+    fn rename_column_sql(&self, table: &str, column: &str, new_name: &str) -> String {
+        format!(r#"ALTER TABLE "{table}" RENAME COLUMN "{column}" TO "{new_name}""#)
+    }
+
+    /// Generate the SQL needed to rename the given table.
+    fn rename_table_sql(&self, table: &str, new_name: &str) -> String {
+        format!(r#"ALTER TABLE "{table}" RENAME TO "{new_name}""#)
+    }
+
+    /// Generate the SQL needed to drop the given column from the given table, or None if the
+    /// database cannot reliably do this in place, in which case the table must be rebuilt.
+    fn drop_column_sql(&self, table: &str, column: &str) -> Option<String>;
+
+    /// Generate the SQL needed to change the SQL type of the given column of the given table, or
+    /// None if the database cannot do this in place, in which case the table must be rebuilt.
+    fn alter_column_type_sql(&self, table: &str, column: &str, sql_type: &str) -> Option<String>;
+
+    /// Generate the SQL needed to add or remove a NOT NULL constraint on the given column of the
+    /// given table, or None if the database cannot do this in place, in which case the table must
+    /// be rebuilt.
+    fn alter_column_not_null_sql(
+        &self,
+        table: &str,
+        column: &str,
+        not_null: bool,
+    ) -> Option<String>;
+
+    /// Generate the SQL needed to add a unique constraint on the given columns to the given table,
+    /// or None if the database cannot do this in place.
+    fn add_unique_constraint_sql(&self, table: &str, columns: &[String]) -> Option<String>;
+
+    /// Generate the SQL needed to add the given foreign key constraint to the given table, or
+    /// None if the database cannot do this in place.
+    fn add_foreign_key_sql(&self, table: &str, foreign_key: &ForeignKey) -> Option<String>;
+
+    /// Generate the SQL needed to drop the constraint with the given name from the given table, or
+    /// None if the database cannot do this in place.
+    fn drop_constraint_sql(&self, table: &str, constraint: &str) -> Option<String>;
+
+    /// Generate the SQL and parameters needed to query the database's metadata for the unique
+    /// constraints of the given table (not including its primary key). The query returns one row
+    /// per constrained column, ordered by constraint and then by the position of the column within
+    /// the constraint, with the columns "constraint_name" and "column_name".
+    fn unique_constraints_sql(&self, table: &str) -> (String, [DbValue; 1]);
+
+    /// Generate the SQL and parameters needed to query the database's metadata for the foreign key
+    /// constraints of the given table. The query returns one row per constrained column, ordered
+    /// by constraint and then by the position of the column within the constraint, with the
+    /// columns "constraint_name", "column_name", "foreign_table", and "foreign_column" (which is
+    /// NULL if the constraint implicitly refers to the primary key of the foreign table).
+    fn foreign_keys_sql(&self, table: &str) -> (String, [DbValue; 1]);
+
+    /// Generate the SQL needed to create the given index, if it does not already exist.
+    fn create_index_sql(&self, index: &Index) -> String {
+        let unique = match index.unique {
+            true => "UNIQUE ",
+            false => "",
+        };
+        let columns = index
+            .columns
+            .iter()
+            .map(|column| format!(r#""{column}""#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let condition = match &index.condition {
+            Some(condition) => format!(" WHERE {condition}"),
+            None => String::new(),
+        };
+        format!(
+            r#"CREATE {unique}INDEX IF NOT EXISTS "{}" ON "{}" ({columns}){condition}"#,
+            index.name, index.table
+        )
+    }
+
+    /// Generate the SQL needed to drop the given index, if it exists.
+    fn drop_index_sql(&self, index: &str) -> String {
+        format!(r#"DROP INDEX IF EXISTS "{index}""#)
+    }
+
+    /// Generate the SQL and parameters needed to query the database's metadata for the indexes
+    /// that have been explicitly created on the given table. The query returns one row per
+    /// indexed column, ordered by index name and then by the position of the column within the
+    /// index, with the columns "index_name", "column_name", "is_unique" (1 or 0), and
+    /// "condition" (NULL unless the index is partial).
+    fn list_indexes_sql(&self, table: &str) -> (String, [DbValue; 1]);
+
     /// Generate the SQL and parameters needed to determine which of the given list of
     /// database names correspond to views in the database.
     fn which_are_views_sql(&self, objects: &[&str]) -> (String, Vec<DbValue>);
@@ -61,21 +231,84 @@ pub trait DbKind: Display {
     /// given view.
     fn view_sql_sql(&self, view: &str) -> (String, [DbValue; 1]);
 
+    /// Generate the LIMIT and OFFSET clauses of a SELECT statement, either of which may be
+    /// omitted.
+    fn limit_offset_sql(&self, limit: Option<u64>, offset: Option<u64>) -> String {
+        let mut clauses = vec![];
+        if let Some(limit) = limit {
+            clauses.push(format!("LIMIT {limit}"));
+        }
+        if let Some(offset) = offset {
+            clauses.push(format!("OFFSET {offset}"));
+        }
+        clauses.join(" ")
+    }
+
     /// Generate the SQL needed to create the query cache.
     fn create_query_cache_table_sql(&self) -> String {
         let get_epoch_now = self.get_epoch_time_sql();
+        let binary_type = self.binary_type();
         format!(
             r#"CREATE TABLE IF NOT EXISTS "{QUERY_CACHE_TABLE}" (
                  "statement" TEXT,
                  "parameters" TEXT,
-                 "tables" TEXT,
                  "value" TEXT,
+                 "binary_value" {binary_type},
                  "last_verified" BIGINT DEFAULT ({get_epoch_now}),
+                 "cached_at" BIGINT,
                  PRIMARY KEY ("statement", "parameters")
              )"#
         )
     }
 
+    /// Generate the SQL needed to create the table that maps query cache entries to the tables
+    /// they depend on, together with an index for looking up the entries for a given table.
+    fn create_query_cache_tables_table_sql(&self) -> String {
+        format!(
+            r#"CREATE TABLE IF NOT EXISTS "{QUERY_CACHE_TABLES_TABLE}" (
+                 "statement" TEXT,
+                 "parameters" TEXT,
+                 "table" TEXT,
+                 PRIMARY KEY ("statement", "parameters", "table")
+               );
+               CREATE INDEX IF NOT EXISTS "{QUERY_CACHE_TABLES_TABLE}_table_idx"
+                 ON "{QUERY_CACHE_TABLES_TABLE}" ("table")"#
+        )
+    }
+
+    /// Generate the SQL statements needed to delete the query cache entries that depend on the
+    /// given table, along with the now unused rows of the query cache tables table (see
+    /// [QUERY_CACHE_TABLES_TABLE]). Here `table` is an SQL expression, e.g., a quoted string or
+    /// a parameter placeholder. If a condition is given, only the entries satisfying it, which
+    /// may refer to the columns of the query cache table, are deleted.
+    fn delete_query_cache_entries_sql(&self, table: &str, condition: Option<&str>) -> Vec<String> {
+        let condition = match condition {
+            Some(condition) => format!("AND ({condition})"),
+            None => String::new(),
+        };
+        vec![
+            format!(
+                r#"DELETE FROM "{QUERY_CACHE_TABLE}"
+                   WHERE ("statement", "parameters") IN (
+                     SELECT "statement", "parameters"
+                     FROM "{QUERY_CACHE_TABLES_TABLE}"
+                     WHERE "table" = {table}
+                   )
+                   {condition}"#
+            ),
+            format!(
+                r#"DELETE FROM "{QUERY_CACHE_TABLES_TABLE}"
+                   WHERE "table" = {table}
+                   AND NOT EXISTS (
+                     SELECT 1
+                     FROM "{QUERY_CACHE_TABLE}" q
+                     WHERE q."statement" = "{QUERY_CACHE_TABLES_TABLE}"."statement"
+                       AND q."parameters" = "{QUERY_CACHE_TABLES_TABLE}"."parameters"
+                   )"#
+            ),
+        ]
+    }
+
     /// Generate the SQL needed to create the table cache.
     fn create_table_cache_table_sql(&self) -> String {
         let get_epoch_now = self.get_epoch_time_sql();
@@ -87,6 +320,42 @@ pub trait DbKind: Display {
         )
     }
 
+    /// Generate the SQL needed to create the query cache keys table.
+    fn create_query_cache_keys_table_sql(&self) -> String {
+        format!(
+            r#"CREATE TABLE IF NOT EXISTS "{QUERY_CACHE_KEYS_TABLE}" (
+                 "statement" TEXT,
+                 "parameters" TEXT,
+                 "key" TEXT,
+                 PRIMARY KEY ("statement", "parameters", "key")
+               )"#
+        )
+    }
+
+    /// Generate the SQL needed to create the table used to keep track of applied migrations.
+    fn create_migrations_table_sql(&self) -> String {
+        let get_epoch_now = self.get_epoch_time_sql();
+        format!(
+            r#"CREATE TABLE IF NOT EXISTS "{MIGRATIONS_TABLE}" (
+                 "version" BIGINT PRIMARY KEY,
+                 "name" TEXT NOT NULL,
+                 "checksum" TEXT NOT NULL,
+                 "applied_at" BIGINT DEFAULT ({get_epoch_now})
+               )"#
+        )
+    }
+
+    /// Generate the SQL needed to create the table used to prevent concurrent migrations.
+    fn create_migrations_lock_table_sql(&self) -> String {
+        let get_epoch_now = self.get_epoch_time_sql();
+        format!(
+            r#"CREATE TABLE IF NOT EXISTS "{MIGRATIONS_LOCK_TABLE}" (
+                 "id" BIGINT PRIMARY KEY,
+                 "locked_at" BIGINT DEFAULT ({get_epoch_now})
+               )"#
+        )
+    }
+
     /// Generate the SQL statements needed to create caching triggers for the given table.
     fn create_table_caching_triggers_for_table_sql(
         &self,
@@ -94,10 +363,12 @@ pub trait DbKind: Display {
     ) -> Result<Vec<String>, DbError> {
         let table = validate_table_name(table)?;
         let trigger_basename = format!("{table}");
-        let trigger_content = format!(
-            r#"DELETE FROM "{QUERY_CACHE_TABLE}"
-               WHERE "tables" LIKE '%"{table}"%';"#
-        );
+        let trigger_content = self
+            .delete_query_cache_entries_sql(&format!("'{table}'"), None)
+            .iter()
+            .map(|sql| format!("{sql};"))
+            .collect::<Vec<_>>()
+            .join("\n");
         self.wrap_trigger_content(&table, &trigger_basename, &trigger_content)
     }
 
@@ -112,24 +383,81 @@ pub trait DbKind: Display {
         let view = validate_table_name(view)?;
         let get_epoch_now = self.get_epoch_time_sql();
         let trigger_basename = format!("{table}_{view}");
-        let trigger_content = format!(
-            r#"INSERT INTO "{TABLE_CACHE_TABLE}"
-               ("table", "last_modified")
-               VALUES ('{table}', {get_epoch_now})
-               ON CONFLICT ("table")
-                 DO UPDATE SET "last_modified" = {get_epoch_now};
-               DELETE FROM "{QUERY_CACHE_TABLE}"
-               WHERE "tables" LIKE '%"{view}"%'
-               AND EXISTS (
+        let condition = format!(
+            r#"EXISTS (
                  SELECT 1
                  FROM "{TABLE_CACHE_TABLE}" t
                  WHERE t."table" = '{table}'
                    AND t."last_modified" >= "{QUERY_CACHE_TABLE}"."last_verified"
-               );"#
+               )"#
         );
+        let trigger_content = [format!(
+            r#"INSERT INTO "{TABLE_CACHE_TABLE}"
+               ("table", "last_modified")
+               VALUES ('{table}', {get_epoch_now})
+               ON CONFLICT ("table")
+                 DO UPDATE SET "last_modified" = {get_epoch_now}"#
+        )]
+        .into_iter()
+        .chain(self.delete_query_cache_entries_sql(&format!("'{view}'"), Some(&condition)))
+        .map(|sql| format!("{sql};"))
+        .collect::<Vec<_>>()
+        .join("\n");
         self.wrap_trigger_content(&table, &trigger_basename, &trigger_content)
     }
 
+    /// Generate the SQL statements needed to create row-level caching triggers for the given
+    /// table, whose primary key is the given column. Cache entries for the table are deleted
+    /// when one of its rows is edited unless their keys have been recorded in the query cache
+    /// keys table and do not include the key of the edited row.
+    fn create_row_caching_triggers_for_table_sql(
+        &self,
+        table: &str,
+        key_column: &str,
+    ) -> Result<Vec<String>, DbError> {
+        let table = validate_table_name(table)?;
+        let key_column = validate_table_name(key_column)?;
+        let trigger_content = |rows: &[&str]| {
+            let edited_keys = rows
+                .iter()
+                .map(|row| format!(r#"CAST({row}."{key_column}" AS TEXT)"#))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let condition = format!(
+                r#"NOT EXISTS (
+                     SELECT 1
+                     FROM "{QUERY_CACHE_KEYS_TABLE}" k
+                     WHERE k."statement" = "{QUERY_CACHE_TABLE}"."statement"
+                       AND k."parameters" = "{QUERY_CACHE_TABLE}"."parameters"
+                   )
+                   OR EXISTS (
+                     SELECT 1
+                     FROM "{QUERY_CACHE_KEYS_TABLE}" k
+                     WHERE k."statement" = "{QUERY_CACHE_TABLE}"."statement"
+                       AND k."parameters" = "{QUERY_CACHE_TABLE}"."parameters"
+                       AND k."key" IN ({edited_keys})
+                   )"#
+            );
+            self.delete_query_cache_entries_sql(&format!("'{table}'"), Some(&condition))
+                .iter()
+                .map(|sql| format!("{sql};"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        self.wrap_row_trigger_content(
+            &table,
+            &table,
+            &trigger_content(&["NEW"]),
+            &trigger_content(&["OLD", "NEW"]),
+            &trigger_content(&["OLD"]),
+        )
+    }
+
+    /// Generate the SQL statements needed to create triggers that notify the listeners on the
+    /// [CACHE_NOTIFY_CHANNEL] channel whenever the given table is edited, if the database
+    /// supports notifications.
+    fn create_notify_triggers_for_table_sql(&self, table: &str) -> Result<Vec<String>, DbError>;
+
     /// Generate the SQL statements to create a caching function and triggers with the given
     /// trigger content for the given table using the given trigger basename.
     fn wrap_trigger_content(
@@ -138,6 +466,19 @@ pub trait DbKind: Display {
         trigger_basename: &str,
         trigger_content: &str,
     ) -> Result<Vec<String>, DbError>;
+
+    /// Generate the SQL statements to create caching functions and row-level triggers for the
+    /// given table using the given trigger basename, with the given trigger content for each of
+    /// the insert, update, and delete events. The content may refer to the edited row as NEW
+    /// (for inserts and updates) and OLD (for updates and deletes).
+    fn wrap_row_trigger_content(
+        &self,
+        table: &str,
+        trigger_basename: &str,
+        insert_content: &str,
+        update_content: &str,
+        delete_content: &str,
+    ) -> Result<Vec<String>, DbError>;
 }
 
 // Builtin database kind implementations.
@@ -185,10 +526,27 @@ impl DbKind for SQLiteKind {
         "?"
     }
 
+    fn max_params(&self) -> usize {
+        MAX_PARAMS_SQLITE
+    }
+
     fn get_epoch_time_sql(&self) -> &str {
         "strftime('%s', 'now')"
     }
 
+    fn binary_type(&self) -> &str {
+        "BLOB"
+    }
+
+    fn hex_to_binary_sql(&self, expr: &str) -> String {
+        format!("unhex({expr})")
+    }
+
+    fn binary_to_hex_sql(&self, expr: &str) -> String {
+        // Note that hex() returns an empty string, rather than NULL, when given NULL:
+        format!("CASE WHEN {expr} IS NULL THEN NULL ELSE hex({expr}) END")
+    }
+
     fn columns_sql(&self, table: &str) -> (String, [DbValue; 1]) {
         (
             r#"SELECT "name" AS "column_name", "type" AS "data_type"
@@ -210,6 +568,30 @@ impl DbKind for SQLiteKind {
         )
     }
 
+    fn table_columns_sql(&self, table: &str) -> (String, [DbValue; 1]) {
+        (
+            r#"SELECT "name" AS "column_name", "type" AS "data_type", "notnull" AS "not_null"
+               FROM pragma_table_info(?1)
+               ORDER BY "cid""#
+                .to_string(),
+            params![table],
+        )
+    }
+
+    fn list_tables_sql(&self) -> String {
+        r#"SELECT "name" AS "table_name" FROM "sqlite_master"
+           WHERE "type" = 'table' AND "name" NOT LIKE 'sqlite_%'
+           ORDER BY "name""#
+            .to_string()
+    }
+
+    fn list_views_sql(&self) -> String {
+        r#"SELECT "name" AS "view_name" FROM "sqlite_master"
+           WHERE "type" = 'view'
+           ORDER BY "name""#
+            .to_string()
+    }
+
     fn drop_table_sql(&self, table: &str) -> String {
         format!(r#"DROP TABLE IF EXISTS "{table}""#)
     }
@@ -218,6 +600,98 @@ impl DbKind for SQLiteKind {
         format!(r#"DROP VIEW IF EXISTS "{view}""#)
     }
 
+    fn drop_column_sql(&self, _table: &str, _column: &str) -> Option<String> {
+        // Although SQLite (since version 3.35.0) supports dropping columns directly, it refuses
+        // to do so when the column is indexed or is part of a constraint, so we always rebuild.
+        None
+    }
+
+    fn alter_column_type_sql(
+        &self,
+        _table: &str,
+        _column: &str,
+        _sql_type: &str,
+    ) -> Option<String> {
+        None
+    }
+
+    fn alter_column_not_null_sql(
+        &self,
+        _table: &str,
+        _column: &str,
+        _not_null: bool,
+    ) -> Option<String> {
+        None
+    }
+
+    fn add_unique_constraint_sql(&self, _table: &str, _columns: &[String]) -> Option<String> {
+        None
+    }
+
+    fn add_foreign_key_sql(&self, _table: &str, _foreign_key: &ForeignKey) -> Option<String> {
+        None
+    }
+
+    fn drop_constraint_sql(&self, _table: &str, _constraint: &str) -> Option<String> {
+        None
+    }
+
+    fn unique_constraints_sql(&self, table: &str) -> (String, [DbValue; 1]) {
+        // Unique constraints are implemented using automatic indexes:
+        (
+            r#"SELECT "list"."name" AS "constraint_name", "info"."name" AS "column_name"
+               FROM pragma_index_list(?1) AS "list"
+               JOIN pragma_index_info("list"."name") AS "info"
+               WHERE "list"."origin" = 'u'
+               ORDER BY "list"."name", "info"."seqno""#
+                .to_string(),
+            params![table],
+        )
+    }
+
+    fn foreign_keys_sql(&self, table: &str) -> (String, [DbValue; 1]) {
+        (
+            r#"SELECT
+                 "id" AS "constraint_name",
+                 "from" AS "column_name",
+                 "table" AS "foreign_table",
+                 "to" AS "foreign_column"
+               FROM pragma_foreign_key_list(?1)
+               ORDER BY "id", "seq""#
+                .to_string(),
+            params![table],
+        )
+    }
+
+    fn list_indexes_sql(&self, table: &str) -> (String, [DbValue; 1]) {
+        // The condition of a partial index is not available from SQLite's metadata, so we
+        // extract it from the SQL used to create the index (with tabs and newlines replaced by
+        // spaces so that we can search for the WHERE keyword):
+        (
+            r#"WITH "indexes" AS (
+                 SELECT
+                   "name",
+                   REPLACE(REPLACE(REPLACE("sql", char(9), ' '), char(10), ' '), char(13), ' ')
+                     AS "sql"
+                 FROM "sqlite_master"
+                 WHERE "type" = 'index' AND "tbl_name" = ?1 AND "sql" IS NOT NULL
+               )
+               SELECT
+                 "indexes"."name" AS "index_name",
+                 "info"."name" AS "column_name",
+                 "list"."unique" AS "is_unique",
+                 CASE WHEN "list"."partial"
+                   THEN SUBSTR("indexes"."sql", INSTR(UPPER("indexes"."sql"), ' WHERE ') + 7)
+                 END AS "condition"
+               FROM "indexes"
+               JOIN pragma_index_list(?1) AS "list" ON "list"."name" = "indexes"."name"
+               JOIN pragma_index_info("indexes"."name") AS "info"
+               ORDER BY "indexes"."name", "info"."seqno""#
+                .to_string(),
+            params![table],
+        )
+    }
+
     fn which_are_views_sql(&self, objects: &[&str]) -> (String, Vec<DbValue>) {
         let prefix = self.param_prefix().to_string();
         let mut placeholders = vec![];
@@ -256,6 +730,16 @@ impl DbKind for SQLiteKind {
         )
     }
 
+    fn limit_offset_sql(&self, limit: Option<u64>, offset: Option<u64>) -> String {
+        // SQLite does not allow an OFFSET without a LIMIT, but a negative LIMIT means no limit:
+        match (limit, offset) {
+            (None, Some(offset)) => format!("LIMIT -1 OFFSET {offset}"),
+            (Some(limit), Some(offset)) => format!("LIMIT {limit} OFFSET {offset}"),
+            (Some(limit), None) => format!("LIMIT {limit}"),
+            (None, None) => String::new(),
+        }
+    }
+
     fn view_sql_sql(&self, view: &str) -> (String, [DbValue; 1]) {
         (
             r#"SELECT "sql" FROM "sqlite_master"
@@ -265,6 +749,12 @@ impl DbKind for SQLiteKind {
         )
     }
 
+    fn create_notify_triggers_for_table_sql(&self, table: &str) -> Result<Vec<String>, DbError> {
+        // SQLite has no equivalent of NOTIFY, so there are no triggers to create:
+        validate_table_name(table)?;
+        Ok(vec![])
+    }
+
     fn wrap_trigger_content(
         &self,
         table: &str,
@@ -299,6 +789,35 @@ impl DbKind for SQLiteKind {
         ];
         Ok(ddl)
     }
+
+    fn wrap_row_trigger_content(
+        &self,
+        table: &str,
+        trigger_basename: &str,
+        insert_content: &str,
+        update_content: &str,
+        delete_content: &str,
+    ) -> Result<Vec<String>, DbError> {
+        let mut ddl = vec![];
+        for (event, trigger_content) in [
+            ("insert", insert_content),
+            ("update", update_content),
+            ("delete", delete_content),
+        ] {
+            let trigger_name = format!("{trigger_basename}_after_{event}");
+            ddl.push(format!(r#"DROP TRIGGER IF EXISTS "{trigger_name}""#));
+            ddl.push(format!(
+                r#"CREATE TRIGGER "{trigger_name}"
+                   AFTER {event} ON "{table}"
+                   FOR EACH ROW
+                   BEGIN
+                     {trigger_content}
+                   END"#,
+                event = event.to_uppercase(),
+            ));
+        }
+        Ok(ddl)
+    }
 }
 
 impl DbKind for PostgreSQLKind {
@@ -330,10 +849,26 @@ impl DbKind for PostgreSQLKind {
         "$"
     }
 
+    fn max_params(&self) -> usize {
+        MAX_PARAMS_POSTGRES
+    }
+
     fn get_epoch_time_sql(&self) -> &str {
         "extract(epoch from now())"
     }
 
+    fn binary_type(&self) -> &str {
+        "BYTEA"
+    }
+
+    fn hex_to_binary_sql(&self, expr: &str) -> String {
+        format!("decode({expr}, 'hex')")
+    }
+
+    fn binary_to_hex_sql(&self, expr: &str) -> String {
+        format!("encode({expr}, 'hex')")
+    }
+
     fn columns_sql(&self, table: &str) -> (String, [DbValue; 1]) {
         (
             r#"SELECT
@@ -374,6 +909,58 @@ impl DbKind for PostgreSQLKind {
         )
     }
 
+    fn table_columns_sql(&self, table: &str) -> (String, [DbValue; 1]) {
+        (
+            r#"SELECT
+                 "columns"."column_name"::TEXT,
+                 CASE
+                   WHEN "columns"."data_type" = 'numeric'
+                    AND "columns"."numeric_precision" IS NOT NULL
+                   THEN 'numeric(' || "columns"."numeric_precision" || ', '
+                     || "columns"."numeric_scale" || ')'
+                   ELSE "columns"."data_type"::TEXT
+                 END AS "data_type",
+                 CASE WHEN "columns"."is_nullable" = 'NO' THEN 1 ELSE 0 END AS "not_null"
+               FROM
+                 "information_schema"."columns" "columns"
+               WHERE
+                 "columns"."table_schema" IN (
+                   SELECT REGEXP_SPLIT_TO_TABLE("setting", ', ')
+                   FROM "pg_settings"
+                   WHERE "name" = 'search_path'
+                 )
+                 AND "columns"."table_name" = $1
+               ORDER BY "columns"."ordinal_position""#
+                .to_string(),
+            params![table],
+        )
+    }
+
+    fn list_tables_sql(&self) -> String {
+        r#"SELECT "table_name"::TEXT
+           FROM "information_schema"."tables"
+           WHERE "table_type" = 'BASE TABLE'
+           AND "table_schema" IN (
+             SELECT REGEXP_SPLIT_TO_TABLE("setting", ', ')
+             FROM "pg_settings"
+             WHERE "name" = 'search_path'
+           )
+           ORDER BY "table_name""#
+            .to_string()
+    }
+
+    fn list_views_sql(&self) -> String {
+        r#"SELECT "table_name"::TEXT AS "view_name"
+           FROM "information_schema"."views"
+           WHERE "table_schema" IN (
+             SELECT REGEXP_SPLIT_TO_TABLE("setting", ', ')
+             FROM "pg_settings"
+             WHERE "name" = 'search_path'
+           )
+           ORDER BY "view_name""#
+            .to_string()
+    }
+
     fn drop_table_sql(&self, table: &str) -> String {
         format!(r#"DROP TABLE IF EXISTS "{table}" CASCADE"#)
     }
@@ -382,6 +969,158 @@ impl DbKind for PostgreSQLKind {
         format!(r#"DROP VIEW IF EXISTS "{view}" CASCADE"#)
     }
 
+    fn drop_column_sql(&self, table: &str, column: &str) -> Option<String> {
+        Some(format!(r#"ALTER TABLE "{table}" DROP COLUMN "{column}""#))
+    }
+
+    fn alter_column_type_sql(&self, table: &str, column: &str, sql_type: &str) -> Option<String> {
+        Some(format!(
+            r#"ALTER TABLE "{table}" ALTER COLUMN "{column}" TYPE {sql_type}
+               USING "{column}"::{sql_type}"#
+        ))
+    }
+
+    fn alter_column_not_null_sql(
+        &self,
+        table: &str,
+        column: &str,
+        not_null: bool,
+    ) -> Option<String> {
+        let action = match not_null {
+            true => "SET",
+            false => "DROP",
+        };
+        Some(format!(
+            r#"ALTER TABLE "{table}" ALTER COLUMN "{column}" {action} NOT NULL"#
+        ))
+    }
+
+    fn add_unique_constraint_sql(&self, table: &str, columns: &[String]) -> Option<String> {
+        let columns = columns
+            .iter()
+            .map(|column| format!(r#""{column}""#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Some(format!(r#"ALTER TABLE "{table}" ADD UNIQUE ({columns})"#))
+    }
+
+    fn add_foreign_key_sql(&self, table: &str, foreign_key: &ForeignKey) -> Option<String> {
+        let quote = |columns: &[String]| {
+            columns
+                .iter()
+                .map(|column| format!(r#""{column}""#))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        Some(format!(
+            r#"ALTER TABLE "{table}" ADD FOREIGN KEY ({}) REFERENCES "{}" ({})"#,
+            quote(&foreign_key.columns),
+            foreign_key.foreign_table,
+            quote(&foreign_key.foreign_columns)
+        ))
+    }
+
+    fn drop_constraint_sql(&self, table: &str, constraint: &str) -> Option<String> {
+        Some(format!(
+            r#"ALTER TABLE "{table}" DROP CONSTRAINT "{constraint}""#
+        ))
+    }
+
+    fn unique_constraints_sql(&self, table: &str) -> (String, [DbValue; 1]) {
+        (
+            r#"SELECT
+                 "constraint"."conname"::TEXT AS "constraint_name",
+                 "attribute"."attname"::TEXT AS "column_name"
+               FROM "pg_constraint" "constraint"
+               JOIN "pg_class" "table_class" ON "table_class"."oid" = "constraint"."conrelid"
+               JOIN "pg_namespace" "namespace"
+                 ON "namespace"."oid" = "table_class"."relnamespace"
+               CROSS JOIN LATERAL UNNEST("constraint"."conkey")
+                 WITH ORDINALITY AS "key"("attnum", "position")
+               JOIN "pg_attribute" "attribute"
+                 ON "attribute"."attrelid" = "table_class"."oid"
+                AND "attribute"."attnum" = "key"."attnum"
+               WHERE "constraint"."contype" = 'u'
+                 AND "table_class"."relname" = $1
+                 AND "namespace"."nspname" IN (
+                   SELECT REGEXP_SPLIT_TO_TABLE("setting", ', ')
+                   FROM "pg_settings"
+                   WHERE "name" = 'search_path'
+                 )
+               ORDER BY "constraint_name", "key"."position""#
+                .to_string(),
+            params![table],
+        )
+    }
+
+    fn foreign_keys_sql(&self, table: &str) -> (String, [DbValue; 1]) {
+        (
+            r#"SELECT
+                 "constraint"."conname"::TEXT AS "constraint_name",
+                 "attribute"."attname"::TEXT AS "column_name",
+                 "foreign_class"."relname"::TEXT AS "foreign_table",
+                 "foreign_attribute"."attname"::TEXT AS "foreign_column"
+               FROM "pg_constraint" "constraint"
+               JOIN "pg_class" "table_class" ON "table_class"."oid" = "constraint"."conrelid"
+               JOIN "pg_class" "foreign_class"
+                 ON "foreign_class"."oid" = "constraint"."confrelid"
+               JOIN "pg_namespace" "namespace"
+                 ON "namespace"."oid" = "table_class"."relnamespace"
+               CROSS JOIN LATERAL UNNEST("constraint"."conkey", "constraint"."confkey")
+                 WITH ORDINALITY AS "key"("attnum", "foreign_attnum", "position")
+               JOIN "pg_attribute" "attribute"
+                 ON "attribute"."attrelid" = "table_class"."oid"
+                AND "attribute"."attnum" = "key"."attnum"
+               JOIN "pg_attribute" "foreign_attribute"
+                 ON "foreign_attribute"."attrelid" = "foreign_class"."oid"
+                AND "foreign_attribute"."attnum" = "key"."foreign_attnum"
+               WHERE "constraint"."contype" = 'f'
+                 AND "table_class"."relname" = $1
+                 AND "namespace"."nspname" IN (
+                   SELECT REGEXP_SPLIT_TO_TABLE("setting", ', ')
+                   FROM "pg_settings"
+                   WHERE "name" = 'search_path'
+                 )
+               ORDER BY "constraint_name", "key"."position""#
+                .to_string(),
+            params![table],
+        )
+    }
+
+    fn list_indexes_sql(&self, table: &str) -> (String, [DbValue; 1]) {
+        // Exclude the indexes that implement primary key and unique constraints:
+        (
+            r#"SELECT
+                 "index_class"."relname"::TEXT AS "index_name",
+                 "attribute"."attname"::TEXT AS "column_name",
+                 CASE WHEN "index"."indisunique" THEN 1 ELSE 0 END AS "is_unique",
+                 pg_get_expr("index"."indpred", "index"."indrelid") AS "condition"
+               FROM "pg_index" "index"
+               JOIN "pg_class" "table_class" ON "table_class"."oid" = "index"."indrelid"
+               JOIN "pg_class" "index_class" ON "index_class"."oid" = "index"."indexrelid"
+               JOIN "pg_namespace" "namespace"
+                 ON "namespace"."oid" = "table_class"."relnamespace"
+               CROSS JOIN LATERAL UNNEST("index"."indkey")
+                 WITH ORDINALITY AS "key"("attnum", "position")
+               JOIN "pg_attribute" "attribute"
+                 ON "attribute"."attrelid" = "table_class"."oid"
+                AND "attribute"."attnum" = "key"."attnum"
+               WHERE "table_class"."relname" = $1
+                 AND "namespace"."nspname" IN (
+                   SELECT REGEXP_SPLIT_TO_TABLE("setting", ', ')
+                   FROM "pg_settings"
+                   WHERE "name" = 'search_path'
+                 )
+                 AND NOT EXISTS (
+                   SELECT 1 FROM "pg_constraint"
+                   WHERE "pg_constraint"."conindid" = "index"."indexrelid"
+                 )
+               ORDER BY "index_name", "key"."position""#
+                .to_string(),
+            params![table],
+        )
+    }
+
     fn which_are_views_sql(&self, objects: &[&str]) -> (String, Vec<DbValue>) {
         let prefix = self.param_prefix().to_string();
         let mut placeholders = vec![];
@@ -450,6 +1189,13 @@ impl DbKind for PostgreSQLKind {
         )
     }
 
+    fn create_notify_triggers_for_table_sql(&self, table: &str) -> Result<Vec<String>, DbError> {
+        let table = validate_table_name(table)?;
+        let trigger_basename = format!("{table}_notify");
+        let trigger_content = format!("PERFORM pg_notify('{CACHE_NOTIFY_CHANNEL}', '{table}');");
+        self.wrap_trigger_content(&table, &trigger_basename, &trigger_content)
+    }
+
     fn wrap_trigger_content(
         &self,
         table: &str,
@@ -491,6 +1237,48 @@ impl DbKind for PostgreSQLKind {
         ];
         Ok(ddl)
     }
+
+    fn wrap_row_trigger_content(
+        &self,
+        table: &str,
+        trigger_basename: &str,
+        insert_content: &str,
+        update_content: &str,
+        delete_content: &str,
+    ) -> Result<Vec<String>, DbError> {
+        let mut ddl = vec![];
+        for (event, trigger_content) in [
+            ("insert", insert_content),
+            ("update", update_content),
+            ("delete", delete_content),
+        ] {
+            let trigger_name = format!("{trigger_basename}_after_{event}");
+            let function_name = format!("clean_{trigger_name}");
+            ddl.push(format!(
+                r#"CREATE OR REPLACE FUNCTION "{function_name}"()
+                   RETURNS TRIGGER
+                   LANGUAGE PLPGSQL
+                   AS
+                   $$
+                   BEGIN
+                       {trigger_content}
+                       RETURN NULL;
+                   END;
+                   $$"#
+            ));
+            ddl.push(format!(
+                r#"DROP TRIGGER IF EXISTS "{trigger_name}" ON "{table}""#
+            ));
+            ddl.push(format!(
+                r#"CREATE TRIGGER "{trigger_name}"
+                   AFTER {event} ON "{table}"
+                   FOR EACH ROW
+                   EXECUTE FUNCTION "{function_name}"()"#,
+                event = event.to_uppercase(),
+            ));
+        }
+        Ok(ddl)
+    }
 }
 
 //////////////////////////////////////////////////////////////////////
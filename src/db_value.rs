@@ -847,7 +847,7 @@ impl Eq for DbValue {}
 //////////////////////////////////////////////////////////////////////
 
 /// Database query parameters
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, PartialEq, Serialize)]
 pub enum DbParams {
     None,
     Positional(Vec<DbValue>),
@@ -0,0 +1,518 @@
+//! Code for importing tables from, and exporting query results to, delimited text such as CSV
+//! and TSV.
+//!
+//! When importing into a table that does not yet exist, the table is created using the
+//! [TableSchema] given in the [DelimitedOptions] or, if there is none, a schema that is inferred
+//! from the first [SCHEMA_INFERENCE_ROWS] records: a column is given the type `bigint` if all of
+//! its values are integers, `double precision` if they are all numbers, and `text` otherwise. The
+//! imported values are converted to the types of the table's columns and inserted in batches
+//! using [DbQuery::bulk_insert()].
+//!
+//! Exported rows are retrieved one page at a time (see [crate::paginate]), so that the whole
+//! result never needs to be held in memory.
+
+use crate::{
+    core::{DbError, DbQuery},
+    db_value::{DbRow, DbValue},
+    paginate::for_each_page,
+    schema::{ColumnSchema, SchemaChange, TableSchema, apply_schema_changes},
+    select::{Select, SelectColumn},
+    shared::BULK_INSERT_BATCH_SIZE,
+};
+use std::io::{Read, Write};
+
+/// When to quote the fields of exported rows.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Quoting {
+    /// Quote only the fields that contain the delimiter, the quote character, or a line break.
+    #[default]
+    Necessary,
+    /// Quote every field.
+    Always,
+    /// Never quote fields.
+    Never,
+}
+
+/// Options controlling how delimited text is read and written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DelimitedOptions {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub quoting: Quoting,
+    /// Whether the first line contains the column names. When importing without a header, the
+    /// columns are named `column_1`, `column_2`, etc.
+    pub has_header: bool,
+    /// The text used to represent NULL.
+    pub null: String,
+    /// The schema used to create the table when importing into a table that does not exist.
+    pub schema: Option<TableSchema>,
+}
+
+impl Default for DelimitedOptions {
+    fn default() -> Self {
+        Self::csv()
+    }
+}
+
+impl DelimitedOptions {
+    /// Options for comma-separated values, with a header and NULLs represented by empty fields.
+    pub fn csv() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            quoting: Quoting::Necessary,
+            has_header: true,
+            null: String::new(),
+            schema: None,
+        }
+    }
+
+    /// Options for tab-separated values, with a header and NULLs represented by empty fields.
+    pub fn tsv() -> Self {
+        Self {
+            delimiter: b'\t',
+            ..Self::csv()
+        }
+    }
+
+    /// Use the given field delimiter.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Use the given quote character.
+    pub fn quote(mut self, quote: u8) -> Self {
+        self.quote = quote;
+        self
+    }
+
+    /// Quote exported fields according to the given [Quoting] style.
+    pub fn quoting(mut self, quoting: Quoting) -> Self {
+        self.quoting = quoting;
+        self
+    }
+
+    /// Set whether the first line contains the column names.
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+
+    /// Represent NULL using the given text.
+    pub fn null(mut self, null: &str) -> Self {
+        self.null = null.to_string();
+        self
+    }
+
+    /// Create the table using the given schema if it does not exist when importing.
+    pub fn schema(mut self, schema: TableSchema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+}
+
+/// The number of records, read from the start of the input, from which the schema of a table is
+/// inferred when importing delimited text into a table that does not exist without a given
+/// [TableSchema] (see [import_delimited()]).
+pub static SCHEMA_INFERENCE_ROWS: usize = 1000;
+
+/// Import the delimited text read from the given reader into the given table, creating the
+/// table if it does not exist, and return the number of rows inserted. The input is read and
+/// inserted [BULK_INSERT_BATCH_SIZE] rows at a time, so that it never needs to be held in memory
+/// as a whole, and when the table's schema must be inferred, only the first
+/// [SCHEMA_INFERENCE_ROWS] records are consulted. Each batch is inserted in its own transaction,
+/// so malformed input, or a value that does not match the type of its column, causes the import
+/// to fail, leaving any earlier batches in place.
+pub async fn import_delimited(
+    pool: &impl DbQuery,
+    table: &str,
+    reader: impl Read,
+    options: &DelimitedOptions,
+) -> Result<u64, DbError> {
+    let read_error = |err: csv::Error| DbError::DataError(format!("Error reading {table}: {err}"));
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .quote(options.quote)
+        .has_headers(options.has_header)
+        .from_reader(reader);
+    let header = match options.has_header {
+        true => Some(
+            csv_reader
+                .headers()
+                .map_err(read_error)?
+                .iter()
+                .map(|column| column.to_string())
+                .collect::<Vec<_>>(),
+        ),
+        false => None,
+    };
+
+    // Buffer the records needed to determine the columns and, if necessary, the schema:
+    let exists = pool.table_exists(table).await?;
+    let buffer_size = match exists || options.schema.is_some() {
+        true => 1,
+        false => SCHEMA_INFERENCE_ROWS,
+    };
+    let mut records = csv_reader.into_records();
+    let mut buffered = vec![];
+    for record in records.by_ref().take(buffer_size) {
+        buffered.push(record.map_err(read_error)?);
+    }
+    let columns = match header {
+        Some(header) => header,
+        None => match buffered.first() {
+            None => return Ok(0),
+            Some(record) => (1..=record.len()).map(|i| format!("column_{i}")).collect(),
+        },
+    };
+
+    if !exists {
+        let schema = match &options.schema {
+            Some(schema) => TableSchema {
+                name: table.to_string(),
+                ..schema.clone()
+            },
+            None => infer_schema(table, &columns, &buffered, &options.null),
+        };
+        {
+            let kind = pool.kind();
+            for column in &schema.columns {
+                kind.db_type(&column.sql_type)?;
+            }
+        }
+        apply_schema_changes(pool, &[SchemaChange::AddTable(schema)]).await?;
+    }
+
+    let to_row = |record: &csv::StringRecord| {
+        columns
+            .iter()
+            .zip(record.iter())
+            .map(|(column, field)| {
+                let value = match field == options.null {
+                    true => DbValue::Null,
+                    false => DbValue::Text(field.to_string()),
+                };
+                (column.to_string(), value)
+            })
+            .collect::<DbRow>()
+    };
+    let column_names = columns
+        .iter()
+        .map(|column| column.as_str())
+        .collect::<Vec<_>>();
+    let mut count = 0;
+    let mut batch = buffered.iter().map(to_row).collect::<Vec<_>>();
+    for record in records {
+        batch.push(to_row(&record.map_err(read_error)?));
+        if batch.len() >= BULK_INSERT_BATCH_SIZE {
+            let rows = std::mem::take(&mut batch).into_iter();
+            count += pool.bulk_insert(table, &column_names, rows, |_| ()).await?;
+        }
+    }
+    if !batch.is_empty() {
+        count += pool
+            .bulk_insert(table, &column_names, batch.into_iter(), |_| ())
+            .await?;
+    }
+    Ok(count)
+}
+
+// Infer the schema of a table with the given columns from the given records, in which NULL is
+// represented by the given text.
+fn infer_schema(
+    table: &str,
+    columns: &[String],
+    records: &[csv::StringRecord],
+    null: &str,
+) -> TableSchema {
+    let mut schema = TableSchema::new(table);
+    for (i, column) in columns.iter().enumerate() {
+        let values = records
+            .iter()
+            .filter_map(|record| record.get(i))
+            .filter(|value| *value != null)
+            .collect::<Vec<_>>();
+        let sql_type = if values.is_empty() {
+            "text"
+        } else if values.iter().all(|value| value.parse::<i64>().is_ok()) {
+            "bigint"
+        } else if values.iter().all(|value| value.parse::<f64>().is_ok()) {
+            "double precision"
+        } else {
+            "text"
+        };
+        schema = schema.column(ColumnSchema::new(column, sql_type));
+    }
+    schema
+}
+
+/// Write the rows selected by the given [Select] to the given writer as delimited text. Unless
+/// the query has a LIMIT or OFFSET, its rows are retrieved [crate::paginate::EXPORT_PAGE_SIZE] at
+/// a time, and if it has no ORDER BY clause, the rows are sorted by the primary key of the
+/// selected table (if it has one) so that the pages are consistent. Use [Select::new()] to
+/// export a whole table.
+pub async fn export_delimited(
+    pool: &impl DbQuery,
+    select: &Select,
+    writer: impl Write,
+    options: &DelimitedOptions,
+) -> Result<(), DbError> {
+    let write_error = |err: csv::Error| DbError::DataError(format!("Error writing rows: {err}"));
+    let mut csv_writer = csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .quote(options.quote)
+        .quote_style(match options.quoting {
+            Quoting::Necessary => csv::QuoteStyle::Necessary,
+            Quoting::Always => csv::QuoteStyle::Always,
+            Quoting::Never => csv::QuoteStyle::Never,
+        })
+        .from_writer(writer);
+
+    let mut wrote_header = !options.has_header;
+    for_each_page(pool, select, |rows| {
+        if !wrote_header && let Some(row) = rows.first() {
+            csv_writer.write_record(row.keys()).map_err(write_error)?;
+            wrote_header = true;
+        }
+        for row in rows.iter() {
+            let record = row.values().map(|value| match value {
+                DbValue::Null => options.null.clone(),
+                value => value.to_string(),
+            });
+            csv_writer.write_record(record).map_err(write_error)?;
+        }
+        Ok(())
+    })
+    .await?;
+    if !wrote_header {
+        let header = get_header(pool, select).await?;
+        csv_writer.write_record(&header).map_err(write_error)?;
+    }
+    csv_writer
+        .flush()
+        .map_err(|err| DbError::DataError(format!("Error writing rows: {err}")))
+}
+
+// Get the names of the columns that would be returned by the given query, for use in the header
+// when the query returns no rows.
+async fn get_header(pool: &impl DbQuery, select: &Select) -> Result<Vec<String>, DbError> {
+    match select.columns.is_empty() {
+        false => Ok(select
+            .columns
+            .iter()
+            .map(|column| match column {
+                SelectColumn::Column(column) => {
+                    column.rsplit('.').next().unwrap_or(column).to_string()
+                }
+                SelectColumn::Aggregate { alias, .. } => alias.to_string(),
+            })
+            .collect()),
+        true => {
+            let mut header = vec![];
+            for table in select.tables() {
+                header.extend(pool.columns(table).await?.into_keys());
+            }
+            Ok(header)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{any::AnyPool, filter::Filter, select::Aggregate};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_infer_schema() {
+        let records = [
+            csv::StringRecord::from(vec!["1", "1.5", "a", "NA"]),
+            csv::StringRecord::from(vec!["NA", "2", "3", "NA"]),
+        ];
+        let columns = ["a", "b", "c", "d"].map(|column| column.to_string());
+        assert_eq!(
+            infer_schema("test", &columns, &records, "NA"),
+            TableSchema::new("test")
+                .column(ColumnSchema::new("a", "bigint"))
+                .column(ColumnSchema::new("b", "double precision"))
+                .column(ColumnSchema::new("c", "text"))
+                .column(ColumnSchema::new("d", "text"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_export_delimited() {
+        #[cfg(feature = "rusqlite")]
+        import_export_delimited(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        import_export_delimited("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        import_export_delimited(":memory:").await;
+    }
+
+    async fn import_export_delimited(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.drop_table("test_delimited").await.unwrap();
+        pool.drop_table("test_delimited_schema").await.unwrap();
+
+        // Import into a new table with an inferred schema:
+        let csv = "id,name,score\n1,\"Smith, Jo\",1.5\n2,Lee,\n3,\"Say \"\"hi\"\"\",2\n";
+        let count = import_delimited(
+            &pool,
+            "test_delimited",
+            csv.as_bytes(),
+            &DelimitedOptions::csv(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 3);
+        let schema = TableSchema::from_pool(&pool, "test_delimited")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            schema
+                .columns
+                .iter()
+                .map(|column| column.sql_type.as_str())
+                .collect::<Vec<_>>(),
+            ["bigint", "text", "double precision"]
+        );
+
+        // Export the whole table as CSV, and a query as TSV:
+        let mut output = vec![];
+        export_delimited(
+            &pool,
+            &Select::new("test_delimited"),
+            &mut output,
+            &DelimitedOptions::csv(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), csv);
+
+        let mut output = vec![];
+        let select = Select::new("test_delimited")
+            .columns(&["name", "score"])
+            .filter(Filter::gt("id", 1_i64));
+        let options = DelimitedOptions::tsv().null("NULL").quoting(Quoting::Never);
+        export_delimited(&pool, &select, &mut output, &options)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "name\tscore\nLee\tNULL\nSay \"hi\"\t2\n"
+        );
+
+        // Import without a header into an existing table:
+        let tsv = "4\tKim\tNULL\n";
+        let options = DelimitedOptions::tsv().has_header(false).null("NULL");
+        pool.drop_table("test_delimited").await.unwrap();
+        pool.execute(
+            "CREATE TABLE test_delimited (column_1 BIGINT, column_2 TEXT, column_3 TEXT)",
+            (),
+        )
+        .await
+        .unwrap();
+        import_delimited(&pool, "test_delimited", tsv.as_bytes(), &options)
+            .await
+            .unwrap();
+        let mut output = vec![];
+        export_delimited(
+            &pool,
+            &Select::new("test_delimited"),
+            &mut output,
+            &options.clone().has_header(true),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "column_1\tcolumn_2\tcolumn_3\n4\tKim\tNULL\n"
+        );
+
+        // Import using a given schema, and export an empty table:
+        let options = DelimitedOptions::csv().schema(
+            TableSchema::new("ignored")
+                .column(ColumnSchema::new("id", "bigint"))
+                .column(ColumnSchema::new("label", "text"))
+                .primary_key(&["id"]),
+        );
+        assert_eq!(
+            import_delimited(
+                &pool,
+                "test_delimited_schema",
+                "id,label\n".as_bytes(),
+                &options
+            )
+            .await
+            .unwrap(),
+            0
+        );
+        assert_eq!(
+            pool.primary_keys("test_delimited_schema").await.unwrap(),
+            ["id"]
+        );
+        let mut output = vec![];
+        export_delimited(
+            &pool,
+            &Select::new("test_delimited_schema"),
+            &mut output,
+            &options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "id,label\n");
+
+        // Malformed input and values that do not match the column types are errors:
+        assert!(
+            import_delimited(
+                &pool,
+                "test_delimited_schema",
+                "id,label\n1,a,extra\n".as_bytes(),
+                &options
+            )
+            .await
+            .is_err()
+        );
+        assert!(
+            import_delimited(
+                &pool,
+                "test_delimited_schema",
+                "id,label\nx,a\n".as_bytes(),
+                &options
+            )
+            .await
+            .is_err()
+        );
+
+        // Import more rows than are inserted in a single batch:
+        pool.drop_table("test_delimited").await.unwrap();
+        let rows = BULK_INSERT_BATCH_SIZE + 1;
+        let csv = (1..=rows).fold("id,name\n".to_string(), |csv, i| {
+            csv + &format!("{i},n{i}\n")
+        });
+        assert_eq!(
+            import_delimited(
+                &pool,
+                "test_delimited",
+                csv.as_bytes(),
+                &DelimitedOptions::csv()
+            )
+            .await
+            .unwrap(),
+            rows as u64
+        );
+        let select = Select::new("test_delimited").aggregate(Aggregate::Count, "*", "count");
+        assert_eq!(
+            pool.select(&select).await.unwrap().value().unwrap(),
+            &DbValue::BigInteger(rows as i64)
+        );
+
+        // Clean up:
+        pool.drop_table("test_delimited").await.unwrap();
+        pool.drop_table("test_delimited_schema").await.unwrap();
+    }
+}
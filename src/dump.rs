@@ -0,0 +1,447 @@
+//! Code for dumping a database to, and restoring it from, portable SQL.
+//!
+//! A dump consists of a `CREATE TABLE` statement for each table, followed by batches of
+//! `INSERT` statements containing the table's data (which is read one page at a time if the
+//! table has a primary key, see [crate::paginate]) and by the statements needed to recreate the
+//! table's indexes, and finally by the `CREATE VIEW` statements for the database's views. Column
+//! types are normalized (see [normalize_sql_type()]) and values are written as SQL literals
+//! that are understood by both SQLite and PostgreSQL, so that a dump of one kind of database
+//! can be restored to the other. Note, however, that foreign key and CHECK constraints are not
+//! included in the dump (unlike unique constraints), and that the SQL code defining a view is
+//! written exactly as it is reported by the database that it was dumped from.
+
+use crate::{
+    cache::clear_cache_for_dropped_tables,
+    core::{DbError, DbQuery, get_view_sql, which_are_tables, which_are_views},
+    db_value::{DbRow, DbValue, JsonValue},
+    paginate::for_each_page,
+    parse::get_view_tables,
+    schema::{INTERNAL_TABLE_PREFIX, get_schema, normalize_sql_type},
+    select::Select,
+};
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// The maximum number of rows included in a single INSERT statement in a dump.
+pub static DUMP_INSERT_BATCH_SIZE: usize = 500;
+
+// Write the given string to the given writer.
+fn write_dump(writer: &mut impl Write, content: &str) -> Result<(), DbError> {
+    writer
+        .write_all(content.as_bytes())
+        .map_err(|err| DbError::DataError(format!("Error writing dump: {err}")))
+}
+
+// Quote the given string as a SQL string literal.
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Represent the given value, from a column of the given (normalized) SQL type, as a SQL literal
+/// that can be inserted to a column of that type in either SQLite or PostgreSQL.
+pub fn to_sql_literal(value: &DbValue, sql_type: &str) -> Result<String, DbError> {
+    let literal = match value {
+        DbValue::Null => "NULL".to_string(),
+        // Booleans are written as quoted numbers, since PostgreSQL will not accept an unquoted
+        // number as a boolean and SQLite will convert a quoted number to an integer:
+        DbValue::Boolean(flag) => match flag {
+            true => "'1'".to_string(),
+            false => "'0'".to_string(),
+        },
+        DbValue::SmallInteger(_) | DbValue::Integer(_) | DbValue::BigInteger(_)
+            if sql_type == "boolean" =>
+        {
+            match value.to_string().as_str() {
+                "0" => "'0'".to_string(),
+                _ => "'1'".to_string(),
+            }
+        }
+        DbValue::SmallInteger(number) => number.to_string(),
+        DbValue::Integer(number) => number.to_string(),
+        DbValue::BigInteger(number) => number.to_string(),
+        DbValue::Real(number) if number.is_finite() => number.to_string(),
+        DbValue::BigReal(number) if number.is_finite() => number.to_string(),
+        DbValue::Real(_) | DbValue::BigReal(_) => quote_literal(&value.to_string()),
+        DbValue::Numeric(number) => number.to_string(),
+        DbValue::Text(text) => quote_literal(text),
+        DbValue::Json(JsonValue::String(text)) => quote_literal(text),
+        DbValue::Json(json) => quote_literal(&json.to_string()),
+        DbValue::Other(_, _, Some(text)) => quote_literal(text),
+        DbValue::Other(type_name, _, None) => {
+            return Err(DbError::DataError(format!(
+                "Unable to represent value of type '{type_name}' as SQL: {value:?}"
+            )));
+        }
+    };
+    Ok(literal)
+}
+
+/// Write the schema and the data of all of the tables in the given database, as well as the
+/// definitions of its views, to the given writer as SQL statements, each terminated by a
+/// semicolon. The tables used internally by rltbl_db (e.g., for caching) are not dumped.
+pub async fn dump(pool: &impl DbQuery, writer: &mut impl Write) -> Result<(), DbError> {
+    dump_objects(pool, &[], writer).await
+}
+
+/// Like [dump()], but only dump the given tables and views, or all of them if none are given.
+pub async fn dump_objects(
+    pool: &impl DbQuery,
+    objects: &[&str],
+    writer: &mut impl Write,
+) -> Result<(), DbError> {
+    let kind = pool.kind();
+    let (tables, views) = match objects.is_empty() {
+        true => (
+            vec![],
+            pool.query_no_cache_clean(&kind.list_views_sql(), ())
+                .await?
+                .to_strings()?,
+        ),
+        false => (
+            which_are_tables(pool, objects).await?,
+            which_are_views(pool, objects).await?,
+        ),
+    };
+    if !objects.is_empty() && tables.is_empty() && views.is_empty() {
+        return Err(DbError::InputError(format!(
+            "None of the given objects exist: {objects:?}"
+        )));
+    }
+    let tables = tables
+        .iter()
+        .map(|table| table.as_str())
+        .collect::<Vec<_>>();
+    // Note that get_schema() retrieves all tables when given an empty list:
+    let schema = match !objects.is_empty() && tables.is_empty() {
+        true => vec![],
+        false => get_schema(pool, &tables).await?,
+    };
+
+    write_dump(writer, &format!("-- Dumped by rltbl_db from {kind}\n"))?;
+    for mut table in schema {
+        // Foreign keys are omitted, since they would require the tables to be created and filled
+        // in the order of their dependencies:
+        table.foreign_keys.clear();
+        write_dump(writer, &format!("\n{};\n", kind.create_table_sql(&table)))?;
+        let column_names = table
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect::<Vec<_>>();
+        let columns = column_names
+            .iter()
+            .map(|column| format!(r#""{column}""#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        // The rows of a table with a primary key are read one page at a time, so that the whole
+        // table does not need to be held in memory:
+        let select = Select::new(&table.name).columns(&column_names);
+        for_each_page(pool, &select, |rows| {
+            for batch in rows.chunks(DUMP_INSERT_BATCH_SIZE) {
+                let values = batch
+                    .iter()
+                    .map(|row: &DbRow| {
+                        let literals = table
+                            .columns
+                            .iter()
+                            .map(|column| {
+                                let value = row.get(&column.name).unwrap_or_default();
+                                to_sql_literal(&value, &normalize_sql_type(&column.sql_type))
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(format!("({})", literals.join(", ")))
+                    })
+                    .collect::<Result<Vec<_>, DbError>>()?;
+                write_dump(
+                    writer,
+                    &format!(
+                        "INSERT INTO \"{}\" ({columns}) VALUES\n{};\n",
+                        table.name,
+                        values.join(",\n")
+                    ),
+                )?;
+            }
+            Ok(())
+        })
+        .await?;
+        for index in table.indexes.iter() {
+            write_dump(writer, &format!("{};\n", kind.create_index_sql(index)))?;
+        }
+    }
+
+    // Write the views in an order such that every view comes after any views it depends on:
+    let mut views_with_sources = vec![];
+    for view in views
+        .into_iter()
+        .filter(|view| !view.starts_with(INTERNAL_TABLE_PREFIX))
+    {
+        let sql = get_view_sql(pool, &view).await?;
+        let sources = get_view_tables(&sql)?;
+        views_with_sources.push((view, sql, sources));
+    }
+    let mut views = views_with_sources;
+    let mut written: Vec<String> = vec![];
+    while !views.is_empty() {
+        let ready = views
+            .iter()
+            .position(|(_, _, sources)| {
+                sources.iter().all(|source| {
+                    written.contains(source) || !views.iter().any(|(other, _, _)| other == source)
+                })
+            })
+            // If there is a cycle, just write the remaining views in order:
+            .unwrap_or(0);
+        let (view, sql, _) = views.remove(ready);
+        write_dump(
+            writer,
+            &format!("\n{};\n", sql.trim().trim_end_matches(';')),
+        )?;
+        written.push(view);
+    }
+    writer
+        .flush()
+        .map_err(|err| DbError::DataError(format!("Error writing dump: {err}")))
+}
+
+/// Splits SQL code, supplied one line at a time, into statements separated by semicolons,
+/// ignoring semicolons that occur within quotes or comments.
+#[derive(Debug, Default)]
+pub struct StatementSplitter {
+    current: String,
+    in_single_quotes: bool,
+    in_double_quotes: bool,
+}
+
+impl StatementSplitter {
+    /// Add the given line to the code seen so far, and return any statements that have been
+    /// completed as a result.
+    pub fn push_line(&mut self, line: &str) -> Vec<String> {
+        let mut statements = vec![];
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' if !self.in_double_quotes => self.in_single_quotes = !self.in_single_quotes,
+                '"' if !self.in_single_quotes => self.in_double_quotes = !self.in_double_quotes,
+                '-' if !self.in_single_quotes
+                    && !self.in_double_quotes
+                    && chars.peek() == Some(&'-') =>
+                {
+                    // Skip the rest of the line:
+                    break;
+                }
+                ';' if !self.in_single_quotes && !self.in_double_quotes => {
+                    statements.extend(self.take());
+                    continue;
+                }
+                _ => (),
+            };
+            self.current.push(c);
+        }
+        self.current.push('\n');
+        statements
+    }
+
+    /// Return the final statement, if it was not terminated by a semicolon.
+    pub fn finish(mut self) -> Option<String> {
+        self.take()
+    }
+
+    // Return the current statement, if it is not empty, and start a new one.
+    fn take(&mut self) -> Option<String> {
+        let statement = self.current.trim().to_string();
+        self.current.clear();
+        match statement.is_empty() {
+            true => None,
+            false => Some(statement),
+        }
+    }
+}
+
+/// Execute the SQL statements read from the given reader, which is assumed to contain a dump
+/// produced by [dump()], against the given database. The statements are executed in order, and
+/// execution stops at the first statement that fails.
+pub async fn restore(pool: &impl DbQuery, reader: impl Read) -> Result<(), DbError> {
+    let mut splitter = StatementSplitter::default();
+    let mut tables = vec![];
+    let mut execute = async |statement: String| -> Result<(), DbError> {
+        if let Some(table) = statement
+            .strip_prefix("CREATE TABLE \"")
+            .and_then(|rest| rest.split('"').next())
+        {
+            tables.push(table.to_string());
+        }
+        pool.execute_no_cache_clean(&statement, ())
+            .await
+            .map_err(|err| DbError::DatabaseError(format!("Error restoring dump: {err}")))
+    };
+    for line in BufReader::new(reader).lines() {
+        let line = line.map_err(|err| DbError::DataError(format!("Error reading dump: {err}")))?;
+        for statement in splitter.push_line(&line) {
+            execute(statement).await?;
+        }
+    }
+    if let Some(statement) = splitter.finish() {
+        execute(statement).await?;
+    }
+
+    // Make sure that there is nothing in the cache for the restored tables:
+    let tables = tables
+        .iter()
+        .map(|table| table.as_str())
+        .collect::<Vec<_>>();
+    clear_cache_for_dropped_tables(pool, &tables).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        any::AnyPool, db_row, index::Index, paginate::EXPORT_PAGE_SIZE, schema::TableSchema,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_statement_splitter() {
+        let mut splitter = StatementSplitter::default();
+        let mut statements = vec![];
+        for line in [
+            "-- A comment; with a semicolon",
+            r#"INSERT INTO "a;b" VALUES ('it''s; here', 'x -- y'),"#,
+            "('multi",
+            "line'); SELECT 1;",
+            "SELECT 2",
+        ] {
+            statements.append(&mut splitter.push_line(line));
+        }
+        statements.extend(splitter.finish());
+        assert_eq!(
+            statements,
+            vec![
+                "INSERT INTO \"a;b\" VALUES ('it''s; here', 'x -- y'),\n('multi\nline')",
+                "SELECT 1",
+                "SELECT 2",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dump_and_restore() {
+        let sqlite_source = "test_dump_source.db";
+        let sqlite_target = "test_dump_target.db";
+        #[cfg(feature = "rusqlite")]
+        dump_and_restore(sqlite_source, sqlite_target).await;
+        #[cfg(feature = "tokio-postgres")]
+        {
+            // PostgreSQL to SQLite:
+            create_dump_source("postgresql:///rltbl_db").await;
+            dump_and_restore_from("postgresql:///rltbl_db", sqlite_target).await;
+            // SQLite to PostgreSQL:
+            create_dump_source(sqlite_source).await;
+            dump_and_restore_from(sqlite_source, "postgresql:///rltbl_db").await;
+        }
+        #[cfg(feature = "libsql")]
+        dump_and_restore(sqlite_source, sqlite_target).await;
+        for file in [sqlite_source, sqlite_target] {
+            std::fs::remove_file(file).ok();
+        }
+    }
+
+    async fn dump_and_restore(source: &str, target: &str) {
+        create_dump_source(source).await;
+        dump_and_restore_from(source, target).await;
+    }
+
+    // Create the tables and views to be dumped in the given database, after first removing any
+    // other tables and views from it.
+    async fn create_dump_source(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        drop_dump_objects(&pool).await;
+        pool.execute_batch(
+            r#"CREATE TABLE test_dump1 (
+                 id BIGINT PRIMARY KEY,
+                 name TEXT NOT NULL,
+                 score DOUBLE PRECISION,
+                 active BOOLEAN
+               );
+               CREATE UNIQUE INDEX test_dump1_name ON test_dump1 (name);
+               CREATE TABLE test_dump2 (note TEXT);
+               CREATE VIEW test_dump_view1 AS SELECT id, name FROM test_dump1;
+               CREATE VIEW test_dump_view2 AS SELECT name FROM test_dump_view1"#,
+        )
+        .await
+        .unwrap();
+        pool.execute(
+            r#"INSERT INTO test_dump1 VALUES
+                 (1, 'alice', 1.5, TRUE),
+                 (2, 'it''s bob; really', NULL, FALSE),
+                 (3, 'multi
+line -- not a comment', -2.25, NULL)"#,
+            (),
+        )
+        .await
+        .unwrap();
+        // Add enough rows for the table to be dumped in more than one page:
+        let rows = (4..4 + EXPORT_PAGE_SIZE as i64)
+            .map(|id| db_row! {"id" => id, "name" => format!("name {id}")});
+        pool.bulk_insert("test_dump1", &["id", "name"], rows, |_| ())
+            .await
+            .unwrap();
+    }
+
+    // Drop the tables and views used by these tests from the given database.
+    async fn drop_dump_objects(pool: &AnyPool) {
+        pool.drop_view("test_dump_view2").await.unwrap();
+        pool.drop_view("test_dump_view1").await.unwrap();
+        pool.drop_table("test_dump1").await.unwrap();
+        pool.drop_table("test_dump2").await.unwrap();
+    }
+
+    async fn dump_and_restore_from(source: &str, target: &str) {
+        let source = AnyPool::connect(source).await.unwrap();
+        source.memory_caches().clear_meta_cache();
+        let target = AnyPool::connect(target).await.unwrap();
+        let objects = [
+            "test_dump1",
+            "test_dump2",
+            "test_dump_view1",
+            "test_dump_view2",
+        ];
+        let mut dumped = vec![];
+        dump_objects(&source, &objects, &mut dumped).await.unwrap();
+        assert!(
+            String::from_utf8(dumped.clone())
+                .unwrap()
+                .contains(r#"CREATE TABLE "test_dump1""#)
+        );
+        drop_dump_objects(&target).await;
+        restore(&target, dumped.as_slice()).await.unwrap();
+
+        let tables = ["test_dump1", "test_dump2"];
+        assert_eq!(
+            get_schema(&target, &tables).await.unwrap(),
+            get_schema(&source, &tables).await.unwrap()
+        );
+        assert_eq!(
+            TableSchema::from_pool(&target, "test_dump1")
+                .await
+                .unwrap()
+                .unwrap()
+                .indexes,
+            vec![Index::new("test_dump1_name", "test_dump1", &["name"]).unique()]
+        );
+        let select = "SELECT id, name, score, CASE WHEN active THEN 'y' ELSE 'n' END AS active \
+                      FROM test_dump1 ORDER BY id";
+        let expected = source.query(select, ()).await.unwrap();
+        let actual = target.query(select, ()).await.unwrap();
+        assert_eq!(format!("{actual:?}"), format!("{expected:?}"));
+        let names = target
+            .query("SELECT name FROM test_dump_view2 ORDER BY name", ())
+            .await
+            .unwrap();
+        assert_eq!(names.len(), 3 + EXPORT_PAGE_SIZE as usize);
+
+        // Clean up:
+        drop_dump_objects(&source).await;
+        drop_dump_objects(&target).await;
+    }
+}
@@ -0,0 +1,465 @@
+//! Code for updating and deleting the rows of a table that satisfy a given filter condition.
+
+use crate::{
+    cache::clear_cache_for_edited_tables,
+    core::{DbError, DbQuery},
+    db_kind::DbKind,
+    db_value::{ColumnMap, DbRow, DbValue, IntoDbValue},
+    parse::validate_table_name,
+    shared::convert_value,
+};
+
+/// A condition on the rows of a table. Values are converted to the SQL types of the columns they
+/// are compared with, and are passed to the database as bound parameters. Comparing a column for
+/// (in)equality with [DbValue::Null] is equivalent to [Filter::IsNull] ([Filter::IsNotNull]).
+/// Since each of the values of [Filter::In] is bound separately, the number of values it can
+/// contain is limited by the maximum number of parameters that the database accepts in a query.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    Eq(String, DbValue),
+    NotEq(String, DbValue),
+    Lt(String, DbValue),
+    LtEq(String, DbValue),
+    Gt(String, DbValue),
+    GtEq(String, DbValue),
+    In(String, Vec<DbValue>),
+    IsNull(String),
+    IsNotNull(String),
+    /// Satisfied when all of the given filters are (in particular when there are none).
+    And(Vec<Filter>),
+    /// Satisfied when any of the given filters are (in particular never when there are none).
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    /// The given column is equal to the given value.
+    pub fn eq(column: &str, value: impl IntoDbValue) -> Self {
+        Filter::Eq(column.to_string(), value.into_db_value())
+    }
+
+    /// The given column is not equal to the given value.
+    pub fn not_eq(column: &str, value: impl IntoDbValue) -> Self {
+        Filter::NotEq(column.to_string(), value.into_db_value())
+    }
+
+    /// The given column is less than the given value.
+    pub fn lt(column: &str, value: impl IntoDbValue) -> Self {
+        Filter::Lt(column.to_string(), value.into_db_value())
+    }
+
+    /// The given column is less than or equal to the given value.
+    pub fn lt_eq(column: &str, value: impl IntoDbValue) -> Self {
+        Filter::LtEq(column.to_string(), value.into_db_value())
+    }
+
+    /// The given column is greater than the given value.
+    pub fn gt(column: &str, value: impl IntoDbValue) -> Self {
+        Filter::Gt(column.to_string(), value.into_db_value())
+    }
+
+    /// The given column is greater than or equal to the given value.
+    pub fn gt_eq(column: &str, value: impl IntoDbValue) -> Self {
+        Filter::GtEq(column.to_string(), value.into_db_value())
+    }
+
+    /// The given column is equal to one of the given values.
+    pub fn is_in<T: IntoDbValue>(column: &str, values: impl IntoIterator<Item = T>) -> Self {
+        Filter::In(
+            column.to_string(),
+            values
+                .into_iter()
+                .map(|value| value.into_db_value())
+                .collect(),
+        )
+    }
+
+    /// The given column is NULL.
+    pub fn is_null(column: &str) -> Self {
+        Filter::IsNull(column.to_string())
+    }
+
+    /// The given column is not NULL.
+    pub fn is_not_null(column: &str) -> Self {
+        Filter::IsNotNull(column.to_string())
+    }
+
+    /// Both this filter and the given filter are satisfied.
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Filter::And(mut filters) => {
+                filters.push(other);
+                Filter::And(filters)
+            }
+            filter => Filter::And(vec![filter, other]),
+        }
+    }
+
+    /// Either this filter or the given filter is satisfied.
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Filter::Or(mut filters) => {
+                filters.push(other);
+                Filter::Or(filters)
+            }
+            filter => Filter::Or(vec![filter, other]),
+        }
+    }
+
+    /// Render this filter as a SQL condition on a table with the given columns, appending the
+    /// values to be bound to `params`. Parameters are numbered following the ones that are
+    /// already in `params`.
+    pub fn to_sql(
+        &self,
+        kind: &dyn DbKind,
+        column_map: &ColumnMap,
+        params: &mut Vec<DbValue>,
+    ) -> Result<String, DbError> {
+        // Quote the given column and convert the given value to the column's type, then bind it:
+        let bind = |column: &str, value: &DbValue, params: &mut Vec<DbValue>| {
+            let sql_type = get_sql_type(column_map, column)?;
+            params.push(convert_value(kind, sql_type, value)?);
+            Ok::<_, DbError>((
+                quote_column(column),
+                format!("{}{}", kind.param_prefix(), params.len()),
+            ))
+        };
+        let sql = match self {
+            // `column = NULL` is never satisfied, so we render comparisons with NULL as tests
+            // for NULL instead:
+            Filter::Eq(column, DbValue::Null) => {
+                Filter::is_null(column).to_sql(kind, column_map, params)?
+            }
+            Filter::NotEq(column, DbValue::Null) => {
+                Filter::is_not_null(column).to_sql(kind, column_map, params)?
+            }
+            Filter::Eq(column, value) => {
+                let (column, param) = bind(column, value, params)?;
+                format!("{column} = {param}")
+            }
+            Filter::NotEq(column, value) => {
+                let (column, param) = bind(column, value, params)?;
+                format!("{column} <> {param}")
+            }
+            Filter::Lt(column, value) => {
+                let (column, param) = bind(column, value, params)?;
+                format!("{column} < {param}")
+            }
+            Filter::LtEq(column, value) => {
+                let (column, param) = bind(column, value, params)?;
+                format!("{column} <= {param}")
+            }
+            Filter::Gt(column, value) => {
+                let (column, param) = bind(column, value, params)?;
+                format!("{column} > {param}")
+            }
+            Filter::GtEq(column, value) => {
+                let (column, param) = bind(column, value, params)?;
+                format!("{column} >= {param}")
+            }
+            // PostgreSQL does not accept an empty IN list, so we render that case as a condition
+            // that is never satisfied:
+            Filter::In(column, values) if values.is_empty() => {
+                get_sql_type(column_map, column)?;
+                "1 = 0".to_string()
+            }
+            Filter::In(column, values) if params.len() + values.len() > kind.max_params() => {
+                return Err(DbError::InputError(format!(
+                    "Too many values ({}) in IN filter on column '{column}': at most {} \
+                     parameters can be bound to a {kind} query. Split the values into smaller \
+                     lists and combine the resulting filters using Filter::or()",
+                    values.len(),
+                    kind.max_params(),
+                )));
+            }
+            Filter::In(column, values) => {
+                let mut quoted_column = String::new();
+                let mut bound_params = vec![];
+                for value in values {
+                    let (column, param) = bind(column, value, params)?;
+                    quoted_column = column;
+                    bound_params.push(param);
+                }
+                format!("{quoted_column} IN ({})", bound_params.join(", "))
+            }
+            Filter::IsNull(column) => {
+                get_sql_type(column_map, column)?;
+                format!("{} IS NULL", quote_column(column))
+            }
+            Filter::IsNotNull(column) => {
+                get_sql_type(column_map, column)?;
+                format!("{} IS NOT NULL", quote_column(column))
+            }
+            Filter::And(filters) if filters.is_empty() => "1 = 1".to_string(),
+            Filter::Or(filters) if filters.is_empty() => "1 = 0".to_string(),
+            Filter::And(filters) | Filter::Or(filters) => {
+                let separator = match self {
+                    Filter::And(_) => " AND ",
+                    _ => " OR ",
+                };
+                let mut conditions = vec![];
+                for filter in filters {
+                    conditions.push(format!("({})", filter.to_sql(kind, column_map, params)?));
+                }
+                conditions.join(separator)
+            }
+            Filter::Not(filter) => format!("NOT ({})", filter.to_sql(kind, column_map, params)?),
+        };
+        Ok(sql)
+    }
+}
+
+impl std::ops::Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Self::Output {
+        Filter::Not(Box::new(self))
+    }
+}
+
+// Quote the given column, which may be qualified with a table name (e.g., `table.column`) and
+// may be `*`.
+pub(crate) fn quote_column(column: &str) -> String {
+    column
+        .split('.')
+        .map(|part| match part {
+            "*" => part.to_string(),
+            part => format!(r#""{part}""#),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+// Get the SQL type of the given column from the given column map.
+fn get_sql_type<'a>(column_map: &'a ColumnMap, column: &str) -> Result<&'a str, DbError> {
+    column_map
+        .get(column)
+        .map(|sql_type| sql_type.as_str())
+        .ok_or(DbError::InputError(format!("No such column '{column}'")))
+}
+
+/// Set the columns of the given table to the given values in every row that satisfies the given
+/// filter.
+pub async fn update_where(
+    pool: &impl DbQuery,
+    table: &str,
+    values: &DbRow,
+    filter: &Filter,
+) -> Result<(), DbError> {
+    let table = validate_table_name(table)?;
+    if values.is_empty() {
+        return Err(DbError::InputError(format!(
+            "No values to set in table '{table}'"
+        )));
+    }
+    let column_map = pool.columns(&table).await?;
+    if column_map.is_empty() {
+        return Err(DbError::InputError(format!("No such table '{table}'")));
+    }
+    // Note that the database kind must not be held across an await:
+    let (sql, params) = {
+        let kind = pool.kind();
+        let mut params = vec![];
+        let mut set_clause = vec![];
+        for (column, value) in values.iter() {
+            let sql_type = get_sql_type(&column_map, column)?;
+            params.push(convert_value(kind.as_ref(), sql_type, value)?);
+            set_clause.push(format!(
+                r#""{column}" = {}{}"#,
+                kind.param_prefix(),
+                params.len()
+            ));
+        }
+        let condition = filter.to_sql(kind.as_ref(), &column_map, &mut params)?;
+        let sql = format!(
+            r#"UPDATE "{table}" SET {} WHERE {condition}"#,
+            set_clause.join(", ")
+        );
+        (sql, params)
+    };
+    pool.execute_no_cache_clean(&sql, params).await?;
+    clear_cache_for_edited_tables(pool, &[&table]).await
+}
+
+/// Delete every row of the given table that satisfies the given filter.
+pub async fn delete_where(
+    pool: &impl DbQuery,
+    table: &str,
+    filter: &Filter,
+) -> Result<(), DbError> {
+    let table = validate_table_name(table)?;
+    let column_map = pool.columns(&table).await?;
+    if column_map.is_empty() {
+        return Err(DbError::InputError(format!("No such table '{table}'")));
+    }
+    let mut params = vec![];
+    let condition = filter.to_sql(pool.kind().as_ref(), &column_map, &mut params)?;
+    let sql = format!(r#"DELETE FROM "{table}" WHERE {condition}"#);
+    pool.execute_no_cache_clean(&sql, params).await?;
+    clear_cache_for_edited_tables(pool, &[&table]).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        any::AnyPool,
+        db_kind::{MAX_PARAMS_SQLITE, PostgreSQLKind, SQLiteKind},
+        db_row,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_filter_to_sql() {
+        let column_map = ColumnMap::from([
+            ("id".to_string(), "bigint".to_string()),
+            ("status".to_string(), "text".to_string()),
+        ]);
+        let filter = (Filter::lt("id", "10").and(Filter::is_in("status", ["a", "b"])))
+            .or(!Filter::is_null("status"));
+
+        let mut params = vec![DbValue::from("x")];
+        assert_eq!(
+            filter
+                .to_sql(&SQLiteKind {}, &column_map, &mut params)
+                .unwrap(),
+            r#"(("id" < ?2) AND ("status" IN (?3, ?4))) OR (NOT ("status" IS NULL))"#
+        );
+        assert_eq!(
+            params,
+            [
+                DbValue::from("x"),
+                DbValue::BigInteger(10),
+                DbValue::from("a"),
+                DbValue::from("b"),
+            ]
+        );
+
+        let mut params = vec![];
+        assert_eq!(
+            Filter::is_in("id", Vec::<i64>::new())
+                .and(Filter::Or(vec![]))
+                .to_sql(&PostgreSQLKind {}, &column_map, &mut params)
+                .unwrap(),
+            "(1 = 0) AND (1 = 0)"
+        );
+
+        let mut params = vec![];
+        assert_eq!(
+            Filter::eq("status", DbValue::Null)
+                .or(Filter::not_eq("id", DbValue::Null))
+                .to_sql(&SQLiteKind {}, &column_map, &mut params)
+                .unwrap(),
+            r#"("status" IS NULL) OR ("id" IS NOT NULL)"#
+        );
+        assert_eq!(params, []);
+
+        let too_many = vec![1_i64; MAX_PARAMS_SQLITE + 1];
+        assert!(
+            Filter::is_in("id", too_many.clone())
+                .to_sql(&SQLiteKind {}, &column_map, &mut params)
+                .is_err()
+        );
+        assert!(
+            Filter::is_in("id", too_many[1..].to_vec())
+                .to_sql(&SQLiteKind {}, &column_map, &mut params)
+                .is_ok()
+        );
+        assert!(
+            Filter::eq("no_such_column", 1_i64)
+                .to_sql(&PostgreSQLKind {}, &column_map, &mut params)
+                .is_err()
+        );
+        assert!(
+            Filter::eq("id", "not a number")
+                .to_sql(&PostgreSQLKind {}, &column_map, &mut params)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete_where() {
+        #[cfg(feature = "rusqlite")]
+        update_and_delete_where(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        update_and_delete_where("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        update_and_delete_where(":memory:").await;
+    }
+
+    async fn update_and_delete_where(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.drop_table("test_filter").await.unwrap();
+        pool.execute(
+            r#"CREATE TABLE test_filter (
+                 id BIGINT PRIMARY KEY,
+                 created INTEGER,
+                 status TEXT
+               )"#,
+            (),
+        )
+        .await
+        .unwrap();
+        pool.insert(
+            "test_filter",
+            &["id", "created", "status"],
+            &(1..=6_i64)
+                .map(|i| db_row! {"id" => i, "created" => (i * 10) as i32, "status" => "active"})
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap();
+        let select = "SELECT status FROM test_filter ORDER BY id";
+
+        // The values are converted to the types of the columns:
+        pool.update_where(
+            "test_filter",
+            &db_row! {"status" => "archived"},
+            &Filter::lt("created", 30_i64).or(Filter::eq("id", "6")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            pool.query(select, ()).await.unwrap().to_strings().unwrap(),
+            [
+                "archived", "archived", "active", "active", "active", "archived"
+            ]
+        );
+
+        pool.update_where(
+            "test_filter",
+            &db_row! {"status" => DbValue::Null},
+            &Filter::is_in("id", [3_i64, 4]),
+        )
+        .await
+        .unwrap();
+        assert!(
+            pool.update_where(
+                "test_filter",
+                &db_row! {"no_such_column" => 1_i64},
+                &Filter::And(vec![]),
+            )
+            .await
+            .is_err()
+        );
+
+        pool.delete_where(
+            "test_filter",
+            &Filter::eq("status", "archived").or(Filter::is_null("status")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            pool.query("SELECT id FROM test_filter", ())
+                .await
+                .unwrap()
+                .to_strings()
+                .unwrap(),
+            ["5"]
+        );
+
+        // Clean up:
+        pool.drop_table("test_filter").await.unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+//! Code for managing indexes.
+
+use crate::{
+    core::{DbError, DbQuery},
+    parse::validate_table_name,
+};
+
+/// An index on one or more columns of a table.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Index {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    /// Whether the index enforces the uniqueness of the indexed values.
+    pub unique: bool,
+    /// A SQL condition restricting the index to the rows that satisfy it (a partial index).
+    pub condition: Option<String>,
+}
+
+impl Index {
+    /// Define a (non-unique, non-partial) index with the given name on the given columns of the
+    /// given table.
+    pub fn new(name: &str, table: &str, columns: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            table: table.to_string(),
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Make this a unique index.
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    /// Make this a partial index, restricted to the rows that satisfy the given SQL condition.
+    pub fn partial(mut self, condition: &str) -> Self {
+        self.condition = Some(condition.to_string());
+        self
+    }
+}
+
+/// Create the given index, if an index with the same name does not already exist.
+pub async fn create_index(pool: &impl DbQuery, index: &Index) -> Result<(), DbError> {
+    if index.columns.is_empty() {
+        return Err(DbError::InputError(format!(
+            "No columns specified for index '{}'",
+            index.name
+        )));
+    }
+    // Column names must satisfy the same constraints as table names:
+    let index = Index {
+        name: validate_table_name(&index.name)?,
+        table: validate_table_name(&index.table)?,
+        columns: index
+            .columns
+            .iter()
+            .map(|column| validate_table_name(column))
+            .collect::<Result<Vec<_>, _>>()?,
+        ..index.clone()
+    };
+    let sql = pool.kind().create_index_sql(&index);
+    pool.execute_no_cache_clean(&sql, ()).await
+}
+
+/// Drop the given index, if it exists.
+pub async fn drop_index(pool: &impl DbQuery, index: &str) -> Result<(), DbError> {
+    let index = validate_table_name(index)?;
+    let sql = pool.kind().drop_index_sql(&index);
+    pool.execute_no_cache_clean(&sql, ()).await
+}
+
+/// List the indexes that have been explicitly created on the given table, in order of name. Note
+/// that the indexes that the database creates implicitly in order to implement primary key and
+/// unique constraints are not included.
+pub async fn list_indexes(pool: &impl DbQuery, table: &str) -> Result<Vec<Index>, DbError> {
+    let table = validate_table_name(table)?;
+    let (sql, params) = pool.kind().list_indexes_sql(&table);
+    let rows = pool.query_no_cache_clean(&sql, params).await?;
+    let mut indexes: Vec<Index> = vec![];
+    for row in rows.iter() {
+        let get = |column: &str| {
+            row.get(column).ok_or(DbError::DataError(format!(
+                "No column '{column}' in row {row:?}"
+            )))
+        };
+        let name = get("index_name")?.to_string();
+        let column = get("column_name")?.to_string();
+        match indexes.last_mut() {
+            Some(index) if index.name == name => index.columns.push(column),
+            _ => {
+                let is_unique: u64 = get("is_unique")?.try_into()?;
+                let condition = get("condition")?;
+                indexes.push(Index {
+                    name,
+                    table: table.to_string(),
+                    columns: vec![column],
+                    unique: is_unique != 0,
+                    condition: match condition.is_null() {
+                        true => None,
+                        false => Some(condition.to_string().trim().to_string()),
+                    },
+                });
+            }
+        };
+    }
+    Ok(indexes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::any::AnyPool;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_indexes() {
+        #[cfg(feature = "rusqlite")]
+        indexes(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        indexes("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        indexes(":memory:").await;
+    }
+
+    async fn indexes(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.drop_table("test_indexes").await.unwrap();
+        pool.execute(
+            r#"CREATE TABLE test_indexes (
+                 id BIGINT PRIMARY KEY,
+                 name TEXT UNIQUE,
+                 city TEXT,
+                 age BIGINT
+               )"#,
+            (),
+        )
+        .await
+        .unwrap();
+        assert_eq!(pool.list_indexes("test_indexes").await.unwrap(), vec![]);
+
+        let by_city = Index::new("test_indexes_city_age", "test_indexes", &["city", "age"]);
+        let adults = Index::new("test_indexes_adults", "test_indexes", &["name"])
+            .unique()
+            .partial("age >= 18");
+        pool.create_index(&by_city).await.unwrap();
+        pool.create_index(&adults).await.unwrap();
+        // Creating an index that already exists does nothing:
+        pool.create_index(&by_city).await.unwrap();
+        assert!(
+            pool.create_index(&Index::new("test_indexes_none", "test_indexes", &[]))
+                .await
+                .is_err()
+        );
+        assert!(
+            pool.create_index(&Index::new(
+                "test_indexes_bad",
+                "test_indexes",
+                &["city\" TEXT); --"]
+            ))
+            .await
+            .is_err()
+        );
+        // Double-quoted names are accepted, and the quotes are stripped:
+        pool.create_index(&Index::new(
+            r#""test_indexes_quoted""#,
+            r#""test_indexes""#,
+            &[r#""age""#],
+        ))
+        .await
+        .unwrap();
+        pool.drop_index("test_indexes_quoted").await.unwrap();
+
+        let indexes = pool.list_indexes("test_indexes").await.unwrap();
+        assert_eq!(indexes.len(), 2);
+        assert_eq!(indexes[1], by_city);
+        assert_eq!(indexes[0].name, adults.name);
+        assert_eq!(indexes[0].columns, adults.columns);
+        assert!(indexes[0].unique);
+        // The database may normalize the condition, e.g., by adding parentheses:
+        assert!(indexes[0].condition.as_ref().unwrap().contains("18"));
+
+        pool.drop_index("test_indexes_city_age").await.unwrap();
+        // Dropping an index that does not exist does nothing:
+        pool.drop_index("test_indexes_city_age").await.unwrap();
+        assert_eq!(pool.list_indexes("test_indexes").await.unwrap().len(), 1);
+
+        // Clean up:
+        pool.drop_table("test_indexes").await.unwrap();
+    }
+}
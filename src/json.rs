@@ -0,0 +1,366 @@
+//! Code for importing rows into tables from, and exporting query results to, JSON Lines (one
+//! JSON object per line) and JSON arrays of objects.
+//!
+//! Imported values are converted to the types of the table's columns (see [DbType::convert()]). A
+//! line that cannot be parsed, that is not an object, that is an empty object, that refers to a
+//! column that is not in the table, or whose values cannot be converted, is skipped and reported in
+//! the returned [ImportReport] rather than causing the whole import to fail. Only the columns that
+//! appear as keys in at least one of the objects in a batch are inserted, so columns that do not
+//! appear in any of them are given their default values, while keys that are missing from an object
+//! but present in others are inserted as NULL. Rows are inserted in batches of
+//! [BULK_INSERT_BATCH_SIZE] using [DbQuery::bulk_insert()], so errors reported by the database
+//! itself (e.g., constraint violations) do cause the import to fail, leaving any earlier batches in
+//! place.
+//!
+//! [DbType::convert()]: crate::db_kind::DbType::convert()
+
+use crate::{
+    core::{DbError, DbQuery},
+    db_value::{ColumnMap, DbRow, IntoDbRows, JsonRow, JsonValue},
+    paginate::for_each_page,
+    select::Select,
+    shared::{BULK_INSERT_BATCH_SIZE, convert_value},
+};
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// An input line (or, for a JSON array, an element) that could not be imported.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LineError {
+    /// The number of the line, or of the array element, starting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+/// The outcome of an import.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ImportReport {
+    /// The number of rows that were inserted.
+    pub inserted: u64,
+    /// The lines that were skipped, in order.
+    pub errors: Vec<LineError>,
+}
+
+/// Import the JSON Lines read from the given reader into the given table, which must exist,
+/// reading and inserting the rows one batch at a time. Blank lines are ignored.
+pub async fn import_jsonl(
+    pool: &impl DbQuery,
+    table: &str,
+    reader: impl Read,
+) -> Result<ImportReport, DbError> {
+    let columns = get_table_columns(pool, table).await?;
+    let mut report = ImportReport::default();
+    let mut batch = vec![];
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line =
+            line.map_err(|err| DbError::DataError(format!("Error reading {table}: {err}")))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<JsonValue>(&line) {
+            Ok(value) => batch.push((i + 1, value)),
+            Err(err) => report.errors.push(LineError {
+                line: i + 1,
+                message: format!("Invalid JSON: {err}"),
+            }),
+        }
+        if batch.len() >= BULK_INSERT_BATCH_SIZE {
+            insert_batch(
+                pool,
+                table,
+                &columns,
+                std::mem::take(&mut batch),
+                &mut report,
+            )
+            .await?;
+        }
+    }
+    insert_batch(pool, table, &columns, batch, &mut report).await?;
+    report.errors.sort_by_key(|error| error.line);
+    Ok(report)
+}
+
+/// Import the JSON array of objects read from the given reader into the given table, which must
+/// exist. Unlike [import_jsonl()], the whole array is parsed before any rows are inserted, and
+/// the array must be valid JSON. Errors are reported by the position of the offending element.
+pub async fn import_json(
+    pool: &impl DbQuery,
+    table: &str,
+    reader: impl Read,
+) -> Result<ImportReport, DbError> {
+    let columns = get_table_columns(pool, table).await?;
+    let values: Vec<JsonValue> = serde_json::from_reader(reader)
+        .map_err(|err| DbError::DataError(format!("Error reading {table}: {err}")))?;
+    let mut report = ImportReport::default();
+    let mut values = values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| (i + 1, value));
+    loop {
+        let batch = values
+            .by_ref()
+            .take(BULK_INSERT_BATCH_SIZE)
+            .collect::<Vec<_>>();
+        if batch.is_empty() {
+            report.errors.sort_by_key(|error| error.line);
+            return Ok(report);
+        }
+        insert_batch(pool, table, &columns, batch, &mut report).await?;
+    }
+}
+
+// Get the columns of the given table, which must exist.
+async fn get_table_columns(pool: &impl DbQuery, table: &str) -> Result<ColumnMap, DbError> {
+    let columns = pool.columns(table).await?;
+    match columns.is_empty() {
+        true => Err(DbError::InputError(format!("No such table: {table}"))),
+        false => Ok(columns),
+    }
+}
+
+// Convert the given JSON values, numbered by line, to rows of the given table with the given
+// columns, and insert those that are valid, recording the others in the given report.
+async fn insert_batch(
+    pool: &impl DbQuery,
+    table: &str,
+    columns: &ColumnMap,
+    batch: Vec<(usize, JsonValue)>,
+    report: &mut ImportReport,
+) -> Result<(), DbError> {
+    let mut lines = vec![];
+    let mut json_rows: Vec<JsonRow> = vec![];
+    for (line, value) in batch {
+        match value {
+            JsonValue::Object(json_row) => {
+                lines.push(line);
+                json_rows.push(json_row);
+            }
+            _ => report.errors.push(LineError {
+                line,
+                message: "Not a JSON object".to_string(),
+            }),
+        }
+    }
+
+    let rows = {
+        let kind = pool.kind();
+        let mut rows = vec![];
+        for (line, row) in lines.into_iter().zip(json_rows.into_db_rows().content) {
+            let convert_row = |row: DbRow| -> Result<DbRow, DbError> {
+                if row.is_empty() {
+                    return Err(DbError::InputError("Empty JSON object".to_string()));
+                }
+                if let Some(column) = row.keys().find(|column| !columns.contains_key(*column)) {
+                    return Err(DbError::InputError(format!(
+                        "No column '{column}' in table '{table}'"
+                    )));
+                }
+                let mut converted = DbRow::new();
+                for (column, sql_type) in columns {
+                    if let Some(value) = row.get(column) {
+                        converted
+                            .insert(column.to_string(), convert_value(&*kind, sql_type, &value)?);
+                    }
+                }
+                Ok(converted)
+            };
+            match convert_row(row) {
+                Ok(row) => rows.push(row),
+                Err(err) => report.errors.push(LineError {
+                    line,
+                    message: err.to_string(),
+                }),
+            }
+        }
+        rows
+    };
+
+    if !rows.is_empty() {
+        let columns = columns
+            .keys()
+            .filter(|column| rows.iter().any(|row| row.contains_key(*column)))
+            .map(|column| column.as_str())
+            .collect::<Vec<_>>();
+        report.inserted += pool
+            .bulk_insert(table, &columns, rows.into_iter(), |_| ())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Write the rows selected by the given [Select] to the given writer as JSON Lines, retrieving
+/// them one page at a time in the same way as [crate::delimited::export_delimited()].
+pub async fn export_jsonl(
+    pool: &impl DbQuery,
+    select: &Select,
+    mut writer: impl Write,
+) -> Result<(), DbError> {
+    for_each_page(pool, select, |rows| {
+        for row in rows.content {
+            let json_row: JsonRow = row.into();
+            serde_json::to_writer(&mut writer, &json_row).map_err(write_error)?;
+            writer.write_all(b"\n").map_err(write_error)?;
+        }
+        Ok(())
+    })
+    .await?;
+    writer.flush().map_err(write_error)
+}
+
+/// Write the rows selected by the given [Select] to the given writer as a JSON array of objects,
+/// with one object per line, retrieving them one page at a time in the same way as
+/// [crate::delimited::export_delimited()].
+pub async fn export_json(
+    pool: &impl DbQuery,
+    select: &Select,
+    mut writer: impl Write,
+) -> Result<(), DbError> {
+    writer.write_all(b"[").map_err(write_error)?;
+    let mut separator = "\n";
+    for_each_page(pool, select, |rows| {
+        for row in rows.content {
+            let json_row: JsonRow = row.into();
+            writer
+                .write_all(separator.as_bytes())
+                .map_err(write_error)?;
+            serde_json::to_writer(&mut writer, &json_row).map_err(write_error)?;
+            separator = ",\n";
+        }
+        Ok(())
+    })
+    .await?;
+    writer.write_all(b"\n]\n").map_err(write_error)?;
+    writer.flush().map_err(write_error)
+}
+
+// Convert the given error, encountered while writing rows, to a [DbError].
+fn write_error(err: impl std::fmt::Display) -> DbError {
+    DbError::DataError(format!("Error writing rows: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{any::AnyPool, filter::Filter};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_import_export_json() {
+        #[cfg(feature = "rusqlite")]
+        import_export_json(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        import_export_json("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        import_export_json(":memory:").await;
+    }
+
+    async fn import_export_json(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.drop_table("test_json").await.unwrap();
+        pool.execute_batch(
+            "CREATE TABLE test_json (\
+               id BIGINT PRIMARY KEY,\
+               name TEXT,\
+               score DOUBLE PRECISION DEFAULT 0\
+             )",
+        )
+        .await
+        .unwrap();
+
+        // Invalid lines are reported, and the others are imported:
+        let jsonl = [
+            r#"{"id": 1, "name": "a", "score": 1.5}"#,
+            r#"{"id": "2", "score": "2.5"}"#,
+            "",
+            r#"{"id": 3, "name": "c""#,
+            r#"[4, "d"]"#,
+            r#"{"id": 5, "nickname": "e"}"#,
+            r#"{"id": "six", "name": "f"}"#,
+            r#"{"id": 7, "name": 7, "score": null}"#,
+        ]
+        .join("\n");
+        let report = import_jsonl(&pool, "test_json", jsonl.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(report.inserted, 3);
+        assert_eq!(
+            report
+                .errors
+                .iter()
+                .map(|error| error.line)
+                .collect::<Vec<_>>(),
+            [4, 5, 6, 7]
+        );
+        assert!(report.errors[0].message.starts_with("Invalid JSON"));
+        assert_eq!(report.errors[1].message, "Not a JSON object");
+
+        let mut output = vec![];
+        export_jsonl(&pool, &Select::new("test_json"), &mut output)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            [
+                r#"{"id":1,"name":"a","score":1.5}"#,
+                r#"{"id":2,"name":null,"score":2.5}"#,
+                r#"{"id":7,"name":"7","score":null}"#,
+                "",
+            ]
+            .join("\n")
+        );
+
+        // JSON arrays:
+        let json = r#"[{"id": 8, "name": "h"}, "i", {"id": 9.5}, {}]"#;
+        let report = import_json(&pool, "test_json", json.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(report.inserted, 1);
+        assert_eq!(
+            report
+                .errors
+                .iter()
+                .map(|error| error.line)
+                .collect::<Vec<_>>(),
+            [2, 3, 4]
+        );
+        assert_eq!(report.errors[2].message, "Empty JSON object");
+        // Columns that are missing from all of the objects in a batch get their default values:
+        let rows = pool
+            .query("SELECT score FROM test_json WHERE id = 8", ())
+            .await
+            .unwrap();
+        assert_eq!(rows.to_strings().unwrap(), ["0"]);
+        assert!(
+            import_json(&pool, "test_json", "[{}".as_bytes())
+                .await
+                .is_err()
+        );
+
+        let mut output = vec![];
+        let select = Select::new("test_json")
+            .columns(&["id", "name"])
+            .filter(Filter::gt("id", 2_i64));
+        export_json(&pool, &select, &mut output).await.unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "[\n{\"id\":7,\"name\":\"7\"},\n{\"id\":8,\"name\":\"h\"}\n]\n"
+        );
+        assert!(serde_json::from_str::<JsonValue>(&output).is_ok());
+
+        let mut output = vec![];
+        let select = Select::new("test_json").filter(Filter::gt("id", 100_i64));
+        export_json(&pool, &select, &mut output).await.unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "[\n]\n");
+
+        // The table must exist:
+        assert!(
+            import_jsonl(&pool, "test_json_missing", "{}".as_bytes())
+                .await
+                .is_err()
+        );
+
+        // Clean up:
+        pool.drop_table("test_json").await.unwrap();
+    }
+}
@@ -1,11 +1,26 @@
 //! Module declarations and macro definitions for rltbl_db.
 
+pub mod alter;
 pub mod any;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
 pub mod cache;
+pub mod copy;
 pub mod core;
 pub mod db_kind;
 pub mod db_value;
+pub mod delimited;
+pub mod dump;
+pub mod filter;
+pub mod index;
+pub mod json;
+pub mod migrate;
+pub mod paginate;
 pub mod parse;
+pub mod schema;
+pub mod select;
 pub mod serde;
 pub mod shared;
 
@@ -2,19 +2,25 @@
 
 use crate::{
     any::AnyPool,
-    cache::{CachingStrategy, clear_cache_for_dropped_tables},
+    cache::{
+        CacheCounters, CacheEncoding, CachingStrategy, MemoryCaches,
+        clear_cache_for_dropped_tables, clear_cache_for_edited_tables, sqlite_database_identity,
+    },
     core::{DbError, DbQuery},
     db_kind::{DbKind, MAX_PARAMS_SQLITE, SQLiteKind},
     db_value::{DbParams, DbRow, DbRows, DbValue, IntoDbParams, IntoDbRows, JsonValue},
     parse::validate_table_name,
-    shared::{EditType, edit},
+    shared::{
+        BULK_INSERT_BATCH_SIZE, EditType, UpsertOptions, edit, generate_bulk_insert_statement,
+        get_bulk_insert_types, get_bulk_insert_values,
+    },
 };
 use deadpool_libsql::{
     Manager, Pool,
     libsql::{Builder, Value},
 };
 use rust_decimal::prelude::ToPrimitive;
-use std::str::from_utf8;
+use std::{str::from_utf8, sync::Arc, time::Duration};
 
 impl TryFrom<Value> for DbValue {
     type Error = DbError;
@@ -86,12 +92,20 @@ impl TryFrom<DbParams> for Vec<Value> {
 #[derive(Clone, Debug)]
 pub struct LibSQLPool {
     pool: Pool,
+    memory_caches: Arc<MemoryCaches>,
+    cache_counters: Arc<CacheCounters>,
     caching_strategy: CachingStrategy,
     /// When set to true, SQL statements sent to the [DbQuery::query()] and [DbQuery::execute()]
     /// functions will be parsed and if they will result in tables being edited and/or dropped,
     /// the cache will be maintained in accordance with the given [CachingStrategy].
     /// For further information, see [DbQuery::set_cache_aware_query()].
     cache_aware_query: bool,
+    /// The maximum age of the cached query results returned by [DbQuery::cache()], if any.
+    /// For further information, see [DbQuery::set_cache_ttl()].
+    cache_ttl: Option<Duration>,
+    /// The encoding in which cached query results are stored in the query cache table.
+    /// For further information, see [DbQuery::set_cache_encoding()].
+    cache_encoding: CacheEncoding,
 }
 
 impl LibSQLPool {
@@ -106,8 +120,12 @@ impl LibSQLPool {
         })?;
         Ok(Self {
             pool: pool,
+            memory_caches: MemoryCaches::for_database(sqlite_database_identity(url).as_deref()),
+            cache_counters: Arc::default(),
             caching_strategy: CachingStrategy::None,
             cache_aware_query: false,
+            cache_ttl: None,
+            cache_encoding: CacheEncoding::default(),
         })
     }
 }
@@ -122,11 +140,25 @@ impl DbQuery for LibSQLPool {
     fn pool(&self) -> AnyPool {
         AnyPool::LibSQL(LibSQLPool {
             pool: self.pool.clone(),
+            memory_caches: self.memory_caches.clone(),
+            cache_counters: self.cache_counters.clone(),
             caching_strategy: self.caching_strategy,
             cache_aware_query: self.cache_aware_query,
+            cache_ttl: self.cache_ttl,
+            cache_encoding: self.cache_encoding,
         })
     }
 
+    /// Implements [DbQuery::memory_caches()] for SQLite.
+    fn memory_caches(&self) -> Arc<MemoryCaches> {
+        self.memory_caches.clone()
+    }
+
+    /// Implements [DbQuery::cache_counters()] for SQLite.
+    fn cache_counters(&self) -> Arc<CacheCounters> {
+        self.cache_counters.clone()
+    }
+
     /// Implements [DbQuery::set_caching_strategy()] for SQLite.
     fn set_caching_strategy(&mut self, strategy: &CachingStrategy) {
         self.caching_strategy = *strategy;
@@ -147,6 +179,26 @@ impl DbQuery for LibSQLPool {
         self.cache_aware_query
     }
 
+    /// Implements [DbQuery::set_cache_ttl()] for SQLite.
+    fn set_cache_ttl(&mut self, ttl: Option<Duration>) {
+        self.cache_ttl = ttl;
+    }
+
+    /// Implements [DbQuery::get_cache_ttl()] for SQLite.
+    fn get_cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl
+    }
+
+    /// Implements [DbQuery::set_cache_encoding()] for SQLite.
+    fn set_cache_encoding(&mut self, encoding: CacheEncoding) {
+        self.cache_encoding = encoding;
+    }
+
+    /// Implements [DbQuery::get_cache_encoding()] for SQLite.
+    fn get_cache_encoding(&self) -> CacheEncoding {
+        self.cache_encoding
+    }
+
     /// Implements [DbQuery::execute_batch()] for SQLite
     async fn execute_batch(&self, sql: &str) -> Result<(), DbError> {
         let conn = self
@@ -162,6 +214,26 @@ impl DbQuery for LibSQLPool {
         }
     }
 
+    /// Implements [DbQuery::execute_batch_with_recovery()] for SQLite.
+    async fn execute_batch_with_recovery(
+        &self,
+        sql: &str,
+        recovery: &[&str],
+    ) -> Result<(), DbError> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|err| DbError::ConnectError(format!("Error getting from pool: {err}")))?;
+        if let Err(err) = conn.execute_batch(sql).await {
+            for sql in recovery {
+                conn.execute_batch(sql).await.ok();
+            }
+            return Err(DbError::DatabaseError(format!("Error during query: {err}")));
+        }
+        Ok(())
+    }
+
     /// Implements [DbQuery::query_no_cache_clean()] for SQLite.
     async fn query_no_cache_clean(
         &self,
@@ -295,7 +367,7 @@ impl DbQuery for LibSQLPool {
     ) -> Result<(), DbError> {
         edit(
             self,
-            &EditType::Upsert,
+            &EditType::Upsert(UpsertOptions::default()),
             &MAX_PARAMS_SQLITE,
             table,
             columns,
@@ -317,7 +389,7 @@ impl DbQuery for LibSQLPool {
     ) -> Result<DbRows, DbError> {
         edit(
             self,
-            &EditType::Upsert,
+            &EditType::Upsert(UpsertOptions::default()),
             &MAX_PARAMS_SQLITE,
             table,
             columns,
@@ -328,6 +400,148 @@ impl DbQuery for LibSQLPool {
         .await
     }
 
+    /// Implements [DbQuery::bulk_insert()] for SQLite. The rows are inserted using a single
+    /// prepared statement, executed repeatedly within one transaction.
+    async fn bulk_insert(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl Iterator<Item = DbRow> + Send,
+        mut progress: impl FnMut(u64) + Send,
+    ) -> Result<u64, DbError> {
+        let (table, sql_types) = get_bulk_insert_types(self, table, columns).await?;
+        let sql = generate_bulk_insert_statement(&table, columns, SQLiteKind.param_prefix());
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|err| DbError::ConnectError(format!("Error getting from pool: {err}")))?;
+
+        conn.execute_batch("BEGIN")
+            .await
+            .map_err(|err| DbError::DatabaseError(format!("Error during query: {err}")))?;
+        let mut count = 0;
+        let result: Result<(), DbError> = async {
+            let stmt = conn.prepare(&sql).await.map_err(|err| {
+                DbError::DatabaseError(format!("Error preparing statement: {err}"))
+            })?;
+            for row in rows {
+                let values = get_bulk_insert_values(&SQLiteKind, &row, columns, &sql_types)?;
+                let values: Vec<Value> = DbParams::Positional(values).try_into()?;
+                stmt.execute(values)
+                    .await
+                    .map_err(|err| DbError::DatabaseError(format!("Error during query: {err}")))?;
+                stmt.reset();
+                count += 1;
+                if count % BULK_INSERT_BATCH_SIZE as u64 == 0 {
+                    progress(count);
+                }
+            }
+            Ok(())
+        }
+        .await;
+        match result {
+            Ok(()) => {
+                conn.execute_batch("COMMIT")
+                    .await
+                    .map_err(|err| DbError::DatabaseError(format!("Error during query: {err}")))?;
+            }
+            Err(err) => {
+                // Report the error that caused the rollback rather than any error from the
+                // rollback itself:
+                let _ = conn.execute_batch("ROLLBACK").await;
+                return Err(err);
+            }
+        };
+        drop(conn);
+        if count == 0 || count % BULK_INSERT_BATCH_SIZE as u64 != 0 {
+            progress(count);
+        }
+
+        clear_cache_for_edited_tables(self, &[&table]).await?;
+        Ok(count)
+    }
+
+    /// Implements [DbQuery::upsert_with()] for SQLite.
+    async fn upsert_with(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl IntoDbRows,
+        options: &UpsertOptions,
+    ) -> Result<(), DbError> {
+        edit(
+            self,
+            &EditType::Upsert(options.clone()),
+            &MAX_PARAMS_SQLITE,
+            table,
+            columns,
+            rows,
+            false,
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Implements [DbQuery::upsert_with_returning()] for SQLite.
+    async fn upsert_with_returning(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl IntoDbRows,
+        options: &UpsertOptions,
+        returning: &[&str],
+    ) -> Result<DbRows, DbError> {
+        edit(
+            self,
+            &EditType::Upsert(options.clone()),
+            &MAX_PARAMS_SQLITE,
+            table,
+            columns,
+            rows,
+            true,
+            returning,
+        )
+        .await
+    }
+
+    /// Implements [DbQuery::delete()] for SQLite.
+    async fn delete(&self, table: &str, rows: impl IntoDbRows) -> Result<(), DbError> {
+        edit(
+            self,
+            &EditType::Delete,
+            &MAX_PARAMS_SQLITE,
+            table,
+            &[],
+            rows,
+            false,
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Implements [DbQuery::delete_returning()] for SQLite.
+    async fn delete_returning(
+        &self,
+        table: &str,
+        rows: impl IntoDbRows,
+        returning: &[&str],
+    ) -> Result<DbRows, DbError> {
+        edit(
+            self,
+            &EditType::Delete,
+            &MAX_PARAMS_SQLITE,
+            table,
+            &[],
+            rows,
+            true,
+            returning,
+        )
+        .await
+    }
+
     /// Implements [DbQuery::drop_table()] for SQLite.
     async fn drop_table(&self, table: &str) -> Result<(), DbError> {
         let table = validate_table_name(table)?;
@@ -0,0 +1,637 @@
+//! Code for applying versioned schema migrations.
+//!
+//! A [Migrator] holds an ordered set of [Migration]s, each of which has an `up` step, used to
+//! apply it, and a `down` step, used to revert it. The versions of the migrations that have been
+//! applied to a database are recorded in the [MIGRATIONS_TABLE], along with a checksum of the
+//! steps that were used to apply them, so that changes to migrations that have already been
+//! applied can be detected. A lock, held in the [MIGRATIONS_LOCK_TABLE], prevents two processes
+//! from migrating the same database at the same time.
+//!
+//! A SQL step is run in the same transaction as the statement that records (or erases the record
+//! of) its migration, so that a failure leaves the migration neither half applied nor half
+//! reverted. SQL steps therefore must not begin or end transactions of their own, nor include
+//! statements that cannot be run in a transaction (e.g., PostgreSQL's `CREATE INDEX
+//! CONCURRENTLY`). Rust function steps may use any of the pool's connections, and so are run
+//! outside of a transaction, before their migration is recorded.
+//!
+//! ```
+//! use rltbl_db::{any::AnyPool, core::{DbError, DbQuery}, migrate::{Migration, Migrator}};
+//!
+//! async fn example() -> Result<u64, DbError> {
+//!     let pool = AnyPool::connect("test_migrate.db").await?;
+//!     let migrator = Migrator::new(vec![
+//!         Migration::sql(
+//!             1,
+//!             "create person",
+//!             "CREATE TABLE person ( name TEXT PRIMARY KEY )",
+//!             "DROP TABLE person",
+//!         ),
+//!         Migration::function(
+//!             2,
+//!             "add alice",
+//!             |pool| Box::pin(async move {
+//!                 pool.execute("INSERT INTO person VALUES ('alice')", ()).await
+//!             }),
+//!             |pool| Box::pin(async move {
+//!                 pool.execute("DELETE FROM person WHERE name = 'alice'", ()).await
+//!             }),
+//!         ),
+//!     ])?;
+//!     migrator.migrate_latest(&pool).await?;
+//!     migrator.current_version(&pool).await
+//! }
+//! ```
+
+use crate::{
+    any::AnyPool,
+    core::{DbError, DbQuery},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The name of the table used to keep track of the migrations that have been applied.
+pub static MIGRATIONS_TABLE: &str = "rltbl_db_migrations";
+
+/// The name of the table used to prevent concurrent migrations.
+pub static MIGRATIONS_LOCK_TABLE: &str = "rltbl_db_migrations_lock";
+
+/// Default length of time to wait for another process to release the migration lock.
+pub static DEFAULT_MIGRATION_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Length of time to wait between attempts to acquire the migration lock.
+static LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The type of the Rust functions that can be used as [MigrationStep]s. The futures they return
+/// must be `Send`, so that migrations can be run from within any task.
+pub type MigrationFn = Arc<
+    dyn for<'a> Fn(&'a AnyPool) -> Pin<Box<dyn Future<Output = Result<(), DbError>> + Send + 'a>>
+        + Send
+        + Sync,
+>;
+
+/// One of the two steps (up or down) of a [Migration].
+#[derive(Clone)]
+pub enum MigrationStep {
+    /// A step that does nothing.
+    None,
+    /// Semicolon-delimited SQL statements, to be run using [DbQuery::execute_batch()].
+    Sql(String),
+    /// A Rust function that will be passed the pool being migrated.
+    Function(MigrationFn),
+}
+
+impl std::fmt::Debug for MigrationStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationStep::None => write!(f, "None"),
+            MigrationStep::Sql(sql) => write!(f, "Sql({sql:?})"),
+            MigrationStep::Function(_) => write!(f, "Function(..)"),
+        }
+    }
+}
+
+impl MigrationStep {
+    /// Run this step against the given pool, followed by the given statement, which records that
+    /// the step has been run. If this is a SQL step, both are run in a single transaction.
+    async fn run(&self, pool: &AnyPool, record_sql: &str) -> Result<(), DbError> {
+        match self {
+            MigrationStep::None => pool.execute_no_cache_clean(record_sql, ()).await,
+            MigrationStep::Sql(sql) => {
+                let sql = sql.trim().trim_end_matches(';');
+                pool.execute_batch_with_recovery(
+                    &format!("BEGIN;\n{sql};\n{record_sql};\nCOMMIT"),
+                    &["ROLLBACK"],
+                )
+                .await
+            }
+            MigrationStep::Function(function) => {
+                function(pool).await?;
+                pool.execute_no_cache_clean(record_sql, ()).await
+            }
+        }
+    }
+
+    /// The content of this step that contributes to the checksum of its [Migration]. Since the
+    /// code of a Rust function cannot be inspected, only SQL steps can be verified.
+    fn checksum_content(&self) -> String {
+        match self {
+            MigrationStep::None => "none".to_string(),
+            MigrationStep::Sql(sql) => format!("sql:{sql}"),
+            MigrationStep::Function(_) => "function".to_string(),
+        }
+    }
+}
+
+/// A versioned change to a database schema, along with the step needed to revert it.
+#[derive(Clone, Debug)]
+pub struct Migration {
+    /// The version of the migration. Versions must be unique and greater than zero.
+    pub version: u64,
+    /// A short description of the migration.
+    pub name: String,
+    /// The step used to apply the migration.
+    pub up: MigrationStep,
+    /// The step used to revert the migration.
+    pub down: MigrationStep,
+}
+
+impl Migration {
+    /// Define a migration whose steps are the given SQL statements.
+    pub fn sql(version: u64, name: &str, up: &str, down: &str) -> Self {
+        Self {
+            version,
+            name: name.to_string(),
+            up: MigrationStep::Sql(up.to_string()),
+            down: MigrationStep::Sql(down.to_string()),
+        }
+    }
+
+    /// Define a migration whose steps are the given Rust functions.
+    pub fn function<U, D>(version: u64, name: &str, up: U, down: D) -> Self
+    where
+        U: for<'a> Fn(
+                &'a AnyPool,
+            )
+                -> Pin<Box<dyn Future<Output = Result<(), DbError>> + Send + 'a>>
+            + Send
+            + Sync
+            + 'static,
+        D: for<'a> Fn(
+                &'a AnyPool,
+            )
+                -> Pin<Box<dyn Future<Output = Result<(), DbError>> + Send + 'a>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            version,
+            name: name.to_string(),
+            up: MigrationStep::Function(Arc::new(up)),
+            down: MigrationStep::Function(Arc::new(down)),
+        }
+    }
+
+    /// Returns the hex-encoded SHA-256 checksum of this migration's steps.
+    pub fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.up.checksum_content());
+        hasher.update("\n");
+        hasher.update(self.down.checksum_content());
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+}
+
+/// A record, read from the [MIGRATIONS_TABLE], of a migration that has been applied.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AppliedMigration {
+    pub version: u64,
+    pub name: String,
+    pub checksum: String,
+    /// The epoch time at which the migration was applied.
+    pub applied_at: u64,
+}
+
+/// Applies and reverts an ordered set of [Migration]s.
+#[derive(Clone, Debug)]
+pub struct Migrator {
+    migrations: Vec<Migration>,
+    lock_timeout: Duration,
+}
+
+impl Migrator {
+    /// Create a new migrator for the given migrations, which need not be given in order.
+    pub fn new(mut migrations: Vec<Migration>) -> Result<Self, DbError> {
+        migrations.sort_by_key(|migration| migration.version);
+        if let Some(first) = migrations.first()
+            && first.version == 0
+        {
+            return Err(DbError::InputError(format!(
+                "Invalid version 0 for migration '{}'. Versions must be greater than 0.",
+                first.name
+            )));
+        }
+        for pair in migrations.windows(2) {
+            if pair[0].version == pair[1].version {
+                return Err(DbError::InputError(format!(
+                    "Duplicate migration version {} ('{}' and '{}')",
+                    pair[0].version, pair[0].name, pair[1].name
+                )));
+            }
+        }
+        Ok(Self {
+            migrations,
+            lock_timeout: DEFAULT_MIGRATION_LOCK_TIMEOUT,
+        })
+    }
+
+    /// Set the length of time to wait for another process to release the migration lock.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// The migrations managed by this migrator, in order of version.
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// The highest version among the migrations managed by this migrator, or 0 if there are none.
+    pub fn latest_version(&self) -> u64 {
+        self.migrations
+            .last()
+            .map(|migration| migration.version)
+            .unwrap_or(0)
+    }
+
+    /// Retrieve the migrations that have been applied to the given database, in order of version.
+    pub async fn applied_migrations(
+        &self,
+        pool: &impl DbQuery,
+    ) -> Result<Vec<AppliedMigration>, DbError> {
+        ensure_migration_tables_exist(pool).await?;
+        let rows = pool
+            .query_no_cache_clean(
+                &format!(
+                    r#"SELECT "version", "name", "checksum", "applied_at"
+                       FROM "{MIGRATIONS_TABLE}"
+                       ORDER BY "version""#
+                ),
+                (),
+            )
+            .await?;
+        let get = |row: &crate::db_value::DbRow, column: &str| {
+            row.get(column).ok_or(DbError::DataError(format!(
+                "No column '{column}' in row {row:?} from {MIGRATIONS_TABLE}"
+            )))
+        };
+        rows.iter()
+            .map(|row| {
+                Ok(AppliedMigration {
+                    version: get(row, "version")?.try_into()?,
+                    name: get(row, "name")?.to_string(),
+                    checksum: get(row, "checksum")?.to_string(),
+                    applied_at: match get(row, "applied_at")? {
+                        value if value.is_null() => 0,
+                        value => value.try_into()?,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// The highest version of the migrations that have been applied to the given database, or 0
+    /// if none have been applied.
+    pub async fn current_version(&self, pool: &impl DbQuery) -> Result<u64, DbError> {
+        Ok(self
+            .applied_migrations(pool)
+            .await?
+            .last()
+            .map(|applied| applied.version)
+            .unwrap_or(0))
+    }
+
+    /// Verify that every migration that has been applied to the given database is managed by this
+    /// migrator, and that its checksum has not changed since it was applied.
+    pub async fn verify(&self, pool: &impl DbQuery) -> Result<(), DbError> {
+        let applied = self.applied_migrations(pool).await?;
+        self.verify_applied(&applied)
+    }
+
+    /// Apply all of the migrations that have not yet been applied to the given database.
+    pub async fn migrate_latest(&self, pool: &impl DbQuery) -> Result<(), DbError> {
+        self.migrate_to(pool, self.latest_version()).await
+    }
+
+    /// Migrate the given database to the given version, by applying the migrations up to and
+    /// including that version that have not yet been applied, and reverting, in reverse order,
+    /// those with a higher version that have been. Migrating to version 0 reverts all migrations.
+    pub async fn migrate_to(&self, pool: &impl DbQuery, version: u64) -> Result<(), DbError> {
+        if version != 0 && !self.migrations.iter().any(|m| m.version == version) {
+            return Err(DbError::InputError(format!(
+                "No migration with version {version}"
+            )));
+        }
+        ensure_migration_tables_exist(pool).await?;
+        acquire_migration_lock(pool, &self.lock_timeout).await?;
+        // Make sure to release the lock whether or not the migration succeeds, but report an
+        // error in the migration before any error in releasing the lock:
+        let result = self.migrate_to_while_locked(&pool.pool(), version).await;
+        let released = release_migration_lock(pool).await;
+        result?;
+        released
+    }
+
+    // Implements the body of [Migrator::migrate_to()] under the assumption that the caller
+    // holds the migration lock.
+    async fn migrate_to_while_locked(&self, pool: &AnyPool, version: u64) -> Result<(), DbError> {
+        let applied = self.applied_migrations(pool).await?;
+        self.verify_applied(&applied)?;
+        let applied_versions = applied.iter().map(|a| a.version).collect::<Vec<_>>();
+
+        // Revert the applied migrations that are newer than the target version, newest first.
+        // Since the statements recording the migrations may be run in a batch, along with SQL
+        // steps, their values are given as literals rather than as parameters:
+        for migration in self.migrations.iter().rev() {
+            if migration.version > version && applied_versions.contains(&migration.version) {
+                let record_sql = format!(
+                    r#"DELETE FROM "{MIGRATIONS_TABLE}" WHERE "version" = {}"#,
+                    migration.version
+                );
+                migration.down.run(pool, &record_sql).await.map_err(|err| {
+                    DbError::DatabaseError(format!(
+                        "Error reverting migration {} ('{}'): {err}",
+                        migration.version, migration.name
+                    ))
+                })?;
+            }
+        }
+
+        // Apply the migrations, up to the target version, that have not been applied yet:
+        for migration in self.migrations.iter() {
+            if migration.version <= version && !applied_versions.contains(&migration.version) {
+                let record_sql = format!(
+                    r#"INSERT INTO "{MIGRATIONS_TABLE}" ("version", "name", "checksum")
+                       VALUES ({}, '{}', '{}')"#,
+                    migration.version,
+                    migration.name.replace('\'', "''"),
+                    migration.checksum()
+                );
+                migration.up.run(pool, &record_sql).await.map_err(|err| {
+                    DbError::DatabaseError(format!(
+                        "Error applying migration {} ('{}'): {err}",
+                        migration.version, migration.name
+                    ))
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    // Verify the given applied migrations against the migrations managed by this migrator.
+    fn verify_applied(&self, applied: &[AppliedMigration]) -> Result<(), DbError> {
+        let migrations = self
+            .migrations
+            .iter()
+            .map(|migration| (migration.version, migration))
+            .collect::<BTreeMap<_, _>>();
+        for applied in applied {
+            match migrations.get(&applied.version) {
+                None => {
+                    return Err(DbError::DataError(format!(
+                        "Applied migration {} ('{}') is unknown to this migrator",
+                        applied.version, applied.name
+                    )));
+                }
+                Some(migration) if migration.checksum() != applied.checksum => {
+                    return Err(DbError::DataError(format!(
+                        "Checksum mismatch for migration {} ('{}'): it has been modified \
+                         since it was applied",
+                        applied.version, applied.name
+                    )));
+                }
+                Some(_) => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Ensure that the [MIGRATIONS_TABLE] and the [MIGRATIONS_LOCK_TABLE] exist.
+pub async fn ensure_migration_tables_exist(pool: &impl DbQuery) -> Result<(), DbError> {
+    let statements = [
+        pool.kind().create_migrations_table_sql(),
+        pool.kind().create_migrations_lock_table_sql(),
+    ];
+    for sql in statements {
+        pool.execute_no_cache_clean(&sql, ()).await?;
+    }
+    Ok(())
+}
+
+/// Forcibly release the migration lock. This should only be needed if a process that was holding
+/// the lock exited before it could release it.
+pub async fn force_unlock(pool: &impl DbQuery) -> Result<(), DbError> {
+    ensure_migration_tables_exist(pool).await?;
+    release_migration_lock(pool).await
+}
+
+// Acquire the migration lock, waiting for up to the given length of time for another process
+// to release it. Errors other than the lock being held are returned immediately.
+async fn acquire_migration_lock(pool: &impl DbQuery, timeout: &Duration) -> Result<(), DbError> {
+    // No row is returned if the lock is already held:
+    let sql = format!(
+        r#"INSERT INTO "{MIGRATIONS_LOCK_TABLE}" ("id") VALUES (1)
+           ON CONFLICT ("id") DO NOTHING
+           RETURNING "id""#
+    );
+    let start = Instant::now();
+    loop {
+        if !pool.query_no_cache_clean(&sql, ()).await?.is_empty() {
+            return Ok(());
+        }
+        if start.elapsed() >= *timeout {
+            return Err(DbError::DatabaseError(format!(
+                "Unable to acquire the migration lock within {timeout:?}"
+            )));
+        }
+        tokio::time::sleep(LOCK_RETRY_INTERVAL).await;
+    }
+}
+
+// Release the migration lock.
+async fn release_migration_lock(pool: &impl DbQuery) -> Result<(), DbError> {
+    pool.execute_no_cache_clean(&format!(r#"DELETE FROM "{MIGRATIONS_LOCK_TABLE}""#), ())
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_migrations() {
+        #[cfg(feature = "rusqlite")]
+        migrations(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        migrations("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        migrations(":memory:").await;
+    }
+
+    async fn count_rows(pool: &AnyPool) -> u64 {
+        pool.query("SELECT COUNT(1) FROM test_migrations", ())
+            .await
+            .unwrap()
+            .value()
+            .unwrap()
+            .try_into()
+            .unwrap()
+    }
+
+    async fn migrations(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.drop_table(MIGRATIONS_TABLE).await.unwrap();
+        pool.drop_table(MIGRATIONS_LOCK_TABLE).await.unwrap();
+        pool.drop_table("test_migrations").await.unwrap();
+
+        let migration_1 = Migration::sql(
+            1,
+            "create table",
+            "CREATE TABLE test_migrations ( name TEXT PRIMARY KEY, age BIGINT )",
+            "DROP TABLE test_migrations",
+        );
+        let migration_2 = Migration::sql(
+            2,
+            "add bob",
+            "INSERT INTO test_migrations VALUES ('bob', 40)",
+            "DELETE FROM test_migrations WHERE name = 'bob'",
+        );
+        let migration_3 = Migration::function(
+            3,
+            "add alice",
+            |pool| {
+                Box::pin(async move {
+                    pool.execute("INSERT INTO test_migrations VALUES ('alice', 30)", ())
+                        .await
+                })
+            },
+            |pool| {
+                Box::pin(async move {
+                    pool.execute("DELETE FROM test_migrations WHERE name = 'alice'", ())
+                        .await
+                })
+            },
+        );
+        // The migrations need not be given in order:
+        let migrator = Migrator::new(vec![
+            migration_3.clone(),
+            migration_1.clone(),
+            migration_2.clone(),
+        ])
+        .unwrap();
+        assert_eq!(migrator.latest_version(), 3);
+        assert_eq!(migrator.current_version(&pool).await.unwrap(), 0);
+
+        migrator.migrate_latest(&pool).await.unwrap();
+        assert_eq!(migrator.current_version(&pool).await.unwrap(), 3);
+        assert_eq!(count_rows(&pool).await, 2);
+        let applied = migrator.applied_migrations(&pool).await.unwrap();
+        assert_eq!(
+            applied.iter().map(|a| a.version).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(applied[0].checksum, migration_1.checksum());
+
+        // Migrating again should do nothing:
+        migrator.migrate_latest(&pool).await.unwrap();
+        assert_eq!(count_rows(&pool).await, 2);
+
+        // Migrate down:
+        migrator.migrate_to(&pool, 1).await.unwrap();
+        assert_eq!(migrator.current_version(&pool).await.unwrap(), 1);
+        assert_eq!(count_rows(&pool).await, 0);
+
+        // Changing a migration that has already been applied should be detected:
+        let modified = Migrator::new(vec![
+            Migration::sql(
+                1,
+                "create table",
+                "CREATE TABLE test_migrations ( name TEXT PRIMARY KEY )",
+                "DROP TABLE test_migrations",
+            ),
+            migration_2.clone(),
+        ])
+        .unwrap();
+        assert!(matches!(
+            modified.verify(&pool).await,
+            Err(DbError::DataError(_))
+        ));
+        assert!(modified.migrate_latest(&pool).await.is_err());
+        assert_eq!(migrator.current_version(&pool).await.unwrap(), 1);
+
+        // Migrating to an unknown version is an error:
+        assert!(matches!(
+            migrator.migrate_to(&pool, 4).await,
+            Err(DbError::InputError(_))
+        ));
+
+        // Duplicate versions are not allowed:
+        assert!(Migrator::new(vec![migration_1.clone(), migration_1.clone()]).is_err());
+
+        // Migrations cannot proceed while another process holds the lock:
+        pool.execute(
+            &format!(r#"INSERT INTO "{MIGRATIONS_LOCK_TABLE}" ("id") VALUES (1)"#),
+            (),
+        )
+        .await
+        .unwrap();
+        let impatient = migrator
+            .clone()
+            .with_lock_timeout(Duration::from_millis(250));
+        assert!(impatient.migrate_latest(&pool).await.is_err());
+        assert_eq!(migrator.current_version(&pool).await.unwrap(), 1);
+        force_unlock(&pool).await.unwrap();
+        impatient.migrate_latest(&pool).await.unwrap();
+        assert_eq!(migrator.current_version(&pool).await.unwrap(), 3);
+
+        // Errors other than the lock being held are not retried until the timeout:
+        pool.drop_table(MIGRATIONS_LOCK_TABLE).await.unwrap();
+        let start = Instant::now();
+        assert!(
+            acquire_migration_lock(&pool, &Duration::from_secs(10))
+                .await
+                .is_err()
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+        ensure_migration_tables_exist(&pool).await.unwrap();
+
+        // A SQL step that fails is rolled back, along with the record of its migration:
+        let failing = Migrator::new(vec![
+            migration_1.clone(),
+            migration_2.clone(),
+            migration_3.clone(),
+            Migration::sql(
+                4,
+                "fail",
+                "CREATE TABLE test_migrations_failed ( name TEXT );\
+                 INSERT INTO no_such_table VALUES (1);",
+                "DROP TABLE test_migrations_failed",
+            ),
+        ])
+        .unwrap();
+        assert!(failing.migrate_latest(&pool).await.is_err());
+        assert_eq!(failing.current_version(&pool).await.unwrap(), 3);
+        assert!(!pool.table_exists("test_migrations_failed").await.unwrap());
+
+        // Migrations can be run in a task of their own:
+        let task = {
+            let pool = pool.clone();
+            let migrator = migrator.clone();
+            tokio::spawn(async move { migrator.migrate_to(&pool, 2).await })
+        };
+        task.await.unwrap().unwrap();
+        assert_eq!(migrator.current_version(&pool).await.unwrap(), 2);
+
+        // Revert everything:
+        migrator.migrate_to(&pool, 0).await.unwrap();
+        assert_eq!(migrator.current_version(&pool).await.unwrap(), 0);
+        assert!(!pool.table_exists("test_migrations").await.unwrap());
+
+        // Clean up:
+        pool.drop_table(MIGRATIONS_TABLE).await.unwrap();
+        pool.drop_table(MIGRATIONS_LOCK_TABLE).await.unwrap();
+    }
+}
@@ -0,0 +1,465 @@
+//! Code for retrieving the rows selected by a query one page at a time.
+//!
+//! Where possible, pages are retrieved using keyset pagination, i.e., by filtering on the values
+//! of the sort columns in the last row of the previous page, which is efficient regardless of how
+//! far into the results the page is. This requires the sort columns to uniquely identify a row,
+//! which we ensure by requiring them to include the primary key of the selected table, and to be
+//! NOT NULL, since rows with NULL sort values would not satisfy the filter and would be skipped.
+//! It also requires the query not to join other tables, since a joined table may contribute more
+//! than one row for each primary key of the selected table. Otherwise (or if the previous page
+//! ended with a NULL sort value) we fall back to using an OFFSET.
+//! Pages and total counts are retrieved through the query cache (see [DbQuery::cache_tables()]).
+
+use crate::{
+    core::{DbError, DbQuery},
+    db_value::{DbRows, DbValue},
+    filter::Filter,
+    select::{Aggregate, Order, Select, SelectColumn},
+};
+use serde::{Deserialize, Serialize};
+
+/// Identifies where the next page of results begins.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Cursor {
+    /// The number of rows that precede the next page.
+    pub offset: u64,
+    /// The values of the sort columns in the last row of the previous page, if keyset
+    /// pagination is possible.
+    pub keyset: Option<Vec<DbValue>>,
+}
+
+/// A page of results.
+#[derive(Clone, Debug)]
+pub struct Page {
+    pub rows: DbRows,
+    /// The total number of rows selected by the query, across all pages.
+    pub total: u64,
+    /// The cursor to use to retrieve the next page, or None if this is the last page.
+    pub next: Option<Cursor>,
+}
+
+/// Retrieve the page of at most `page_size` rows, selected by the given query, that begins at the
+/// given cursor, or the first page if no cursor is given. The rows are sorted according to the
+/// query's ORDER BY columns, and any LIMIT or OFFSET in the query is ignored.
+pub async fn paginate(
+    pool: &impl DbQuery,
+    select: &Select,
+    page_size: u64,
+    cursor: Option<&Cursor>,
+) -> Result<Page, DbError> {
+    if page_size == 0 {
+        return Err(DbError::InputError(
+            "Page size must be positive".to_string(),
+        ));
+    }
+
+    let (rows, next) = fetch_page(pool, select, page_size, cursor, true).await?;
+    let total = count(pool, select).await?;
+    Ok(Page { rows, total, next })
+}
+
+// Retrieve the page of rows described by [paginate()], together with the cursor for the next
+// page, through the query cache if `use_cache` is true.
+pub(crate) async fn fetch_page(
+    pool: &impl DbQuery,
+    select: &Select,
+    page_size: u64,
+    cursor: Option<&Cursor>,
+    use_cache: bool,
+) -> Result<(DbRows, Option<Cursor>), DbError> {
+    let keyset_columns = get_keyset_columns(pool, select).await?;
+    let mut page_select = Select {
+        limit: Some(page_size + 1),
+        offset: None,
+        ..select.clone()
+    };
+    let offset = cursor.map(|cursor| cursor.offset).unwrap_or_default();
+    match (
+        &keyset_columns,
+        cursor.and_then(|cursor| cursor.keyset.as_ref()),
+    ) {
+        (Some(columns), Some(values)) if columns.len() == values.len() => {
+            page_select = page_select.filter(keyset_filter(columns, values));
+        }
+        _ if offset > 0 => page_select.offset = Some(offset),
+        _ => (),
+    };
+
+    let (sql, params) = page_select.render(pool).await?;
+    let mut rows = match use_cache {
+        true => pool.cache_tables(&select.tables(), &sql, &params).await?,
+        false => pool.query(&sql, params).await?,
+    };
+
+    let next = match rows.len() as u64 > page_size {
+        false => None,
+        true => {
+            rows.truncate(page_size as usize);
+            let last_row = rows.last().expect("There is at least one row");
+            let keyset = keyset_columns.and_then(|columns| {
+                columns
+                    .iter()
+                    .map(|(column, _)| match last_row.get(unqualified(column)) {
+                        Some(DbValue::Null) | None => None,
+                        Some(value) => Some(value),
+                    })
+                    .collect::<Option<Vec<_>>>()
+            });
+            Some(Cursor {
+                offset: offset + page_size,
+                keyset,
+            })
+        }
+    };
+
+    Ok((rows, next))
+}
+
+/// The number of rows retrieved from the database at a time when exporting the results of a
+/// query (see [crate::delimited] and [crate::json]).
+pub static EXPORT_PAGE_SIZE: u64 = 10_000;
+
+// Call the given function on each page of [EXPORT_PAGE_SIZE] rows selected by the given query,
+// bypassing the query cache, or on all of its rows at once if the query has a LIMIT or OFFSET.
+// If the query has no ORDER BY clause, the rows are sorted by the primary keys of the selected
+// table and of any joined tables so that the pages are consistent, or, if one of them has no
+// primary key (or the query aggregates its rows), they are all retrieved at once. The function
+// is called at least once, even if the query selects no rows.
+pub(crate) async fn for_each_page(
+    pool: &impl DbQuery,
+    select: &Select,
+    mut handle_page: impl FnMut(DbRows) -> Result<(), DbError>,
+) -> Result<(), DbError> {
+    if select.limit.is_some() || select.offset.is_some() {
+        return handle_page(pool.select(select).await?);
+    }
+    let mut select = select.clone();
+    if select.order_by.is_empty() && !is_aggregate(&select) {
+        let mut order_by = vec![];
+        for table in select.tables() {
+            let primary_keys = pool.primary_keys(table).await?;
+            if primary_keys.is_empty() {
+                order_by.clear();
+                break;
+            }
+            order_by.extend(primary_keys.iter().map(|pkey| format!("{table}.{pkey}")));
+        }
+        for column in order_by {
+            select = select.order_by(&column);
+        }
+    }
+    if select.order_by.is_empty() {
+        return handle_page(pool.select(&select).await?);
+    }
+    let mut cursor = None;
+    loop {
+        let (rows, next) =
+            fetch_page(pool, &select, EXPORT_PAGE_SIZE, cursor.as_ref(), false).await?;
+        handle_page(rows)?;
+        match next {
+            Some(next) => cursor = Some(next),
+            None => return Ok(()),
+        }
+    }
+}
+
+// Determine whether the given query aggregates its rows.
+fn is_aggregate(select: &Select) -> bool {
+    !select.group_by.is_empty()
+        || select
+            .columns
+            .iter()
+            .any(|column| matches!(column, SelectColumn::Aggregate { .. }))
+}
+
+// Count the rows selected by the given query.
+async fn count(pool: &impl DbQuery, select: &Select) -> Result<u64, DbError> {
+    let base_select = Select {
+        order_by: vec![],
+        limit: None,
+        offset: None,
+        ..select.clone()
+    };
+    let rows = match is_aggregate(select) {
+        // The number of rows is the number of groups, so we need to count them in a subquery:
+        true => {
+            let (sql, params) = base_select.render(pool).await?;
+            let sql = format!(r#"SELECT COUNT(*) AS "count" FROM ({sql}) AS "rltbl_db_page""#);
+            pool.cache_tables(&select.tables(), &sql, &params).await?
+        }
+        false => {
+            let count_select = Select {
+                columns: vec![],
+                ..base_select
+            }
+            .aggregate(Aggregate::Count, "*", "count");
+            let (sql, params) = count_select.render(pool).await?;
+            pool.cache_tables(&select.tables(), &sql, &params).await?
+        }
+    };
+    rows.value()?.clone().try_into()
+}
+
+// Get the sort columns of the given query if they can be used for keyset pagination, i.e., if the
+// query does not aggregate or join other tables, and its sort columns include the primary key of
+// the selected table, are all among the selected columns, and are all NOT NULL columns of the
+// selected table.
+async fn get_keyset_columns(
+    pool: &impl DbQuery,
+    select: &Select,
+) -> Result<Option<Vec<(String, Order)>>, DbError> {
+    if select.order_by.is_empty() || !select.group_by.is_empty() || !select.joins.is_empty() {
+        return Ok(None);
+    }
+    let mut selected = vec![];
+    for column in &select.columns {
+        match column {
+            SelectColumn::Column(column) => selected.push(unqualified(column)),
+            SelectColumn::Aggregate { .. } => return Ok(None),
+        }
+    }
+    if !selected.is_empty()
+        && !select
+            .order_by
+            .iter()
+            .all(|(column, _)| selected.contains(&unqualified(column)))
+    {
+        return Ok(None);
+    }
+
+    let primary_keys = pool.primary_keys(&select.table).await?;
+    let is_sorted_by_primary_key = !primary_keys.is_empty()
+        && primary_keys.iter().all(|pkey| {
+            select.order_by.iter().any(|(column, _)| {
+                *column == *pkey || *column == format!("{}.{pkey}", select.table)
+            })
+        });
+    if !is_sorted_by_primary_key {
+        return Ok(None);
+    }
+
+    // Primary key columns are treated as NOT NULL. For the other sort columns we need to consult
+    // the database's metadata:
+    let mut other_columns = vec![];
+    for (column, _) in &select.order_by {
+        let column = match column.split_once('.') {
+            Some((table, column)) if table == select.table => column,
+            Some(_) => return Ok(None),
+            None => column.as_str(),
+        };
+        if !primary_keys.iter().any(|pkey| pkey == column) {
+            other_columns.push(column);
+        }
+    }
+    if !other_columns.is_empty() {
+        let (sql, params) = pool.kind().table_columns_sql(&select.table);
+        let rows = pool.query_no_cache_clean(&sql, params).await?;
+        let is_not_null = |column: &str| {
+            rows.iter().any(|row| {
+                row.get("column_name")
+                    .map(|name| name.to_string())
+                    .as_deref()
+                    == Some(column)
+                    && row.get("not_null").and_then(|value| value.try_into().ok()) == Some(1_u64)
+            })
+        };
+        if !other_columns.into_iter().all(is_not_null) {
+            return Ok(None);
+        }
+    }
+    Ok(Some(select.order_by.clone()))
+}
+
+// Generate a filter that is satisfied by the rows that follow the row with the given values for
+// the given sort columns, i.e., (c1 > v1) OR (c1 = v1 AND c2 > v2) OR ...
+fn keyset_filter(columns: &[(String, Order)], values: &[DbValue]) -> Filter {
+    let mut alternatives = vec![];
+    for (i, (column, order)) in columns.iter().enumerate() {
+        let mut conditions = columns[..i]
+            .iter()
+            .zip(values)
+            .map(|((column, _), value)| Filter::Eq(column.to_string(), value.clone()))
+            .collect::<Vec<_>>();
+        conditions.push(match order {
+            Order::Asc => Filter::Gt(column.to_string(), values[i].clone()),
+            Order::Desc => Filter::Lt(column.to_string(), values[i].clone()),
+        });
+        alternatives.push(Filter::And(conditions));
+    }
+    Filter::Or(alternatives)
+}
+
+// Remove the table name, if any, from the given column name.
+fn unqualified(column: &str) -> &str {
+    column.rsplit('.').next().unwrap_or(column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::any::AnyPool;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_keyset_filter() {
+        let columns = [
+            ("name".to_string(), Order::Asc),
+            ("id".to_string(), Order::Desc),
+        ];
+        assert_eq!(
+            keyset_filter(&columns, &[DbValue::from("b"), DbValue::BigInteger(2)]),
+            Filter::Or(vec![
+                Filter::And(vec![Filter::gt("name", "b")]),
+                Filter::And(vec![Filter::eq("name", "b"), Filter::lt("id", 2_i64)]),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_paginate() {
+        #[cfg(feature = "rusqlite")]
+        paginate(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        paginate("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        paginate(":memory:").await;
+    }
+
+    // Retrieve all of the pages of the given query, returning the names in each page, the total,
+    // and whether each cursor used keyset pagination.
+    async fn get_pages(pool: &AnyPool, select: &Select) -> (Vec<Vec<String>>, u64, Vec<bool>) {
+        let mut pages = vec![];
+        let mut keysets = vec![];
+        let mut total;
+        let mut cursor = None;
+        loop {
+            let page = pool.paginate(select, 3, cursor.as_ref()).await.unwrap();
+            pages.push(
+                page.rows
+                    .iter()
+                    .map(|row| row.get("name").unwrap().to_string())
+                    .collect(),
+            );
+            total = page.total;
+            match page.next {
+                Some(next) => {
+                    keysets.push(next.keyset.is_some());
+                    cursor = Some(next);
+                }
+                None => break,
+            }
+        }
+        (pages, total, keysets)
+    }
+
+    async fn paginate(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.drop_table("test_paginate").await.unwrap();
+        pool.execute_batch(
+            r#"CREATE TABLE test_paginate (
+                 id BIGINT PRIMARY KEY,
+                 name TEXT NOT NULL,
+                 team TEXT
+               );
+               INSERT INTO test_paginate VALUES (1, 'g', 'x'), (2, 'a', 'y'), (3, 'f', 'x'),
+                 (4, 'b', 'y'), (5, 'e', 'x'), (6, 'c', 'y'), (7, 'd', 'x'), (8, 'h', NULL)"#,
+        )
+        .await
+        .unwrap();
+
+        // Keyset pagination:
+        let select = Select::new("test_paginate")
+            .filter(Filter::lt("id", 8_i64))
+            .order_by("name")
+            .order_by("id");
+        assert_eq!(
+            get_pages(&pool, &select).await,
+            (
+                vec![
+                    vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                    vec!["d".to_string(), "e".to_string(), "f".to_string()],
+                    vec!["g".to_string()],
+                ],
+                7,
+                vec![true, true]
+            )
+        );
+
+        // Offset pagination, since the sort columns do not include the primary key:
+        let select = Select::new("test_paginate")
+            .columns(&["name", "team"])
+            .filter(Filter::is_not_null("team"))
+            .order_by_desc("team")
+            .order_by_desc("name");
+        let (pages, total, keysets) = get_pages(&pool, &select).await;
+        assert_eq!(pages.concat(), ["c", "b", "a", "g", "f", "e", "d"]);
+        assert_eq!(total, 7);
+        assert_eq!(keysets, [false, false]);
+
+        // Offset pagination, since the sort columns include a nullable column. The row with the
+        // NULL sort value comes after the first page in one order or the other (depending on
+        // where the database sorts NULLs), and should not be skipped:
+        for select in [
+            Select::new("test_paginate").order_by("team").order_by("id"),
+            Select::new("test_paginate")
+                .order_by_desc("team")
+                .order_by("id"),
+        ] {
+            let (pages, total, keysets) = get_pages(&pool, &select).await;
+            let mut names = pages.concat();
+            names.sort();
+            assert_eq!(names, ["a", "b", "c", "d", "e", "f", "g", "h"]);
+            assert_eq!(total, 8);
+            assert_eq!(keysets, [false, false]);
+        }
+
+        // The number of rows in a grouped query is the number of groups:
+        let select = Select::new("test_paginate")
+            .column("team")
+            .aggregate(Aggregate::Max, "name", "name")
+            .group_by(&["team"])
+            .order_by("team");
+        let (pages, total, keysets) = get_pages(&pool, &select).await;
+        assert_eq!(pages.concat().len(), 3);
+        assert_eq!(total, 3);
+        assert!(keysets.is_empty());
+
+        assert!(pool.paginate(&select, 0, None).await.is_err());
+
+        // Offset pagination, since a joined table has more than one row for some primary keys of
+        // the selected table. The first page ends in the middle of the rows for team 1:
+        pool.drop_table("test_paginate_member").await.unwrap();
+        pool.drop_table("test_paginate_team").await.unwrap();
+        pool.execute_batch(
+            r#"CREATE TABLE test_paginate_team (
+                 id BIGINT PRIMARY KEY,
+                 label TEXT NOT NULL
+               );
+               INSERT INTO test_paginate_team VALUES (1, 'x'), (2, 'y');
+               CREATE TABLE test_paginate_member (
+                 team_id BIGINT NOT NULL,
+                 name TEXT NOT NULL
+               );
+               INSERT INTO test_paginate_member VALUES (1, 'a'), (1, 'b'), (1, 'c'), (1, 'd'),
+                 (2, 'e')"#,
+        )
+        .await
+        .unwrap();
+        let select = Select::new("test_paginate_team")
+            .columns(&["test_paginate_team.id", "name"])
+            .join("test_paginate_member", "test_paginate_team.id", "team_id")
+            .order_by("test_paginate_team.id");
+        let (pages, total, keysets) = get_pages(&pool, &select).await;
+        let mut names = pages.concat();
+        names.sort();
+        assert_eq!(names, ["a", "b", "c", "d", "e"]);
+        assert_eq!(total, 5);
+        assert_eq!(keysets, [false]);
+
+        // Clean up:
+        pool.drop_table("test_paginate_member").await.unwrap();
+        pool.drop_table("test_paginate_team").await.unwrap();
+        pool.drop_table("test_paginate").await.unwrap();
+    }
+}
@@ -1,7 +1,9 @@
 //! Code for parsing SQL expressions.
 
 use crate::{
-    cache::{QUERY_CACHE_TABLE, TABLE_CACHE_TABLE},
+    cache::{
+        QUERY_CACHE_KEYS_TABLE, QUERY_CACHE_TABLE, QUERY_CACHE_TABLES_TABLE, TABLE_CACHE_TABLE,
+    },
     core::DbError,
 };
 use lazy_static::lazy_static;
@@ -379,6 +381,195 @@ pub fn get_accessed_tables(sql: &str) -> Result<BTreeSet<String>, DbError> {
     Ok(tables_read)
 }
 
+/// A value to which a query's WHERE clause requires a column to be equal.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum KeyValue {
+    /// A literal value, with any quotes removed.
+    Literal(String),
+    /// The (zero-based) index of one of the query's parameters.
+    Parameter(usize),
+}
+
+// Rewrite every parameter placeholder in the given SQL, outside of quoted strings and
+// identifiers, to the numbered form `$N`, which is the only form the parser accepts in all
+// positions. Unnumbered placeholders (`?`) are numbered as SQLite would number them.
+fn normalize_parameters(sql: &str) -> String {
+    let mut normalized = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut quote = None;
+    let mut max_index = 0;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '?' | '$') => {
+                let mut digits = String::new();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                }
+                let index = match digits.parse::<usize>() {
+                    Ok(index) => index,
+                    Err(_) if c == '?' => max_index + 1,
+                    Err(_) => {
+                        normalized.push(c);
+                        continue;
+                    }
+                };
+                max_index = max_index.max(index);
+                normalized.push_str(&format!("${index}"));
+                continue;
+            }
+            _ => (),
+        };
+        normalized.push(c);
+    }
+    normalized
+}
+
+/// Determine whether the given SQL is a single SELECT statement, reading from a single table,
+/// whose WHERE clause restricts the given column to a list of values, either by means of an
+/// equality or an IN condition, possibly in conjunction with other conditions. If so, return
+/// the list of values, otherwise return None.
+pub fn get_key_filter(sql: &str, column: &str) -> Result<Option<Vec<KeyValue>>, DbError> {
+    // Return the text of the given node:
+    fn text<'a>(sql: &'a str, node: &Node<'_>) -> &'a str {
+        &sql[node.start_byte()..node.end_byte()]
+    }
+
+    // Determine whether the given node refers to the given column. Note that the parser
+    // treats a double-quoted column name as a literal.
+    fn is_column(sql: &str, node: &Node<'_>, column: &str) -> bool {
+        match node.kind() {
+            "field" => node
+                .child_by_field_name("name")
+                .is_some_and(|name| text(sql, &name) == column),
+            "literal" => text(sql, node) == format!("\"{column}\""),
+            _ => false,
+        }
+    }
+
+    // Convert the given node to a key value, if it is a single-quoted string, an integer, or
+    // a parameter.
+    fn key_value(sql: &str, node: &Node<'_>) -> Option<KeyValue> {
+        let value = text(sql, node);
+        match node.kind() {
+            "literal" => match value.strip_prefix("'").and_then(|v| v.strip_suffix("'")) {
+                Some(value) => Some(KeyValue::Literal(value.replace("''", "'"))),
+                None => value
+                    .parse::<i64>()
+                    .ok()
+                    .map(|value| KeyValue::Literal(value.to_string())),
+            },
+            "parameter" => value
+                .strip_prefix("$")
+                .and_then(|index| index.parse::<usize>().ok())
+                .and_then(|index| index.checked_sub(1))
+                .map(KeyValue::Parameter),
+            _ => None,
+        }
+    }
+
+    // Determine the values to which the given predicate restricts the given column, if any:
+    fn restriction(sql: &str, predicate: &Node<'_>, column: &str) -> Option<Vec<KeyValue>> {
+        match predicate.kind() {
+            "parenthesized_expression" => predicate
+                .named_child(0)
+                .and_then(|inner| restriction(sql, &inner, column)),
+            "binary_expression" => {
+                let left = predicate.child_by_field_name("left")?;
+                let operator = predicate.child_by_field_name("operator")?;
+                let right = predicate.child_by_field_name("right")?;
+                match operator.kind() {
+                    "keyword_and" => {
+                        restriction(sql, &left, column).or_else(|| restriction(sql, &right, column))
+                    }
+                    "=" if is_column(sql, &left, column) => {
+                        key_value(sql, &right).map(|value| vec![value])
+                    }
+                    "=" if is_column(sql, &right, column) => {
+                        key_value(sql, &left).map(|value| vec![value])
+                    }
+                    "keyword_in" if is_column(sql, &left, column) && right.kind() == "list" => {
+                        right
+                            .named_children(&mut right.walk())
+                            .map(|value| key_value(sql, &value))
+                            .collect()
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Count the SELECT clauses in the tree rooted at the given node:
+    fn count_selects(node: &Node<'_>) -> usize {
+        let selects = node
+            .named_children(&mut node.walk())
+            .map(|child| count_selects(&child))
+            .sum::<usize>();
+        match node.kind() {
+            "select" => selects + 1,
+            _ => selects,
+        }
+    }
+
+    let sql = normalize_parameters(sql);
+    let mut parser = Parser::new();
+    parser
+        .set_language(&SQL_LANGUAGE.into())
+        .map_err(|err| DbError::ParseError(format!("Error setting language to SQL: {err}")))?;
+    let tree = match parser.parse(&sql, None) {
+        Some(tree) => tree,
+        None => return Err(DbError::ParseError(format!("Could not parse '{sql}'"))),
+    };
+    let root_node = tree.root_node();
+    if root_node.has_error() {
+        return Ok(None);
+    }
+
+    // Any subqueries could read rows other than those with the given keys:
+    if count_selects(&root_node) != 1 {
+        return Ok(None);
+    }
+
+    // There must be exactly one statement, consisting of a SELECT clause and a FROM clause:
+    let statements = root_node
+        .named_children(&mut root_node.walk())
+        .collect::<Vec<_>>();
+    let [statement] = statements[..] else {
+        return Ok(None);
+    };
+    let clauses = statement
+        .named_children(&mut statement.walk())
+        .map(|clause| clause.kind())
+        .collect::<Vec<_>>();
+    if statement.kind() != "statement" || clauses != ["select", "from"] {
+        return Ok(None);
+    }
+
+    // The FROM clause must name a single table, without any joins, and have a WHERE clause:
+    let from = statement.named_child(1).expect("Expected a FROM clause");
+    let mut relations = 0;
+    let mut where_clause = None;
+    for child in from.named_children(&mut from.walk()) {
+        match child.kind() {
+            "keyword_from" => (),
+            "relation" => relations += 1,
+            "where" => where_clause = Some(child),
+            "order_by" | "limit" | "group_by" => (),
+            _ => return Ok(None),
+        }
+    }
+    match (relations, where_clause) {
+        (1, Some(where_clause)) => Ok(where_clause
+            .child_by_field_name("predicate")
+            .and_then(|predicate| restriction(&sql, &predicate, column))),
+        _ => Ok(None),
+    }
+}
+
 /// Parse the given string, representing a series of (semi-colon-separated) SQL commands,
 /// into their constituents and determine the tables and views that will be affected when
 /// the commands are executed, if any. Three sets are returned. The first contains tables
@@ -612,7 +803,15 @@ pub fn get_affected_tables(sql: &str) -> Result<(BTreeSet<String>, BTreeSet<Stri
     // Dropping one of the cache tables, by contrast, is always reported.
     let edited_tables = edited_tables
         .into_iter()
-        .filter(|table| ![QUERY_CACHE_TABLE, TABLE_CACHE_TABLE].contains(&table.as_str()))
+        .filter(|table| {
+            ![
+                QUERY_CACHE_TABLE,
+                QUERY_CACHE_KEYS_TABLE,
+                QUERY_CACHE_TABLES_TABLE,
+                TABLE_CACHE_TABLE,
+            ]
+            .contains(&table.as_str())
+        })
         .collect::<BTreeSet<_>>();
 
     Ok((edited_tables.clone(), dropped_tables.clone()))
@@ -928,4 +1127,59 @@ mod tests {
         );
         assert_eq!(dropped_tables, ["rho", "sigma",]);
     }
+
+    #[test]
+    fn test_key_filter() {
+        use KeyValue::{Literal, Parameter};
+
+        let filter = |sql: &str| get_key_filter(sql, "id").unwrap();
+        assert_eq!(
+            filter("SELECT * FROM alpha WHERE id = 1"),
+            Some(vec![Literal("1".into())])
+        );
+        assert_eq!(
+            filter(r#"SELECT * FROM "alpha" WHERE 'o''k' = "id" ORDER BY "id""#),
+            Some(vec![Literal("o'k".into())])
+        );
+        assert_eq!(
+            filter("SELECT a.foo FROM alpha a WHERE a.foo > 2 AND (a.id = $2) LIMIT 1"),
+            Some(vec![Parameter(1)])
+        );
+        assert_eq!(
+            filter("SELECT * FROM alpha WHERE id IN (?, '?', ?1, ?3, ?, 4)"),
+            Some(vec![
+                Parameter(0),
+                Literal("?".into()),
+                Parameter(0),
+                Parameter(2),
+                Parameter(3),
+                Literal("4".into()),
+            ])
+        );
+
+        // Queries that may read rows other than those with the given keys:
+        assert_eq!(filter("SELECT * FROM alpha"), None);
+        assert_eq!(filter("SELECT * FROM alpha WHERE id > 1"), None);
+        assert_eq!(filter("SELECT * FROM alpha WHERE id = 1 OR foo = 2"), None);
+        assert_eq!(filter("SELECT * FROM alpha WHERE id = 1.5"), None);
+        assert_eq!(filter("SELECT * FROM alpha WHERE other_id = 1"), None);
+        assert_eq!(filter("SELECT * FROM alpha, beta WHERE id = 1"), None);
+        assert_eq!(
+            filter("SELECT * FROM alpha a JOIN beta b ON a.x = b.x WHERE a.id = 1"),
+            None
+        );
+        assert_eq!(
+            filter("SELECT * FROM alpha WHERE id = 1 UNION SELECT * FROM beta"),
+            None
+        );
+        assert_eq!(
+            filter("SELECT * FROM alpha WHERE id = (SELECT MAX(id) FROM alpha)"),
+            None
+        );
+        assert_eq!(
+            filter("SELECT * FROM alpha WHERE id = 1 AND foo IN (SELECT foo FROM alpha)"),
+            None
+        );
+        assert_eq!(filter("DELETE FROM alpha WHERE id = 1"), None);
+    }
 }
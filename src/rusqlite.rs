@@ -2,12 +2,19 @@
 
 use crate::{
     any::AnyPool,
-    cache::{CachingStrategy, clear_cache_for_affected_tables, clear_cache_for_dropped_tables},
+    cache::{
+        CacheCounters, CacheEncoding, CachingStrategy, MemoryCaches,
+        clear_cache_for_affected_tables, clear_cache_for_dropped_tables,
+        clear_cache_for_edited_tables, sqlite_database_identity,
+    },
     core::{DbError, DbQuery},
     db_kind::{DbKind, MAX_PARAMS_SQLITE, SQLiteKind},
     db_value::{DbParams, DbRow, DbRows, DbValue, IntoDbParams, IntoDbRows, JsonValue},
     parse::validate_table_name,
-    shared::{EditType, edit},
+    shared::{
+        BULK_INSERT_BATCH_SIZE, EditType, UpsertOptions, edit, generate_bulk_insert_statement,
+        get_bulk_insert_types, get_bulk_insert_values,
+    },
 };
 use deadpool_sqlite::{
     Config, Pool, Runtime,
@@ -18,7 +25,89 @@ use deadpool_sqlite::{
     },
 };
 use rust_decimal::Decimal;
-use std::str::from_utf8;
+use std::{str::from_utf8, sync::Arc, time::Duration};
+
+/// Bind the given parameters to the given prepared statement.
+fn bind_parameters(stmt: &mut Statement<'_>, params: &[DbValue]) -> Result<(), DbError> {
+    for (i, param) in params.iter().enumerate() {
+        match param {
+            DbValue::Text(text) => {
+                stmt.raw_bind_parameter(i + 1, text).map_err(|err| {
+                    DbError::InputError(format!("Error binding parameter '{param:?}': {err}"))
+                })?;
+            }
+            DbValue::SmallInteger(num) => {
+                stmt.raw_bind_parameter(i + 1, num.to_string())
+                    .map_err(|err| {
+                        DbError::InputError(format!("Error binding parameter '{param:?}': {err}"))
+                    })?;
+            }
+            DbValue::Integer(num) => {
+                stmt.raw_bind_parameter(i + 1, num.to_string())
+                    .map_err(|err| {
+                        DbError::InputError(format!("Error binding parameter '{param:?}': {err}"))
+                    })?;
+            }
+            DbValue::BigInteger(num) => {
+                stmt.raw_bind_parameter(i + 1, num.to_string())
+                    .map_err(|err| {
+                        DbError::InputError(format!("Error binding parameter '{param:?}': {err}"))
+                    })?;
+            }
+            DbValue::Real(num) => {
+                stmt.raw_bind_parameter(i + 1, num.to_string())
+                    .map_err(|err| {
+                        DbError::InputError(format!("Error binding parameter '{param:?}': {err}"))
+                    })?;
+            }
+            DbValue::BigReal(num) => {
+                stmt.raw_bind_parameter(i + 1, num.to_string())
+                    .map_err(|err| {
+                        DbError::InputError(format!("Error binding parameter '{param:?}': {err}"))
+                    })?;
+            }
+            DbValue::Numeric(num) => {
+                stmt.raw_bind_parameter(i + 1, num.to_string())
+                    .map_err(|err| {
+                        DbError::InputError(format!("Error binding parameter '{param:?}': {err}"))
+                    })?;
+            }
+            DbValue::Boolean(flag) => {
+                // Note that SQLite's type affinity means that booleans are actually
+                // implemented as numbers (see https://sqlite.org/datatype3.html).
+                let num = match flag {
+                    true => 1,
+                    false => 0,
+                };
+                stmt.raw_bind_parameter(i + 1, num.to_string())
+                    .map_err(|err| {
+                        DbError::InputError(format!("Error binding parameter '{param:?}': {err}"))
+                    })?;
+            }
+            DbValue::Null => {
+                stmt.raw_bind_parameter(i + 1, &Null).map_err(|err| {
+                    DbError::InputError(format!("Error binding parameter '{param:?}': {err}"))
+                })?;
+            }
+            DbValue::Json(value) => {
+                let value = match value {
+                    JsonValue::String(value) => value.to_string(),
+                    _ => value.to_string(),
+                };
+                stmt.raw_bind_parameter(i + 1, value).map_err(|err| {
+                    DbError::InputError(format!("Error binding parameter '{param:?}': {err}"))
+                })?;
+            }
+            DbValue::Other(type_name, bytes, string_opt) => {
+                return Err(DbError::InputError(format!(
+                    "Not supported for SQLite: \
+                     DbValue::Other({type_name}, {bytes:?}, {string_opt:?})"
+                )));
+            }
+        };
+    }
+    Ok(())
+}
 
 /// Query a database using the given prepared statement and parameters.
 fn query_prepared(
@@ -27,105 +116,7 @@ fn query_prepared(
 ) -> Result<Vec<DbRow>, DbError> {
     match params.into_db_params() {
         DbParams::None => (),
-        DbParams::Positional(params) => {
-            for (i, param) in params.iter().enumerate() {
-                match param {
-                    DbValue::Text(text) => {
-                        stmt.raw_bind_parameter(i + 1, text).map_err(|err| {
-                            DbError::InputError(format!(
-                                "Error binding parameter '{param:?}': {err}"
-                            ))
-                        })?;
-                    }
-                    DbValue::SmallInteger(num) => {
-                        stmt.raw_bind_parameter(i + 1, num.to_string())
-                            .map_err(|err| {
-                                DbError::InputError(format!(
-                                    "Error binding parameter '{param:?}': {err}"
-                                ))
-                            })?;
-                    }
-                    DbValue::Integer(num) => {
-                        stmt.raw_bind_parameter(i + 1, num.to_string())
-                            .map_err(|err| {
-                                DbError::InputError(format!(
-                                    "Error binding parameter '{param:?}': {err}"
-                                ))
-                            })?;
-                    }
-                    DbValue::BigInteger(num) => {
-                        stmt.raw_bind_parameter(i + 1, num.to_string())
-                            .map_err(|err| {
-                                DbError::InputError(format!(
-                                    "Error binding parameter '{param:?}': {err}"
-                                ))
-                            })?;
-                    }
-                    DbValue::Real(num) => {
-                        stmt.raw_bind_parameter(i + 1, num.to_string())
-                            .map_err(|err| {
-                                DbError::InputError(format!(
-                                    "Error binding parameter '{param:?}': {err}"
-                                ))
-                            })?;
-                    }
-                    DbValue::BigReal(num) => {
-                        stmt.raw_bind_parameter(i + 1, num.to_string())
-                            .map_err(|err| {
-                                DbError::InputError(format!(
-                                    "Error binding parameter '{param:?}': {err}"
-                                ))
-                            })?;
-                    }
-                    DbValue::Numeric(num) => {
-                        stmt.raw_bind_parameter(i + 1, num.to_string())
-                            .map_err(|err| {
-                                DbError::InputError(format!(
-                                    "Error binding parameter '{param:?}': {err}"
-                                ))
-                            })?;
-                    }
-                    DbValue::Boolean(flag) => {
-                        // Note that SQLite's type affinity means that booleans are actually
-                        // implemented as numbers (see https://sqlite.org/datatype3.html).
-                        let num = match flag {
-                            true => 1,
-                            false => 0,
-                        };
-                        stmt.raw_bind_parameter(i + 1, num.to_string())
-                            .map_err(|err| {
-                                DbError::InputError(format!(
-                                    "Error binding parameter '{param:?}': {err}"
-                                ))
-                            })?;
-                    }
-                    DbValue::Null => {
-                        stmt.raw_bind_parameter(i + 1, &Null).map_err(|err| {
-                            DbError::InputError(format!(
-                                "Error binding parameter '{param:?}': {err}"
-                            ))
-                        })?;
-                    }
-                    DbValue::Json(value) => {
-                        let value = match value {
-                            JsonValue::String(value) => value.to_string(),
-                            _ => value.to_string(),
-                        };
-                        stmt.raw_bind_parameter(i + 1, value).map_err(|err| {
-                            DbError::InputError(format!(
-                                "Error binding parameter '{param:?}': {err}"
-                            ))
-                        })?;
-                    }
-                    DbValue::Other(type_name, bytes, string_opt) => {
-                        return Err(DbError::InputError(format!(
-                            "Not supported for SQLite: \
-                             DbValue::Other({type_name}, {bytes:?}, {string_opt:?})"
-                        )));
-                    }
-                };
-            }
-        }
+        DbParams::Positional(params) => bind_parameters(stmt, &params)?,
     };
 
     // Define the struct that we will use to represent information about a given column:
@@ -192,12 +183,20 @@ fn query_prepared(
 #[derive(Clone, Debug)]
 pub struct RusqlitePool {
     pool: Pool,
+    memory_caches: Arc<MemoryCaches>,
+    cache_counters: Arc<CacheCounters>,
     caching_strategy: CachingStrategy,
     /// When set to true, SQL statements sent to the [DbQuery::query()] and [DbQuery::execute()]
     /// functions will be parsed and if they will result in tables being edited and/or dropped,
     /// the cache will be maintained in accordance with the given [CachingStrategy].
     /// For further information, see [DbQuery::set_cache_aware_query()].
     cache_aware_query: bool,
+    /// The maximum age of the cached query results returned by [DbQuery::cache()], if any.
+    /// For further information, see [DbQuery::set_cache_ttl()].
+    cache_ttl: Option<Duration>,
+    /// The encoding in which cached query results are stored in the query cache table.
+    /// For further information, see [DbQuery::set_cache_encoding()].
+    cache_encoding: CacheEncoding,
 }
 
 impl RusqlitePool {
@@ -209,8 +208,12 @@ impl RusqlitePool {
             .map_err(|err| DbError::ConnectError(format!("Error creating pool: {err}")))?;
         Ok(Self {
             pool: pool,
+            memory_caches: MemoryCaches::for_database(sqlite_database_identity(url).as_deref()),
+            cache_counters: Arc::default(),
             caching_strategy: CachingStrategy::None,
             cache_aware_query: false,
+            cache_ttl: None,
+            cache_encoding: CacheEncoding::default(),
         })
     }
 }
@@ -225,11 +228,25 @@ impl DbQuery for RusqlitePool {
     fn pool(&self) -> AnyPool {
         AnyPool::Rusqlite(RusqlitePool {
             pool: self.pool.clone(),
+            memory_caches: self.memory_caches.clone(),
+            cache_counters: self.cache_counters.clone(),
             caching_strategy: self.caching_strategy,
             cache_aware_query: self.cache_aware_query,
+            cache_ttl: self.cache_ttl,
+            cache_encoding: self.cache_encoding,
         })
     }
 
+    /// Implements [DbQuery::memory_caches()] for SQLite.
+    fn memory_caches(&self) -> Arc<MemoryCaches> {
+        self.memory_caches.clone()
+    }
+
+    /// Implements [DbQuery::cache_counters()] for SQLite.
+    fn cache_counters(&self) -> Arc<CacheCounters> {
+        self.cache_counters.clone()
+    }
+
     /// Implements [DbQuery::set_caching_strategy()] for SQLite.
     fn set_caching_strategy(&mut self, strategy: &CachingStrategy) {
         self.caching_strategy = *strategy;
@@ -250,6 +267,26 @@ impl DbQuery for RusqlitePool {
         self.cache_aware_query
     }
 
+    /// Implements [DbQuery::set_cache_ttl()] for SQLite.
+    fn set_cache_ttl(&mut self, ttl: Option<Duration>) {
+        self.cache_ttl = ttl;
+    }
+
+    /// Implements [DbQuery::get_cache_ttl()] for SQLite.
+    fn get_cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl
+    }
+
+    /// Implements [DbQuery::set_cache_encoding()] for SQLite.
+    fn set_cache_encoding(&mut self, encoding: CacheEncoding) {
+        self.cache_encoding = encoding;
+    }
+
+    /// Implements [DbQuery::get_cache_encoding()] for SQLite.
+    fn get_cache_encoding(&self) -> CacheEncoding {
+        self.cache_encoding
+    }
+
     /// Implements [DbQuery::execute_batch()] for SQLite.
     async fn execute_batch(&self, sql: &str) -> Result<(), DbError> {
         let conn = self
@@ -268,15 +305,50 @@ impl DbQuery for RusqlitePool {
             .await
         {
             Err(err) => Err(DbError::DatabaseError(format!("Error during query: {err}"))),
-            Ok(_) => {
+            Ok(result) => {
                 // We need to drop conn here to ensure that any changes to the db are persisted.
                 drop(conn);
+                result?;
                 clear_cache_for_affected_tables(&self.pool(), sql).await?;
                 Ok(())
             }
         }
     }
 
+    /// Implements [DbQuery::execute_batch_with_recovery()] for SQLite.
+    async fn execute_batch_with_recovery(
+        &self,
+        sql: &str,
+        recovery: &[&str],
+    ) -> Result<(), DbError> {
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|err| DbError::ConnectError(format!("Unable to get from pool: {err}")))?;
+        let sql_string = sql.to_string();
+        let recovery = recovery
+            .iter()
+            .map(|sql| sql.to_string())
+            .collect::<Vec<_>>();
+        let result = conn
+            .interact(move |conn| {
+                conn.execute_batch(&sql_string).map_err(|err| {
+                    for sql in &recovery {
+                        conn.execute_batch(sql).ok();
+                    }
+                    DbError::DatabaseError(format!("Error during query: {err}"))
+                })
+            })
+            .await
+            .map_err(|err| DbError::DatabaseError(format!("Error during query: {err}")))?;
+        // We need to drop conn here to ensure that any changes to the db are persisted.
+        drop(conn);
+        result?;
+        clear_cache_for_affected_tables(&self.pool(), sql).await?;
+        Ok(())
+    }
+
     /// Implements [DbQuery::query_no_cache_clean()] for SQLite.
     async fn query_no_cache_clean(
         &self,
@@ -406,7 +478,7 @@ impl DbQuery for RusqlitePool {
     ) -> Result<(), DbError> {
         edit(
             self,
-            &EditType::Upsert,
+            &EditType::Upsert(UpsertOptions::default()),
             &MAX_PARAMS_SQLITE,
             table,
             columns,
@@ -428,7 +500,128 @@ impl DbQuery for RusqlitePool {
     ) -> Result<DbRows, DbError> {
         edit(
             self,
-            &EditType::Upsert,
+            &EditType::Upsert(UpsertOptions::default()),
+            &MAX_PARAMS_SQLITE,
+            table,
+            columns,
+            rows,
+            true,
+            returning,
+        )
+        .await
+    }
+
+    /// Implements [DbQuery::bulk_insert()] for SQLite. The rows are inserted using a single
+    /// prepared statement, executed repeatedly within one transaction.
+    async fn bulk_insert(
+        &self,
+        table: &str,
+        columns: &[&str],
+        mut rows: impl Iterator<Item = DbRow> + Send,
+        mut progress: impl FnMut(u64) + Send,
+    ) -> Result<u64, DbError> {
+        let (table, sql_types) = get_bulk_insert_types(self, table, columns).await?;
+        let sql = generate_bulk_insert_statement(&table, columns, SQLiteKind.param_prefix());
+        let conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|err| DbError::ConnectError(format!("Unable to get from pool: {err}")))?;
+        let execute_batch = async |sql: String| -> Result<(), DbError> {
+            conn.interact(move |conn| {
+                conn.execute_batch(&sql)
+                    .map_err(|err| DbError::DatabaseError(format!("Error during query: {err}")))
+            })
+            .await
+            .map_err(|err| DbError::DatabaseError(format!("Error during query: {err}")))?
+        };
+
+        execute_batch("BEGIN".to_string()).await?;
+        let mut count = 0;
+        let result: Result<(), DbError> = async {
+            loop {
+                let batch = rows
+                    .by_ref()
+                    .take(BULK_INSERT_BATCH_SIZE)
+                    .map(|row| get_bulk_insert_values(&SQLiteKind, &row, columns, &sql_types))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if batch.is_empty() {
+                    break;
+                }
+                count += batch.len() as u64;
+                let sql = sql.clone();
+                conn.interact(move |conn| {
+                    let mut stmt = conn.prepare(&sql).map_err(|err| {
+                        DbError::DatabaseError(format!("Error preparing statement: {err}"))
+                    })?;
+                    for values in batch {
+                        bind_parameters(&mut stmt, &values)?;
+                        stmt.raw_execute().map_err(|err| {
+                            DbError::DatabaseError(format!("Error during query: {err}"))
+                        })?;
+                    }
+                    Ok::<_, DbError>(())
+                })
+                .await
+                .map_err(|err| DbError::DatabaseError(format!("Error during query: {err}")))??;
+                progress(count);
+            }
+            Ok(())
+        }
+        .await;
+        match result {
+            Ok(()) => execute_batch("COMMIT".to_string()).await?,
+            Err(err) => {
+                // Report the error that caused the rollback rather than any error from the
+                // rollback itself:
+                let _ = execute_batch("ROLLBACK".to_string()).await;
+                return Err(err);
+            }
+        };
+        // We need to drop conn here to ensure that any changes to the db are persisted.
+        drop(conn);
+        if count == 0 {
+            progress(count);
+        }
+
+        clear_cache_for_edited_tables(self, &[&table]).await?;
+        Ok(count)
+    }
+
+    /// Implements [DbQuery::upsert_with()] for SQLite.
+    async fn upsert_with(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl IntoDbRows,
+        options: &UpsertOptions,
+    ) -> Result<(), DbError> {
+        edit(
+            self,
+            &EditType::Upsert(options.clone()),
+            &MAX_PARAMS_SQLITE,
+            table,
+            columns,
+            rows,
+            false,
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Implements [DbQuery::upsert_with_returning()] for SQLite.
+    async fn upsert_with_returning(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl IntoDbRows,
+        options: &UpsertOptions,
+        returning: &[&str],
+    ) -> Result<DbRows, DbError> {
+        edit(
+            self,
+            &EditType::Upsert(options.clone()),
             &MAX_PARAMS_SQLITE,
             table,
             columns,
@@ -439,6 +632,42 @@ impl DbQuery for RusqlitePool {
         .await
     }
 
+    /// Implements [DbQuery::delete()] for SQLite.
+    async fn delete(&self, table: &str, rows: impl IntoDbRows) -> Result<(), DbError> {
+        edit(
+            self,
+            &EditType::Delete,
+            &MAX_PARAMS_SQLITE,
+            table,
+            &[],
+            rows,
+            false,
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Implements [DbQuery::delete_returning()] for SQLite.
+    async fn delete_returning(
+        &self,
+        table: &str,
+        rows: impl IntoDbRows,
+        returning: &[&str],
+    ) -> Result<DbRows, DbError> {
+        edit(
+            self,
+            &EditType::Delete,
+            &MAX_PARAMS_SQLITE,
+            table,
+            &[],
+            rows,
+            true,
+            returning,
+        )
+        .await
+    }
+
     /// Implements [DbQuery::drop_table()] for SQLite.
     async fn drop_table(&self, table: &str) -> Result<(), DbError> {
         let table = validate_table_name(table)?;
@@ -0,0 +1,992 @@
+//! Code for describing the structure of tables and comparing the schemas of databases.
+//!
+//! The schema of a database (or of some of its tables) can be retrieved using [get_schema()], and
+//! two schemas can be compared using [diff_schemas()], which reports the list of
+//! [SchemaChange]s needed to turn the one into the other. The changes can then be converted to
+//! DDL for a given kind of database using [SchemaChange::to_sql()], or applied directly to a
+//! database using [apply_schema_changes()]. The latter should be preferred for SQLite, since
+//! some changes (e.g., changing the type of a column) cannot be expressed as simple DDL
+//! statements for SQLite (see [crate::alter]).
+//!
+//! Note that SQL types are compared after normalizing them (see [normalize_sql_type()]) so that
+//! equivalent types, such as `INT8` and `BIGINT`, are not reported as being different, and so
+//! that the schema of a SQLite database can be compared with that of a PostgreSQL database.
+
+use crate::{
+    core::{DbError, DbQuery},
+    db_kind::DbKind,
+    db_value::{DbRow, DbValue},
+    index::{Index, list_indexes},
+    parse::validate_table_name,
+};
+
+/// The prefix of the names of the tables used internally by rltbl_db (e.g., for caching), which
+/// are ignored when retrieving the schema of a database.
+pub static INTERNAL_TABLE_PREFIX: &str = "rltbl_db_";
+
+/// A column of a [TableSchema].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColumnSchema {
+    pub name: String,
+    /// The SQL type of the column, normalized using [normalize_sql_type()].
+    pub sql_type: String,
+    pub not_null: bool,
+}
+
+impl ColumnSchema {
+    /// Define a nullable column with the given name and SQL type.
+    pub fn new(name: &str, sql_type: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            sql_type: normalize_sql_type(sql_type),
+            not_null: false,
+        }
+    }
+
+    /// Make this column NOT NULL.
+    pub fn not_null(mut self) -> Self {
+        self.not_null = true;
+        self
+    }
+}
+
+/// A foreign key constraint of a [TableSchema].
+#[derive(Clone, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub struct ForeignKey {
+    pub columns: Vec<String>,
+    pub foreign_table: String,
+    /// The columns of the foreign table to which the constrained columns refer, in order.
+    pub foreign_columns: Vec<String>,
+}
+
+impl ForeignKey {
+    /// Define a foreign key constraint requiring the values of the given columns to match those
+    /// of the given columns of the given foreign table.
+    pub fn new(columns: &[&str], foreign_table: &str, foreign_columns: &[&str]) -> Self {
+        Self {
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+            foreign_table: foreign_table.to_string(),
+            foreign_columns: foreign_columns
+                .iter()
+                .map(|column| column.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// The structure of a table: its columns, primary key, unique and foreign key constraints, and
+/// (explicitly created) indexes.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<ColumnSchema>,
+    pub primary_key: Vec<String>,
+    /// The lists of columns, other than the primary key, whose values must be unique together.
+    pub unique_constraints: Vec<Vec<String>>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub indexes: Vec<Index>,
+}
+
+impl TableSchema {
+    /// Define a table with the given name and no columns.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Add the given column to this table.
+    pub fn column(mut self, column: ColumnSchema) -> Self {
+        self.columns.push(column);
+        self
+    }
+
+    /// Set the primary key of this table. Primary key columns are always NOT NULL.
+    pub fn primary_key(mut self, columns: &[&str]) -> Self {
+        self.primary_key = columns.iter().map(|column| column.to_string()).collect();
+        for column in self.columns.iter_mut() {
+            if self.primary_key.contains(&column.name) {
+                column.not_null = true;
+            }
+        }
+        self
+    }
+
+    /// Add a unique constraint on the given columns to this table.
+    pub fn unique(mut self, columns: &[&str]) -> Self {
+        self.unique_constraints
+            .push(columns.iter().map(|column| column.to_string()).collect());
+        self
+    }
+
+    /// Add the given foreign key constraint to this table.
+    pub fn foreign_key(mut self, foreign_key: ForeignKey) -> Self {
+        self.foreign_keys.push(foreign_key);
+        self
+    }
+
+    /// Add the given index to this table.
+    pub fn index(mut self, index: Index) -> Self {
+        self.indexes.push(index);
+        self
+    }
+
+    /// Retrieve the schema of the given table, or None if there is no such table. Its unique and
+    /// foreign key constraints are sorted by column, since their order is not significant.
+    pub async fn from_pool(pool: &impl DbQuery, table: &str) -> Result<Option<Self>, DbError> {
+        let table = validate_table_name(table)?;
+        let (sql, params) = pool.kind().table_columns_sql(&table);
+        let rows = pool.query_no_cache_clean(&sql, params).await?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        let primary_key = pool.primary_keys(&table).await?;
+        let mut columns = vec![];
+        for row in rows.iter() {
+            let get = |column: &str| {
+                row.get(column).ok_or(DbError::DataError(format!(
+                    "No column '{column}' in row {row:?}"
+                )))
+            };
+            let name = get("column_name")?.to_string();
+            let not_null: u64 = get("not_null")?.try_into()?;
+            columns.push(ColumnSchema {
+                // Primary key columns are implicitly NOT NULL in PostgreSQL, but not in SQLite:
+                not_null: not_null != 0 || primary_key.contains(&name),
+                sql_type: normalize_sql_type(&get("data_type")?.to_string()),
+                name,
+            });
+        }
+        let mut unique_constraints = list_unique_constraints(pool, &table)
+            .await?
+            .into_iter()
+            .map(|(_, columns)| columns)
+            .collect::<Vec<_>>();
+        unique_constraints.sort();
+        let mut foreign_keys = list_foreign_keys(pool, &table)
+            .await?
+            .into_iter()
+            .map(|(_, foreign_key)| foreign_key)
+            .collect::<Vec<_>>();
+        foreign_keys.sort();
+        Ok(Some(Self {
+            name: table.to_string(),
+            columns,
+            primary_key,
+            unique_constraints,
+            foreign_keys,
+            indexes: list_indexes(pool, &table).await?,
+        }))
+    }
+
+    /// Get the column of this table with the given name, if there is one.
+    pub fn get_column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+}
+
+// Query the database's metadata for the constraints of a table using the given SQL and
+// parameters (see [DbKind::unique_constraints_sql()]), returning the rows for each constraint,
+// in order, together with its name.
+async fn query_constraints(
+    pool: &impl DbQuery,
+    sql: &str,
+    params: [DbValue; 1],
+) -> Result<Vec<(String, Vec<DbRow>)>, DbError> {
+    let rows = pool.query_no_cache_clean(sql, params).await?;
+    let mut constraints: Vec<(String, Vec<DbRow>)> = vec![];
+    for row in rows.content.into_iter() {
+        let name = get_value(&row, "constraint_name")?.to_string();
+        match constraints.last_mut() {
+            Some((last_name, rows)) if *last_name == name => rows.push(row),
+            _ => constraints.push((name, vec![row])),
+        };
+    }
+    Ok(constraints)
+}
+
+// Get the value of the given column of the given row of metadata.
+fn get_value(row: &DbRow, column: &str) -> Result<DbValue, DbError> {
+    row.get(column).ok_or(DbError::DataError(format!(
+        "No column '{column}' in row {row:?}"
+    )))
+}
+
+// List the unique constraints of the given table, together with their names.
+async fn list_unique_constraints(
+    pool: &impl DbQuery,
+    table: &str,
+) -> Result<Vec<(String, Vec<String>)>, DbError> {
+    let (sql, params) = pool.kind().unique_constraints_sql(table);
+    query_constraints(pool, &sql, params)
+        .await?
+        .into_iter()
+        .map(|(name, rows)| {
+            let columns = rows
+                .iter()
+                .map(|row| get_value(row, "column_name").map(|value| value.to_string()))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((name, columns))
+        })
+        .collect()
+}
+
+// List the foreign key constraints of the given table, together with their names.
+async fn list_foreign_keys(
+    pool: &impl DbQuery,
+    table: &str,
+) -> Result<Vec<(String, ForeignKey)>, DbError> {
+    let (sql, params) = pool.kind().foreign_keys_sql(table);
+    let mut foreign_keys = vec![];
+    for (name, rows) in query_constraints(pool, &sql, params).await? {
+        let mut foreign_key = ForeignKey::default();
+        for row in rows.iter() {
+            foreign_key
+                .columns
+                .push(get_value(row, "column_name")?.to_string());
+            foreign_key.foreign_table = get_value(row, "foreign_table")?.to_string();
+            let foreign_column = get_value(row, "foreign_column")?;
+            if !foreign_column.is_null() {
+                foreign_key.foreign_columns.push(foreign_column.to_string());
+            }
+        }
+        // SQLite allows the foreign columns to be omitted, in which case they are the primary
+        // key of the foreign table:
+        if foreign_key.foreign_columns.is_empty() {
+            foreign_key.foreign_columns = pool.primary_keys(&foreign_key.foreign_table).await?;
+        }
+        foreign_keys.push((name, foreign_key));
+    }
+    Ok(foreign_keys)
+}
+
+/// Normalize the given SQL type by converting it to lowercase and mapping it to a canonical name
+/// that is understood by both SQLite and PostgreSQL, if there is one. For example, `INT8` is
+/// normalized to `bigint` and `VARCHAR(20)` to `text`.
+pub fn normalize_sql_type(sql_type: &str) -> String {
+    let sql_type = sql_type.trim().to_lowercase();
+    match sql_type.as_str() {
+        "bool" | "boolean" => "boolean".to_string(),
+        "int2" | "smallint" | "smallserial" => "smallint".to_string(),
+        "int" | "int4" | "integer" | "serial" | "mediumint" => "integer".to_string(),
+        "int8" | "bigint" | "bigserial" => "bigint".to_string(),
+        "real" | "float4" | "float" => "real".to_string(),
+        "float8" | "double" | "double precision" => "double precision".to_string(),
+        "numeric" | "decimal" => "numeric".to_string(),
+        "text" | "clob" | "bpchar" => "text".to_string(),
+        other if other.starts_with("decimal") || other.starts_with("numeric") => {
+            "numeric".to_string()
+        }
+        other
+            if ["character", "varchar", "char", "nchar", "nvarchar"]
+                .iter()
+                .any(|prefix| other.starts_with(prefix)) =>
+        {
+            "text".to_string()
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Retrieve the schemas of the given tables, in the given order, skipping any that do not exist.
+/// If no tables are given, retrieve the schemas of all of the tables in the database, in order
+/// of name, excluding those used internally by rltbl_db (see [INTERNAL_TABLE_PREFIX]).
+pub async fn get_schema(pool: &impl DbQuery, tables: &[&str]) -> Result<Vec<TableSchema>, DbError> {
+    let tables = match tables.is_empty() {
+        false => tables.iter().map(|table| table.to_string()).collect(),
+        true => pool
+            .query_no_cache_clean(&pool.kind().list_tables_sql(), ())
+            .await?
+            .to_strings()?
+            .into_iter()
+            .filter(|table| !table.starts_with(INTERNAL_TABLE_PREFIX))
+            .collect::<Vec<_>>(),
+    };
+    let mut schema = vec![];
+    for table in tables {
+        if let Some(table_schema) = TableSchema::from_pool(pool, &table).await? {
+            schema.push(table_schema);
+        }
+    }
+    Ok(schema)
+}
+
+/// A change to the schema of a database, as reported by [diff_schemas()].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SchemaChange {
+    AddTable(TableSchema),
+    DropTable(String),
+    AddColumn {
+        table: String,
+        column: ColumnSchema,
+    },
+    DropColumn {
+        table: String,
+        column: String,
+    },
+    AlterColumnType {
+        table: String,
+        column: String,
+        from: String,
+        to: String,
+    },
+    AlterColumnNotNull {
+        table: String,
+        column: String,
+        not_null: bool,
+    },
+    AlterPrimaryKey {
+        table: String,
+        from: Vec<String>,
+        to: Vec<String>,
+    },
+    AddUniqueConstraint {
+        table: String,
+        columns: Vec<String>,
+    },
+    DropUniqueConstraint {
+        table: String,
+        columns: Vec<String>,
+    },
+    AddForeignKey {
+        table: String,
+        foreign_key: ForeignKey,
+    },
+    DropForeignKey {
+        table: String,
+        foreign_key: ForeignKey,
+    },
+    AddIndex(Index),
+    DropIndex(Index),
+}
+
+impl SchemaChange {
+    /// Generate the DDL statements needed to make this change to a database of the given kind.
+    /// Returns an error if the change cannot be expressed as DDL for that kind of database, in
+    /// which case it may still be possible to apply it using [apply_schema_changes()].
+    pub fn to_sql(&self, kind: &dyn DbKind) -> Result<Vec<String>, DbError> {
+        let unsupported = |description: &str| {
+            DbError::InputError(format!(
+                "{description} cannot be expressed as DDL for {kind}. \
+                 Use apply_schema_changes() instead."
+            ))
+        };
+        match self {
+            SchemaChange::AddTable(schema) => {
+                let mut sql = vec![kind.create_table_sql(schema)];
+                for index in schema.indexes.iter() {
+                    sql.push(kind.create_index_sql(index));
+                }
+                Ok(sql)
+            }
+            SchemaChange::DropTable(table) => Ok(vec![kind.drop_table_sql(table)]),
+            SchemaChange::AddColumn { table, column } => {
+                if column.not_null {
+                    return Err(DbError::InputError(format!(
+                        "Cannot add NOT NULL column '{}' to existing table '{table}'",
+                        column.name
+                    )));
+                }
+                Ok(vec![kind.add_column_sql(
+                    table,
+                    &column.name,
+                    &column.sql_type,
+                )])
+            }
+            SchemaChange::DropColumn { table, column } => kind
+                .drop_column_sql(table, column)
+                .map(|sql| vec![sql])
+                .ok_or(unsupported(&format!("Dropping column '{table}.{column}'"))),
+            SchemaChange::AlterColumnType {
+                table, column, to, ..
+            } => kind
+                .alter_column_type_sql(table, column, to)
+                .map(|sql| vec![sql])
+                .ok_or(unsupported(&format!(
+                    "Changing the type of column '{table}.{column}'"
+                ))),
+            SchemaChange::AlterColumnNotNull {
+                table,
+                column,
+                not_null,
+            } => kind
+                .alter_column_not_null_sql(table, column, *not_null)
+                .map(|sql| vec![sql])
+                .ok_or(unsupported(&format!(
+                    "Changing the nullability of column '{table}.{column}'"
+                ))),
+            SchemaChange::AlterPrimaryKey { table, .. } => Err(DbError::InputError(format!(
+                "Changing the primary key of table '{table}' is not supported"
+            ))),
+            SchemaChange::AddUniqueConstraint { table, columns } => kind
+                .add_unique_constraint_sql(table, columns)
+                .map(|sql| vec![sql])
+                .ok_or(unsupported_constraint_change(kind, self)),
+            SchemaChange::AddForeignKey { table, foreign_key } => kind
+                .add_foreign_key_sql(table, foreign_key)
+                .map(|sql| vec![sql])
+                .ok_or(unsupported_constraint_change(kind, self)),
+            // Constraints are compared without regard to their names, which are needed to drop
+            // them, and which must be looked up in the database:
+            SchemaChange::DropUniqueConstraint { table, columns } => Err(unsupported(&format!(
+                "Dropping the unique constraint on {columns:?} of table '{table}'"
+            ))),
+            SchemaChange::DropForeignKey { table, foreign_key } => Err(unsupported(&format!(
+                "Dropping the foreign key constraint on {:?} of table '{table}'",
+                foreign_key.columns
+            ))),
+            SchemaChange::AddIndex(index) => Ok(vec![kind.create_index_sql(index)]),
+            SchemaChange::DropIndex(index) => Ok(vec![kind.drop_index_sql(&index.name)]),
+        }
+    }
+}
+
+/// Compare the two given schemas, returning the list of changes needed to turn the schema
+/// `from` into the schema `to`. Note that the conditions of partial indexes are not compared,
+/// since databases normalize them in different ways; only whether or not an index is partial is.
+pub fn diff_schemas(from: &[TableSchema], to: &[TableSchema]) -> Vec<SchemaChange> {
+    let find = |schema: &[TableSchema], name: &str| {
+        schema.iter().find(|table| table.name == name).cloned()
+    };
+    let mut changes = vec![];
+    for old in from.iter() {
+        if find(to, &old.name).is_none() {
+            changes.push(SchemaChange::DropTable(old.name.to_string()));
+        }
+    }
+    for new in to.iter() {
+        match find(from, &new.name) {
+            None => changes.push(SchemaChange::AddTable(new.clone())),
+            Some(old) => changes.append(&mut diff_tables(&old, new)),
+        }
+    }
+    changes
+}
+
+// Generate the error returned when the given change to the constraints of a table cannot be made
+// in a database of the given kind.
+fn unsupported_constraint_change(kind: &dyn DbKind, change: &SchemaChange) -> DbError {
+    DbError::InputError(format!(
+        "Changing the constraints of an existing table is not supported for {kind}: {change:?}"
+    ))
+}
+
+// Compare the two given versions of a table, returning the list of changes needed to turn the
+// one into the other.
+fn diff_tables(old: &TableSchema, new: &TableSchema) -> Vec<SchemaChange> {
+    let table = new.name.to_string();
+    let same_index = |a: &Index, b: &Index| {
+        a.columns == b.columns
+            && a.unique == b.unique
+            && a.condition.is_some() == b.condition.is_some()
+    };
+    let mut changes = vec![];
+
+    // Indexes that have been removed or changed are dropped first, in case they refer to
+    // columns that are about to be dropped:
+    let mut added_indexes = vec![];
+    for old_index in old.indexes.iter() {
+        match new
+            .indexes
+            .iter()
+            .find(|index| index.name == old_index.name)
+        {
+            Some(new_index) if same_index(old_index, new_index) => (),
+            Some(new_index) => {
+                changes.push(SchemaChange::DropIndex(old_index.clone()));
+                added_indexes.push(new_index.clone());
+            }
+            None => changes.push(SchemaChange::DropIndex(old_index.clone())),
+        };
+    }
+    for new_index in new.indexes.iter() {
+        if !old.indexes.iter().any(|index| index.name == new_index.name) {
+            added_indexes.push(new_index.clone());
+        }
+    }
+
+    // So are constraints that have been removed:
+    for columns in old.unique_constraints.iter() {
+        if !new.unique_constraints.contains(columns) {
+            changes.push(SchemaChange::DropUniqueConstraint {
+                table: table.to_string(),
+                columns: columns.clone(),
+            });
+        }
+    }
+    for foreign_key in old.foreign_keys.iter() {
+        if !new.foreign_keys.contains(foreign_key) {
+            changes.push(SchemaChange::DropForeignKey {
+                table: table.to_string(),
+                foreign_key: foreign_key.clone(),
+            });
+        }
+    }
+
+    for old_column in old.columns.iter() {
+        if new.get_column(&old_column.name).is_none() {
+            changes.push(SchemaChange::DropColumn {
+                table: table.to_string(),
+                column: old_column.name.to_string(),
+            });
+        }
+    }
+    for new_column in new.columns.iter() {
+        match old.get_column(&new_column.name) {
+            None => changes.push(SchemaChange::AddColumn {
+                table: table.to_string(),
+                column: new_column.clone(),
+            }),
+            Some(old_column) => {
+                if old_column.sql_type != new_column.sql_type {
+                    changes.push(SchemaChange::AlterColumnType {
+                        table: table.to_string(),
+                        column: new_column.name.to_string(),
+                        from: old_column.sql_type.to_string(),
+                        to: new_column.sql_type.to_string(),
+                    });
+                }
+                if old_column.not_null != new_column.not_null {
+                    changes.push(SchemaChange::AlterColumnNotNull {
+                        table: table.to_string(),
+                        column: new_column.name.to_string(),
+                        not_null: new_column.not_null,
+                    });
+                }
+            }
+        };
+    }
+
+    if old.primary_key != new.primary_key {
+        changes.push(SchemaChange::AlterPrimaryKey {
+            table: table.to_string(),
+            from: old.primary_key.clone(),
+            to: new.primary_key.clone(),
+        });
+    }
+    for columns in new.unique_constraints.iter() {
+        if !old.unique_constraints.contains(columns) {
+            changes.push(SchemaChange::AddUniqueConstraint {
+                table: table.to_string(),
+                columns: columns.clone(),
+            });
+        }
+    }
+    for foreign_key in new.foreign_keys.iter() {
+        if !old.foreign_keys.contains(foreign_key) {
+            changes.push(SchemaChange::AddForeignKey {
+                table: table.to_string(),
+                foreign_key: foreign_key.clone(),
+            });
+        }
+    }
+    changes.extend(added_indexes.into_iter().map(SchemaChange::AddIndex));
+    changes
+}
+
+/// Compare the schemas of the given tables (or of all tables, if none are given; see
+/// [get_schema()]) in the two given databases, returning the list of changes needed to turn the
+/// schema of the database `from` into that of the database `to`.
+pub async fn diff_pools(
+    from: &impl DbQuery,
+    to: &impl DbQuery,
+    tables: &[&str],
+) -> Result<Vec<SchemaChange>, DbError> {
+    let from = get_schema(from, tables).await?;
+    let to = get_schema(to, tables).await?;
+    Ok(diff_schemas(&from, &to))
+}
+
+/// Compare the schema of the given database with the given specification, returning the list of
+/// changes needed to make the database conform to it. Only the tables in the specification are
+/// compared; other tables in the database are ignored.
+pub async fn diff_spec(
+    pool: &impl DbQuery,
+    spec: &[TableSchema],
+) -> Result<Vec<SchemaChange>, DbError> {
+    let tables = spec
+        .iter()
+        .map(|table| table.name.as_str())
+        .collect::<Vec<_>>();
+    let actual = get_schema(pool, &tables).await?;
+    Ok(diff_schemas(&actual, spec))
+}
+
+/// Generate the DDL needed to make the given changes to a database of the given kind (see
+/// [SchemaChange::to_sql()]).
+pub fn schema_changes_sql(
+    kind: &dyn DbKind,
+    changes: &[SchemaChange],
+) -> Result<Vec<String>, DbError> {
+    let mut sql = vec![];
+    for change in changes {
+        sql.append(&mut change.to_sql(kind)?);
+    }
+    Ok(sql)
+}
+
+/// Apply the given changes to the given database. Unlike [schema_changes_sql()], this makes
+/// use of the helpers in [crate::alter] so that columns can be dropped, retyped, and have their
+/// NOT NULL constraints changed in SQLite, and looks up the names of the unique and foreign key
+/// constraints to be dropped. Note that the unique and foreign key constraints of an existing
+/// table cannot be changed in SQLite.
+pub async fn apply_schema_changes(
+    pool: &impl DbQuery,
+    changes: &[SchemaChange],
+) -> Result<(), DbError> {
+    for change in changes {
+        match change {
+            SchemaChange::DropTable(table) => pool.drop_table(table).await?,
+            SchemaChange::DropColumn { table, column } => pool.drop_column(table, column).await?,
+            SchemaChange::AlterColumnType {
+                table, column, to, ..
+            } => pool.alter_column_type(table, column, to).await?,
+            SchemaChange::AlterColumnNotNull {
+                table,
+                column,
+                not_null,
+            } => pool.alter_column_not_null(table, column, *not_null).await?,
+            SchemaChange::AddIndex(index) => pool.create_index(index).await?,
+            SchemaChange::DropIndex(index) => pool.drop_index(&index.name).await?,
+            SchemaChange::DropUniqueConstraint { table, columns } => {
+                let name = list_unique_constraints(pool, table)
+                    .await?
+                    .into_iter()
+                    .find(|(_, other)| other == columns)
+                    .map(|(name, _)| name);
+                drop_constraint(pool, table, name, change).await?;
+            }
+            SchemaChange::DropForeignKey { table, foreign_key } => {
+                let name = list_foreign_keys(pool, table)
+                    .await?
+                    .into_iter()
+                    .find(|(_, other)| other == foreign_key)
+                    .map(|(name, _)| name);
+                drop_constraint(pool, table, name, change).await?;
+            }
+            other => {
+                let sql = other.to_sql(&*pool.kind())?.join(";\n");
+                pool.execute_batch(&sql).await?;
+            }
+        };
+    }
+    Ok(())
+}
+
+// Drop the constraint with the given name, if it was found, from the given table, as required
+// by the given change.
+async fn drop_constraint(
+    pool: &impl DbQuery,
+    table: &str,
+    name: Option<String>,
+    change: &SchemaChange,
+) -> Result<(), DbError> {
+    let name = name.ok_or(DbError::InputError(format!(
+        "No such constraint in table '{table}': {change:?}"
+    )))?;
+    let sql = pool
+        .kind()
+        .drop_constraint_sql(table, &name)
+        .ok_or(unsupported_constraint_change(&*pool.kind(), change))?;
+    pool.execute_no_cache_clean(&sql, ()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::any::AnyPool;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_normalize_sql_type() {
+        assert_eq!(normalize_sql_type("INT8"), "bigint");
+        assert_eq!(normalize_sql_type("VARCHAR(20)"), "text");
+        assert_eq!(normalize_sql_type("Double Precision"), "double precision");
+        assert_eq!(normalize_sql_type("DECIMAL(10, 2)"), "numeric");
+        assert_eq!(normalize_sql_type("bool"), "boolean");
+        assert_eq!(normalize_sql_type("json"), "json");
+    }
+
+    #[tokio::test]
+    async fn test_schema_diff() {
+        #[cfg(feature = "rusqlite")]
+        schema_diff(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        schema_diff("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        schema_diff(":memory:").await;
+    }
+
+    async fn schema_diff(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.drop_table("test_schema_diff1").await.unwrap();
+        pool.drop_table("test_schema_diff2").await.unwrap();
+        pool.drop_table("test_schema_diff3").await.unwrap();
+        pool.execute_batch(
+            "CREATE TABLE test_schema_diff1 (\
+               id INT8 PRIMARY KEY,\
+               name VARCHAR(20) NOT NULL,\
+               age TEXT,\
+               city TEXT\
+             );\
+             CREATE INDEX test_schema_diff1_city ON test_schema_diff1 (city);\
+             CREATE TABLE test_schema_diff2 (foo TEXT)",
+        )
+        .await
+        .unwrap();
+
+        let schema = get_schema(
+            &pool,
+            &["test_schema_diff1", "test_schema_diff2", "no_such_table"],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            schema[0],
+            TableSchema::new("test_schema_diff1")
+                .column(ColumnSchema::new("id", "bigint"))
+                .column(ColumnSchema::new("name", "text").not_null())
+                .column(ColumnSchema::new("age", "text"))
+                .column(ColumnSchema::new("city", "text"))
+                .primary_key(&["id"])
+                .index(Index::new(
+                    "test_schema_diff1_city",
+                    "test_schema_diff1",
+                    &["city"]
+                ))
+        );
+        assert_eq!(schema.len(), 2);
+        assert_eq!(diff_schemas(&schema, &schema), vec![]);
+
+        // Compare the database with a spec:
+        let spec = vec![
+            TableSchema::new("test_schema_diff1")
+                .column(ColumnSchema::new("id", "BIGINT"))
+                .column(ColumnSchema::new("name", "TEXT"))
+                .column(ColumnSchema::new("age", "BIGINT"))
+                .column(ColumnSchema::new("country", "TEXT"))
+                .primary_key(&["id"])
+                .index(Index::new("test_schema_diff1_age", "test_schema_diff1", &["age"]).unique()),
+            TableSchema::new("test_schema_diff3")
+                .column(ColumnSchema::new("bar", "INTEGER").not_null())
+                .primary_key(&["bar"]),
+        ];
+        let changes = diff_spec(&pool, &spec).await.unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                SchemaChange::DropIndex(Index::new(
+                    "test_schema_diff1_city",
+                    "test_schema_diff1",
+                    &["city"]
+                )),
+                SchemaChange::DropColumn {
+                    table: "test_schema_diff1".to_string(),
+                    column: "city".to_string()
+                },
+                SchemaChange::AlterColumnNotNull {
+                    table: "test_schema_diff1".to_string(),
+                    column: "name".to_string(),
+                    not_null: false,
+                },
+                SchemaChange::AlterColumnType {
+                    table: "test_schema_diff1".to_string(),
+                    column: "age".to_string(),
+                    from: "text".to_string(),
+                    to: "bigint".to_string(),
+                },
+                SchemaChange::AddColumn {
+                    table: "test_schema_diff1".to_string(),
+                    column: ColumnSchema::new("country", "text"),
+                },
+                SchemaChange::AddIndex(
+                    Index::new("test_schema_diff1_age", "test_schema_diff1", &["age"]).unique()
+                ),
+                SchemaChange::AddTable(spec[1].clone()),
+            ]
+        );
+
+        // Generate the DDL, which is not possible for all changes in SQLite:
+        let ddl = schema_changes_sql(&*pool.kind(), &changes);
+        match pool.kind().to_string().as_str() {
+            "postgresql" => assert_eq!(ddl.unwrap().len(), changes.len()),
+            _ => assert!(ddl.is_err()),
+        };
+
+        // Apply the changes and verify that the database now conforms to the spec:
+        apply_schema_changes(&pool, &changes).await.unwrap();
+        assert_eq!(diff_spec(&pool, &spec).await.unwrap(), vec![]);
+
+        // Clean up:
+        pool.drop_table("test_schema_diff1").await.unwrap();
+        pool.drop_table("test_schema_diff2").await.unwrap();
+        pool.drop_table("test_schema_diff3").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_constraint_diff() {
+        #[cfg(feature = "rusqlite")]
+        constraint_diff(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        constraint_diff("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        constraint_diff(":memory:").await;
+    }
+
+    async fn constraint_diff(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.drop_table("test_constraint_diff2").await.unwrap();
+        pool.drop_table("test_constraint_diff1").await.unwrap();
+        pool.execute_batch(
+            "CREATE TABLE test_constraint_diff1 (\
+               id INT8 PRIMARY KEY,\
+               code TEXT UNIQUE\
+             );\
+             CREATE TABLE test_constraint_diff2 (\
+               id INT8 PRIMARY KEY,\
+               parent_id INT8 REFERENCES test_constraint_diff1,\
+               parent_code TEXT,\
+               x TEXT,\
+               y TEXT,\
+               UNIQUE (y, x)\
+             )",
+        )
+        .await
+        .unwrap();
+
+        // A foreign key that does not name the columns it refers to refers to the primary key:
+        let schema = get_schema(&pool, &["test_constraint_diff1", "test_constraint_diff2"])
+            .await
+            .unwrap();
+        assert_eq!(schema[0].unique_constraints, [["code"]]);
+        assert_eq!(schema[0].foreign_keys, []);
+        assert_eq!(schema[1].unique_constraints, [["y", "x"]]);
+        assert_eq!(
+            schema[1].foreign_keys,
+            [ForeignKey::new(
+                &["parent_id"],
+                "test_constraint_diff1",
+                &["id"]
+            )]
+        );
+        assert_eq!(diff_schemas(&schema, &schema), vec![]);
+
+        // Tables that differ only in their constraints are different:
+        let spec = vec![
+            TableSchema::new("test_constraint_diff2")
+                .column(ColumnSchema::new("id", "bigint"))
+                .column(ColumnSchema::new("parent_id", "bigint"))
+                .column(ColumnSchema::new("parent_code", "text"))
+                .column(ColumnSchema::new("x", "text"))
+                .column(ColumnSchema::new("y", "text"))
+                .primary_key(&["id"])
+                .unique(&["x"])
+                .foreign_key(ForeignKey::new(
+                    &["parent_code"],
+                    "test_constraint_diff1",
+                    &["code"],
+                )),
+        ];
+        let changes = diff_spec(&pool, &spec).await.unwrap();
+        let table = "test_constraint_diff2".to_string();
+        assert_eq!(
+            changes,
+            vec![
+                SchemaChange::DropUniqueConstraint {
+                    table: table.clone(),
+                    columns: vec!["y".to_string(), "x".to_string()],
+                },
+                SchemaChange::DropForeignKey {
+                    table: table.clone(),
+                    foreign_key: schema[1].foreign_keys[0].clone(),
+                },
+                SchemaChange::AddUniqueConstraint {
+                    table: table.clone(),
+                    columns: vec!["x".to_string()],
+                },
+                SchemaChange::AddForeignKey {
+                    table: table.clone(),
+                    foreign_key: spec[0].foreign_keys[0].clone(),
+                },
+            ]
+        );
+
+        // Constraints can only be dropped by name, which the DDL does not know:
+        assert!(schema_changes_sql(&*pool.kind(), &changes).is_err());
+        match pool.kind().to_string().as_str() {
+            "postgresql" => {
+                assert_eq!(
+                    schema_changes_sql(&*pool.kind(), &changes[2..])
+                        .unwrap()
+                        .len(),
+                    2
+                );
+                apply_schema_changes(&pool, &changes).await.unwrap();
+                assert_eq!(diff_spec(&pool, &spec).await.unwrap(), vec![]);
+            }
+            _ => assert!(apply_schema_changes(&pool, &changes).await.is_err()),
+        };
+
+        // A table created from a schema has its constraints:
+        pool.drop_table("test_constraint_diff2").await.unwrap();
+        apply_schema_changes(&pool, &[SchemaChange::AddTable(spec[0].clone())])
+            .await
+            .unwrap();
+        assert_eq!(diff_spec(&pool, &spec).await.unwrap(), vec![]);
+
+        // Clean up:
+        pool.drop_table("test_constraint_diff2").await.unwrap();
+        pool.drop_table("test_constraint_diff1").await.unwrap();
+    }
+
+    #[cfg(all(feature = "rusqlite", feature = "tokio-postgres"))]
+    #[tokio::test]
+    async fn test_diff_pools() {
+        let sqlite = AnyPool::connect(":memory:").await.unwrap();
+        sqlite.memory_caches().clear_meta_cache();
+        let postgresql = AnyPool::connect("postgresql:///rltbl_db").await.unwrap();
+        for pool in [&sqlite, &postgresql] {
+            pool.drop_table("test_diff_pools1").await.unwrap();
+            pool.drop_table("test_diff_pools2").await.unwrap();
+        }
+        // Equivalent definitions of the same table:
+        sqlite
+            .execute(
+                "CREATE TABLE test_diff_pools1 (id INT8 PRIMARY KEY, x FLOAT8, y VARCHAR(10))",
+                (),
+            )
+            .await
+            .unwrap();
+        postgresql
+            .execute(
+                "CREATE TABLE test_diff_pools1 (id BIGINT PRIMARY KEY, x DOUBLE PRECISION, y TEXT)",
+                (),
+            )
+            .await
+            .unwrap();
+        postgresql
+            .execute("CREATE TABLE test_diff_pools2 (id BIGINT)", ())
+            .await
+            .unwrap();
+        let tables = ["test_diff_pools1", "test_diff_pools2"];
+        assert_eq!(
+            diff_pools(&sqlite, &postgresql, &tables).await.unwrap(),
+            vec![SchemaChange::AddTable(
+                TableSchema::new("test_diff_pools2").column(ColumnSchema::new("id", "bigint"))
+            )]
+        );
+        assert_eq!(
+            diff_pools(&postgresql, &sqlite, &tables).await.unwrap(),
+            vec![SchemaChange::DropTable("test_diff_pools2".to_string())]
+        );
+
+        // Clean up:
+        for pool in [&sqlite, &postgresql] {
+            pool.drop_table("test_diff_pools1").await.unwrap();
+            pool.drop_table("test_diff_pools2").await.unwrap();
+        }
+    }
+}
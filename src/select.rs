@@ -0,0 +1,453 @@
+//! Code for building SELECT statements that can be rendered for any supported database kind.
+
+use crate::{
+    core::{DbError, DbQuery},
+    db_kind::DbKind,
+    db_value::{ColumnMap, DbParams},
+    filter::{Filter, quote_column},
+    parse::validate_table_name,
+};
+
+/// An aggregate function that can be applied to a column in a [Select].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn to_sql(self) -> &'static str {
+        match self {
+            Aggregate::Count => "COUNT",
+            Aggregate::Sum => "SUM",
+            Aggregate::Avg => "AVG",
+            Aggregate::Min => "MIN",
+            Aggregate::Max => "MAX",
+        }
+    }
+}
+
+/// A column, or an aggregate over a column, to be returned by a [Select].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SelectColumn {
+    Column(String),
+    Aggregate {
+        function: Aggregate,
+        /// The column to aggregate over, which may be `*` in the case of [Aggregate::Count].
+        column: String,
+        alias: String,
+    },
+}
+
+/// The kinds of joins supported by a [Select].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+}
+
+/// A join of another table, on the equality of a column of a table that has already been
+/// selected from with a column of the joined table.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Join {
+    pub kind: JoinKind,
+    pub table: String,
+    pub left_column: String,
+    pub right_column: String,
+}
+
+/// The direction of an ORDER BY term.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+/// A SELECT statement over a table and, optionally, the tables joined to it. Columns may be
+/// qualified with a table name, e.g., `table.column`, which is necessary when they would
+/// otherwise be ambiguous. Values in filters are passed to the database as bound parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Select {
+    pub table: String,
+    pub columns: Vec<SelectColumn>,
+    pub joins: Vec<Join>,
+    /// Filters that must all be satisfied by the selected rows.
+    pub filters: Vec<Filter>,
+    pub group_by: Vec<String>,
+    pub order_by: Vec<(String, Order)>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+impl Select {
+    /// Select all of the columns of all of the rows of the given table.
+    pub fn new(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            columns: vec![],
+            joins: vec![],
+            filters: vec![],
+            group_by: vec![],
+            order_by: vec![],
+            limit: None,
+            offset: None,
+        }
+    }
+
+    /// Select the given column.
+    pub fn column(mut self, column: &str) -> Self {
+        self.columns.push(SelectColumn::Column(column.to_string()));
+        self
+    }
+
+    /// Select the given columns.
+    pub fn columns(mut self, columns: &[&str]) -> Self {
+        for column in columns {
+            self = self.column(column);
+        }
+        self
+    }
+
+    /// Select the result of applying the given aggregate function to the given column, under
+    /// the given name.
+    pub fn aggregate(mut self, function: Aggregate, column: &str, alias: &str) -> Self {
+        self.columns.push(SelectColumn::Aggregate {
+            function,
+            column: column.to_string(),
+            alias: alias.to_string(),
+        });
+        self
+    }
+
+    /// Join the given table on the equality of the given columns.
+    pub fn join(mut self, table: &str, left_column: &str, right_column: &str) -> Self {
+        self.joins.push(Join {
+            kind: JoinKind::Inner,
+            table: table.to_string(),
+            left_column: left_column.to_string(),
+            right_column: right_column.to_string(),
+        });
+        self
+    }
+
+    /// Left join the given table on the equality of the given columns.
+    pub fn left_join(mut self, table: &str, left_column: &str, right_column: &str) -> Self {
+        self = self.join(table, left_column, right_column);
+        if let Some(join) = self.joins.last_mut() {
+            join.kind = JoinKind::Left;
+        }
+        self
+    }
+
+    /// Only select the rows that satisfy the given filter (as well as any other filters).
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Group the selected rows by the given columns.
+    pub fn group_by(mut self, columns: &[&str]) -> Self {
+        self.group_by
+            .extend(columns.iter().map(|column| column.to_string()));
+        self
+    }
+
+    /// Order the selected rows by the given column, in ascending order.
+    pub fn order_by(mut self, column: &str) -> Self {
+        self.order_by.push((column.to_string(), Order::Asc));
+        self
+    }
+
+    /// Order the selected rows by the given column, in descending order.
+    pub fn order_by_desc(mut self, column: &str) -> Self {
+        self.order_by.push((column.to_string(), Order::Desc));
+        self
+    }
+
+    /// Select at most the given number of rows.
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skip the given number of rows.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// The tables that this statement reads from, with the surrounding double-quotes (if any)
+    /// removed from their names.
+    pub fn tables(&self) -> Vec<&str> {
+        let mut tables = vec![unquoted(&self.table)];
+        for join in &self.joins {
+            if !tables.contains(&unquoted(&join.table)) {
+                tables.push(unquoted(&join.table));
+            }
+        }
+        tables
+    }
+
+    /// Render this statement as SQL for the given database kind, together with the parameters to
+    /// be bound to it. The given column map must contain the SQL types of all of the columns
+    /// referred to in this statement's filters, under the same names (qualified or not) that the
+    /// filters use. See [Select::render()], which gets the column map from the database.
+    pub fn to_sql(
+        &self,
+        kind: &dyn DbKind,
+        column_map: &ColumnMap,
+    ) -> Result<(String, DbParams), DbError> {
+        let table = validate_table_name(&self.table)?;
+        let join_tables = self
+            .joins
+            .iter()
+            .map(|join| validate_table_name(&join.table))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let columns = match self.columns.is_empty() {
+            true => "*".to_string(),
+            false => self
+                .columns
+                .iter()
+                .map(|column| match column {
+                    SelectColumn::Column(column) => quote_column(column),
+                    SelectColumn::Aggregate {
+                        function,
+                        column,
+                        alias,
+                    } => format!(
+                        r#"{}({}) AS "{alias}""#,
+                        function.to_sql(),
+                        quote_column(column)
+                    ),
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let mut sql = format!(r#"SELECT {columns} FROM "{table}""#);
+
+        for (join, join_table) in self.joins.iter().zip(join_tables) {
+            let kind = match join.kind {
+                JoinKind::Inner => "JOIN",
+                JoinKind::Left => "LEFT JOIN",
+            };
+            sql.push_str(&format!(
+                r#" {kind} "{join_table}" ON {} = {}"#,
+                quote_column(&join.left_column),
+                quote_column(&join.right_column)
+            ));
+        }
+
+        let mut params = vec![];
+        if !self.filters.is_empty() {
+            let condition =
+                Filter::And(self.filters.clone()).to_sql(kind, column_map, &mut params)?;
+            sql.push_str(&format!(" WHERE {condition}"));
+        }
+
+        if !self.group_by.is_empty() {
+            let group_by = self
+                .group_by
+                .iter()
+                .map(|column| quote_column(column))
+                .collect::<Vec<_>>();
+            sql.push_str(&format!(" GROUP BY {}", group_by.join(", ")));
+        }
+
+        if !self.order_by.is_empty() {
+            let order_by = self
+                .order_by
+                .iter()
+                .map(|(column, order)| match order {
+                    Order::Asc => format!("{} ASC", quote_column(column)),
+                    Order::Desc => format!("{} DESC", quote_column(column)),
+                })
+                .collect::<Vec<_>>();
+            sql.push_str(&format!(" ORDER BY {}", order_by.join(", ")));
+        }
+
+        let limit_offset = kind.limit_offset_sql(self.limit, self.offset);
+        if !limit_offset.is_empty() {
+            sql.push_str(&format!(" {limit_offset}"));
+        }
+
+        let params = match params.is_empty() {
+            true => DbParams::None,
+            false => DbParams::Positional(params),
+        };
+        Ok((sql, params))
+    }
+
+    /// Render this statement as SQL for the kind of the given pool, together with the parameters
+    /// to be bound to it. The types of the columns referred to in this statement's filters are
+    /// looked up in the database.
+    pub async fn render(&self, pool: &impl DbQuery) -> Result<(String, DbParams), DbError> {
+        // Columns may be qualified with any of the tables, or unqualified, in which case they
+        // are looked up in the selected table first, and then in the joined tables in order:
+        let mut column_map = ColumnMap::new();
+        for table in self.tables() {
+            for (column, sql_type) in pool.columns(table).await? {
+                column_map.insert(format!("{table}.{column}"), sql_type.clone());
+                column_map.entry(column).or_insert(sql_type);
+            }
+        }
+        self.to_sql(pool.kind().as_ref(), &column_map)
+    }
+}
+
+// Remove the surrounding double-quotes, if any, from the given table name.
+fn unquoted(table: &str) -> &str {
+    table
+        .strip_prefix('"')
+        .and_then(|table| table.strip_suffix('"'))
+        .unwrap_or(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        any::AnyPool,
+        db_kind::{PostgreSQLKind, SQLiteKind},
+        db_value::DbValue,
+    };
+    use pretty_assertions::assert_eq;
+    use std::ops::Deref;
+
+    #[test]
+    fn test_select_to_sql() {
+        let column_map = ColumnMap::from([
+            ("id".to_string(), "bigint".to_string()),
+            ("people.id".to_string(), "bigint".to_string()),
+            ("city".to_string(), "text".to_string()),
+        ]);
+        let select = Select::new("people")
+            .column("city")
+            .aggregate(Aggregate::Count, "*", "count")
+            .aggregate(Aggregate::Max, "people.id", "max_id")
+            .left_join("cities", "people.city", "cities.name")
+            .filter(Filter::gt("people.id", 1_i64))
+            .filter(Filter::is_in("city", ["Paris", "Rome"]))
+            .group_by(&["city"])
+            .order_by_desc("count")
+            .order_by("city")
+            .offset(2);
+        assert_eq!(select.tables(), ["people", "cities"]);
+
+        let (sql, params) = select.to_sql(&SQLiteKind {}, &column_map).unwrap();
+        assert_eq!(
+            sql,
+            r#"SELECT "city", COUNT(*) AS "count", MAX("people"."id") AS "max_id" FROM "people" LEFT JOIN "cities" ON "people"."city" = "cities"."name" WHERE ("people"."id" > ?1) AND ("city" IN (?2, ?3)) GROUP BY "city" ORDER BY "count" DESC, "city" ASC LIMIT -1 OFFSET 2"#
+        );
+        assert_eq!(
+            params,
+            DbParams::Positional(vec![
+                DbValue::BigInteger(1),
+                DbValue::from("Paris"),
+                DbValue::from("Rome"),
+            ])
+        );
+
+        let (sql, params) = Select::new("people")
+            .limit(10)
+            .offset(20)
+            .to_sql(&PostgreSQLKind {}, &column_map)
+            .unwrap();
+        assert_eq!(sql, r#"SELECT * FROM "people" LIMIT 10 OFFSET 20"#);
+        assert_eq!(params, DbParams::None);
+
+        // Quoted table names are accepted, and invalid ones are not:
+        let select = Select::new(r#""people""#).join(r#""cities""#, "people.city", "cities.name");
+        assert_eq!(select.tables(), ["people", "cities"]);
+        let (sql, _) = select.to_sql(&SQLiteKind {}, &column_map).unwrap();
+        assert_eq!(
+            sql,
+            r#"SELECT * FROM "people" JOIN "cities" ON "people"."city" = "cities"."name""#
+        );
+        assert!(
+            Select::new("people")
+                .join("cities; DROP TABLE people", "people.city", "cities.name")
+                .to_sql(&SQLiteKind {}, &column_map)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_select() {
+        #[cfg(feature = "rusqlite")]
+        select(":memory:").await;
+        #[cfg(feature = "tokio-postgres")]
+        select("postgresql:///rltbl_db").await;
+        #[cfg(feature = "libsql")]
+        select(":memory:").await;
+    }
+
+    async fn select(url: &str) {
+        let pool = AnyPool::connect(url).await.unwrap();
+        pool.memory_caches().clear_meta_cache();
+        pool.drop_table("test_select_people").await.unwrap();
+        pool.drop_table("test_select_cities").await.unwrap();
+        pool.execute_batch(
+            r#"CREATE TABLE test_select_cities (
+                 name TEXT PRIMARY KEY,
+                 country TEXT
+               );
+               INSERT INTO test_select_cities VALUES ('Paris', 'France'), ('Lyon', 'France'),
+                 ('Rome', 'Italy');
+               CREATE TABLE test_select_people (
+                 id BIGINT PRIMARY KEY,
+                 name TEXT,
+                 city TEXT
+               );
+               INSERT INTO test_select_people VALUES (1, 'Ann', 'Paris'), (2, 'Bob', 'Lyon'),
+                 (3, 'Cat', 'Rome'), (4, 'Dan', 'Paris'), (5, 'Eve', NULL)"#,
+        )
+        .await
+        .unwrap();
+
+        let select = Select::new("test_select_people")
+            .column("name")
+            .filter(Filter::gt("id", "1"))
+            .order_by_desc("id")
+            .limit(2)
+            .offset(1);
+        assert_eq!(
+            pool.select(&select).await.unwrap().to_strings().unwrap(),
+            ["Dan", "Cat"]
+        );
+
+        let select = Select::new("test_select_people")
+            .column("country")
+            .aggregate(Aggregate::Count, "*", "count")
+            .join(
+                "test_select_cities",
+                "test_select_people.city",
+                "test_select_cities.name",
+            )
+            .filter(Filter::not_eq("test_select_people.name", "Ann"))
+            .group_by(&["country"])
+            .order_by("country");
+        let rows = pool.select(&select).await.unwrap();
+        assert_eq!(
+            rows.iter()
+                .map(|row| format!(
+                    "{}: {}",
+                    row.get("country").unwrap(),
+                    row.get("count").unwrap()
+                ))
+                .collect::<Vec<_>>(),
+            ["France: 2", "Italy: 1"]
+        );
+        assert_eq!(
+            *pool.cache_select(&select).await.unwrap().deref(),
+            *rows.deref()
+        );
+
+        // Clean up:
+        pool.drop_table("test_select_people").await.unwrap();
+        pool.drop_table("test_select_cities").await.unwrap();
+    }
+}
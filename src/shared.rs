@@ -1,16 +1,66 @@
 use crate::{
     cache::clear_cache_for_edited_tables,
     core::{DbError, DbQuery},
-    db_value::{DbRows, DbValue, IntoDbRows},
+    db_kind::DbKind,
+    db_value::{DbRow, DbRows, DbValue, IntoDbRows},
     parse::validate_table_name,
 };
 use std::fmt::Display;
 
+/// The number of rows after which progress is reported during a bulk insert.
+pub static BULK_INSERT_BATCH_SIZE: usize = 10_000;
+
+/// Options controlling what happens when a row to be upserted conflicts with an existing row.
+/// By default, a conflict is a duplicate primary key, and every other given column of the
+/// existing row is updated with the new value.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UpsertOptions {
+    /// The columns of the unique constraint or index whose violation constitutes a conflict. If
+    /// empty, the table's primary key is used.
+    pub conflict_columns: Vec<String>,
+    /// Whether to leave the existing row as it is in case of a conflict.
+    pub do_nothing: bool,
+    /// The columns to update in case of a conflict. If empty, all of the given columns that are
+    /// not conflict columns are updated.
+    pub update_columns: Vec<String>,
+    /// A SQL condition that the existing row must satisfy for it to be updated. The existing
+    /// row's values can be referred to by qualifying the column with the table name, and the new
+    /// values by qualifying it with "excluded".
+    pub condition: Option<String>,
+}
+
+impl UpsertOptions {
+    /// Use the unique constraint or index on the given columns to detect conflicts.
+    pub fn on_conflict(mut self, columns: &[&str]) -> Self {
+        self.conflict_columns = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Leave existing rows as they are in case of a conflict, i.e., only insert absent rows.
+    pub fn do_nothing(mut self) -> Self {
+        self.do_nothing = true;
+        self
+    }
+
+    /// Only update the given columns in case of a conflict.
+    pub fn update_columns(mut self, columns: &[&str]) -> Self {
+        self.update_columns = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Only update existing rows that satisfy the given SQL condition in case of a conflict.
+    pub fn update_where(mut self, condition: &str) -> Self {
+        self.condition = Some(condition.to_string());
+        self
+    }
+}
+
 #[derive(PartialEq, Eq)]
 pub(crate) enum EditType {
     Insert,
     Update,
-    Upsert,
+    Upsert(UpsertOptions),
+    Delete,
 }
 
 impl Display for EditType {
@@ -18,7 +68,8 @@ impl Display for EditType {
         match self {
             EditType::Update => write!(f, "UPDATE"),
             EditType::Insert => write!(f, "INSERT"),
-            EditType::Upsert => write!(f, "UPSERT"),
+            EditType::Upsert(_) => write!(f, "UPSERT"),
+            EditType::Delete => write!(f, "DELETE"),
         }
     }
 }
@@ -89,11 +140,13 @@ VALUES
 }
 
 // Generate SQL statement of the form:
-// INSERT INTO <table> VALUES <tuples> ON CONFLICT (<primary key constraint>) DO UPDATE ...
+// INSERT INTO <table> VALUES <tuples> ON CONFLICT (<conflict columns>) DO UPDATE ...
+// where the conflict action is determined by the given options.
 pub(crate) fn generate_upsert_statement(
     table: &str,
     columns: &[&str],
-    primary_keys: &[&str],
+    conflict_columns: &[&str],
+    options: &UpsertOptions,
     returning_clause: &str,
     value_lines: &[&str],
 ) -> String {
@@ -103,32 +156,70 @@ pub(crate) fn generate_upsert_statement(
         .collect::<Vec<_>>()
         .join(", ");
 
-    let constraint_clause = primary_keys
+    let constraint_clause = conflict_columns
         .iter()
-        .map(|pk| format!(r#""{pk}""#))
+        .map(|column| format!(r#""{column}""#))
         .collect::<Vec<_>>()
         .join(", ");
 
     let set_clause = columns
         .iter()
-        .filter(|column| !primary_keys.contains(&column))
+        .filter(|column| !conflict_columns.contains(&column))
+        .filter(|column| {
+            options.update_columns.is_empty() || options.update_columns.iter().any(|c| c == *column)
+        })
         .map(|column| format!(r#""{column}" = "excluded"."{column}""#))
         .collect::<Vec<_>>()
         .join(", ");
 
+    // If there is nothing to update then there is nothing to do in case of a conflict:
+    let action = match options.do_nothing || set_clause.is_empty() {
+        true => "DO NOTHING".to_string(),
+        false => match &options.condition {
+            Some(condition) => format!("DO UPDATE SET {set_clause} WHERE {condition}"),
+            None => format!("DO UPDATE SET {set_clause}"),
+        },
+    };
+
     format!(
         r#"INSERT INTO "{table}" ({quoted_columns})
 VALUES
 {}
-ON CONFLICT ({constraint_clause}) DO UPDATE SET {set_clause}{returning_clause}"#,
+ON CONFLICT ({constraint_clause}) {action}{returning_clause}"#,
         value_lines.join(",\n"),
     )
 }
 
+// Generate a SQL DELETE statement for the given table using the given clauses and the given
+// value lines, each of which contains the primary key values of a single row to be deleted.
+pub(crate) fn generate_delete_statement(
+    table: &str,
+    primary_keys: &[&str],
+    returning_clause: &str,
+    value_lines: &[&str],
+) -> String {
+    let quoted_primary_keys = primary_keys
+        .iter()
+        .map(|pk| format!(r#""{pk}""#))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        r#"DELETE FROM "{table}"
+WHERE ({quoted_primary_keys}) IN (
+  VALUES
+  {}
+){returning_clause}"#,
+        value_lines.join(",\n")
+    )
+}
+
 /// Edit the given rows in the given table using the given queryable pool and optional returning
 /// clause (set with_returning = false to turn this off). When generating the SQL statements
 /// used to edit the table, do not use more than max_params bound parameters at a time. If more
-/// than max_params are required, multiple SQL statements will be generated.
+/// than max_params are required, multiple SQL statements will be generated. Note that when
+/// deleting, the rows are identified using the table's primary key columns, and the given
+/// columns are ignored.
 pub(crate) async fn edit(
     pool: &(impl DbQuery + Sync),
     edit_type: &EditType,
@@ -143,19 +234,6 @@ pub(crate) async fn edit(
     // removing any enclosing double-quotes:
     let table = validate_table_name(table)?;
 
-    // This is very unlikely but we check anyway to be sure:
-    if columns.len() > *max_params {
-        return Err(DbError::InputError(format!(
-            "Unable to {} table '{}', which has more columns ({}) than the \
-             maximum number of variables ({}) allowed in a SQL statement by {}.",
-            edit_type,
-            table,
-            columns.len(),
-            max_params,
-            pool.kind(),
-        )));
-    }
-
     // Use the `returning` argument to restrict the RETURNING clause, defaulting
     // to '*' if `returning` is empty:
     let returning_clause = match with_returning {
@@ -173,29 +251,76 @@ pub(crate) async fn edit(
         false => String::new(),
     };
 
+    // For upserts, the primary keys are replaced by the conflict columns if any were specified:
     let primary_keys = match edit_type {
-        EditType::Update | EditType::Upsert => match pool.primary_keys(&table).await? {
-            primary_keys if primary_keys.is_empty() => {
-                return Err(DbError::InputError(
-                    "Primary keys must not be empty.".to_string(),
-                ));
-            }
-            primary_keys
-                if !primary_keys
-                    .iter()
-                    .all(|pkey| columns.contains(&pkey.as_str())) =>
+        EditType::Upsert(options) if !options.conflict_columns.is_empty() => {
+            let conflict_columns = &options.conflict_columns;
+            if !conflict_columns
+                .iter()
+                .all(|column| columns.contains(&column.as_str()))
             {
                 return Err(DbError::InputError(format!(
-                    "Not all of the table's primary keys: {primary_keys:?} are in {columns:?}"
+                    "Not all of the conflict columns: {conflict_columns:?} are in {columns:?}"
                 )));
             }
-            primary_keys => primary_keys,
-        },
+            conflict_columns.clone()
+        }
+        EditType::Update | EditType::Upsert(_) | EditType::Delete => {
+            match pool.primary_keys(&table).await? {
+                primary_keys if primary_keys.is_empty() => {
+                    return Err(DbError::InputError(
+                        "Primary keys must not be empty.".to_string(),
+                    ));
+                }
+                primary_keys
+                    if *edit_type != EditType::Delete
+                        && !primary_keys
+                            .iter()
+                            .all(|pkey| columns.contains(&pkey.as_str())) =>
+                {
+                    return Err(DbError::InputError(format!(
+                        "Not all of the table's primary keys: {primary_keys:?} are in {columns:?}"
+                    )));
+                }
+                primary_keys => primary_keys,
+            }
+        }
         // Since we don't need a list of the table's primary keys to do an insert, we save
         // the database access and just return an empty list here:
         EditType::Insert => vec![],
     };
 
+    // Rows to be deleted are identified by the values of their primary key columns only:
+    let primary_key_columns = primary_keys.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    let columns = match edit_type {
+        EditType::Delete => primary_key_columns.as_slice(),
+        _ => columns,
+    };
+
+    if let EditType::Upsert(options) = edit_type
+        && let Some(column) = options
+            .update_columns
+            .iter()
+            .find(|column| !columns.contains(&column.as_str()))
+    {
+        return Err(DbError::InputError(format!(
+            "Column to update on conflict: '{column}' is not in {columns:?}"
+        )));
+    }
+
+    // This is very unlikely but we check anyway to be sure:
+    if columns.len() > *max_params {
+        return Err(DbError::InputError(format!(
+            "Unable to {} table '{}', which has more columns ({}) than the \
+             maximum number of variables ({}) allowed in a SQL statement by {}.",
+            edit_type,
+            table,
+            columns.len(),
+            max_params,
+            pool.kind(),
+        )));
+    }
+
     // We use the column_map to determine the SQL type of each parameter.
     let column_map = pool.columns(&table).await?;
     let param_prefix = pool.kind().param_prefix().to_string();
@@ -237,7 +362,7 @@ pub(crate) async fn edit(
                     .collect::<Vec<_>>()
                     .as_slice(),
             ),
-            EditType::Upsert => generate_upsert_statement(
+            EditType::Upsert(options) => generate_upsert_statement(
                 &table,
                 columns,
                 primary_keys
@@ -245,6 +370,17 @@ pub(crate) async fn edit(
                     .map(|s| s.as_str())
                     .collect::<Vec<_>>()
                     .as_slice(),
+                options,
+                &returning_clause,
+                lines_to_bind
+                    .iter()
+                    .map(|s| s.as_str())
+                    .collect::<Vec<_>>()
+                    .as_slice(),
+            ),
+            EditType::Delete => generate_delete_statement(
+                &table,
+                columns,
                 &returning_clause,
                 lines_to_bind
                     .iter()
@@ -286,9 +422,10 @@ pub(crate) async fn edit(
                 "Column '{column}' does not exist in table '{table}'"
             )))?;
             param_idx += 1;
-            // In the CTE we generate for UPDATE statements, tokio-postgres can't infer the types
-            // of the VALUES, so we explicitly cast them.
-            if *edit_type == EditType::Update
+            // In the CTE we generate for UPDATE statements, and in the VALUES list we generate for
+            // DELETE statements, tokio-postgres can't infer the types of the VALUES, so we
+            // explicitly cast them.
+            if (*edit_type == EditType::Update || *edit_type == EditType::Delete)
                 && pool.kind().to_string() == "postgresql"
                 // We only need to cast the first value row. The rest are inferred by Postgres:
                 && lines_to_bind.len() == 0
@@ -328,3 +465,75 @@ pub(crate) async fn edit(
 
     Ok(rows_to_return.into_db_rows())
 }
+
+// Convert the given value to the given SQL type, leaving NULLs as they are.
+pub(crate) fn convert_value(
+    kind: &dyn DbKind,
+    sql_type: &str,
+    value: &DbValue,
+) -> Result<DbValue, DbError> {
+    match value {
+        DbValue::Null => Ok(DbValue::Null),
+        value => kind.db_type(sql_type)?.convert(value),
+    }
+}
+
+// Generate a SQL INSERT statement for a single row of the given table and columns, using the
+// given parameter prefix.
+pub(crate) fn generate_bulk_insert_statement(
+    table: &str,
+    columns: &[&str],
+    param_prefix: &str,
+) -> String {
+    let quoted_columns = columns
+        .iter()
+        .map(|c| format!(r#""{c}""#))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let params = (1..=columns.len())
+        .map(|i| format!("{param_prefix}{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(r#"INSERT INTO "{table}" ({quoted_columns}) VALUES ({params})"#)
+}
+
+/// Validate the given table and columns for a bulk insert, returning the table name with any
+/// enclosing double-quotes removed and the SQL types of the given columns.
+pub(crate) async fn get_bulk_insert_types(
+    pool: &impl DbQuery,
+    table: &str,
+    columns: &[&str],
+) -> Result<(String, Vec<String>), DbError> {
+    let table = validate_table_name(table)?;
+    if columns.is_empty() {
+        return Err(DbError::InputError(format!(
+            "No columns specified for bulk insert into '{table}'"
+        )));
+    }
+    let column_map = pool.columns(&table).await?;
+    let mut sql_types = vec![];
+    for column in columns {
+        let sql_type = column_map.get(*column).ok_or(DbError::InputError(format!(
+            "Column '{column}' does not exist in table '{table}'"
+        )))?;
+        sql_types.push(sql_type.to_string());
+    }
+    Ok((table, sql_types))
+}
+
+/// Get the values of the given columns from the given row for a bulk insert, converted to the
+/// given SQL types. Missing values are treated as NULL.
+pub(crate) fn get_bulk_insert_values(
+    kind: &dyn DbKind,
+    row: &DbRow,
+    columns: &[&str],
+    sql_types: &[String],
+) -> Result<Vec<DbValue>, DbError> {
+    columns
+        .iter()
+        .zip(sql_types)
+        .map(|(column, sql_type)| {
+            convert_value(kind, sql_type, &row.get(column).unwrap_or_default())
+        })
+        .collect()
+}
@@ -2,23 +2,37 @@
 
 use crate::{
     any::AnyPool,
-    cache::{CachingStrategy, clear_cache_for_affected_tables, clear_cache_for_dropped_tables},
+    cache::{
+        CACHE_NOTIFY_CHANNEL, CacheCounters, CacheEncoding, CachingStrategy, MemoryCaches,
+        clear_cache_for_affected_tables, clear_cache_for_dropped_tables,
+        clear_cache_for_edited_tables,
+    },
     core::{DbError, DbQuery},
     db_kind::{DbKind, MAX_PARAMS_POSTGRES, PostgreSQLKind},
     db_value::{DbParams, DbRow, DbRows, DbValue, IntoDbParams, IntoDbRows, JsonValue},
     parse::validate_table_name,
-    shared::{EditType, edit},
+    shared::{
+        BULK_INSERT_BATCH_SIZE, EditType, UpsertOptions, edit, get_bulk_insert_types,
+        get_bulk_insert_values,
+    },
 };
 use bytes::{BufMut, BytesMut};
 use deadpool_postgres::{
     Config, Pool, Runtime,
     tokio_postgres::{
-        NoTls,
+        AsyncMessage, NoTls,
+        binary_copy::BinaryCopyInWriter,
         row::Row,
         types::{FromSql, IsNull, ToSql, Type, to_sql_checked},
     },
 };
 use rust_decimal::Decimal;
+use std::{
+    future::poll_fn,
+    pin::{Pin, pin},
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
 // Represents a PostgreSQL datatype that is not explicitly handled in extract_value() and query().
 #[derive(Clone, Debug)]
@@ -70,6 +84,67 @@ impl ToSql for GenericTypeValue {
     to_sql_checked!();
 }
 
+/// Converts the given [DbValue] into a parameter that can be bound to a query as a value of the
+/// given [Type]. If the value is of the wrong type, the name of the expected type is returned as
+/// an error.
+fn to_pg_param(param: &DbValue, pg_type: &Type) -> Result<Box<dyn ToSql + Sync + Send>, String> {
+    let param: Box<dyn ToSql + Sync + Send> = match pg_type {
+        &Type::TEXT | &Type::VARCHAR | &Type::NAME => match param {
+            DbValue::Null => Box::new(None::<String>),
+            DbValue::Text(text) => Box::new(text.to_string()),
+            _ => return Err("TEXT".to_string()),
+        },
+        &Type::INT2 => match param {
+            DbValue::Null => Box::new(None::<i16>),
+            DbValue::SmallInteger(num) => Box::new(*num),
+            _ => return Err("INT2".to_string()),
+        },
+        &Type::INT4 => match param {
+            DbValue::Null => Box::new(None::<i32>),
+            DbValue::Integer(num) => Box::new(*num),
+            _ => return Err("INT4".to_string()),
+        },
+        &Type::INT8 => match param {
+            DbValue::Null => Box::new(None::<i64>),
+            DbValue::BigInteger(num) => Box::new(*num),
+            _ => return Err("INT8".to_string()),
+        },
+        &Type::FLOAT4 => match param {
+            DbValue::Null => Box::new(None::<f32>),
+            DbValue::Real(num) => Box::new(*num),
+            _ => return Err("FLOAT4".to_string()),
+        },
+        &Type::FLOAT8 => match param {
+            DbValue::Null => Box::new(None::<f64>),
+            DbValue::BigReal(num) => Box::new(*num),
+            _ => return Err("FLOAT8".to_string()),
+        },
+        &Type::NUMERIC => match param {
+            DbValue::Null => Box::new(None::<Decimal>),
+            DbValue::Numeric(num) => Box::new(*num),
+            _ => return Err("NUMERIC".to_string()),
+        },
+        &Type::BOOL => match param {
+            DbValue::Null => Box::new(None::<bool>),
+            DbValue::Boolean(flag) => Box::new(*flag),
+            _ => return Err("BOOL".to_string()),
+        },
+        &Type::JSON | &Type::JSONB => match param {
+            DbValue::Null => Box::new(None::<JsonValue>),
+            DbValue::Json(value) => Box::new(value.clone()),
+            _ => return Err(pg_type.to_string()),
+        },
+        other => match param {
+            DbValue::Null => Box::new(GenericTypeValue { bytes: None }),
+            DbValue::Other(_cname, bytes, _string_opt) => Box::new(GenericTypeValue {
+                bytes: Some(bytes.clone()),
+            }),
+            _ => return Err(other.to_string()),
+        },
+    };
+    Ok(param)
+}
+
 /// Extracts the value at the given index from the given [Row].
 fn extract_value(row: &Row, idx: usize) -> Result<DbValue, DbError> {
     let column = &row.columns()[idx];
@@ -177,12 +252,21 @@ fn extract_value(row: &Row, idx: usize) -> Result<DbValue, DbError> {
 #[derive(Clone, Debug)]
 pub struct TokioPostgresPool {
     pool: Pool,
+    config: Arc<Config>,
+    memory_caches: Arc<MemoryCaches>,
+    cache_counters: Arc<CacheCounters>,
     caching_strategy: CachingStrategy,
     /// When set to true, SQL statements sent to the [DbQuery::query()] and [DbQuery::execute()]
     /// functions will be parsed and if they will result in tables being edited and/or dropped,
     /// the cache will be maintained in accordance with the given [CachingStrategy].
     /// For further information, see [DbQuery::set_cache_aware_query()].
     cache_aware_query: bool,
+    /// The maximum age of the cached query results returned by [DbQuery::cache()], if any.
+    /// For further information, see [DbQuery::set_cache_ttl()].
+    cache_ttl: Option<Duration>,
+    /// The encoding in which cached query results are stored in the query cache table.
+    /// For further information, see [DbQuery::set_cache_encoding()].
+    cache_encoding: CacheEncoding,
 }
 
 impl TokioPostgresPool {
@@ -203,8 +287,13 @@ impl TokioPostgresPool {
                     })?;
                 Ok(Self {
                     pool: pool,
+                    config: Arc::new(cfg),
+                    memory_caches: MemoryCaches::for_database(Some(url)),
+                    cache_counters: Arc::default(),
                     caching_strategy: CachingStrategy::None,
                     cache_aware_query: false,
+                    cache_ttl: None,
+                    cache_encoding: CacheEncoding::default(),
                 })
             }
             false => Err(DbError::ConnectError(format!(
@@ -212,6 +301,119 @@ impl TokioPostgresPool {
             ))),
         }
     }
+
+    /// Listen for the notifications sent on the [CACHE_NOTIFY_CHANNEL] channel whenever a table
+    /// is edited, and clear the entries for that table from the memory caches shared by this
+    /// pool. Once listening, [CachingStrategy::Memory] creates triggers to send these
+    /// notifications for every table that it caches, so that edits made by other processes
+    /// connected to the same database (which have their own memory caches) are not missed.
+    /// Listening continues, on a dedicated connection, until the memory caches are dropped. If
+    /// the connection is lost, the memory caches are cleared, since notifications may have been
+    /// missed, and the connection is re-established.
+    pub async fn listen_for_cache_invalidations(&self) -> Result<(), DbError> {
+        if self.memory_caches.set_listening(true) {
+            return Ok(());
+        }
+        let caches = Arc::downgrade(&self.memory_caches);
+        if let Err(err) = listen(self.config.clone(), caches).await {
+            self.memory_caches.set_listening(false);
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+/// The longest time to wait between attempts to re-establish a lost connection on which to listen
+/// for cache invalidations (see [TokioPostgresPool::listen_for_cache_invalidations()]).
+pub static MAX_LISTEN_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+// Connect to the database with the given configuration and listen on the [CACHE_NOTIFY_CHANNEL],
+// invalidating the entries in the given caches for the tables named in the notifications. If the
+// connection is later lost, clear the caches and keep trying to reconnect.
+fn listen(
+    config: Arc<Config>,
+    caches: Weak<MemoryCaches>,
+) -> Pin<Box<dyn Future<Output = Result<(), DbError>> + Send>> {
+    Box::pin(async move {
+        let pg_config = config
+            .get_pg_config()
+            .map_err(|err| DbError::ConnectError(format!("Invalid configuration: {err}")))?;
+        let (client, mut connection) = pg_config
+            .connect(NoTls)
+            .await
+            .map_err(|err| DbError::ConnectError(format!("Error connecting: {err}")))?;
+        let client = Arc::new(client);
+
+        // The connection must be polled for the client to make progress, and for notifications
+        // to be received. It is polled with a timeout so that the task can end soon after the
+        // caches are dropped or the listener is stopped.
+        let listening_client = client.clone();
+        let listening_caches = caches.clone();
+        tokio::spawn(async move {
+            // The connection is closed when the last reference to the client is dropped:
+            let _client = listening_client;
+            let caches = listening_caches;
+            loop {
+                let message = tokio::time::timeout(
+                    Duration::from_secs(1),
+                    poll_fn(|cx| connection.poll_message(cx)),
+                )
+                .await;
+                let Some(caches) = caches.upgrade().filter(|caches| caches.is_listening()) else {
+                    return;
+                };
+                match message {
+                    Ok(Some(Ok(AsyncMessage::Notification(notification)))) => {
+                        if let Err(err) = caches.invalidate_tables(&[notification.payload()]) {
+                            eprintln!("WARNING: Got error: '{err}' while invalidating cache.");
+                        }
+                    }
+                    Ok(Some(Ok(_))) | Err(_) => (),
+                    Ok(Some(Err(_))) | Ok(None) => {
+                        eprintln!(
+                            "WARNING: Lost the connection used to listen for cache \
+                             invalidations. Clearing the memory caches and reconnecting."
+                        );
+                        caches.clear_query_cache(&[]);
+                        caches.clear_table_cache(&[]);
+                        break;
+                    }
+                }
+            }
+
+            let mut delay = Duration::from_secs(1);
+            loop {
+                tokio::time::sleep(delay).await;
+                let Some(caches) = caches.upgrade().filter(|caches| caches.is_listening()) else {
+                    return;
+                };
+                match listen(config.clone(), Arc::downgrade(&caches)).await {
+                    Ok(()) => {
+                        // Edits may have been made while there was no connection:
+                        caches.clear_query_cache(&[]);
+                        caches.clear_table_cache(&[]);
+                        return;
+                    }
+                    Err(err) => {
+                        delay = (delay * 2).min(MAX_LISTEN_RECONNECT_DELAY);
+                        eprintln!(
+                            "WARNING: Got error: '{err}' while reconnecting to listen for cache \
+                             invalidations. Retrying in {delay:?}."
+                        );
+                    }
+                }
+            }
+        });
+
+        client
+            .batch_execute(&format!(r#"LISTEN "{CACHE_NOTIFY_CHANNEL}""#))
+            .await
+            .map_err(|err| {
+                DbError::DatabaseError(format!(
+                    "Error listening on channel '{CACHE_NOTIFY_CHANNEL}': {err}"
+                ))
+            })
+    })
 }
 
 impl DbQuery for TokioPostgresPool {
@@ -224,11 +426,26 @@ impl DbQuery for TokioPostgresPool {
     fn pool(&self) -> AnyPool {
         AnyPool::TokioPostgres(TokioPostgresPool {
             pool: self.pool.clone(),
+            config: self.config.clone(),
+            memory_caches: self.memory_caches.clone(),
+            cache_counters: self.cache_counters.clone(),
             caching_strategy: self.caching_strategy,
             cache_aware_query: self.cache_aware_query,
+            cache_ttl: self.cache_ttl,
+            cache_encoding: self.cache_encoding,
         })
     }
 
+    /// Implements [DbQuery::memory_caches()] for PostgreSQL.
+    fn memory_caches(&self) -> Arc<MemoryCaches> {
+        self.memory_caches.clone()
+    }
+
+    /// Implements [DbQuery::cache_counters()] for PostgreSQL.
+    fn cache_counters(&self) -> Arc<CacheCounters> {
+        self.cache_counters.clone()
+    }
+
     /// Implements [DbQuery::set_caching_strategy()] for PostgreSQL.
     fn set_caching_strategy(&mut self, strategy: &CachingStrategy) {
         self.caching_strategy = *strategy;
@@ -249,6 +466,26 @@ impl DbQuery for TokioPostgresPool {
         self.cache_aware_query
     }
 
+    /// Implements [DbQuery::set_cache_ttl()] for PostgreSQL.
+    fn set_cache_ttl(&mut self, ttl: Option<Duration>) {
+        self.cache_ttl = ttl;
+    }
+
+    /// Implements [DbQuery::get_cache_ttl()] for PostgreSQL.
+    fn get_cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl
+    }
+
+    /// Implements [DbQuery::set_cache_encoding()] for PostgreSQL.
+    fn set_cache_encoding(&mut self, encoding: CacheEncoding) {
+        self.cache_encoding = encoding;
+    }
+
+    /// Implements [DbQuery::get_cache_encoding()] for PostgreSQL.
+    fn get_cache_encoding(&self) -> CacheEncoding {
+        self.cache_encoding
+    }
+
     /// Implements [DbQuery::execute_batch()] for PostgreSQL
     async fn execute_batch(&self, sql: &str) -> Result<(), DbError> {
         let client =
@@ -264,6 +501,27 @@ impl DbQuery for TokioPostgresPool {
         Ok(())
     }
 
+    /// Implements [DbQuery::execute_batch_with_recovery()] for PostgreSQL.
+    async fn execute_batch_with_recovery(
+        &self,
+        sql: &str,
+        recovery: &[&str],
+    ) -> Result<(), DbError> {
+        let client =
+            self.pool.get().await.map_err(|err| {
+                DbError::ConnectError(format!("Unable to get from pool: {err:?}"))
+            })?;
+        if let Err(err) = client.batch_execute(sql).await {
+            for sql in recovery {
+                client.batch_execute(sql).await.ok();
+            }
+            return Err(DbError::DatabaseError(format!("Error in query(): {err:?}")));
+        }
+
+        clear_cache_for_affected_tables(&self.pool(), sql).await?;
+        Ok(())
+    }
+
     /// Implements [DbQuery::query_no_cache_clean()] for PostgreSQL.
     async fn query_no_cache_clean(
         &self,
@@ -293,92 +551,10 @@ impl DbQuery for TokioPostgresPool {
             DbParams::Positional(plist) => {
                 for (i, param) in plist.iter().enumerate() {
                     let pg_type = &param_pg_types[i];
-                    match pg_type {
-                        &Type::TEXT | &Type::VARCHAR | &Type::NAME => {
-                            match param {
-                                DbValue::Null => params.push(Box::new(None::<String>)),
-                                DbValue::Text(text) => params.push(Box::new(text.to_string())),
-                                _ => return Err(DbError::InputError(gen_err(&param, "TEXT"))),
-                            };
-                        }
-                        &Type::INT2 => {
-                            match param {
-                                DbValue::Null => params.push(Box::new(None::<i16>)),
-                                DbValue::SmallInteger(num) => params.push(Box::new(*num)),
-                                _ => return Err(DbError::InputError(gen_err(&param, "INT2"))),
-                            };
-                        }
-                        &Type::INT4 => {
-                            match param {
-                                DbValue::Null => params.push(Box::new(None::<i32>)),
-                                DbValue::Integer(num) => params.push(Box::new(*num)),
-                                _ => return Err(DbError::InputError(gen_err(&param, "INT4"))),
-                            };
-                        }
-                        &Type::INT8 => {
-                            match param {
-                                DbValue::Null => params.push(Box::new(None::<i64>)),
-                                DbValue::BigInteger(num) => params.push(Box::new(*num)),
-                                _ => return Err(DbError::InputError(gen_err(&param, "INT8"))),
-                            };
-                        }
-                        &Type::FLOAT4 => {
-                            match param {
-                                DbValue::Null => params.push(Box::new(None::<f32>)),
-                                DbValue::Real(num) => params.push(Box::new(*num)),
-                                _ => return Err(DbError::InputError(gen_err(&param, "FLOAT4"))),
-                            };
-                        }
-                        &Type::FLOAT8 => {
-                            match param {
-                                DbValue::Null => params.push(Box::new(None::<f64>)),
-                                DbValue::BigReal(num) => params.push(Box::new(*num)),
-                                _ => return Err(DbError::InputError(gen_err(&param, "FLOAT8"))),
-                            };
-                        }
-                        &Type::NUMERIC => {
-                            match param {
-                                DbValue::Null => params.push(Box::new(None::<Decimal>)),
-                                DbValue::Numeric(num) => params.push(Box::new(*num)),
-                                _ => return Err(DbError::InputError(gen_err(&param, "NUMERIC"))),
-                            };
-                        }
-                        &Type::BOOL => {
-                            match param {
-                                DbValue::Null => params.push(Box::new(None::<bool>)),
-                                DbValue::Boolean(flag) => params.push(Box::new(*flag)),
-                                _ => return Err(DbError::InputError(gen_err(&param, "BOOL"))),
-                            };
-                        }
-                        &Type::JSON | &Type::JSONB => match param {
-                            DbValue::Null => params.push(Box::new(None::<JsonValue>)),
-                            DbValue::Json(value) => params.push(Box::new(value.clone())),
-                            _ => {
-                                return Err(DbError::InputError(gen_err(
-                                    &param,
-                                    &pg_type.to_string(),
-                                )));
-                            }
-                        },
-                        other => {
-                            match param {
-                                DbValue::Null => {
-                                    params.push(Box::new(GenericTypeValue { bytes: None }))
-                                }
-                                DbValue::Other(_cname, bytes, _string_opt) => {
-                                    params.push(Box::new(GenericTypeValue {
-                                        bytes: Some(bytes.clone()),
-                                    }))
-                                }
-                                _ => {
-                                    return Err(DbError::InputError(gen_err(
-                                        &param,
-                                        &other.to_string(),
-                                    )));
-                                }
-                            };
-                        }
-                    };
+                    params.push(
+                        to_pg_param(param, pg_type)
+                            .map_err(|sql_type| DbError::InputError(gen_err(param, &sql_type)))?,
+                    );
                 }
             }
         };
@@ -507,7 +683,7 @@ impl DbQuery for TokioPostgresPool {
     ) -> Result<(), DbError> {
         edit(
             self,
-            &EditType::Upsert,
+            &EditType::Upsert(UpsertOptions::default()),
             &MAX_PARAMS_POSTGRES,
             table,
             columns,
@@ -529,7 +705,129 @@ impl DbQuery for TokioPostgresPool {
     ) -> Result<DbRows, DbError> {
         edit(
             self,
-            &EditType::Upsert,
+            &EditType::Upsert(UpsertOptions::default()),
+            &MAX_PARAMS_POSTGRES,
+            table,
+            columns,
+            rows,
+            true,
+            returning,
+        )
+        .await
+    }
+
+    /// Implements [DbQuery::bulk_insert()] for PostgreSQL. The rows are inserted using
+    /// `COPY ... FROM STDIN` in binary format.
+    async fn bulk_insert(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl Iterator<Item = DbRow> + Send,
+        mut progress: impl FnMut(u64) + Send,
+    ) -> Result<u64, DbError> {
+        let (table, sql_types) = get_bulk_insert_types(self, table, columns).await?;
+        let quoted_columns = columns
+            .iter()
+            .map(|column| format!(r#""{column}""#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let client =
+            self.pool.get().await.map_err(|err| {
+                DbError::ConnectError(format!("Unable to get from pool: {err:?}"))
+            })?;
+
+        // The types of the columns as reported by the database:
+        let pg_types = client
+            .prepare(&format!(
+                r#"SELECT {quoted_columns} FROM "{table}" LIMIT 0"#
+            ))
+            .await
+            .map_err(|err| DbError::DatabaseError(format!("Error preparing statement: {err:?}")))?
+            .columns()
+            .iter()
+            .map(|column| column.type_().clone())
+            .collect::<Vec<_>>();
+
+        let sink = client
+            .copy_in(&format!(
+                r#"COPY "{table}" ({quoted_columns}) FROM STDIN (FORMAT binary)"#
+            ))
+            .await
+            .map_err(|err| DbError::DatabaseError(format!("Error starting COPY: {err:?}")))?;
+        // If we return early due to an error, the writer is dropped without being finished,
+        // which aborts the COPY, so that none of the rows are inserted:
+        let mut writer = pin!(BinaryCopyInWriter::new(sink, &pg_types));
+        let mut count = 0;
+        for row in rows {
+            let values = get_bulk_insert_values(&PostgreSQLKind, &row, columns, &sql_types)?;
+            let mut params = vec![];
+            for (value, pg_type) in values.iter().zip(&pg_types) {
+                params.push(to_pg_param(value, pg_type).map_err(|sql_type| {
+                    DbError::InputError(format!(
+                        "Value {value:?} is wrong type for {sql_type} in table '{table}'"
+                    ))
+                })?);
+            }
+            let params = params
+                .iter()
+                .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+                .collect::<Vec<_>>();
+            writer
+                .as_mut()
+                .write(&params)
+                .await
+                .map_err(|err| DbError::DatabaseError(format!("Error during COPY: {err:?}")))?;
+            count += 1;
+            if count % BULK_INSERT_BATCH_SIZE as u64 == 0 {
+                progress(count);
+            }
+        }
+        writer
+            .finish()
+            .await
+            .map_err(|err| DbError::DatabaseError(format!("Error finishing COPY: {err:?}")))?;
+        if count == 0 || count % BULK_INSERT_BATCH_SIZE as u64 != 0 {
+            progress(count);
+        }
+
+        clear_cache_for_edited_tables(self, &[&table]).await?;
+        Ok(count)
+    }
+
+    /// Implements [DbQuery::upsert_with()] for PostgreSQL.
+    async fn upsert_with(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl IntoDbRows,
+        options: &UpsertOptions,
+    ) -> Result<(), DbError> {
+        edit(
+            self,
+            &EditType::Upsert(options.clone()),
+            &MAX_PARAMS_POSTGRES,
+            table,
+            columns,
+            rows,
+            false,
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Implements [DbQuery::upsert_with_returning()] for PostgreSQL.
+    async fn upsert_with_returning(
+        &self,
+        table: &str,
+        columns: &[&str],
+        rows: impl IntoDbRows,
+        options: &UpsertOptions,
+        returning: &[&str],
+    ) -> Result<DbRows, DbError> {
+        edit(
+            self,
+            &EditType::Upsert(options.clone()),
             &MAX_PARAMS_POSTGRES,
             table,
             columns,
@@ -540,6 +838,42 @@ impl DbQuery for TokioPostgresPool {
         .await
     }
 
+    /// Implements [DbQuery::delete()] for PostgreSQL.
+    async fn delete(&self, table: &str, rows: impl IntoDbRows) -> Result<(), DbError> {
+        edit(
+            self,
+            &EditType::Delete,
+            &MAX_PARAMS_POSTGRES,
+            table,
+            &[],
+            rows,
+            false,
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Implements [DbQuery::delete_returning()] for PostgreSQL.
+    async fn delete_returning(
+        &self,
+        table: &str,
+        rows: impl IntoDbRows,
+        returning: &[&str],
+    ) -> Result<DbRows, DbError> {
+        edit(
+            self,
+            &EditType::Delete,
+            &MAX_PARAMS_POSTGRES,
+            table,
+            &[],
+            rows,
+            true,
+            returning,
+        )
+        .await
+    }
+
     /// Implements [DbQuery::drop_table()] for PostgreSQL.
     async fn drop_table(&self, table: &str) -> Result<(), DbError> {
         let table = validate_table_name(table)?;
@@ -567,7 +901,7 @@ impl DbQuery for TokioPostgresPool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{db_row, params};
+    use crate::{cache::MemoryCacheConfig, db_row, params};
     use pretty_assertions::assert_eq;
     use std::{ops::Deref, str::FromStr};
 
@@ -1441,4 +1775,95 @@ mod tests {
              }"
         );
     }
+
+    #[tokio::test]
+    async fn test_listen_for_cache_invalidations() {
+        let mut pool = TokioPostgresPool::connect("postgresql:///rltbl_db")
+            .await
+            .unwrap();
+        // Give the pool caches of its own, standing in for those of another process, so that
+        // listening does not affect the other tests connected to the same database:
+        pool.memory_caches = Arc::new(MemoryCaches::default());
+        pool.set_caching_strategy(&CachingStrategy::Memory(MemoryCacheConfig::default()));
+        pool.execute_batch(
+            "DROP TABLE IF EXISTS test_listen CASCADE;\
+             CREATE TABLE test_listen (value INT8)",
+        )
+        .await
+        .unwrap();
+
+        pool.listen_for_cache_invalidations().await.unwrap();
+        assert!(pool.memory_caches().is_listening());
+        // Listening again has no effect:
+        pool.listen_for_cache_invalidations().await.unwrap();
+
+        // Caching a query creates the triggers that notify the listener of edits:
+        let sql = "SELECT * FROM test_listen";
+        assert_eq!(pool.cache(sql, ()).await.unwrap().content.len(), 0);
+        assert_eq!(pool.memory_caches().query_cache().len(), 1);
+        assert!(
+            pool.memory_caches()
+                .exists_in_meta_cache("test_listen_notify_triggers")
+        );
+
+        // Edit the table without maintaining the cache, as another process would, and wait for
+        // the listener to clear the cache:
+        pool.execute_no_cache_clean("INSERT INTO test_listen VALUES (1)", ())
+            .await
+            .unwrap();
+        for _ in 0..50 {
+            if pool.memory_caches().query_cache().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(pool.memory_caches().query_cache().len(), 0);
+        assert_eq!(pool.cache(sql, ()).await.unwrap().content.len(), 1);
+        assert_eq!(pool.memory_caches().query_cache().len(), 1);
+
+        // Losing the listener's connection clears the caches:
+        let listeners =
+            r#"SELECT pid FROM pg_stat_activity WHERE query = 'LISTEN "rltbl_db_cache"'"#;
+        pool.query_no_cache_clean(
+            &format!("SELECT pg_terminate_backend(pid) FROM ({listeners}) AS listeners"),
+            (),
+        )
+        .await
+        .unwrap();
+        for _ in 0..50 {
+            if pool.memory_caches().query_cache().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(pool.memory_caches().query_cache().len(), 0);
+        assert!(pool.memory_caches().is_listening());
+
+        // Wait for the listener to reconnect, after which edits are noticed once again:
+        for _ in 0..50 {
+            if !pool
+                .query_no_cache_clean(listeners, ())
+                .await
+                .unwrap()
+                .is_empty()
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(pool.cache(sql, ()).await.unwrap().content.len(), 1);
+        assert_eq!(pool.memory_caches().query_cache().len(), 1);
+        pool.execute_no_cache_clean("INSERT INTO test_listen VALUES (2)", ())
+            .await
+            .unwrap();
+        for _ in 0..50 {
+            if pool.memory_caches().query_cache().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(pool.memory_caches().query_cache().len(), 0);
+        assert_eq!(pool.cache(sql, ()).await.unwrap().content.len(), 2);
+    }
 }